@@ -0,0 +1,130 @@
+// hot.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The bench command times a whole search, which says whether a change
+// made TSCP faster but not where. These Criterion benchmarks time the
+// functions the search spends its time in, one at a time, on the bench
+// position, e.g.:
+//
+//     cargo bench -- gen
+//
+// Criterion keeps the last run's results in target/criterion, and reports how
+// much each benchmark changed since then.
+
+#![allow(clippy::empty_line_after_doc_comments)]
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use tscp::board;
+use tscp::eval;
+use tscp::search;
+
+use tscp::data::Data;
+use tscp::defs::{Int, DARK, LIGHT};
+use tscp::search::{NoOutput, SearchLimits};
+
+/// the position the bench command searches
+const BENCH_FEN: &str = "1rb2rk1/p4ppp/1p1qp1n1/3n2N1/2pP4/2P3P1/PPQ2PBP/R1B1R1K1 w - - 0 1";
+
+/// the depth bench_search() searches to
+const SEARCH_DEPTH: Int = 4;
+
+/// bench_data() returns a Data with the bench position set up
+
+fn bench_data() -> Data {
+    let mut d = Data::new();
+    board::init_hash(&mut d.pos);
+    board::set_fen(&mut d, BENCH_FEN).unwrap();
+    d
+}
+
+fn bench_gen(c: &mut Criterion) {
+    let mut d = bench_data();
+    c.bench_function("gen", |b| {
+        b.iter(|| {
+            board::gen(&mut d);
+            black_box(d.search.gen_dat[0].len())
+        })
+    });
+}
+
+fn bench_gen_caps(c: &mut Criterion) {
+    let mut d = bench_data();
+    c.bench_function("gen_caps", |b| {
+        b.iter(|| {
+            board::gen_caps(&mut d);
+            black_box(d.search.gen_dat[0].len())
+        })
+    });
+}
+
+fn bench_attack(c: &mut Criterion) {
+    let d = bench_data();
+    // every square, attacked by either side
+    c.bench_function("attack", |b| {
+        b.iter(|| {
+            let mut n = 0;
+            for sq in 0..64 {
+                for &s in &[LIGHT, DARK] {
+                    n += board::attack(&d.pos, black_box(sq), s) as usize;
+                }
+            }
+            n
+        })
+    });
+}
+
+fn bench_eval(c: &mut Criterion) {
+    let mut d = bench_data();
+    c.bench_function("eval", |b| b.iter(|| eval::eval(black_box(&mut d.pos))));
+}
+
+fn bench_makemove(c: &mut Criterion) {
+    let mut d = bench_data();
+    board::gen(&mut d);
+    let moves: Vec<_> = d.search.gen_dat[0].iter().map(|g| g.m.bytes()).collect();
+    // every pseudo-legal move, made and taken back (makemove() takes an
+    // illegal move back itself)
+    c.bench_function("makemove/takeback", |b| {
+        b.iter(|| {
+            for &m in &moves {
+                if board::makemove(&mut d.pos, black_box(m)) {
+                    board::takeback(&mut d.pos);
+                }
+            }
+        })
+    });
+}
+
+fn bench_search(c: &mut Criterion) {
+    let mut d = bench_data();
+    let limits = SearchLimits {
+        depth: Some(SEARCH_DEPTH),
+        ..SearchLimits::default()
+    };
+    let mut group = c.benchmark_group("search");
+    group.sample_size(10);
+    group.bench_function(format!("depth {}", SEARCH_DEPTH), |b| {
+        b.iter(|| {
+            // each search starts from scratch, as the bench command's do
+            d.search.clear();
+            search::think(&mut d, &limits, &mut NoOutput).unwrap().nodes
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_gen,
+    bench_gen_caps,
+    bench_attack,
+    bench_eval,
+    bench_makemove,
+    bench_search
+);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+/* tscp.h
+ * Tom Kerrigan's Simple Chess Program (TSCP)
+ *
+ * Copyright 1997 Tom Kerrigan
+ *
+ * Rust port by Kristopher Johnson
+ *
+ * The C interface to the engine, in the library built by
+ * `cargo build --release -p tscp-ffi` (libtscp_ffi.so, tscp_ffi.dll, etc.).
+ *
+ * Strings are NUL-terminated UTF-8. Functions that return a string copy it to
+ * the caller's buffer the way snprintf() does (truncating it if necessary) and
+ * return the length of the whole string. Moves are in coordinate notation,
+ * e.g., "e2e4" or "e7e8q". The functions that return an int return TSCP_ERROR
+ * if the engine has failed (its thread has died), and the ones that return
+ * an engine return NULL if it can't be started.
+ */
+
+#ifndef TSCP_H
+#define TSCP_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define TSCP_OK 0
+#define TSCP_ERROR (-1)
+
+typedef struct TscpEngine TscpEngine;
+
+/* create an engine (with no opening book), set up for a new game */
+TscpEngine *tscp_engine_new(void);
+
+/* create an engine that uses the opening book file at book_path; returns NULL
+   if the book can't be read */
+TscpEngine *tscp_engine_new_with_book(const char *book_path);
+
+/* stop the engine and free it */
+void tscp_engine_free(TscpEngine *engine);
+
+/* start a new game (keeping the transposition table) */
+void tscp_engine_new_game(const TscpEngine *engine);
+
+/* set up the position described by a FEN string; returns TSCP_ERROR (and
+   leaves the board alone) if it isn't valid */
+int tscp_engine_set_position(const TscpEngine *engine, const char *fen);
+
+/* make a move; returns TSCP_ERROR if it's illegal */
+int tscp_engine_make_move(const TscpEngine *engine, const char *move);
+
+/* search for max_time milliseconds or max_depth (1 to 32) ply, whichever comes
+   first, and copy the best move (without making it) to buf; the move is an
+   empty string if there are no legal moves. Returns TSCP_ERROR if the limits
+   are out of range. */
+int tscp_engine_search(const TscpEngine *engine, int max_time, int max_depth,
+                       char *buf, size_t len);
+
+/* copy the principal variation found by the last search to buf, as moves
+   separated by spaces */
+int tscp_engine_pv(const TscpEngine *engine, char *buf, size_t len);
+
+/* make a search that's running on another thread return as soon as possible */
+void tscp_engine_stop(const TscpEngine *engine);
+
+/* copy the FEN string for the current position to buf */
+int tscp_engine_fen(const TscpEngine *engine, char *buf, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TSCP_H */
@@ -0,0 +1,343 @@
+// lib.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The translated code puts a blank line between a function's doc comment
+// and the function itself, which newer versions of clippy complain about.
+#![allow(clippy::empty_line_after_doc_comments)]
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Mutex;
+
+use tscp::engine::Engine;
+use tscp::search::SearchLimits;
+use tscp::util;
+
+use tscp::defs::{Int, MoveBytes, MAX_PLY};
+
+// #rust This is a C interface to Engine, for GUIs written in C, C++, C#, etc.
+// (include/tscp.h declares it). It's a crate of its own so that the library it
+// builds (libtscp_ffi.so, tscp_ffi.dll, etc.) only has the C interface in it.
+// An engine is an opaque pointer that's created by tscp_engine_new() and
+// destroyed by tscp_engine_free(). Strings go in as NUL-terminated UTF-8, and
+// come out the way snprintf() does it: the string is copied into the caller's
+// buffer (truncated if necessary, and always NUL-terminated if len isn't 0),
+// and the function returns the length of the whole string, so the caller can
+// tell whether the buffer was big enough.
+//
+// Engine's methods panic if its thread has died, and a panic can't unwind
+// into C (it aborts the whole process), so the functions catch panics (see
+// guard()) and return TSCP_ERROR (or NULL) instead.
+
+/// returned by the functions that succeed or fail
+pub const TSCP_OK: c_int = 0;
+pub const TSCP_ERROR: c_int = -1;
+
+/// the engine behind the C interface. Everything is behind a shared reference,
+/// so tscp_engine_stop() can be called from another thread while
+/// tscp_engine_search() is running.
+
+pub struct TscpEngine {
+    engine: Engine,
+    pv: Mutex<String>,
+}
+
+impl TscpEngine {
+    fn new(engine: Engine) -> TscpEngine {
+        TscpEngine {
+            engine,
+            pv: Mutex::new(String::new()),
+        }
+    }
+}
+
+/// tscp_engine_new() creates an engine (with no opening book), set up for a
+/// new game. It returns NULL if the engine can't be started.
+
+#[no_mangle]
+pub extern "C" fn tscp_engine_new() -> *mut TscpEngine {
+    panic::catch_unwind(|| Box::into_raw(Box::new(TscpEngine::new(Engine::new()))))
+        .unwrap_or(ptr::null_mut())
+}
+
+/// tscp_engine_new_with_book() creates an engine that uses the opening book
+/// file at book_path. It returns NULL if the book can't be read.
+///
+/// # Safety
+///
+/// book_path must be a NUL-terminated string.
+
+#[no_mangle]
+pub unsafe extern "C" fn tscp_engine_new_with_book(book_path: *const c_char) -> *mut TscpEngine {
+    let path = match to_str(book_path) {
+        Some(path) => path,
+        None => return ptr::null_mut(),
+    };
+    match panic::catch_unwind(|| Engine::builder().book_path(path).build()) {
+        Ok(Ok(engine)) => Box::into_raw(Box::new(TscpEngine::new(engine))),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// tscp_engine_free() stops the engine and frees it
+///
+/// # Safety
+///
+/// engine must be NULL or a pointer returned by tscp_engine_new() or
+/// tscp_engine_new_with_book() that hasn't been freed, and no other thread may
+/// be using it.
+
+#[no_mangle]
+pub unsafe extern "C" fn tscp_engine_free(engine: *mut TscpEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// tscp_engine_new_game() starts a new game (keeping the transposition
+/// table)
+///
+/// # Safety
+///
+/// engine must be a live pointer returned by tscp_engine_new() or
+/// tscp_engine_new_with_book().
+
+#[no_mangle]
+pub unsafe extern "C" fn tscp_engine_new_game(engine: *const TscpEngine) {
+    if let Some(e) = engine.as_ref() {
+        guard(|| {
+            e.engine.new_game(false);
+            TSCP_OK
+        });
+    }
+}
+
+/// tscp_engine_set_position() sets up the position described by the FEN
+/// string fen. It returns TSCP_ERROR (and the board doesn't change) if the
+/// string isn't valid FEN.
+///
+/// # Safety
+///
+/// engine must be a live engine pointer, and fen must be a NUL-terminated
+/// string.
+
+#[no_mangle]
+pub unsafe extern "C" fn tscp_engine_set_position(
+    engine: *const TscpEngine,
+    fen: *const c_char,
+) -> c_int {
+    match (engine.as_ref(), to_str(fen)) {
+        (Some(e), Some(fen)) => guard(|| match e.engine.set_position(fen) {
+            Ok(()) => TSCP_OK,
+            Err(_) => TSCP_ERROR,
+        }),
+        _ => TSCP_ERROR,
+    }
+}
+
+/// tscp_engine_make_move() makes the move m (in coordinate notation, e.g.,
+/// "e2e4" or "e7e8q"). It returns TSCP_ERROR if the move is illegal.
+///
+/// # Safety
+///
+/// engine must be a live engine pointer, and m must be a NUL-terminated
+/// string.
+
+#[no_mangle]
+pub unsafe extern "C" fn tscp_engine_make_move(
+    engine: *const TscpEngine,
+    m: *const c_char,
+) -> c_int {
+    let m = to_str(m).and_then(|m| m.parse::<MoveBytes>().ok());
+    match (engine.as_ref(), m) {
+        (Some(e), Some(m)) => guard(|| match e.engine.make_move(m) {
+            Ok(()) => TSCP_OK,
+            Err(_) => TSCP_ERROR,
+        }),
+        _ => TSCP_ERROR,
+    }
+}
+
+/// tscp_engine_search() searches for max_time milliseconds or max_depth ply,
+/// whichever comes first, and copies the best move it found (without making
+/// it) to buf. The result is an empty string if there are no legal moves. It
+/// returns TSCP_ERROR if max_time isn't positive or max_depth isn't 1 to 32.
+///
+/// # Safety
+///
+/// engine must be a live engine pointer, and buf must point to at least len
+/// bytes (or be NULL if len is 0).
+
+#[no_mangle]
+pub unsafe extern "C" fn tscp_engine_search(
+    engine: *const TscpEngine,
+    max_time: c_int,
+    max_depth: c_int,
+    buf: *mut c_char,
+    len: usize,
+) -> c_int {
+    let e = match engine.as_ref() {
+        Some(e) => e,
+        None => return TSCP_ERROR,
+    };
+    if max_time <= 0 || max_depth < 1 || max_depth as usize > MAX_PLY {
+        return TSCP_ERROR;
+    }
+
+    guard(|| {
+        let result = e.engine.think(SearchLimits {
+            movetime: Some(max_time as Int),
+            depth: Some(max_depth as Int),
+            ..SearchLimits::default()
+        });
+
+        // the PV of the last iteration (or just the move, if it's a book move)
+        let moves: Vec<String> = result.pv.iter().map(|&m| util::move_str(m)).collect();
+        *e.pv.lock().expect("pv poisoned") = moves.join(" ");
+
+        let best = result.best_move.map(util::move_str).unwrap_or_default();
+        copy_out(&best, buf, len)
+    })
+}
+
+/// tscp_engine_pv() copies the principal variation found by the last
+/// tscp_engine_search() to buf, as moves in coordinate notation separated by
+/// spaces
+///
+/// # Safety
+///
+/// engine must be a live engine pointer, and buf must point to at least len
+/// bytes (or be NULL if len is 0).
+
+#[no_mangle]
+pub unsafe extern "C" fn tscp_engine_pv(
+    engine: *const TscpEngine,
+    buf: *mut c_char,
+    len: usize,
+) -> c_int {
+    match engine.as_ref() {
+        Some(e) => guard(|| copy_out(&e.pv.lock().expect("pv poisoned"), buf, len)),
+        None => TSCP_ERROR,
+    }
+}
+
+/// tscp_engine_stop() makes a tscp_engine_search() that's in progress on
+/// another thread return as soon as possible with the best move found so far
+///
+/// # Safety
+///
+/// engine must be a live engine pointer.
+
+#[no_mangle]
+pub unsafe extern "C" fn tscp_engine_stop(engine: *const TscpEngine) {
+    if let Some(e) = engine.as_ref() {
+        e.engine.stop_thinking();
+    }
+}
+
+/// tscp_engine_fen() copies the FEN string for the current position to buf
+///
+/// # Safety
+///
+/// engine must be a live engine pointer, and buf must point to at least len
+/// bytes (or be NULL if len is 0).
+
+#[no_mangle]
+pub unsafe extern "C" fn tscp_engine_fen(
+    engine: *const TscpEngine,
+    buf: *mut c_char,
+    len: usize,
+) -> c_int {
+    match engine.as_ref() {
+        Some(e) => guard(|| copy_out(&e.engine.fen(), buf, len)),
+        None => TSCP_ERROR,
+    }
+}
+
+/// guard() calls f, and returns what it returns, or TSCP_ERROR if it panics
+
+fn guard(f: impl FnOnce() -> c_int) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(TSCP_ERROR)
+}
+
+/// to_str() returns the string s points to, or None if s is NULL or isn't
+/// UTF-8
+
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        None
+    } else {
+        CStr::from_ptr(s).to_str().ok()
+    }
+}
+
+/// copy_out() copies s to the len-byte buffer buf, snprintf()-style, and
+/// returns the length of s
+
+unsafe fn copy_out(s: &str, buf: *mut c_char, len: usize) -> c_int {
+    if !buf.is_null() && len > 0 {
+        let n = s.len().min(len - 1);
+        ptr::copy_nonoverlapping(s.as_ptr() as *const c_char, buf, n);
+        *buf.add(n) = 0;
+    }
+    s.len() as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::CString;
+
+    fn get(f: impl Fn(*mut c_char, usize) -> c_int) -> String {
+        let mut buf = [0 as c_char; 128];
+        let n = f(buf.as_mut_ptr(), buf.len());
+        assert!(n >= 0);
+        let s = unsafe { CStr::from_ptr(buf.as_ptr()) };
+        s.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_ffi() {
+        let e = tscp_engine_new();
+        unsafe {
+            let e4 = CString::new("e2e4").unwrap();
+            let e5 = CString::new("e7e4").unwrap();
+            assert_eq!(tscp_engine_make_move(e, e4.as_ptr()), TSCP_OK);
+            assert_eq!(tscp_engine_make_move(e, e5.as_ptr()), TSCP_ERROR);
+            assert_eq!(
+                get(|buf, len| tscp_engine_fen(e, buf, len)),
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+            );
+
+            // a buffer that's too small gets as much as fits
+            let mut small = [0x7f as c_char; 4];
+            assert_eq!(tscp_engine_fen(e, small.as_mut_ptr(), small.len()), 59);
+            assert_eq!(CStr::from_ptr(small.as_ptr()).to_bytes(), b"rnb");
+            assert_eq!(tscp_engine_fen(e, ptr::null_mut(), 0), 59);
+
+            // mate in one
+            let fen = CString::new("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+            assert_eq!(tscp_engine_set_position(e, fen.as_ptr()), TSCP_OK);
+            let best = get(|buf, len| tscp_engine_search(e, 10_000, 3, buf, len));
+            assert_eq!(best, "a1a8");
+            let pv = get(|buf, len| tscp_engine_pv(e, buf, len));
+            assert!(pv.starts_with("a1a8"), "{}", pv);
+            assert_eq!(
+                tscp_engine_search(e, 10_000, 0, ptr::null_mut(), 0),
+                TSCP_ERROR
+            );
+
+            let bad = CString::new("not a fen").unwrap();
+            assert_eq!(tscp_engine_set_position(e, bad.as_ptr()), TSCP_ERROR);
+            assert_eq!(tscp_engine_set_position(e, ptr::null()), TSCP_ERROR);
+            tscp_engine_free(e);
+        }
+    }
+}
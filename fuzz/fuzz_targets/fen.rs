@@ -0,0 +1,31 @@
+// fen.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// set_fen() gets FENs from the setboard command, from PGN files, and from the
+// Engine API. Whatever it accepts has to be a board that validate() is happy
+// with, that the move generator can work with, and that comes back out of
+// fen() as a FEN set_fen() accepts again.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use tscp::board;
+use tscp::data::Data;
+
+fuzz_target!(|fen: &str| {
+    let mut d = Data::new();
+    board::init_hash(&mut d.pos);
+    if board::set_fen(&mut d, fen).is_err() {
+        return;
+    }
+    assert!(board::validate(&d).is_ok(), "{}", fen);
+    let out = board::fen(&d.pos);
+    board::set_fen(&mut d, &out).expect("fen() output rejected");
+    assert_eq!(board::fen(&d.pos), out);
+    assert!(board::perft(&mut d, 2) < 1 << 20);
+});
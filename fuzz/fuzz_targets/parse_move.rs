@@ -0,0 +1,36 @@
+// parse_move.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// parse_move() gets whatever the user or the XBoard GUI sends as a move. The
+// input is a position's FEN and a move, on separate lines; if the FEN isn't
+// valid, the move is parsed in the starting position. A move that parses has
+// to be one of the generated ones, and has to be makeable.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use tscp::board;
+use tscp::data::Data;
+use tscp::util;
+
+fuzz_target!(|input: &str| {
+    let (fen, s) = input.split_once('\n').unwrap_or(("", input));
+    let mut d = Data::new();
+    board::init_hash(&mut d.pos);
+    if board::set_fen(&mut d, fen).is_err() {
+        board::init_board(&mut d.pos);
+        board::set_incremental(&mut d.pos);
+    }
+    board::gen(&mut d);
+    if let Ok(i) = util::parse_move(&d, s) {
+        let m = d.search.gen_dat[0][i].m.bytes();
+        if board::makemove(&mut d.pos, m) {
+            board::takeback(&mut d.pos);
+        }
+    }
+});
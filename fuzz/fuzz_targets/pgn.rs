@@ -0,0 +1,32 @@
+// pgn.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// parse_pgn() reads PGN files (for the annotate executable, and the puzzle
+// and book commands), and replay() plays the games it finds, SAN moves and
+// FEN tags and all. Neither may panic, whatever the file holds.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use tscp::board;
+use tscp::data::Data;
+use tscp::pgn;
+
+fuzz_target!(|text: &str| {
+    let games = match pgn::parse_pgn(text) {
+        Ok(games) => games,
+        Err(_) => return,
+    };
+    let mut d = Data::new();
+    for game in &games {
+        if pgn::replay(&mut d, game).is_ok() {
+            assert!(board::validate(&d).is_ok());
+            let _ = board::history_san(&d.pos);
+        }
+    }
+});
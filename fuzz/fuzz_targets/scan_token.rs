@@ -0,0 +1,42 @@
+// scan_token.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// The console and the XBoard loop read their commands with the scan
+// functions, from whatever bytes come in on stdin. Reading the input as
+// tokens (and, for a second pass, as integers) has to use it all up, with
+// each token coming out of the input whole, and bytes that aren't UTF-8 have
+// to be reported as bad data.
+
+#![no_main]
+
+use std::io::ErrorKind;
+
+use libfuzzer_sys::fuzz_target;
+
+use tscp::scan;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = data;
+    let mut tokens = 0;
+    loop {
+        match scan::scan_token_line_from(&mut reader) {
+            Ok((token, _)) if token.is_empty() => break,
+            Ok((token, _)) => assert!(!token.bytes().any(scan::is_whitespace)),
+            // a token that isn't UTF-8 is an error, but not the end
+            Err(err) => assert_eq!(err.kind(), ErrorKind::InvalidData),
+        }
+        tokens += 1;
+        assert!(tokens <= data.len());
+    }
+
+    let mut reader = data;
+    for _ in 0..=data.len() {
+        if scan::scan_int_from(&mut reader).is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,294 @@
+// analysis.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP doesn't analyze games. analyze_game() replays a
+// game, comparing each move with the engine's choice (see
+// util::assess_move()), and print_analysis() reports how much each move lost,
+// the bad ones, and each side's accuracy.
+
+use std::io;
+use std::io::Write;
+
+use super::board;
+use super::util;
+
+use super::data::{Data, Position};
+use super::defs::{Int, MAX_PLY};
+use super::pgn::MoveNote;
+use super::search::SearchLimits;
+
+/// how many centipawns a move has to lose, compared with the engine's
+/// choice, to be an inaccuracy, a mistake, or a blunder
+pub const INACCURACY: Int = 50;
+pub const MISTAKE: Int = 100;
+pub const BLUNDER: Int = 300;
+
+/// how bad a move is
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    /// judge() returns how bad a move that loses loss centipawns is, or None
+    /// if it's a reasonable move
+
+    pub fn judge(loss: Int) -> Option<Judgement> {
+        if loss >= BLUNDER {
+            Some(Judgement::Blunder)
+        } else if loss >= MISTAKE {
+            Some(Judgement::Mistake)
+        } else if loss >= INACCURACY {
+            Some(Judgement::Inaccuracy)
+        } else {
+            None
+        }
+    }
+
+    /// mark() returns the annotation for the move: "?!", "?", or "??"
+    pub fn mark(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "?!",
+            Judgement::Mistake => "?",
+            Judgement::Blunder => "??",
+        }
+    }
+
+    /// nag() returns PGN's Numeric Annotation Glyph for the mark
+    pub fn nag(self) -> Int {
+        match self {
+            Judgement::Inaccuracy => 6,
+            Judgement::Mistake => 2,
+            Judgement::Blunder => 4,
+        }
+    }
+}
+
+/// what analyze_game() finds out about a move
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MoveAnalysis {
+    /// the ply of the game the move was played at (0 is White's first move)
+    pub ply: usize,
+    pub assessment: util::Assessment,
+}
+
+impl MoveAnalysis {
+    /// judgement() returns how bad the move is, if it's bad
+    pub fn judgement(&self) -> Option<Judgement> {
+        Judgement::judge(self.assessment.loss())
+    }
+
+    /// accuracy() returns how accurate the move is, from 0 to 100 percent,
+    /// worked out from how much it lowers the mover's chance of winning
+    /// (see win_chance())
+    pub fn accuracy(&self) -> f64 {
+        let before = win_chance(self.assessment.best_score);
+        let after = win_chance(self.assessment.score);
+        let drop = (before - after).max(0.0);
+        (103.1668 * (-0.04354 * drop).exp() - 3.1669).clamp(0.0, 100.0)
+    }
+}
+
+/// win_chance() returns the chance, in percent, that the side with a score
+/// of score centipawns wins the game (the formula and the accuracy()
+/// formula are the ones lichess uses)
+
+fn win_chance(score: Int) -> f64 {
+    let score = score.clamp(-1000, 1000) as f64;
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.003_682_08 * score).exp()) - 1.0)
+}
+
+/// analyze_game() replays the game in pos's history, and assesses each move
+/// with a search of the position before it, with the given limits. The
+/// opening book isn't used, and pos doesn't change.
+
+pub fn analyze_game(pos: &Position, limits: &SearchLimits) -> io::Result<Vec<MoveAnalysis>> {
+    // replay the game on a copy of the position
+    let mut d = Data::new();
+    d.pos = pos.clone();
+    while d.pos.hply > 0 {
+        board::takeback(&mut d.pos);
+    }
+    let mut analysis = Vec::new();
+    for h in pos.history() {
+        let m = h.m.bytes();
+        if let Some(assessment) = util::assess_move_with(&mut d, m, limits)? {
+            analysis.push(MoveAnalysis {
+                ply: d.pos.start_ply + d.pos.hply,
+                assessment,
+            });
+        }
+        board::makemove(&mut d.pos, m);
+    }
+    Ok(analysis)
+}
+
+/// notes() returns annotations for the moves in analyze_game()'s analysis,
+/// for pgn::annotated_pgn(): each move's score, and the engine's choice if
+/// it's different, from White's point of view, and a NAG for a bad move
+
+pub fn notes(analysis: &[MoveAnalysis]) -> Vec<MoveNote> {
+    analysis
+        .iter()
+        .map(|a| {
+            // the scores are from the point of view of the side that moved
+            let white = |score: Int| if a.ply % 2 == 0 { score } else { -score };
+            let mut comment = eval_str(white(a.assessment.score));
+            if !a.assessment.is_best() {
+                comment += &format!(
+                    " (best: {} {})",
+                    a.assessment.best_san,
+                    eval_str(white(a.assessment.best_score))
+                );
+            }
+            MoveNote {
+                nag: a.judgement().map(Judgement::nag),
+                comment: Some(comment),
+            }
+        })
+        .collect()
+}
+
+/// eval_str() formats a score from White's point of view the way PGN
+/// annotations usually do: in pawns (e.g., "+0.25"), or as "#n" for White
+/// mating in n moves, or "#-n" for Black mating in n
+
+fn eval_str(score: Int) -> String {
+    // a mate is scored as 10000 minus the number of ply to it
+    let mate_ply = 10000 - score.abs();
+    if mate_ply < MAX_PLY as Int {
+        let moves = (mate_ply + 1) / 2;
+        format!("#{}", if score > 0 { moves } else { -moves })
+    } else {
+        format!("{:+.2}", score as f64 / 100.0)
+    }
+}
+
+/// print_analysis() prints a report of analyze_game()'s analysis: each
+/// move's score, the engine's choice if it's different, and how much the
+/// move lost, and then each side's accuracy and the number of bad moves
+
+pub fn print_analysis(analysis: &[MoveAnalysis], out: &mut dyn Write) -> io::Result<()> {
+    for a in analysis {
+        let number = if a.ply % 2 == 0 {
+            format!("{}.", a.ply / 2 + 1)
+        } else {
+            format!("{}...", a.ply / 2 + 1)
+        };
+        let mark = a.judgement().map_or("", Judgement::mark);
+        let san = format!("{}{}", a.assessment.san, mark);
+        write!(
+            out,
+            "{:>6} {:<9} {:>12}",
+            number,
+            san,
+            util::score_str(a.assessment.score)
+        )?;
+        if !a.assessment.is_best() {
+            write!(
+                out,
+                "  (best: {} {}",
+                a.assessment.best_san,
+                util::score_str(a.assessment.best_score)
+            )?;
+            if a.assessment.loss() > 0 {
+                write!(out, ", loss {:.2}", a.assessment.loss() as f64 / 100.0)?;
+            }
+            write!(out, ")")?;
+        }
+        writeln!(out)?;
+    }
+
+    writeln!(out)?;
+    for (side, name) in ["White", "Black"].iter().enumerate() {
+        let moves: Vec<&MoveAnalysis> = analysis.iter().filter(|a| a.ply % 2 == side).collect();
+        if moves.is_empty() {
+            continue;
+        }
+        let n = moves.len() as f64;
+        let accuracy = moves.iter().map(|a| a.accuracy()).sum::<f64>() / n;
+        // a mate that's missed or walked into isn't worth counting in pawns
+        let loss = moves
+            .iter()
+            .map(|a| a.assessment.loss().min(1000))
+            .sum::<Int>() as f64
+            / n;
+        let count = |j| moves.iter().filter(|a| a.judgement() == Some(j)).count();
+        writeln!(
+            out,
+            "{}: accuracy {:.1}%, average loss {:.2}, {} blunders, {} mistakes, {} inaccuracies",
+            name,
+            accuracy,
+            loss / 100.0,
+            count(Judgement::Blunder),
+            count(Judgement::Mistake),
+            count(Judgement::Inaccuracy)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze_game() {
+        assert_eq!(Judgement::judge(20), None);
+        assert_eq!(Judgement::judge(50), Some(Judgement::Inaccuracy));
+        assert_eq!(Judgement::judge(299), Some(Judgement::Mistake));
+        assert_eq!(Judgement::judge(10000), Some(Judgement::Blunder));
+
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        for m in &["f2f3", "e7e5", "g2g4", "d8h4"] {
+            board::try_move(&mut d, m.parse().unwrap()).unwrap();
+        }
+        let fen = board::fen(&d.pos);
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let analysis = analyze_game(&d.pos, &limits).unwrap();
+        assert_eq!(board::fen(&d.pos), fen);
+        assert_eq!(analysis.len(), 4);
+        assert_eq!(
+            analysis.iter().map(|a| a.ply).collect::<Vec<_>>(),
+            [0, 1, 2, 3]
+        );
+        // g4 walks into a mate in 1, and Qh4# is the best move there is
+        assert_eq!(analysis[2].judgement(), Some(Judgement::Blunder));
+        assert!(analysis[2].accuracy() < 20.0);
+        assert!(analysis[3].assessment.is_best());
+        assert!(analysis[3].accuracy() > 99.9);
+
+        let mut out = Vec::new();
+        print_analysis(&analysis, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("2. g4??"), "{}", out);
+        assert!(out.contains("White: accuracy"), "{}", out);
+        assert!(out.contains("Black: accuracy"), "{}", out);
+
+        let notes = notes(&analysis);
+        assert_eq!(notes.len(), 4);
+        assert_eq!(notes[2].nag, Some(4));
+        assert!(notes[2]
+            .comment
+            .as_deref()
+            .unwrap()
+            .starts_with("#-1 (best: "));
+        assert_eq!(notes[3].nag, None);
+        assert_eq!(notes[3].comment.as_deref(), Some("#-1"));
+        assert_eq!(eval_str(25), "+0.25");
+        assert_eq!(eval_str(9997), "#2");
+    }
+}
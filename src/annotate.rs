@@ -0,0 +1,282 @@
+// annotate.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP doesn't annotate games. This is the
+// tscp-annotate executable's code: it reads the games in a PGN file, analyzes
+// each one (see analysis::analyze_game()), and writes them out again with the
+// engine's evaluation of every move in a comment, and a NAG ("$2", "$4", or
+// "$6") for the bad ones. It's the batch counterpart of the console's
+// "analyze" command.
+
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use super::analysis;
+use super::pgn;
+
+use super::data::Data;
+use super::defs::{Int, MAX_PLY};
+use super::search::SearchLimits;
+
+pub const USAGE: &str = "usage: tscp-annotate [options] [file.pgn]\n\
+    \n\
+    Annotates the games in file.pgn (or standard input) with the engine's\n\
+    evaluation of each move, writing them out as PGN.\n\
+    \n\
+    --st n         search each position for n seconds (1; decimals are allowed)\n\
+    --depth n      search each position n ply, instead of for a time\n\
+    --game n       annotate only the nth game, instead of all of them\n\
+    --output path  write the annotated games to path, instead of standard output\n\
+    --help         show this message";
+
+/// the seconds each position is searched for if there's no --st or --depth
+const DEFAULT_ST: f64 = 1.0;
+
+/// the options that can be given on tscp-annotate's command line; see
+/// parse_args()
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    /// the PGN file to read, instead of standard input
+    pub input: Option<PathBuf>,
+    /// the file to write, instead of standard output
+    pub output: Option<PathBuf>,
+    /// the search depth, and the seconds to search each position; if neither
+    /// is given, each position is searched for DEFAULT_ST seconds
+    pub depth: Option<Int>,
+    pub st: Option<f64>,
+    /// the number (from 1) of the only game to annotate
+    pub game: Option<usize>,
+    /// show the usage message and exit
+    pub help: bool,
+}
+
+impl Options {
+    /// limits() returns the search limits for each position
+    pub fn limits(&self) -> SearchLimits {
+        match self.depth {
+            Some(depth) => SearchLimits {
+                depth: Some(depth),
+                ..SearchLimits::default()
+            },
+            None => SearchLimits {
+                movetime: Some((self.st.unwrap_or(DEFAULT_ST) * 1000.0) as Int),
+                ..SearchLimits::default()
+            },
+        }
+    }
+}
+
+/// parse_args() reads tscp-annotate's command line arguments (without the
+/// program's name) into Options. It returns a message saying what's wrong if
+/// they don't make sense.
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_ref() {
+            "--help" | "-h" => options.help = true,
+            "--st" => {
+                let n = value()?;
+                options.st = match n.parse::<f64>() {
+                    Ok(st) if st.is_finite() && st > 0.0 => Some(st),
+                    _ => return Err(format!("bad {} value: {}", arg, n)),
+                };
+            }
+            "--depth" | "--game" => {
+                let n = value()?;
+                let n = match n.parse::<usize>() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("bad {} value: {}", arg, n)),
+                };
+                if arg == "--game" {
+                    options.game = Some(n);
+                } else if n >= MAX_PLY {
+                    return Err(format!(
+                        "bad {} value: {} (the most is {})",
+                        arg,
+                        n,
+                        MAX_PLY - 1
+                    ));
+                } else {
+                    options.depth = Some(n as Int);
+                }
+            }
+            "--output" | "-o" => options.output = Some(PathBuf::from(value()?)),
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option: {}", arg))
+            }
+            _ if options.input.is_none() => {
+                if arg != "-" {
+                    options.input = Some(PathBuf::from(arg));
+                }
+            }
+            _ => return Err(format!("only one file can be annotated: {}", arg)),
+        }
+    }
+    if options.st.is_some() && options.depth.is_some() {
+        return Err(String::from("--st and --depth can't both be given"));
+    }
+    Ok(options)
+}
+
+/// run() annotates the games the options ask for, reporting its progress,
+/// and any games it can't annotate, on standard error. It returns a message
+/// if the games can't be read or written.
+
+pub fn run(options: &Options) -> Result<(), String> {
+    let text = match &options.input {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|err| format!("unable to read {}: {}", path.display(), err))?,
+        None => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|err| format!("unable to read standard input: {}", err))?;
+            text
+        }
+    };
+    let mut out: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(io::BufWriter::new(
+            fs::File::create(path)
+                .map_err(|err| format!("unable to create {}: {}", path.display(), err))?,
+        )),
+        None => Box::new(io::stdout()),
+    };
+    annotate(&text, options, &mut out, &mut io::stderr())
+        .and_then(|()| out.flush())
+        .map_err(|err| format!("output error: {}", err))
+}
+
+/// annotate() annotates the games in text that the options ask for, writing
+/// them to out as PGN, and its progress to log. A game that isn't valid (e.g.,
+/// it has an illegal move) is reported and left out.
+
+pub fn annotate(
+    text: &str,
+    options: &Options,
+    out: &mut dyn Write,
+    log: &mut dyn Write,
+) -> io::Result<()> {
+    let games = match pgn::parse_pgn(text) {
+        Ok(games) => games,
+        Err(err) => return writeln!(log, "{}", err),
+    };
+    if let Some(n) = options.game {
+        if n > games.len() {
+            return writeln!(log, "there are only {} games", games.len());
+        }
+    }
+    let limits = options.limits();
+    let mut d = Data::new();
+    let mut first = true;
+    for (i, game) in games.iter().enumerate() {
+        if options.game.is_some_and(|n| n != i + 1) {
+            continue;
+        }
+        write!(log, "Game {} of {}", i + 1, games.len())?;
+        if let (Some(white), Some(black)) = (game.tag("White"), game.tag("Black")) {
+            write!(log, " ({} - {})", white, black)?;
+        }
+        writeln!(log, ": {} moves", game.moves.len())?;
+        if let Err(err) = pgn::replay(&mut d, game) {
+            writeln!(log, "Game {}: {}", i + 1, err)?;
+            continue;
+        }
+
+        let analysis = analysis::analyze_game(&d.pos, &limits)?;
+        let notes = analysis::notes(&analysis);
+        // annotated_pgn() writes the tags about the position and the result
+        let tags: Vec<(&str, &str)> = game
+            .tags
+            .iter()
+            .filter(|(name, _)| !["Result", "SetUp", "FEN"].contains(&name.as_str()))
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        let tags = [&tags[..], &[("Annotator", "TSCP")]].concat();
+        if !first {
+            writeln!(out)?;
+        }
+        first = false;
+        write!(
+            out,
+            "{}",
+            pgn::annotated_pgn(&d.pos, &tags, &game.result, &notes)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Result<Options, String> {
+        parse_args(s.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = args("--st 0.5 -o out.pgn games.pgn").unwrap();
+        assert_eq!(options.st, Some(0.5));
+        assert_eq!(options.output, Some(PathBuf::from("out.pgn")));
+        assert_eq!(options.input, Some(PathBuf::from("games.pgn")));
+        assert_eq!(options.limits().movetime, Some(500));
+
+        let options = args("--depth 3 --game 2").unwrap();
+        assert_eq!((options.depth, options.game), (Some(3), Some(2)));
+        assert_eq!(options.input, None);
+        assert_eq!(options.limits().depth, Some(3));
+        assert_eq!(args("").unwrap().limits().movetime, Some(1000));
+
+        assert!(args("--st").is_err());
+        assert!(args("--st 0").is_err());
+        assert!(args("--depth 40").is_err());
+        assert!(args("--game 0").is_err());
+        assert!(args("--st 1 --depth 3").is_err());
+        assert!(args("--uci").is_err());
+        assert!(args("a.pgn b.pgn").is_err());
+    }
+
+    #[test]
+    fn test_annotate() {
+        let text = "[White \"A\"]\n[Black \"B\"]\n[Result \"0-1\"]\n\n\
+                    1. f3 e5 2. g4 Qh4# 0-1\n\n\
+                    [White \"C\"]\n\n1. e4 e5 2. Ke3 *\n\n\
+                    1. d4 d5 1/2-1/2\n";
+        let options = args("--depth 3").unwrap();
+        let mut out = Vec::new();
+        let mut log = Vec::new();
+        annotate(text, &options, &mut out, &mut log).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let log = String::from_utf8(log).unwrap();
+
+        let games = pgn::parse_pgn(&out).unwrap();
+        assert_eq!(games.len(), 2, "{}", out);
+        assert_eq!(games[0].tag("White"), Some("A"));
+        assert_eq!(games[0].tag("Annotator"), Some("TSCP"));
+        assert_eq!(games[0].moves, ["f3", "e5", "g4", "Qh4#"]);
+        assert_eq!(games[0].result, "0-1");
+        assert_eq!(games[1].moves, ["d4", "d5"]);
+        assert_eq!(games[1].result, "1/2-1/2");
+        assert!(out.contains("g4 $4 {#-1"), "{}", out);
+        assert!(out.contains("Qh4# {#-1}"), "{}", out);
+        assert!(log.contains("Game 1 of 3 (A - B): 4 moves"), "{}", log);
+        assert!(log.contains("Game 2: illegal move: 2. Ke3"), "{}", log);
+
+        let options = args("--depth 2 --game 3").unwrap();
+        let mut out = Vec::new();
+        annotate(text, &options, &mut out, &mut io::sink()).unwrap();
+        let games = pgn::parse_pgn(std::str::from_utf8(&out).unwrap()).unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].moves, ["d4", "d5"]);
+    }
+}
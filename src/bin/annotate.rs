@@ -0,0 +1,29 @@
+// annotate.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+use std::env;
+use std::process;
+
+use tscp::annotate;
+
+fn main() {
+    let options = match annotate::parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("tscp-annotate: {}\n\n{}", err, annotate::USAGE);
+            process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", annotate::USAGE);
+        return;
+    }
+    if let Err(err) = annotate::run(&options) {
+        eprintln!("tscp-annotate: {}", err);
+        process::exit(1);
+    }
+}
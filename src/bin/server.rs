@@ -0,0 +1,29 @@
+// server.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+use std::env;
+use std::process;
+
+use tscp::server;
+
+fn main() {
+    let options = match server::parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("tscp-server: {}\n\n{}", err, server::USAGE);
+            process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", server::USAGE);
+        return;
+    }
+    if let Err(err) = server::run(&options) {
+        eprintln!("tscp-server: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,29 @@
+// tui.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+use std::env;
+use std::process;
+
+use tscp::tui;
+
+fn main() {
+    let options = match tui::parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("tscp-tui: {}\n\n{}", err, tui::USAGE);
+            process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", tui::USAGE);
+        return;
+    }
+    if let Err(err) = tui::run(&options) {
+        eprintln!("tscp-tui: {}", err);
+        process::exit(1);
+    }
+}
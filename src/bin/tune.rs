@@ -0,0 +1,29 @@
+// tune.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+use std::env;
+use std::process;
+
+use tscp::tune;
+
+fn main() {
+    let options = match tune::parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("tscp-tune: {}\n\n{}", err, tune::USAGE);
+            process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", tune::USAGE);
+        return;
+    }
+    if let Err(err) = tune::run(&options) {
+        eprintln!("tscp-tune: {}", err);
+        process::exit(1);
+    }
+}
@@ -5,69 +5,115 @@
 //
 // Rust port by Kristopher Johnson
 
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::eval;
+use super::rand;
+
 use super::data::{
-    Data, CASTLE_MASK, INIT_COLOR, INIT_PIECE, MAILBOX, MAILBOX64, OFFSET, OFFSETS, SLIDE,
+    Data, Position, SearchState, Squares, ATTACKS, CASTLE_MASK, DIAGONALS, INIT_COLOR, INIT_PIECE,
+    MAILBOX, MAILBOX64, OFFSET, OFFSETS, PIECE_CHAR, PIECE_GLYPH, SLIDE,
 };
 use super::defs::{
-    Int, MoveBytes, A1, A8, B1, B8, C1, C8, D1, D8, DARK, E1, E8, EMPTY, F1, F8, G1, G8, H1, H8,
-    KING, KNIGHT, LIGHT, PAWN, QUEEN, ROOK,
+    Color, FenError, Gen, Hist, IllegalMove, Inconsistency, Int, Move, MoveBytes, Square, A1, A8,
+    B1, B8, BISHOP, C1, C8, D1, D8, DARK, E1, E8, EMPTY, F1, F8, G1, G8, H1, H8, KING, KNIGHT,
+    LIGHT, PAWN, QUEEN, ROOK,
 };
 
-// #rust gen_push!(d, from, to, bits) coerces the arguments to the right types,
+// #rust gen_push!(pos, search, from, to, bits) coerces the arguments to the right types,
 // avoiding the need for a lot of explicit "as usize" and "as u8" coercions in
 // calls to gen_push().
 macro_rules! gen_push {
-    ( $dref:ident, $from:expr, $to:expr, $bits:expr ) => {
-        gen_push($dref, $from as usize, $to as usize, $bits as u8)
+    ( $pos:ident, $search:ident, $from:expr, $to:expr, $bits:expr ) => {
+        gen_push($pos, $search, $from as usize, $to as usize, $bits as u8)
     };
 }
 
 /// init_board() sets the board to the initial game state.
 
-pub fn init_board(d: &mut Data) {
-    d.color = INIT_COLOR;
-    d.piece = INIT_PIECE;
-    d.side = LIGHT;
-    d.xside = DARK;
-    d.castle = 15;
-    d.ep = -1;
-    d.fifty = 0;
-    d.ply = 0;
-    d.hply = 0;
-    set_hash(d); // init_hash() must be called
-    d.first_move[0] = 0;
+pub fn init_board(pos: &mut Position) {
+    pos.color = INIT_COLOR;
+    pos.piece = INIT_PIECE;
+    pos.side = LIGHT;
+    pos.xside = DARK;
+    pos.castle = 15;
+    pos.ep = -1;
+    pos.fifty = 0;
+    pos.hply = 0;
+    pos.start_ply = 0;
+    set_incremental(pos); // init_hash() must be called
 }
 
-/// init_hash() initializes the random numbers used by set_hash().
+/// new_game() sets everything up for a new game in one step: the board and
+/// the game's history, and the search state left over from the last game. If
+/// clear_tt is true, the transposition table (if there is one) is emptied,
+/// too; note that it may be shared with other searches.
+
+pub fn new_game(d: &mut Data, clear_tt: bool) {
+    init_board(&mut d.pos);
+    d.pos.hist_dat.clear();
+    d.search.clear();
+    if clear_tt {
+        if let Some(tt) = &d.search.tt {
+            tt.clear();
+        }
+    }
+    gen_legal(d);
+}
 
-pub fn init_hash(d: &mut Data) {
-    unsafe {
-        libc::srand(0);
+/// set_incremental() computes the values that makemove() and takeback() keep up
+/// to date incrementally: the hash, the king squares, and the material and
+/// piece/square sums. It must be called whenever the pieces are put on the
+/// board directly, as init_board() and bench() do.
+
+pub fn set_incremental(pos: &mut Position) {
+    set_hash(pos);
+    eval::set_material(pos);
+    pos.pieces = [0; 2];
+    for i in 0..64 {
+        if pos.color(i) != EMPTY {
+            pos.pieces[pos.color(i) as usize] |= 1 << i;
+        }
+        if pos.piece(i) == KING {
+            pos.king_sq[pos.color(i) as usize] = i;
+        }
     }
+}
+
+/// init_hash() initializes the random numbers used by set_hash().
+
+pub fn init_hash(pos: &mut Position) {
+    rand::srand(0);
     for i in 0..2 {
         for j in 0..6 {
             for k in 0..64 {
-                d.hash_piece[i][j][k] = hash_rand();
+                pos.hash_piece[i][j][k] = hash_rand();
             }
         }
     }
-    d.hash_side = hash_rand();
+    pos.hash_side = hash_rand();
     for i in 0..64 {
-        d.hash_ep[i] = hash_rand();
+        pos.hash_ep[i] = hash_rand();
+    }
+    for i in 0..16 {
+        pos.hash_castle[i] = hash_rand();
     }
 }
 
 /// hash_rand() XORs some shifted random numbers together to make sure
-/// we have good coverage of all 32 bits. (rand() returns 16-bit numbers
-/// on some systems.)
+/// we have good coverage of all 64 bits.
+// #rust The C version XORs 32 rand()s shifted left by 1, for a 32-bit int;
+// with this rand()'s 31 bits, that left the hash below 2^32 and even, so the
+// transposition table never used its odd slots, and its key check had only
+// 31 bits to go on. Three 31-bit numbers, 31 bits apart, cover a 64-bit Int.
 fn hash_rand() -> Int {
-    let mut r: Int = 0;
-    unsafe {
-        for _ in 0..32 {
-            r ^= (libc::rand() << 1) as Int;
-        }
+    let mut r: u64 = 0;
+    for _ in 0..3 {
+        r = (r << 31) ^ rand::rand() as u64;
     }
-    r
+    r as Int
 }
 
 /// set_hash() uses the Zobrist method of generating a unique number (hash)
@@ -81,85 +127,589 @@ fn hash_rand() -> Int {
 /// hash_side is XORed if it's black's move, and the en passant square is
 /// XORed if there is one. (A chess technicality is that one position can't
 /// be a repetition of another if the en passant state is different.)
+///
+/// #rust set_hash() only needs to be called when the board is set up. After
+/// that, makemove() updates the hash by XORing out the features of the position
+/// that change and XORing in the new ones, and takeback() restores the saved
+/// hash.
 
-pub fn set_hash(d: &mut Data) {
-    d.hash = 0;
+pub fn set_hash(pos: &mut Position) {
+    pos.hash = compute_hash(pos);
+}
+
+/// compute_hash() returns the hash of the position without storing it, so
+/// validate() can compare it to the hash that makemove() has been updating.
+
+fn compute_hash(pos: &Position) -> Int {
+    let mut hash = 0;
     for i in 0..64 {
-        if d.color[i] != EMPTY {
-            d.hash ^= d.hash_piece[d.color[i] as usize][d.piece[i] as usize][i as usize];
+        if pos.color(i) != EMPTY {
+            hash ^= pos.hash_piece[pos.color(i) as usize][pos.piece(i) as usize][i];
         }
     }
-    if d.side == DARK {
-        d.hash ^= d.hash_side;
+    if pos.side == DARK {
+        hash ^= pos.hash_side;
     }
-    if d.ep != -1 {
-        d.hash ^= d.hash_ep[d.ep as usize];
+    if pos.ep != -1 {
+        hash ^= pos.hash_ep[pos.ep as usize];
     }
+    hash
 }
 
-/// in_check() returns TRUE if side s is in check and FALSE otherwise. It just
-/// scans the board to find side s's king and calls attack() to see if it's
-/// being attacked.
+/// validate() checks that the board representation makes sense: the color and
+/// piece arrays agree, each side has one king (and king_sq knows where it is),
+/// there are no pawns on the first or eighth rank, the en passant square is
+/// behind a pawn that could have just moved two squares, the castle
+/// permissions match the king and rook placement, and the hash is right. It
+/// returns everything that's wrong, not just the first problem it finds.
+///
+/// #rust This is meant for debugging, e.g., after setting up a position or
+/// after a sequence of makemove() and takeback() calls.
 
-pub fn in_check(d: &Data, s: Int) -> bool {
+pub fn validate(d: &Data) -> Result<(), Vec<Inconsistency>> {
+    validate_position(&d.pos)
+}
+
+/// validate_position() is validate() for a Position on its own
+
+pub fn validate_position(pos: &Position) -> Result<(), Vec<Inconsistency>> {
+    let mut errors = Vec::new();
+
+    let mut squares_ok = true;
+    let mut kings = [0; 2];
+    let mut king_sq = [0; 2];
+    let mut pieces = [0u64; 2];
     for i in 0..64 {
-        if d.piece[i] == KING && d.color[i] == s {
-            return attack(d, i, s ^ 1);
+        let c = pos.color(i);
+        let p = pos.piece(i);
+        if c == LIGHT || c == DARK {
+            pieces[c as usize] |= 1 << i;
+        }
+        let ok = match c {
+            EMPTY => p == EMPTY,
+            LIGHT | DARK => (PAWN..=KING).contains(&p),
+            _ => false,
+        };
+        if !ok {
+            errors.push(Inconsistency::BadSquare(Square::from_index(i).unwrap()));
+            squares_ok = false;
+            continue;
+        }
+        if p == KING {
+            kings[c as usize] += 1;
+            king_sq[c as usize] = i;
+        }
+        if p == PAWN && (i <= H8 || i >= A1) {
+            errors.push(Inconsistency::PawnOnBackRank(
+                Square::from_index(i).unwrap(),
+            ));
+        }
+    }
+
+    for s in [Color::Light, Color::Dark] {
+        if pos.pieces[s.index()] != pieces[s.index()] {
+            errors.push(Inconsistency::PieceList(s));
+        }
+    }
+
+    let sides_ok = (pos.side == LIGHT || pos.side == DARK) && pos.xside == pos.side ^ 1;
+    if !sides_ok {
+        errors.push(Inconsistency::BadSide);
+    }
+
+    let mut kings_ok = squares_ok;
+    for s in [Color::Light, Color::Dark].iter() {
+        let s = *s;
+        let n = kings[s.index()];
+        if n != 1 {
+            errors.push(Inconsistency::KingCount(s, n));
+            kings_ok = false;
+        } else if pos.king_sq[s.index()] != king_sq[s.index()] {
+            errors.push(Inconsistency::KingSquare(s));
+            kings_ok = false;
+        }
+    }
+    if kings_ok && sides_ok && side_in_check(pos, pos.xside) {
+        errors.push(Inconsistency::XsideInCheck);
+    }
+
+    if pos.ep != -1 {
+        // the pawn that just moved is in front of the en passant square, and
+        // the square it came from is behind it
+        let (row, pawn, origin, xside) = match pos.side {
+            DARK => (5, pos.ep - 8, pos.ep + 8, LIGHT),
+            _ => (2, pos.ep + 8, pos.ep - 8, DARK),
+        };
+        let ok = sides_ok
+            && (0..64).contains(&pos.ep)
+            && row!(pos.ep) == row
+            && pos.color(pos.ep as usize) == EMPTY
+            && pos.color(origin as usize) == EMPTY
+            && pos.color(pawn as usize) == xside
+            && pos.piece(pawn as usize) == PAWN;
+        if !ok {
+            errors.push(Inconsistency::BadEnPassant(pos.ep));
+        }
+    }
+
+    let castles = [
+        (1, E1, H1, LIGHT),
+        (2, E1, A1, LIGHT),
+        (4, E8, H8, DARK),
+        (8, E8, A8, DARK),
+    ];
+    for &(bit, king, rook, c) in castles.iter() {
+        if (pos.castle & bit) != 0
+            && (pos.color(king) != c
+                || pos.piece(king) != KING
+                || pos.color(rook) != c
+                || pos.piece(rook) != ROOK)
+        {
+            errors.push(Inconsistency::BadCastle(bit));
+        }
+    }
+
+    // compute_hash() can't be trusted with squares (or an en passant square)
+    // that are out of range
+    if squares_ok && (-1..64).contains(&pos.ep) && compute_hash(pos) != pos.hash {
+        errors.push(Inconsistency::BadHash);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// check_incremental() compares the values that makemove() and takeback()
+/// update incrementally (the hash, the material, the piece/square values, the
+/// pawn structure, the piece lists, and the king squares) with the ones
+/// set_incremental() computes from scratch. If any are different, it returns
+/// the position and the differences, one per line.
+
+pub fn check_incremental(pos: &Position) -> Result<(), String> {
+    let mut fresh = pos.clone();
+    set_incremental(&mut fresh);
+
+    let mut diff = String::new();
+    let mut compare = |name: &str, incremental: String, computed: String| {
+        if incremental != computed {
+            diff.push_str(&format!(
+                "\n{}: incremental {}, computed {}",
+                name, incremental, computed
+            ));
+        }
+    };
+    compare("hash", format!("{}", pos.hash), format!("{}", fresh.hash));
+    compare(
+        "piece_mat",
+        format!("{:?}", pos.piece_mat),
+        format!("{:?}", fresh.piece_mat),
+    );
+    compare(
+        "pawn_mat",
+        format!("{:?}", pos.pawn_mat),
+        format!("{:?}", fresh.pawn_mat),
+    );
+    compare(
+        "pcsq",
+        format!("{:?}", pos.pcsq),
+        format!("{:?}", fresh.pcsq),
+    );
+    compare(
+        "pawns",
+        format!("{:x?}", pos.pawns),
+        format!("{:x?}", fresh.pawns),
+    );
+    compare(
+        "pawn_rank",
+        format!("{:?}", pos.pawn_rank),
+        format!("{:?}", fresh.pawn_rank),
+    );
+    compare(
+        "pieces",
+        format!("{:x?}", pos.pieces),
+        format!("{:x?}", fresh.pieces),
+    );
+    compare(
+        "king_sq",
+        format!("{:?}", pos.king_sq),
+        format!("{:?}", fresh.king_sq),
+    );
+    if diff.is_empty() {
+        return Ok(());
+    }
+    let moves: Vec<String> = pos.hist_dat[..pos.hply]
+        .iter()
+        .map(|h| h.m.bytes().to_string())
+        .collect();
+    Err(format!("{} (moves: {}){}", fen(pos), moves.join(" "), diff))
+}
+
+/// assert_incremental() panics with the differences if check_incremental()
+/// finds any, saying which function left them.
+///
+/// #rust With the "integrity" feature, makemove() and takeback() call this
+/// after every move, so a bug in the incremental updates panics on the move
+/// that exposes it, rather than showing up later as a bad evaluation or a
+/// missed transposition. It makes searches several times slower, which is why
+/// it isn't on in every debug build.
+
+#[cfg(feature = "integrity")]
+fn assert_incremental(pos: &Position, function: &str) {
+    if let Err(diff) = check_incremental(pos) {
+        panic!("{}() broke the position: {}", function, diff);
+    }
+}
+
+/// set_fen() sets up the position described by the FEN (Forsyth-Edwards
+/// Notation) string fen, as the start of a new game. The position is checked
+/// with validate() before it's accepted; if there's anything wrong with it,
+/// the board doesn't change.
+
+pub fn set_fen(d: &mut Data, fen: &str) -> Result<(), FenError> {
+    let mut fields = fen.split_whitespace();
+    let mut field = |name| fields.next().ok_or(FenError::Syntax(name));
+    let board = field("missing piece placement")?;
+    let side = field("missing side to move")?;
+    let castle = field("missing castling availability")?;
+    let ep = field("missing en passant square")?;
+    // the move counters are often left off
+    let fifty = field("").unwrap_or("0");
+    let fullmove = field("").unwrap_or("1");
+
+    let mut color = [EMPTY as i8; 64];
+    let mut piece = [EMPTY as i8; 64];
+    let mut sq = 0;
+    for (i, rank) in board.split('/').enumerate() {
+        if i >= 8 {
+            return Err(FenError::Syntax("too many ranks"));
+        }
+        let end = (i + 1) * 8;
+        for c in rank.chars() {
+            if let Some(n) = c.to_digit(10) {
+                if n == 0 {
+                    return Err(FenError::Syntax("bad empty square count"));
+                }
+                sq += n as usize;
+            } else {
+                let p = PIECE_CHAR
+                    .iter()
+                    .position(|&p| p == c.to_ascii_uppercase())
+                    .ok_or(FenError::Syntax("bad piece"))?;
+                if sq >= end {
+                    return Err(FenError::Syntax("too many squares in a rank"));
+                }
+                color[sq] = (if c.is_ascii_uppercase() { LIGHT } else { DARK }) as i8;
+                piece[sq] = p as i8;
+                sq += 1;
+            }
+            if sq > end {
+                return Err(FenError::Syntax("too many squares in a rank"));
+            }
+        }
+        if sq != end {
+            return Err(FenError::Syntax("too few squares in a rank"));
+        }
+    }
+    if sq != 64 {
+        return Err(FenError::Syntax("too few ranks"));
+    }
+
+    let side = match side {
+        "w" => LIGHT,
+        "b" => DARK,
+        _ => return Err(FenError::Syntax("bad side to move")),
+    };
+
+    let mut castle_bits = 0;
+    if castle != "-" {
+        for c in castle.chars() {
+            castle_bits |= match c {
+                'K' => 1,
+                'Q' => 2,
+                'k' => 4,
+                'q' => 8,
+                _ => return Err(FenError::Syntax("bad castling availability")),
+            };
+        }
+    }
+
+    let ep = match ep {
+        "-" => -1,
+        _ => parse_square(ep).ok_or(FenError::Syntax("bad en passant square"))? as Int,
+    };
+
+    let fifty = match fifty.parse::<Int>() {
+        Ok(n) if n >= 0 => n,
+        _ => return Err(FenError::Syntax("bad halfmove clock")),
+    };
+    let fullmove = match fullmove.parse::<usize>() {
+        Ok(n) if (1..1_000_000).contains(&n) => n,
+        _ => return Err(FenError::Syntax("bad fullmove number")),
+    };
+
+    let saved = d.pos.clone();
+    let pos = &mut d.pos;
+    pos.color = color;
+    pos.piece = piece;
+    pos.side = side;
+    pos.xside = side ^ 1;
+    pos.castle = castle_bits;
+    pos.ep = ep;
+    pos.fifty = fifty;
+    pos.hply = 0;
+    pos.start_ply = (fullmove - 1) * 2 + side as usize;
+    pos.hist_dat.clear();
+    set_incremental(pos);
+    if let Err(errors) = validate(d) {
+        d.pos = saved;
+        return Err(FenError::Invalid(errors));
+    }
+    d.search.ply = 0;
+    gen_legal(d);
+    Ok(())
+}
+
+/// fen() returns the FEN string for the current position
+
+pub fn fen(pos: &Position) -> String {
+    let mut s = String::new();
+    for row in 0..8 {
+        if row != 0 {
+            s.push('/');
+        }
+        let mut empty = 0;
+        for sq in (row * 8)..(row * 8 + 8) {
+            if pos.color(sq) == EMPTY {
+                empty += 1;
+                continue;
+            }
+            if empty != 0 {
+                s += &empty.to_string();
+                empty = 0;
+            }
+            let c = PIECE_CHAR[pos.piece(sq) as usize];
+            s.push(if pos.color(sq) == LIGHT {
+                c
+            } else {
+                c.to_ascii_lowercase()
+            });
+        }
+        if empty != 0 {
+            s += &empty.to_string();
+        }
+    }
+
+    s += if pos.side == LIGHT { " w " } else { " b " };
+    if pos.castle == 0 {
+        s.push('-');
+    }
+    for &(bit, c) in [(1, 'K'), (2, 'Q'), (4, 'k'), (8, 'q')].iter() {
+        if (pos.castle & bit) != 0 {
+            s.push(c);
+        }
+    }
+    s.push(' ');
+    if pos.ep == -1 {
+        s.push('-');
+    } else {
+        s.push((b'a' + col!(pos.ep) as u8) as char);
+        s += &(8 - row!(pos.ep)).to_string();
+    }
+    s + &format!(" {} {}", pos.fifty, (pos.start_ply + pos.hply) / 2 + 1)
+}
+
+/// the size of a square in the images to_svg() draws, and of the margin
+/// around the board, where the coordinates go, in pixels
+const SVG_SQUARE: usize = 45;
+const SVG_MARGIN: usize = 20;
+
+/// the options for to_svg_with()
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SvgOptions {
+    /// draw the board from Black's side
+    pub flipped: bool,
+    /// highlight the last move's squares, and draw an arrow from one to the
+    /// other
+    pub last_move: bool,
+}
+
+/// to_svg() returns an SVG (Scalable Vector Graphics) image of the board,
+/// from White's side, with the coordinates around it
+
+pub fn to_svg(d: &Data) -> String {
+    to_svg_with(d, &SvgOptions::default())
+}
+
+/// to_svg_with() is to_svg() with options. The pieces are drawn with the
+/// Unicode chess symbols (the solid ones for both sides, filled in white or
+/// black), so they look like the font the image is viewed with has them.
+
+pub fn to_svg_with(d: &Data, options: &SvgOptions) -> String {
+    let size = SVG_SQUARE * 8 + SVG_MARGIN * 2;
+    // the top left corner and the center of a square in the image
+    let corner = |sq: usize| {
+        let (mut x, mut y) = (col!(sq), row!(sq));
+        if options.flipped {
+            x = 7 - x;
+            y = 7 - y;
         }
+        (SVG_MARGIN + x * SVG_SQUARE, SVG_MARGIN + y * SVG_SQUARE)
+    };
+    let center = |sq: usize| {
+        let (x, y) = corner(sq);
+        (x + SVG_SQUARE / 2, y + SVG_SQUARE / 2)
+    };
+    let last_move = match d.pos.history().last() {
+        Some(h) if options.last_move => Some(h.m.bytes()),
+        _ => None,
+    };
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">\n",
+        size
+    );
+    svg += "<defs><marker id=\"arrowhead\" markerWidth=\"4\" markerHeight=\"4\" refX=\"2\" refY=\"2\" orient=\"auto\"><path d=\"M0,0 L4,2 L0,4 Z\" fill=\"#15781b\"/></marker></defs>\n";
+    svg += &format!(
+        "<rect width=\"{0}\" height=\"{0}\" fill=\"#ffffff\"/>\n",
+        size
+    );
+    for sq in 0..64 {
+        let (x, y) = corner(sq);
+        let highlighted = last_move.is_some_and(|m| m.from as usize == sq || m.to as usize == sq);
+        let fill = match ((row!(sq) + col!(sq)) % 2 == 1, highlighted) {
+            (false, false) => "#f0d9b5",
+            (true, false) => "#b58863",
+            (false, true) => "#cdd26a",
+            (true, true) => "#aaa23a",
+        };
+        svg += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{2}\" height=\"{2}\" fill=\"{3}\"/>\n",
+            x, y, SVG_SQUARE, fill
+        );
+    }
+
+    // the coordinates: the files along the bottom, and the ranks down the left
+    for i in 0..8 {
+        let (x, _) = center(56 + i);
+        svg += &format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"12\" text-anchor=\"middle\">{}</text>\n",
+            x,
+            size - SVG_MARGIN / 3,
+            (b'a' + i as u8) as char
+        );
+        let (_, y) = center(i * 8);
+        svg += &format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"12\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>\n",
+            SVG_MARGIN / 2,
+            y,
+            8 - i
+        );
     }
-    panic!("in_check: shouldn't get here");
+
+    for sq in 0..64 {
+        if d.pos.color(sq) == EMPTY {
+            continue;
+        }
+        let (x, y) = center(sq);
+        let (fill, stroke) = if d.pos.color(sq) == LIGHT {
+            ("#ffffff", "#000000")
+        } else {
+            ("#000000", "#ffffff")
+        };
+        svg += &format!(
+            "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"{}\" stroke=\"{}\" stroke-width=\"1\">{}</text>\n",
+            x,
+            y,
+            SVG_SQUARE * 4 / 5,
+            fill,
+            stroke,
+            PIECE_GLYPH[DARK as usize][d.pos.piece(sq) as usize]
+        );
+    }
+
+    if let Some(m) = last_move {
+        let (x1, y1) = center(m.from as usize);
+        let (x2, y2) = center(m.to as usize);
+        svg += &format!(
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#15781b\" stroke-width=\"8\" stroke-opacity=\"0.7\" marker-end=\"url(#arrowhead)\"/>\n",
+            x1, y1, x2, y2
+        );
+    }
+    svg += "</svg>\n";
+    svg
+}
+
+/// parse_square() converts a square's name, e.g., "e3", to its index
+
+fn parse_square(s: &str) -> Option<usize> {
+    match s.as_bytes() {
+        &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+            Some((7 - (rank - b'1') as usize) * 8 + (file - b'a') as usize)
+        }
+        _ => None,
+    }
+}
+
+/// in_check() returns TRUE if side s is in check and FALSE otherwise.
+
+pub fn in_check(pos: &Position, s: Color) -> bool {
+    side_in_check(pos, s as Int)
+}
+
+/// side_in_check() is in_check() for a LIGHT or DARK value. It just calls
+/// attack() to see if side s's king is being attacked.
+
+pub(crate) fn side_in_check(pos: &Position, s: Int) -> bool {
+    attack(pos, pos.king_sq[s as usize], s ^ 1)
 }
 
 /// attack() returns true if square sq is being attacked by side s and false
 /// otherwise.
 
-fn attack(d: &Data, sq: usize, s: Int) -> bool {
-    for i in 0..64 {
-        if d.color[i] == s {
-            match d.piece[i] {
-                PAWN => match s {
-                    LIGHT => {
-                        let col = col!(i);
-                        if col != 0 && i - 9 == sq {
-                            return true;
-                        }
-                        if col != 7 && i - 7 == sq {
-                            return true;
-                        }
-                    }
-                    _ => {
-                        let col = col!(i);
-                        if col != 0 && i + 7 == sq {
-                            return true;
-                        }
-                        if col != 7 && i + 9 == sq {
-                            return true;
-                        }
-                    }
-                },
-                _ => {
-                    let piece = d.piece[i] as usize;
-                    for j in 0..(OFFSETS[piece] as usize) {
-                        let mut n = i as Int;
-                        loop {
-                            let m64 = MAILBOX64[n as usize];
-                            let offset = OFFSET[piece][j];
-                            n = MAILBOX[(m64 + offset) as usize];
-                            if n == -1 {
-                                break;
-                            }
-                            let n = n as usize;
-                            if n == sq {
-                                return true;
-                            }
-                            if d.color[n] != EMPTY {
-                                break;
-                            }
-                            if !SLIDE[piece] {
-                                break;
-                            }
-                        }
-                    }
+pub fn attack(pos: &Position, sq: usize, s: Int) -> bool {
+    let attacker = |n: usize, piece: Int| pos.color(n) == s && pos.piece(n) == piece;
+
+    // a pawn attacks diagonally forward, so look diagonally backward from sq
+    // (from s's point of view)
+    let col = col!(sq);
+    if s == LIGHT {
+        if col != 0 && sq + 7 < 64 && attacker(sq + 7, PAWN) {
+            return true;
+        }
+        if col != 7 && sq + 9 < 64 && attacker(sq + 9, PAWN) {
+            return true;
+        }
+    } else {
+        if col != 0 && sq >= 9 && attacker(sq - 9, PAWN) {
+            return true;
+        }
+        if col != 7 && sq >= 7 && attacker(sq - 7, PAWN) {
+            return true;
+        }
+    }
+
+    let knight = &ATTACKS.knight[sq][..ATTACKS.knight_len[sq] as usize];
+    if knight.iter().any(|&n| attacker(n as usize, KNIGHT)) {
+        return true;
+    }
+
+    // the first piece in each direction attacks sq if it's one of s's pieces
+    // that moves that way (and as far)
+    for dir in 0..8 {
+        let ray = &ATTACKS.rays[sq][dir][..ATTACKS.ray_len[sq][dir] as usize];
+        if let Some(i) = ray.iter().position(|&n| pos.color(n as usize) != EMPTY) {
+            let n = ray[i] as usize;
+            if pos.color(n) == s {
+                let slider = if dir < DIAGONALS { BISHOP } else { ROOK };
+                match pos.piece(n) {
+                    QUEEN => return true,
+                    KING if i == 0 => return true,
+                    piece if piece == slider => return true,
+                    _ => {}
                 }
             }
         }
@@ -172,69 +722,73 @@ fn attack(d: &Data, sq: usize, s: Int) -> bool {
 /// When it finds a piece/square combination, it calls gen_push to put the move
 /// on the "move stack."
 
-#[allow(clippy::cognitive_complexity)]
+// #rust Indexing OFFSET in the loops below is measurably faster than iterating
+// over it, so we ask clippy not to complain about that.
+#[allow(clippy::cognitive_complexity, clippy::needless_range_loop)]
 pub fn gen(d: &mut Data) {
+    let pos = &d.pos;
+    let search = &mut d.search;
+
     // so far, we have no moves for the current ply
-    d.first_move[d.ply + 1] = d.first_move[d.ply];
+    search.gen_dat[search.ply].clear();
+    search.cont = cont_base(pos);
 
-    for i in 0..64 {
-        if d.color[i] == d.side {
-            match d.piece[i] {
-                PAWN => match d.side {
-                    LIGHT => {
-                        let col = col!(i);
-                        if col != 0 && d.color[i - 9] == DARK {
-                            gen_push!(d, i, i - 9, 17);
-                        }
-                        if col != 7 && d.color[i - 7] == DARK {
-                            gen_push!(d, i, i - 7, 17);
-                        }
-                        if d.color[i - 8] == EMPTY {
-                            gen_push!(d, i, i - 8, 16);
-                            if i >= 48 && d.color[i - 16] == EMPTY {
-                                gen_push!(d, i, i - 16, 24);
-                            }
+    for i in Squares(pos.pieces[pos.side as usize]) {
+        match pos.piece(i) {
+            PAWN => match pos.side {
+                LIGHT => {
+                    let col = col!(i);
+                    if col != 0 && pos.color(i - 9) == DARK {
+                        gen_push!(pos, search, i, i - 9, 17);
+                    }
+                    if col != 7 && pos.color(i - 7) == DARK {
+                        gen_push!(pos, search, i, i - 7, 17);
+                    }
+                    if pos.color(i - 8) == EMPTY {
+                        gen_push!(pos, search, i, i - 8, 16);
+                        if i >= 48 && pos.color(i - 16) == EMPTY {
+                            gen_push!(pos, search, i, i - 16, 24);
                         }
                     }
-                    _ => {
-                        let col = col!(i);
-                        if col != 0 && d.color[i + 7] == LIGHT {
-                            gen_push!(d, i, i + 7, 17);
+                }
+                _ => {
+                    let col = col!(i);
+                    if col != 0 && pos.color(i + 7) == LIGHT {
+                        gen_push!(pos, search, i, i + 7, 17);
+                    }
+                    if col != 7 && pos.color(i + 9) == LIGHT {
+                        gen_push!(pos, search, i, i + 9, 17);
+                    }
+                    if pos.color(i + 8) == EMPTY {
+                        gen_push!(pos, search, i, i + 8, 16);
+                        if i <= 15 && pos.color(i + 16) == EMPTY {
+                            gen_push!(pos, search, i, i + 16, 24);
                         }
-                        if col != 7 && d.color[i + 9] == LIGHT {
-                            gen_push!(d, i, i + 9, 17);
+                    }
+                }
+            },
+            _ => {
+                let piece = pos.piece(i) as usize;
+                for j in 0..(OFFSETS[piece] as usize) {
+                    let mut n = i as Int;
+                    loop {
+                        let m64 = MAILBOX64[n as usize];
+                        let offset = OFFSET[piece][j];
+                        n = MAILBOX[(m64 + offset) as usize];
+                        if n == -1 {
+                            break;
                         }
-                        if d.color[i + 8] == EMPTY {
-                            gen_push!(d, i, i + 8, 16);
-                            if i <= 15 && d.color[i + 16] == EMPTY {
-                                gen_push!(d, i, i + 16, 24);
+                        let n = n as usize;
+                        let color = pos.color(n);
+                        if color != EMPTY {
+                            if color == pos.xside {
+                                gen_push!(pos, search, i, n, 1);
                             }
+                            break;
                         }
-                    }
-                },
-                _ => {
-                    let piece = d.piece[i] as usize;
-                    for j in 0..(OFFSETS[piece] as usize) {
-                        let mut n = i as Int;
-                        loop {
-                            let m64 = MAILBOX64[n as usize];
-                            let offset = OFFSET[piece][j];
-                            n = MAILBOX[(m64 + offset) as usize];
-                            if n == -1 {
-                                break;
-                            }
-                            let n = n as usize;
-                            let color = d.color[n];
-                            if color != EMPTY {
-                                if color == d.xside {
-                                    gen_push!(d, i, n, 1);
-                                }
-                                break;
-                            }
-                            gen_push!(d, i, n, 0);
-                            if !SLIDE[piece] {
-                                break;
-                            }
+                        gen_push!(pos, search, i, n, 0);
+                        if !SLIDE[piece] {
+                            break;
                         }
                     }
                 }
@@ -243,106 +797,379 @@ pub fn gen(d: &mut Data) {
     }
 
     // generate castle moves
-    match d.side {
+    match pos.side {
         LIGHT => {
-            if (d.castle & 1) != 0 {
-                gen_push!(d, E1, G1, 2);
+            if (pos.castle & 1) != 0 {
+                gen_push!(pos, search, E1, G1, 2);
             }
-            if (d.castle & 2) != 0 {
-                gen_push!(d, E1, C1, 2);
+            if (pos.castle & 2) != 0 {
+                gen_push!(pos, search, E1, C1, 2);
             }
         }
         _ => {
-            if (d.castle & 4) != 0 {
-                gen_push!(d, E8, G8, 2);
+            if (pos.castle & 4) != 0 {
+                gen_push!(pos, search, E8, G8, 2);
             }
-            if (d.castle & 8) != 0 {
-                gen_push!(d, E8, C8, 2);
+            if (pos.castle & 8) != 0 {
+                gen_push!(pos, search, E8, C8, 2);
             }
         }
     }
 
     // generate en passant moves
-    if d.ep != -1 {
-        let i_ep = d.ep as usize;
-        match d.side {
+    if pos.ep != -1 {
+        let i_ep = pos.ep as usize;
+        match pos.side {
             LIGHT => {
-                let col = col!(d.ep);
-                if col != 0 && d.color[i_ep + 7] == LIGHT && d.piece[i_ep + 7] == PAWN {
-                    gen_push!(d, d.ep + 7, d.ep, 21);
+                let col = col!(pos.ep);
+                if col != 0 && pos.color(i_ep + 7) == LIGHT && pos.piece(i_ep + 7) == PAWN {
+                    gen_push!(pos, search, pos.ep + 7, pos.ep, 21);
                 }
-                if col != 7 && d.color[i_ep + 9] == LIGHT && d.piece[i_ep + 9] == PAWN {
-                    gen_push!(d, d.ep + 9, d.ep, 21);
+                if col != 7 && pos.color(i_ep + 9) == LIGHT && pos.piece(i_ep + 9) == PAWN {
+                    gen_push!(pos, search, pos.ep + 9, pos.ep, 21);
                 }
             }
             _ => {
-                let col = col!(d.ep);
-                if col != 0 && d.color[i_ep - 9] == DARK && d.piece[i_ep - 9] == PAWN {
-                    gen_push!(d, d.ep - 9, d.ep, 21);
+                let col = col!(pos.ep);
+                if col != 0 && pos.color(i_ep - 9) == DARK && pos.piece(i_ep - 9) == PAWN {
+                    gen_push!(pos, search, pos.ep - 9, pos.ep, 21);
                 }
-                if col != 7 && d.color[i_ep - 7] == DARK && d.piece[i_ep - 7] == PAWN {
-                    gen_push!(d, d.ep - 7, d.ep, 21);
+                if col != 7 && pos.color(i_ep - 7) == DARK && pos.piece(i_ep - 7) == PAWN {
+                    gen_push!(pos, search, pos.ep - 7, pos.ep, 21);
                 }
             }
         }
     }
 }
 
+/// gen_legal() is like gen(), but it only generates legal moves. It calls gen()
+/// and then removes the moves that would leave the king in check. If the side
+/// to move isn't in check, a move by a piece that isn't pinned to its king is
+/// always legal, unless it's a king move, a castle, or an en passant capture
+/// (which can uncover a check along the rank). Only the moves that don't pass
+/// that test need to be checked with makemove() and takeback().
+
+pub fn gen_legal(d: &mut Data) {
+    gen(d);
+    let pos = &mut d.pos;
+    let search = &mut d.search;
+    let ks = pos.king_sq[pos.side as usize];
+    let check = attack(pos, ks, pos.xside);
+    let pinned = pinned_pieces(pos, ks, pos.side);
+    search.gen_dat[search.ply].retain(|g| {
+        let m = g.m.bytes();
+        let from = m.from as usize;
+        if !check
+            && from != ks
+            && !m.is_castle()
+            && !m.is_en_passant()
+            && (pinned & (1 << from)) == 0
+        {
+            true
+        } else if makemove(pos, m) {
+            takeback(pos);
+            true
+        } else {
+            false
+        }
+    });
+}
+
+/// is_legal() returns true if m is a legal move in the current position. Only
+/// m's from and to squares (and the promotion piece, if it's a pawn promotion)
+/// are checked; the bits are filled in by the move generator.
+
+pub fn is_legal(d: &mut Data, m: MoveBytes) -> bool {
+    find_legal_move(d, m).is_some()
+}
+
+/// try_move() makes the move m if it's legal, and then generates the legal
+/// moves for the new position, so callers don't need to know about gen_legal(),
+/// makemove(), and ply. If m isn't legal, the position doesn't change.
+
+pub fn try_move(d: &mut Data, m: MoveBytes) -> Result<(), IllegalMove> {
+    let m = find_legal_move(d, m).ok_or(IllegalMove)?;
+    makemove(&mut d.pos, m);
+    gen_legal(d);
+    Ok(())
+}
+
+/// find_legal_move() looks for m in the list of legal moves for the current
+/// position, and returns the matching move from the list.
+
+fn find_legal_move(d: &mut Data, m: MoveBytes) -> Option<MoveBytes> {
+    gen_legal(d);
+    for g in d.search.gen_dat[d.search.ply].iter() {
+        let g = g.m.bytes();
+        if g.from == m.from && g.to == m.to && (!g.is_promotion() || g.promote == m.promote) {
+            return Some(g);
+        }
+    }
+    None
+}
+
+/// undo_move() takes back the last move of the game, and generates the legal
+/// moves for the position before it. It returns the move, or None if there
+/// are no moves to take back.
+
+pub fn undo_move(d: &mut Data) -> Option<MoveBytes> {
+    if d.pos.hply == 0 {
+        return None;
+    }
+    let m = d.pos.hist_dat[d.pos.hply - 1].m.bytes();
+    takeback(&mut d.pos);
+    gen_legal(d);
+    Some(m)
+}
+
+/// undo_moves() takes back the last n moves of the game (or all of them, if
+/// there are fewer than n), and generates the legal moves for the position
+/// before them. It returns the moves that were taken back, the last one first.
+
+pub fn undo_moves(d: &mut Data, n: usize) -> Vec<MoveBytes> {
+    let mut moves = Vec::new();
+    while moves.len() < n && d.pos.hply > 0 {
+        moves.push(d.pos.hist_dat[d.pos.hply - 1].m.bytes());
+        takeback(&mut d.pos);
+    }
+    gen_legal(d);
+    moves
+}
+
+/// san() returns the move m in Standard Algebraic Notation (e.g., "Nbd7",
+/// "exd5", "e8=Q+", or "O-O"), or None if m isn't legal in the current
+/// position. As with is_legal(), only m's squares and promotion piece matter.
+
+pub fn san(d: &mut Data, m: MoveBytes) -> Option<String> {
+    let m = find_legal_move(d, m)?;
+    let from = m.from as usize;
+    let to = m.to as usize;
+    let p = d.pos.piece(from);
+
+    let mut s = if m.is_castle() {
+        String::from(if col!(to) == 6 { "O-O" } else { "O-O-O" })
+    } else if p == PAWN {
+        let mut s = String::new();
+        if m.is_capture() {
+            s.push(m.from_sq().to_string().remove(0));
+            s.push('x');
+        }
+        s += &m.to_sq().to_string();
+        if let Some(promote) = m.promotion() {
+            s.push('=');
+            s.push(PIECE_CHAR[promote.index()]);
+        }
+        s
+    } else {
+        // name the from square's file, rank, or both, if another piece of
+        // the same kind can move to the same square
+        let (mut ambiguous, mut same_file, mut same_rank) = (false, false, false);
+        for g in d.search.gen_dat[d.search.ply].iter() {
+            let g = g.m.bytes().from as usize;
+            if g != from && g != to && d.pos.piece(g) == p && legal_to(d, g, to) {
+                ambiguous = true;
+                same_file |= col!(g) == col!(from);
+                same_rank |= row!(g) == row!(from);
+            }
+        }
+        let from_name = m.from_sq().to_string();
+        let mut s = PIECE_CHAR[p as usize].to_string();
+        if ambiguous && (!same_file || same_rank) {
+            s += &from_name[..1];
+        }
+        if same_file {
+            s += &from_name[1..];
+        }
+        if m.is_capture() {
+            s.push('x');
+        }
+        s + &m.to_sq().to_string()
+    };
+
+    // check or checkmate?
+    makemove(&mut d.pos, m);
+    if side_in_check(&d.pos, d.pos.side) {
+        d.search.ply += 1;
+        gen_legal(d);
+        s.push(if d.search.gen_dat[d.search.ply].is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+        d.search.ply -= 1;
+    }
+    takeback(&mut d.pos);
+    Some(s)
+}
+
+/// parse_san() returns the legal move that s names in Standard Algebraic
+/// Notation, or None if there isn't one. The check and annotation marks
+/// ("+", "#", "!", and "?") are optional, as is the "=" in a promotion, and
+/// castling can be written with zeros ("0-0").
+
+pub fn parse_san(d: &mut Data, s: &str) -> Option<MoveBytes> {
+    fn key(s: &str) -> String {
+        s.trim_end_matches(|c| "+#!?".contains(c))
+            .replace('=', "")
+            .replace('0', "O")
+    }
+    let s = key(s);
+    gen_legal(d);
+    let moves: Vec<MoveBytes> = d.search.gen_dat[d.search.ply]
+        .iter()
+        .map(|g| g.m.bytes())
+        .collect();
+    moves
+        .into_iter()
+        .find(|&m| san(d, m).is_some_and(|san| key(&san) == s))
+}
+
+/// legal_to() returns true if the legal moves for the current ply include a
+/// move from square from to square to
+
+fn legal_to(d: &Data, from: usize, to: usize) -> bool {
+    d.search.gen_dat[d.search.ply].iter().any(|g| {
+        let g = g.m.bytes();
+        g.from as usize == from && g.to as usize == to
+    })
+}
+
+/// history_san() returns the moves in pos's history in Standard Algebraic
+/// Notation
+
+pub fn history_san(pos: &Position) -> Vec<String> {
+    // replay the game from the beginning, on a copy of the position
+    let mut d = Data::new();
+    d.pos = pos.clone();
+    while d.pos.hply > 0 {
+        takeback(&mut d.pos);
+    }
+    let mut moves = Vec::new();
+    for h in pos.history() {
+        let m = h.m.bytes();
+        moves.push(san(&mut d, m).expect("illegal move in the history"));
+        makemove(&mut d.pos, m);
+    }
+    moves
+}
+
+/// line_san() returns a line of moves from the current position (e.g., a
+/// PV) in Standard Algebraic Notation. It stops at the first move that isn't
+/// legal. The position is left as it was.
+
+pub fn line_san(d: &mut Data, line: &[MoveBytes]) -> Vec<String> {
+    let mut moves = Vec::new();
+    for &m in line {
+        match san(d, m) {
+            Some(s) => moves.push(s),
+            None => break,
+        }
+        try_move(d, m).expect("san() found the move legal");
+    }
+    for _ in 0..moves.len() {
+        undo_move(d);
+    }
+    moves
+}
+
+/// pinned_pieces() returns a bitmask (bit n is square n) of side s's pieces
+/// that are pinned to its king on square ks, i.e., the pieces that stand
+/// between the king and an enemy bishop, rook, or queen.
+
+fn pinned_pieces(pos: &Position, ks: usize, s: Int) -> u64 {
+    let mut pinned = 0;
+    for &offset in OFFSET[QUEEN as usize].iter() {
+        let slider = match offset {
+            -10 | -1 | 1 | 10 => ROOK,
+            _ => BISHOP,
+        };
+        let mut friend = None;
+        let mut n = ks as Int;
+        loop {
+            n = MAILBOX[(MAILBOX64[n as usize] + offset) as usize];
+            if n == -1 {
+                break;
+            }
+            let n = n as usize;
+            if pos.color(n) == EMPTY {
+                continue;
+            }
+            if pos.color(n) == s {
+                if friend.is_some() {
+                    break;
+                }
+                friend = Some(n);
+                continue;
+            }
+            if let Some(f) = friend {
+                if pos.piece(n) == slider || pos.piece(n) == QUEEN {
+                    pinned |= 1 << f;
+                }
+            }
+            break;
+        }
+    }
+    pinned
+}
+
 /// gen_caps() is basically a copy of gen() that's modified to only generate
 /// capture and promote moves. It's used by the quiescence search.
 
+#[allow(clippy::needless_range_loop)]
 pub fn gen_caps(d: &mut Data) {
-    d.first_move[d.ply + 1] = d.first_move[d.ply];
-    for i in 0..64 {
-        if d.color[i] == d.side {
-            match d.piece[i] {
-                PAWN => match d.side {
-                    LIGHT => {
-                        let col = col!(i);
-                        if col != 0 && d.color[i - 9] == DARK {
-                            gen_push!(d, i, i - 9, 17);
-                        }
-                        if col != 7 && d.color[i - 7] == DARK {
-                            gen_push!(d, i, i - 7, 17);
-                        }
-                        if i <= 15 && d.color[i - 8] == EMPTY {
-                            gen_push!(d, i, i - 8, 16);
-                        }
+    let pos = &d.pos;
+    let search = &mut d.search;
+
+    search.gen_dat[search.ply].clear();
+    search.cont = cont_base(pos);
+    for i in Squares(pos.pieces[pos.side as usize]) {
+        match pos.piece(i) {
+            PAWN => match pos.side {
+                LIGHT => {
+                    let col = col!(i);
+                    if col != 0 && pos.color(i - 9) == DARK {
+                        gen_push!(pos, search, i, i - 9, 17);
                     }
-                    _ => {
-                        let col = col!(i);
-                        if col != 0 && d.color[i + 7] == LIGHT {
-                            gen_push!(d, i, i + 7, 17);
-                        }
-                        if col != 7 && d.color[i + 9] == LIGHT {
-                            gen_push!(d, i, i + 9, 17);
-                        }
-                        if i >= 48 && d.color[i + 8] == EMPTY {
-                            gen_push!(d, i, i + 8, 16);
-                        }
+                    if col != 7 && pos.color(i - 7) == DARK {
+                        gen_push!(pos, search, i, i - 7, 17);
+                    }
+                    if i <= 15 && pos.color(i - 8) == EMPTY {
+                        gen_push!(pos, search, i, i - 8, 16);
                     }
-                },
+                }
                 _ => {
-                    let piece = d.piece[i] as usize;
-                    for j in 0..(OFFSETS[piece] as usize) {
-                        let mut n = i as Int;
-                        loop {
-                            let m64 = MAILBOX64[n as usize];
-                            let offset = OFFSET[piece][j];
-                            n = MAILBOX[(m64 + offset) as usize];
-                            if n == -1 {
-                                break;
-                            }
-                            let color = d.color[n as usize];
-                            if color != EMPTY {
-                                if color == d.xside {
-                                    gen_push!(d, i, n, 1);
-                                }
-                                break;
-                            }
-                            if !SLIDE[piece] {
-                                break;
+                    let col = col!(i);
+                    if col != 0 && pos.color(i + 7) == LIGHT {
+                        gen_push!(pos, search, i, i + 7, 17);
+                    }
+                    if col != 7 && pos.color(i + 9) == LIGHT {
+                        gen_push!(pos, search, i, i + 9, 17);
+                    }
+                    if i >= 48 && pos.color(i + 8) == EMPTY {
+                        gen_push!(pos, search, i, i + 8, 16);
+                    }
+                }
+            },
+            _ => {
+                let piece = pos.piece(i) as usize;
+                for j in 0..(OFFSETS[piece] as usize) {
+                    let mut n = i as Int;
+                    loop {
+                        let m64 = MAILBOX64[n as usize];
+                        let offset = OFFSET[piece][j];
+                        n = MAILBOX[(m64 + offset) as usize];
+                        if n == -1 {
+                            break;
+                        }
+                        let color = pos.color(n as usize);
+                        if color != EMPTY {
+                            if color == pos.xside {
+                                gen_push!(pos, search, i, n, 1);
                             }
+                            break;
+                        }
+                        if !SLIDE[piece] {
+                            break;
                         }
                     }
                 }
@@ -350,25 +1177,25 @@ pub fn gen_caps(d: &mut Data) {
         }
     }
 
-    if d.ep != -1 {
-        let i_ep = d.ep as usize;
-        match d.side {
+    if pos.ep != -1 {
+        let i_ep = pos.ep as usize;
+        match pos.side {
             LIGHT => {
-                let col = col!(d.ep);
-                if col != 0 && d.color[i_ep + 7] == LIGHT && d.piece[i_ep + 7] == PAWN {
-                    gen_push!(d, d.ep + 7, d.ep, 21);
+                let col = col!(pos.ep);
+                if col != 0 && pos.color(i_ep + 7) == LIGHT && pos.piece(i_ep + 7) == PAWN {
+                    gen_push!(pos, search, pos.ep + 7, pos.ep, 21);
                 }
-                if col != 7 && d.color[i_ep + 9] == LIGHT && d.piece[i_ep + 9] == PAWN {
-                    gen_push!(d, d.ep + 9, d.ep, 21);
+                if col != 7 && pos.color(i_ep + 9) == LIGHT && pos.piece(i_ep + 9) == PAWN {
+                    gen_push!(pos, search, pos.ep + 9, pos.ep, 21);
                 }
             }
             _ => {
-                let col = col!(d.ep);
-                if col != 0 && d.color[i_ep - 9] == DARK && d.piece[i_ep - 9] == PAWN {
-                    gen_push!(d, d.ep - 9, d.ep, 21);
+                let col = col!(pos.ep);
+                if col != 0 && pos.color(i_ep - 9) == DARK && pos.piece(i_ep - 9) == PAWN {
+                    gen_push!(pos, search, pos.ep - 9, pos.ep, 21);
                 }
-                if col != 7 && d.color[i_ep - 7] == DARK && d.piece[i_ep - 7] == PAWN {
-                    gen_push!(d, d.ep - 7, d.ep, 21);
+                if col != 7 && pos.color(i_ep - 7) == DARK && pos.piece(i_ep - 7) == PAWN {
+                    gen_push!(pos, search, pos.ep - 7, pos.ep, 21);
                 }
             }
         }
@@ -382,47 +1209,76 @@ pub fn gen_caps(d: &mut Data) {
 /// move's history heuristic value. Note that 1,000,000 is added to a capture
 /// move's score, so it always gets ordered above a "normal" move. */
 
-fn gen_push(d: &mut Data, from: usize, to: usize, bits: u8) {
+fn gen_push(pos: &Position, search: &mut SearchState, from: usize, to: usize, bits: u8) {
     if (bits & 16) != 0 {
-        match d.side {
+        match pos.side {
             LIGHT => {
                 if to <= H8 {
-                    gen_promote(d, from, to, bits);
+                    gen_promote(search, from, to, bits);
                     return;
                 }
             }
             _ => {
                 if to >= A1 {
-                    gen_promote(d, from, to, bits);
+                    gen_promote(search, from, to, bits);
                     return;
                 }
             }
         }
     }
-    let g = &mut d.gen_dat[d.first_move[d.ply + 1] as usize];
-    d.first_move[d.ply + 1] += 1;
-    g.m.b.from = from as u8;
-    g.m.b.to = to as u8;
-    g.m.b.promote = 0;
-    g.m.b.bits = bits;
-    g.score = match d.color[to] {
-        EMPTY => d.history[from][to],
-        _ => 1_000_000 + d.piece[to] * 10 - d.piece[from],
+    let score = match pos.color(to) {
+        EMPTY => {
+            let score = search.history[pos.side as usize][from][to];
+            match search.cont {
+                Some(base) => {
+                    score + search.cont_history[base + pos.piece(from) as usize * 64 + to]
+                }
+                None => score,
+            }
+        }
+        _ => 1_000_000 + (pos.piece(to) * 10 - pos.piece(from)) as i32,
+    };
+    search.gen_dat[search.ply].push(Gen {
+        m: Move::from(MoveBytes {
+            from: from as u8,
+            to: to as u8,
+            promote: 0,
+            bits,
+        }),
+        score,
+    });
+}
+
+/// cont_base() returns where the continuation history of the moves that can
+/// be made in pos starts in SearchState.cont_history: the entries for the
+/// piece the last move moved, on the square it moved to. It returns None if
+/// there's no last move (or it was a null move). The entry for a move of
+/// piece p to square sq is at cont_base() + p * 64 + sq.
+
+#[inline(always)]
+pub fn cont_base(pos: &Position) -> Option<usize> {
+    let m = pos.hist_dat[..pos.hply].last()?.m.bytes();
+    if m.from == m.to {
+        return None;
     }
+    let to = m.to as usize;
+    Some((pos.piece(to) as usize * 64 + to) * 6 * 64)
 }
 
 /// gen_promote() is just like gen_push(), only it puts 4 moves on the move
 /// stack, one for each possible promotion piece
 
-fn gen_promote(d: &mut Data, from: usize, to: usize, bits: u8) {
+fn gen_promote(search: &mut SearchState, from: usize, to: usize, bits: u8) {
     for i in KNIGHT..=QUEEN {
-        let g = &mut d.gen_dat[d.first_move[d.ply + 1] as usize];
-        d.first_move[d.ply + 1] += 1;
-        g.m.b.from = from as u8;
-        g.m.b.to = to as u8;
-        g.m.b.promote = i as u8;
-        g.m.b.bits = bits | 32;
-        g.score = 1_000_000 + (i * 10);
+        search.gen_dat[search.ply].push(Gen {
+            m: Move::from(MoveBytes {
+                from: from as u8,
+                to: to as u8,
+                promote: i as u8,
+                bits: bits | 32,
+            }),
+            score: 1_000_000 + (i * 10) as i32,
+        });
     }
 }
 
@@ -430,22 +1286,41 @@ fn gen_promote(d: &mut Data, from: usize, to: usize, bits: u8) {
 /// undoes whatever it did and returns FALSE. Otherwise, it
 /// returns TRUE.
 
-pub fn makemove(d: &mut Data, m: MoveBytes) -> bool {
+pub fn makemove(pos: &mut Position, m: MoveBytes) -> bool {
     let from: usize;
     let to: usize;
+    let m_to = m.to as usize;
+    let m_from = m.from as usize;
+
+    // back up information so we can take the move back later.
+    //
+    // #rust This has to happen before the castling rook is moved below, because
+    // moving the rook updates the hash.
+    //
+    // #rust takeback() only decrements hply, so hist_dat may still hold the
+    // moves that were taken back; they're thrown away here.
+    pos.hist_dat.truncate(pos.hply);
+    pos.hist_dat.push(Hist {
+        m: Move::from(m),
+        capture: pos.piece[m_to],
+        castle: pos.castle as i8,
+        ep: pos.ep as i8,
+        fifty: pos.fifty,
+        hash: pos.hash,
+    });
 
     // test to see if a castle move is legal and move the rook (the king is
     // moved with the usual move code later)
-    if (m.bits & 2) != 0 {
-        if in_check(&d, d.side) {
+    if m.is_castle() {
+        if side_in_check(pos, pos.side) {
             return false;
         }
         match m.to {
             62 => {
-                if d.color[F1] != EMPTY
-                    || d.color[G1] != EMPTY
-                    || attack(&d, F1, d.xside)
-                    || attack(&d, G1, d.xside)
+                if pos.color(F1) != EMPTY
+                    || pos.color(G1) != EMPTY
+                    || attack(pos, F1, pos.xside)
+                    || attack(pos, G1, pos.xside)
                 {
                     return false;
                 }
@@ -453,11 +1328,11 @@ pub fn makemove(d: &mut Data, m: MoveBytes) -> bool {
                 to = F1;
             }
             58 => {
-                if d.color[B1] != EMPTY
-                    || d.color[C1] != EMPTY
-                    || d.color[D1] != EMPTY
-                    || attack(&d, C1, d.xside)
-                    || attack(&d, D1, d.xside)
+                if pos.color(B1) != EMPTY
+                    || pos.color(C1) != EMPTY
+                    || pos.color(D1) != EMPTY
+                    || attack(pos, C1, pos.xside)
+                    || attack(pos, D1, pos.xside)
                 {
                     return false;
                 }
@@ -465,10 +1340,10 @@ pub fn makemove(d: &mut Data, m: MoveBytes) -> bool {
                 to = D1;
             }
             6 => {
-                if d.color[F8] != EMPTY
-                    || d.color[G8] != EMPTY
-                    || attack(&d, F8, d.xside)
-                    || attack(&d, G8, d.xside)
+                if pos.color(F8) != EMPTY
+                    || pos.color(G8) != EMPTY
+                    || attack(pos, F8, pos.xside)
+                    || attack(pos, G8, pos.xside)
                 {
                     return false;
                 }
@@ -476,11 +1351,11 @@ pub fn makemove(d: &mut Data, m: MoveBytes) -> bool {
                 to = F8;
             }
             2 => {
-                if d.color[B8] != EMPTY
-                    || d.color[C8] != EMPTY
-                    || d.color[D8] != EMPTY
-                    || attack(&d, C8, d.xside)
-                    || attack(&d, D8, d.xside)
+                if pos.color(B8) != EMPTY
+                    || pos.color(C8) != EMPTY
+                    || pos.color(D8) != EMPTY
+                    || attack(pos, C8, pos.xside)
+                    || attack(pos, D8, pos.xside)
                 {
                     return false;
                 }
@@ -491,110 +1366,102 @@ pub fn makemove(d: &mut Data, m: MoveBytes) -> bool {
                 panic!("makemove: invalid castling move");
             }
         }
-        d.color[to] = d.color[from];
-        d.piece[to] = d.piece[from];
-        d.color[from] = EMPTY;
-        d.piece[from] = EMPTY;
+        remove_piece(pos, from);
+        add_piece(pos, to, pos.side, ROOK);
     }
 
-    let m_to = m.to as usize;
-    let m_from = m.from as usize;
-
-    // back up information so we can take the move back later.
-    d.hist_dat[d.hply].m.set_bytes(m);
-    d.hist_dat[d.hply].capture = d.piece[m_to];
-    d.hist_dat[d.hply].castle = d.castle;
-    d.hist_dat[d.hply].ep = d.ep;
-    d.hist_dat[d.hply].fifty = d.fifty;
-    d.hist_dat[d.hply].hash = d.hash;
-    d.ply += 1;
-    d.hply += 1;
+    pos.hply += 1;
 
     // update the castle, en passant, and fifty-move-draw variables
-    d.castle &= CASTLE_MASK[m_from] & CASTLE_MASK[m_to];
-    if (m.bits & 8) != 0 {
-        d.ep = match d.side {
+    pos.castle &= CASTLE_MASK[m_from] & CASTLE_MASK[m_to];
+    if pos.ep != -1 {
+        pos.hash ^= pos.hash_ep[pos.ep as usize];
+    }
+    if m.is_double_push() {
+        pos.ep = match pos.side {
             LIGHT => m.to as Int + 8,
             _ => m.to as Int - 8,
-        }
+        };
+        pos.hash ^= pos.hash_ep[pos.ep as usize];
     } else {
-        d.ep = -1;
+        pos.ep = -1;
     }
-    if (m.bits & 17) != 0 {
-        d.fifty = 0;
+    if m.is_capture() || m.is_pawn_move() {
+        pos.fifty = 0;
     } else {
-        d.fifty += 1;
+        pos.fifty += 1;
     }
 
     // move the piece
-    d.color[m_to] = d.side;
-    d.piece[m_to] = if (m.bits & 32) != 0 {
+    let p = if m.is_promotion() {
         m.promote as Int
     } else {
-        d.piece[m_from]
+        pos.piece(m_from)
     };
-    d.color[m_from] = EMPTY;
-    d.piece[m_from] = EMPTY;
+    if pos.color(m_to) != EMPTY {
+        remove_piece(pos, m_to);
+    }
+    remove_piece(pos, m_from);
+    add_piece(pos, m_to, pos.side, p);
 
     // erase the pawn if this is an en passant move
-    if (m.bits & 4) != 0 {
-        let pawn_sq = match d.side {
+    if m.is_en_passant() {
+        let pawn_sq = match pos.side {
             LIGHT => m_to + 8,
             _ => m_to - 8,
         };
-        d.color[pawn_sq] = EMPTY;
-        d.piece[pawn_sq] = EMPTY;
+        remove_piece(pos, pawn_sq);
     }
 
     // switch sides and test for legality (if we can capture the other guy's
     // king, it's an illegal position and we need to take the move back)
-    d.side ^= 1;
-    d.xside ^= 1;
-    if in_check(&d, d.xside) {
-        takeback(d);
+    pos.side ^= 1;
+    pos.xside ^= 1;
+    pos.hash ^= pos.hash_side;
+    if side_in_check(pos, pos.xside) {
+        takeback(pos);
         return false;
     }
-    set_hash(d);
+    #[cfg(feature = "integrity")]
+    assert_incremental(pos, "makemove");
     true
 }
 
 /// takeback() is very similar to makemove(), only backwards :)
 
-pub fn takeback(d: &mut Data) {
-    d.side ^= 1;
-    d.xside ^= 1;
-    // #rust Need to avoid underflow of ply and hply, which are unsigned, or
-    // debug builds will panic on an "undo" command in main().
-    if d.ply > 0 {
-        d.ply -= 1
+pub fn takeback(pos: &mut Position) {
+    pos.side ^= 1;
+    pos.xside ^= 1;
+    // #rust Need to avoid underflow of hply, which is unsigned, or debug builds
+    // will panic on an "undo" command in main().
+    if pos.hply > 0 {
+        pos.hply -= 1
     };
-    if d.hply > 0 {
-        d.hply -= 1
-    };
-    let m = d.hist_dat[d.hply].m.bytes();
-    d.castle = d.hist_dat[d.hply].castle;
-    d.ep = d.hist_dat[d.hply].ep;
-    d.fifty = d.hist_dat[d.hply].fifty;
-    d.hash = d.hist_dat[d.hply].hash;
+    let m = pos.hist_dat[pos.hply].m.bytes();
+    pos.castle = pos.hist_dat[pos.hply].castle as Int;
+    pos.ep = pos.hist_dat[pos.hply].ep as Int;
+    pos.fifty = pos.hist_dat[pos.hply].fifty;
+
+    // a null move didn't move any pieces
+    if m.from == m.to {
+        pos.hash = pos.hist_dat[pos.hply].hash;
+        return;
+    }
+
     let m_from = m.from as usize;
     let m_to = m.to as usize;
-    d.color[m_from] = d.side;
-    d.piece[m_from] = if (m.bits & 32) != 0 {
+    let p = if m.is_promotion() {
         PAWN
     } else {
-        d.piece[m_to]
+        pos.piece(m_to)
     };
-    match d.hist_dat[d.hply].capture {
-        EMPTY => {
-            d.color[m_to] = EMPTY;
-            d.piece[m_to] = EMPTY;
-        }
-        _ => {
-            d.color[m_to] = d.xside;
-            d.piece[m_to] = d.hist_dat[d.hply].capture;
-        }
+    remove_piece(pos, m_to);
+    add_piece(pos, m_from, pos.side, p);
+    let capture = pos.hist_dat[pos.hply].capture as Int;
+    if capture != EMPTY {
+        add_piece(pos, m_to, pos.xside, capture);
     }
-    if (m.bits & 2) != 0 {
+    if m.is_castle() {
         let from: usize;
         let to: usize;
         match m_to {
@@ -616,17 +1483,626 @@ pub fn takeback(d: &mut Data) {
             }
             _ => panic!("takeback: invalid castling move"),
         }
-        d.color[to] = d.side;
-        d.piece[to] = ROOK;
-        d.color[from] = EMPTY;
-        d.piece[from] = EMPTY;
+        remove_piece(pos, from);
+        add_piece(pos, to, pos.side, ROOK);
     }
-    if (m.bits & 4) != 0 {
-        let pawn_sq = match d.side {
+    if m.is_en_passant() {
+        let pawn_sq = match pos.side {
             LIGHT => m.to + 8,
             _ => m.to - 8,
         } as usize;
-        d.color[pawn_sq] = d.xside;
-        d.piece[pawn_sq] = PAWN;
+        add_piece(pos, pawn_sq, pos.xside, PAWN);
+    }
+
+    // #rust Moving the pieces back updates the hash, so the saved hash is
+    // restored last.
+    pos.hash = pos.hist_dat[pos.hply].hash;
+    #[cfg(feature = "integrity")]
+    assert_incremental(pos, "takeback");
+}
+
+/// make_null_move() makes a "null move," i.e., it passes the move to the other
+/// side without moving a piece. It's recorded in hist_dat like any other move
+/// (with the same from and to squares, which no real move has), so it can be
+/// taken back with either unmake_null_move() or takeback().
+
+pub fn make_null_move(pos: &mut Position) {
+    pos.hist_dat.truncate(pos.hply);
+    pos.hist_dat.push(Hist {
+        m: Move::default(),
+        capture: EMPTY as i8,
+        castle: pos.castle as i8,
+        ep: pos.ep as i8,
+        fifty: pos.fifty,
+        hash: pos.hash,
+    });
+    pos.hply += 1;
+    if pos.ep != -1 {
+        pos.hash ^= pos.hash_ep[pos.ep as usize];
+        pos.ep = -1;
+    }
+    pos.fifty += 1;
+    pos.side ^= 1;
+    pos.xside ^= 1;
+    pos.hash ^= pos.hash_side;
+}
+
+/// unmake_null_move() takes back a null move made by make_null_move().
+
+pub fn unmake_null_move(pos: &mut Position) {
+    pos.side ^= 1;
+    pos.xside ^= 1;
+    pos.hply -= 1;
+    pos.ep = pos.hist_dat[pos.hply].ep as Int;
+    pos.fifty = pos.hist_dat[pos.hply].fifty;
+    pos.hash = pos.hist_dat[pos.hply].hash;
+}
+
+/// perft() walks the move tree to the given depth and returns the number of
+/// leaf nodes. The node counts for many positions are well known, so comparing
+/// perft() results against them is a good way to make sure that gen(),
+/// makemove(), and takeback() are working correctly.
+
+pub fn perft(d: &mut Data, depth: Int) -> u64 {
+    if depth <= 0 {
+        return 1;
+    }
+    let mut nodes = 0;
+    gen_legal(d);
+    // gen_legal() only generates legal moves, so the last ply's leaves can be
+    // counted without making them
+    if depth == 1 {
+        return d.search.gen_dat[d.search.ply].len() as u64;
+    }
+    for i in 0..d.search.gen_dat[d.search.ply].len() {
+        makemove(&mut d.pos, d.search.gen_dat[d.search.ply][i].m.bytes());
+        d.search.ply += 1;
+        nodes += perft(d, depth - 1);
+        takeback(&mut d.pos);
+        d.search.ply -= 1;
+    }
+    nodes
+}
+
+// #rust add_piece() and remove_piece() keep the hash, king squares, piece
+// lists, material, and piece/square sums up to date as makemove() and
+// takeback() move pieces around, so they don't have to be recomputed from
+// scratch after every move.
+
+/// add_piece() puts a piece of color c and type p on the empty square sq.
+
+#[inline(always)]
+fn add_piece(pos: &mut Position, sq: usize, c: Int, p: Int) {
+    pos.color[sq] = c as i8;
+    pos.piece[sq] = p as i8;
+    pos.pieces[c as usize] |= 1 << sq;
+    pos.hash ^= pos.hash_piece[c as usize][p as usize][sq];
+    eval::add_piece_score(pos, c, p, sq);
+    if p == KING {
+        pos.king_sq[c as usize] = sq;
+    }
+}
+
+/// remove_piece() takes the piece on square sq off the board.
+
+#[inline(always)]
+fn remove_piece(pos: &mut Position, sq: usize) {
+    pos.hash ^= pos.hash_piece[pos.color(sq) as usize][pos.piece(sq) as usize][sq];
+    eval::sub_piece_score(pos, pos.color(sq), pos.piece(sq), sq);
+    pos.pieces[pos.color(sq) as usize] &= !(1 << sq);
+    pos.color[sq] = EMPTY as i8;
+    pos.piece[sq] = EMPTY as i8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::vec;
+
+    use super::super::defs::Piece;
+
+    #[test]
+    fn test_perft_initial_position() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        init_board(&mut d.pos);
+        assert_eq!(perft(&mut d, 1), 20);
+        assert_eq!(perft(&mut d, 2), 400);
+        assert_eq!(perft(&mut d, 3), 8902);
+    }
+
+    /// pseudo_perft() is perft() done the slow way, with gen() and
+    /// makemove()'s check test instead of gen_legal()
+
+    fn pseudo_perft(d: &mut Data, depth: Int) -> u64 {
+        if depth <= 0 {
+            return 1;
+        }
+        let mut nodes = 0;
+        gen(d);
+        for i in 0..d.search.gen_dat[d.search.ply].len() {
+            if makemove(&mut d.pos, d.search.gen_dat[d.search.ply][i].m.bytes()) {
+                d.search.ply += 1;
+                nodes += pseudo_perft(d, depth - 1);
+                takeback(&mut d.pos);
+                d.search.ply -= 1;
+            }
+        }
+        nodes
+    }
+
+    #[test]
+    fn test_gen_legal_rank_pin() {
+        // white's pawn on b5 is pinned to its king on a5 by the rook on h5,
+        // so it can't push
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        set_fen(&mut d, "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
+
+        assert_eq!(perft(&mut d, 1), 14);
+        for depth in 1..=3 {
+            assert_eq!(perft(&mut d, depth), pseudo_perft(&mut d, depth));
+        }
+        assert_eq!(perft(&mut d, 3), 2812);
+    }
+
+    /// check_hash() walks the move tree and makes sure that the hash, the
+    /// piece lists, and the pawn structure that makemove() and takeback()
+    /// maintain match the ones set_incremental() computes.
+    fn check_hash(d: &mut Data, depth: Int) {
+        let hash = d.pos.hash;
+        let pieces = d.pos.pieces;
+        let pawns = (d.pos.pawns, d.pos.pawn_rank);
+        set_incremental(&mut d.pos);
+        assert_eq!(hash, d.pos.hash);
+        assert_eq!(pieces, d.pos.pieces);
+        assert_eq!(pawns, (d.pos.pawns, d.pos.pawn_rank));
+        if depth == 0 {
+            return;
+        }
+        gen_legal(d);
+        for i in 0..d.search.gen_dat[d.search.ply].len() {
+            makemove(&mut d.pos, d.search.gen_dat[d.search.ply][i].m.bytes());
+            d.search.ply += 1;
+            check_hash(d, depth - 1);
+            takeback(&mut d.pos);
+            d.search.ply -= 1;
+            assert_eq!(hash, d.pos.hash);
+        }
+    }
+
+    #[test]
+    fn test_incremental_hash() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        init_board(&mut d.pos);
+        check_hash(&mut d, 3);
+    }
+
+    #[test]
+    fn test_try_move() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        init_board(&mut d.pos);
+        let e2e4 = MoveBytes {
+            from: 52,
+            to: 36,
+            ..MoveBytes::default()
+        };
+        let e2e5 = MoveBytes {
+            from: 52,
+            to: 28,
+            ..MoveBytes::default()
+        };
+        assert!(is_legal(&mut d, e2e4));
+        assert!(!is_legal(&mut d, e2e5));
+        assert_eq!(try_move(&mut d, e2e5), Err(IllegalMove));
+        assert_eq!(d.pos.side, LIGHT);
+        assert_eq!(try_move(&mut d, e2e4), Ok(()));
+        assert_eq!(d.pos.side, DARK);
+        assert_eq!(d.pos.ep, 44);
+        assert_eq!(d.search.gen_dat[0].len(), 20);
+    }
+
+    #[test]
+    fn test_move_accessors() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        set_fen(&mut d, "r3k3/1P6/8/3pP3/8/8/8/R3K3 w Qq d6 0 1").unwrap();
+        let find = |d: &Data, s: &str| {
+            let i = crate::util::parse_move(d, s).unwrap();
+            d.search.gen_dat[0][i].m.bytes()
+        };
+
+        let m = find(&d, "e1c1");
+        assert!(m.is_castle() && !m.is_capture());
+        assert_eq!((m.from_sq(), m.to_sq()), (Square::E1, Square::C1));
+        let m = find(&d, "e5d6");
+        assert!(m.is_en_passant() && m.is_capture() && m.is_pawn_move());
+        let m = find(&d, "b7b8q");
+        assert_eq!(m.promotion(), Some(Piece::Queen));
+        assert_eq!(m.to_string(), "b7b8q");
+        let m = find(&d, "b7a8n");
+        assert!(m.is_capture());
+        assert_eq!(m.promotion(), Some(Piece::Knight));
+        assert_eq!(Move::from(m).to_string(), "b7a8n");
+        assert!(Move::from(m) == Move::from(find(&d, "b7a8n")));
+        assert!(Move::from(m) != Move::from(find(&d, "b7a8q")));
+        let mut none = Move::default();
+        none.set_value(-1);
+        assert_eq!(none.value(), -1);
+        let m = find(&d, "a1b1");
+        assert_eq!(m.promotion(), None);
+        assert!(!m.is_castle() && !m.is_pawn_move() && !m.is_double_push());
+
+        assert_eq!(d.pos.side_to_move(), Color::Light);
+        assert_eq!(d.pos.king_square(Color::Dark), Square::E8);
+        assert_eq!(d.pos.en_passant(), Some(Square::D6));
+        assert_eq!(
+            d.pos.piece_at(Square::B7),
+            Some((Color::Light, Piece::Pawn))
+        );
+        assert_eq!(d.pos.piece_at(Square::A8), Some((Color::Dark, Piece::Rook)));
+        assert_eq!(d.pos.piece_at(Square::E4), None);
+        assert_eq!("d6".parse(), Ok(Square::D6));
+        assert_eq!(Square::D6.to_string(), "d6");
+        assert_eq!((Square::D6.file(), Square::D6.rank()), (3, 5));
+        assert!("i9".parse::<Square>().is_err());
+    }
+
+    #[test]
+    fn test_history_and_undo() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        init_board(&mut d.pos);
+        for s in ["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a5"].iter() {
+            try_move(&mut d, s.parse().unwrap()).unwrap();
+        }
+        assert_eq!(
+            history_san(&d.pos),
+            ["e4", "d5", "exd5", "Qxd5", "Nc3", "Qa5"]
+        );
+        assert_eq!(d.pos.history().len(), 6);
+        assert_eq!(d.pos.history()[0].captured(), None);
+        assert_eq!(d.pos.history()[2].captured(), Some(Piece::Pawn));
+
+        assert_eq!(undo_move(&mut d).unwrap().to_string(), "d5a5");
+        assert_eq!(d.pos.history().len(), 5);
+        assert_eq!(d.search.gen_dat[0].len(), legal_move_count(&mut d));
+        while undo_move(&mut d).is_some() {}
+        assert_eq!(d.pos.hply, 0);
+        assert_eq!(fen(&d.pos), fen(&Position::new()));
+    }
+
+    fn legal_move_count(d: &mut Data) -> usize {
+        gen_legal(d);
+        d.search.gen_dat[d.search.ply].len()
+    }
+
+    #[test]
+    fn test_san() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        let mut san_of = |fen: &str, m: &str| {
+            set_fen(&mut d, fen).unwrap();
+            san(&mut d, m.parse().unwrap())
+        };
+        let fen = "4k3/8/8/R7/8/8/8/RN2KN2 w - - 0 1";
+        assert_eq!(san_of(fen, "b1d2").unwrap(), "Nbd2");
+        assert_eq!(san_of(fen, "f1d2").unwrap(), "Nfd2");
+        assert_eq!(san_of(fen, "a1a3").unwrap(), "R1a3");
+        assert_eq!(san_of(fen, "a5a3").unwrap(), "R5a3");
+        assert_eq!(san_of(fen, "a5e5").unwrap(), "Re5+");
+        assert_eq!(san_of(fen, "f1g3").unwrap(), "Ng3");
+        assert_eq!(san_of(fen, "a5a8").unwrap(), "Ra8+");
+        assert_eq!(san_of(fen, "a5b6"), None);
+        let fen = "7k/P7/6K1/8/8/8/8/8 w - - 0 1";
+        assert_eq!(san_of(fen, "a7a8q").unwrap(), "a8=Q#");
+        assert_eq!(san_of(fen, "a7a8n").unwrap(), "a8=N");
+        assert_eq!(san_of(KIWIPETE, "e1g1").unwrap(), "O-O");
+        assert_eq!(san_of(KIWIPETE, "e1c1").unwrap(), "O-O-O");
+        assert_eq!(san_of(KIWIPETE, "e5f7").unwrap(), "Nxf7");
+    }
+
+    #[test]
+    fn test_svg() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        init_board(&mut d.pos);
+        gen_legal(&mut d);
+        let svg = to_svg(&d);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("\u{265f}</text>").count(), 16);
+        assert_eq!(svg.matches("\u{265a}</text>").count(), 2);
+        assert!(!svg.contains("<line"));
+
+        try_move(&mut d, "e2e4".parse().unwrap()).unwrap();
+        let options = SvgOptions {
+            flipped: true,
+            last_move: true,
+        };
+        let svg = to_svg_with(&d, &options);
+        assert_eq!(svg.matches("<line").count(), 1);
+        assert_eq!(
+            svg.matches("#cdd26a").count() + svg.matches("#aaa23a").count(),
+            2
+        );
+        // flipped, the a file is on the right, and e2 is above e4
+        assert!(svg.contains(
+            "<text x=\"357\" y=\"394\" font-family=\"sans-serif\" font-size=\"12\" text-anchor=\"middle\">a</text>"
+        ));
+        assert!(svg.contains("<line x1=\"177\" y1=\"87\" x2=\"177\" y2=\"177\""));
+    }
+
+    #[test]
+    fn test_parse_san() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        let mut parse = |fen: &str, s: &str| {
+            set_fen(&mut d, fen).unwrap();
+            parse_san(&mut d, s).map(|m| m.to_string())
+        };
+        let fen = "4k3/8/8/R7/8/8/8/RN2KN2 w - - 0 1";
+        assert_eq!(parse(fen, "Nbd2").unwrap(), "b1d2");
+        assert_eq!(parse(fen, "R5a3").unwrap(), "a5a3");
+        assert_eq!(parse(fen, "Re5+").unwrap(), "a5e5");
+        assert_eq!(parse(fen, "Re5!?").unwrap(), "a5e5");
+        assert_eq!(parse(fen, "Nd2"), None);
+        assert_eq!(parse(fen, "Rb6"), None);
+        let fen = "7k/P7/6K1/8/8/8/8/8 w - - 0 1";
+        assert_eq!(parse(fen, "a8=Q#").unwrap(), "a7a8q");
+        assert_eq!(parse(fen, "a8N").unwrap(), "a7a8n");
+        assert_eq!(parse(KIWIPETE, "0-0").unwrap(), "e1g1");
+        assert_eq!(parse(KIWIPETE, "O-O-O").unwrap(), "e1c1");
+        assert_eq!(parse(KIWIPETE, "Nxf7").unwrap(), "e5f7");
+    }
+
+    #[test]
+    fn test_null_move() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        init_board(&mut d.pos);
+        let e2e4 = MoveBytes {
+            from: 52,
+            to: 36,
+            ..MoveBytes::default()
+        };
+        try_move(&mut d, e2e4).unwrap();
+        let hash = d.pos.hash;
+
+        make_null_move(&mut d.pos);
+        assert_eq!(d.pos.side, LIGHT);
+        assert_eq!(d.pos.ep, -1);
+        let null_hash = d.pos.hash;
+        set_hash(&mut d.pos);
+        assert_eq!(null_hash, d.pos.hash);
+        unmake_null_move(&mut d.pos);
+        assert_eq!(d.pos.side, DARK);
+        assert_eq!(d.pos.hash, hash);
+
+        make_null_move(&mut d.pos);
+        takeback(&mut d.pos);
+        assert_eq!(d.pos.side, DARK);
+        assert_eq!(d.pos.ep, 44);
+        assert_eq!(d.pos.hash, hash);
+        assert_eq!(d.pos.hply, 1);
+    }
+
+    #[test]
+    fn test_long_game() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        init_board(&mut d.pos);
+        let hash = d.pos.hash;
+
+        // shuffle the knights back and forth for 1000 ply, far longer than the
+        // 400 ply the history used to have room for
+        let squares = [(62, 45), (6, 21), (45, 62), (21, 6)];
+        for i in 0..1000 {
+            let (from, to) = squares[i % 4];
+            let m = MoveBytes {
+                from,
+                to,
+                ..MoveBytes::default()
+            };
+            try_move(&mut d, m).unwrap();
+        }
+        assert_eq!(d.pos.hply, 1000);
+        assert_eq!(d.pos.hash, hash);
+
+        while d.pos.hply > 0 {
+            takeback(&mut d.pos);
+        }
+        assert_eq!(d.pos.hash, hash);
+        assert_eq!(d.pos.fifty, 0);
+    }
+
+    #[test]
+    fn test_check_incremental() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        init_board(&mut d.pos);
+        assert_eq!(check_incremental(&d.pos), Ok(()));
+        let e2e4 = MoveBytes {
+            from: 52,
+            to: 36,
+            ..MoveBytes::default()
+        };
+        try_move(&mut d, e2e4).unwrap();
+        assert_eq!(check_incremental(&d.pos), Ok(()));
+
+        d.pos.pcsq[LIGHT as usize] += 1;
+        d.pos.hash ^= 1;
+        let diff = check_incremental(&d.pos).unwrap_err();
+        let pcsq = d.pos.pcsq[LIGHT as usize];
+        assert!(diff.starts_with(&format!("{} (moves: e2e4)\n", fen(&d.pos))));
+        assert!(diff.contains(&format!(
+            "\npcsq: incremental [{}, {}], computed [{}, {}]",
+            pcsq,
+            d.pos.pcsq[DARK as usize],
+            pcsq - 1,
+            d.pos.pcsq[DARK as usize]
+        )));
+        assert!(diff.contains("\nhash: incremental "), "{}", diff);
+        assert!(!diff.contains("pawns"), "{}", diff);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        init_board(&mut d.pos);
+        assert_eq!(validate(&d), Ok(()));
+        let e2e4 = MoveBytes {
+            from: 52,
+            to: 36,
+            ..MoveBytes::default()
+        };
+        try_move(&mut d, e2e4).unwrap();
+        assert_eq!(validate(&d), Ok(()));
+
+        // take the white king off the board without telling anyone
+        d.pos.color[E1] = EMPTY as i8;
+        d.pos.piece[E1] = EMPTY as i8;
+        d.pos.ep = 43;
+        assert_eq!(
+            validate(&d),
+            Err(vec![
+                Inconsistency::PieceList(Color::Light),
+                Inconsistency::KingCount(Color::Light, 0),
+                Inconsistency::BadEnPassant(43),
+                Inconsistency::BadCastle(1),
+                Inconsistency::BadCastle(2),
+                Inconsistency::BadHash,
+            ])
+        );
+    }
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn test_fen() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        init_board(&mut d.pos);
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(fen(&d.pos), start);
+        let e2e4 = MoveBytes {
+            from: 52,
+            to: 36,
+            ..MoveBytes::default()
+        };
+        try_move(&mut d, e2e4).unwrap();
+        let after = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        assert_eq!(fen(&d.pos), after);
+        let hash = d.pos.hash;
+
+        set_fen(&mut d, KIWIPETE).unwrap();
+        assert_eq!(fen(&d.pos), KIWIPETE);
+        set_fen(&mut d, after).unwrap();
+        assert_eq!(fen(&d.pos), after);
+        assert_eq!(d.pos.hash, hash);
+
+        let bad = [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - -1 1",
+        ];
+        for s in bad.iter() {
+            match set_fen(&mut d, s) {
+                Err(FenError::Syntax(_)) => {}
+                r => panic!("{:?} for {}", r, s),
+            }
+        }
+        assert_eq!(set_fen(&mut d, "4k3/8/8/8/8/8/8/4K2R b K - 0 1"), Ok(()));
+        assert_eq!(
+            set_fen(&mut d, "4k3/8/8/8/8/8/8/4R1K1 w K - 0 1"),
+            Err(FenError::Invalid(vec![
+                Inconsistency::XsideInCheck,
+                Inconsistency::BadCastle(1),
+            ]))
+        );
+        // a rejected position leaves the board alone
+        assert_eq!(fen(&d.pos), "4k3/8/8/8/8/8/8/4K2R b K - 0 1");
+    }
+
+    #[test]
+    fn test_attack() {
+        // the C version's attack(): look for a piece of side s that gets to
+        // sq by walking its offsets through the mailbox
+        fn walk_attack(pos: &Position, sq: usize, s: Int) -> bool {
+            (0..64).filter(|&i| pos.color(i) == s).any(|i| {
+                let piece = pos.piece(i) as usize;
+                if piece == PAWN as usize {
+                    let forward = if s == LIGHT { -10 } else { 10 };
+                    return [forward - 1, forward + 1]
+                        .iter()
+                        .any(|offset| MAILBOX[(MAILBOX64[i] + offset) as usize] == sq as Int);
+                }
+                OFFSET[piece][..OFFSETS[piece] as usize]
+                    .iter()
+                    .any(|offset| {
+                        let mut n = i as Int;
+                        loop {
+                            n = MAILBOX[(MAILBOX64[n as usize] + offset) as usize];
+                            if n == -1 {
+                                return false;
+                            }
+                            if n as usize == sq {
+                                return true;
+                            }
+                            if pos.color(n as usize) != EMPTY || !SLIDE[piece] {
+                                return false;
+                            }
+                        }
+                    })
+            })
+        }
+
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        for fen in &[
+            KIWIPETE,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bq1rk1/pp2nppp/2n1p3/3pP3/1b1P4/2NB1N2/PP3PPP/R1BQK2R w KQ - 0 9",
+        ] {
+            set_fen(&mut d, fen).unwrap();
+            for sq in 0..64 {
+                for s in [LIGHT, DARK] {
+                    assert_eq!(
+                        attack(&d.pos, sq, s),
+                        walk_attack(&d.pos, sq, s),
+                        "{} {} {}",
+                        fen,
+                        sq,
+                        s
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_perft_kiwipete() {
+        let mut d = Data::new();
+        init_hash(&mut d.pos);
+        set_fen(&mut d, KIWIPETE).unwrap();
+        assert_eq!(perft(&mut d, 1), 48);
+        assert_eq!(perft(&mut d, 2), 2039);
+        assert_eq!(perft(&mut d, 3), 97862);
+        check_hash(&mut d, 2);
+
+        // this one has lots of en passant captures and checks along the ranks
+        set_fen(&mut d, "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
+        assert_eq!(perft(&mut d, 4), 43238);
+        check_hash(&mut d, 3);
     }
 }
@@ -5,122 +5,577 @@
 //
 // Rust port by Kristopher Johnson
 
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::BufReader;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+#[cfg(feature = "cli")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::Path;
 
+use super::board;
+use super::rand;
 use super::util;
 
 use super::data::Data;
-use super::defs::Int;
+use super::defs::{Color, Int, Move};
 
 // #rust The original C code keeps the book.txt file open throughout the
 // lifetime of the program and re-reads its contents whenever it wants to look
-// up a book move. In the Rust translation, we read the file's lines into
-// Data.book_lines at initialization, close the file, and use that in-memory
-// collection from then on.
+// up a book move, comparing the game's moves so far with the start of each
+// line. In the Rust translation, we read the file into a Book at
+// initialization, close the file, and use that in-memory Book from then on.
+// The Book maps the hash of each position in the book to the moves played
+// from it, so looking up a book move doesn't depend on the length of the
+// book, and a position is found even if the game reached it by a different
+// move order than the book's lines. (Though not always: the hash includes the
+// en passant square, which is set after every two-square pawn move, so the
+// positions after 1. Nf3 Nf6 2. c4 and 1. c4 Nf6 2. Nf3 are different.)
+//
+// #rust Each line of the book is a game's opening moves, e.g.,
+// "e2e4 e7e5 g1f3". In the C version, book_move() picks one of the lines that
+// continue the game at random, so a move is played as often as the lines that
+// contain it. Here, a move can also be given a weight, e.g., "e2e4:30"; then
+// the line counts that many times for the move (and a weight of 0 means the
+// move is never played from that line). A move without a weight has weight 1,
+// so a book without weights works as it always has.
+//
+// #rust There can be several books, in Data.books. book_move() looks in them
+// in order, and plays a move from the first one that has any for the position,
+// so a narrow main book can be backed up by a wider one. A book can also be
+// just for one side, e.g., to play a different repertoire with each color.
+
+/// an opening book: the moves played from each position in the book, with
+/// their weights, indexed by the position's hash value
+
+#[derive(Clone, Default)]
+pub struct Book {
+    positions: BTreeMap<Int, Vec<(Move, Int)>>,
+    side: Option<Color>,
+}
+
+impl Book {
+    /// create an empty book
+
+    pub fn new() -> Book {
+        Book::default()
+    }
+
+    /// len() returns the number of positions in the book
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// is_empty() returns true if the book has no positions
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// for_side() returns the book, to be used only when side is to move
+
+    pub fn for_side(mut self, side: Color) -> Book {
+        self.side = Some(side);
+        self
+    }
+
+    /// side() returns the side the book is for, or None if it's for both
+    #[inline(always)]
+    pub fn side(&self) -> Option<Color> {
+        self.side
+    }
 
-/// open_book() opens the opening book file and initializes the random number
-/// generator so we play random book moves.
+    /// moves() returns the moves the book has for the position with the given
+    /// hash value, and their weights. (A position with a different hash value
+    /// may have the same moves, so the caller should check they're legal.)
 
-pub fn open_book(d: &mut Data) {
-    unsafe {
-        // srand(time(NULL));
-        libc::srand(libc::time(std::ptr::null_mut()) as u32);
+    pub fn moves(&self, hash: Int) -> &[(Move, Int)] {
+        self.positions.get(&hash).map_or(&[], |moves| &moves[..])
     }
 
-    let f = match File::open("book.txt") {
-        Ok(file) => file,
+    /// add() adds weight to the move m from the position with the given hash
+    /// value
+
+    pub fn add(&mut self, hash: Int, m: Move, weight: Int) {
+        let moves = self.positions.entry(hash).or_default();
+        match moves.iter_mut().find(|(n, _)| *n == m) {
+            Some((_, w)) => *w += weight,
+            None => moves.push((m, weight)),
+        }
+    }
+}
+
+/// how deep into the game the opening book is used. The C version stops using
+/// it after 25 ply, which is the default.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BookDepth {
+    /// the book is used for as long as it has moves for the game
+    Unlimited,
+    /// the book is used until more than this many ply have been played
+    Ply(usize),
+    /// the book is used until more than this many full moves (pairs of ply)
+    /// have been played
+    Moves(usize),
+}
+
+impl BookDepth {
+    /// allows() returns true if the book can be used after hply ply
+    pub fn allows(self, hply: usize) -> bool {
+        match self {
+            BookDepth::Unlimited => true,
+            BookDepth::Ply(n) => hply <= n,
+            BookDepth::Moves(n) => hply <= n.saturating_mul(2),
+        }
+    }
+}
+
+impl Default for BookDepth {
+    fn default() -> Self {
+        BookDepth::Ply(25)
+    }
+}
+
+/// how book_move() chooses among the book's moves for a position
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BookSelection {
+    /// at random, in proportion to the moves' weights, using rand(). This is
+    /// the default, as in the C version.
+    #[default]
+    Random,
+    /// the same way, but with random numbers that only depend on this seed
+    /// and the position, so the same positions get the same book moves every
+    /// time (e.g., for testing)
+    Seeded(u64),
+    /// always the move with the most weight (the first of them in the book if
+    /// there's a tie)
+    Best,
+}
+
+/// the opening book file the console uses
+
+#[cfg(feature = "cli")]
+pub const BOOK_FILE: &str = "book.txt";
+
+/// open_book() opens the opening book file at path (normally BOOK_FILE) and
+/// initializes the random number generator so we play random book moves.
+
+#[cfg(feature = "cli")]
+pub fn open_book(d: &mut Data, path: &Path) {
+    d.books = match load_book(path) {
+        Ok(book) => vec![Arc::new(book)],
         Err(err) => {
             println!("Opening book missing: {}.", err);
-            d.book_lines = Vec::new();
-            return;
+            Vec::new()
         }
     };
 
-    let reader = BufReader::new(f);
-    let lines: Vec<String> = reader
-        .lines()
-        .map(|line| line.expect("unable to read line from book.txt"))
+    // srand(time(NULL));
+    // #rust (this has to come after load_book(), which reseeds rand())
+    rand::srand(util::get_ms() as u32);
+}
+
+/// load_book() reads the opening book file at path
+
+#[cfg(feature = "std")]
+pub fn load_book(path: &Path) -> io::Result<Book> {
+    Ok(parse_book(&fs::read_to_string(path)?))
+}
+
+/// parse_book() builds a Book from the text of an opening book, by playing
+/// out each line on a board of its own. A line that has a move that isn't
+/// legal, or a weight that isn't a number, is only used up to that move.
+/// (Without the "std" feature, there's no file system, so this is how the book
+/// gets loaded, e.g., from the text of book.txt included with include_str!().)
+/// Note that it calls init_hash(), which reseeds rand().
+
+pub fn parse_book(text: &str) -> Book {
+    let mut book = Book::new();
+    let mut d = Data::new();
+    board::init_hash(&mut d.pos);
+    for line in text.lines() {
+        play_line(&mut d, line, |d, m, weight| book.add(d.pos.hash, m, weight));
+    }
+    book
+}
+
+/// play_line() plays out a line of the book on d's board, from the initial
+/// position, calling f with each move and its weight before it's made. It
+/// stops at the first move that isn't legal or has a bad weight.
+
+fn play_line<F: FnMut(&Data, Move, Int)>(d: &mut Data, line: &str, mut f: F) {
+    board::init_board(&mut d.pos);
+    board::gen_legal(d);
+    for token in line.split_whitespace() {
+        let (s, weight) = match book_token(token) {
+            Some(t) => t,
+            None => break,
+        };
+        let m = match util::parse_move(d, s) {
+            Ok(i) => d.search.gen_dat[0][i].m,
+            Err(_) => break,
+        };
+        f(d, m, weight);
+        if board::try_move(d, m.bytes()).is_err() {
+            break;
+        }
+    }
+}
+
+/// scratch_data() returns a Data to play out lines on, with the same hash
+/// values as d, so its positions can be compared with d's. (Unlike calling
+/// init_hash(), this doesn't reseed rand().)
+
+fn scratch_data(d: &Data) -> Data {
+    let mut scratch = Data::new();
+    scratch.pos.hash_piece = d.pos.hash_piece;
+    scratch.pos.hash_side = d.pos.hash_side;
+    scratch.pos.hash_ep = d.pos.hash_ep;
+    scratch
+}
+
+/// lines_through() returns the indexes of the lines of a book's text that
+/// pass through (or end in) d's current position
+
+pub fn lines_through(d: &Data, lines: &[String]) -> Vec<usize> {
+    let mut scratch = scratch_data(d);
+    let hash = d.pos.hash;
+    let mut indexes = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let mut found = false;
+        play_line(&mut scratch, line, |s, _, _| found |= s.pos.hash == hash);
+        if found || scratch.pos.hash == hash {
+            indexes.push(i);
+        }
+    }
+    indexes
+}
+
+/// game_line() returns the moves of d's game as a line for the book, e.g.,
+/// "e2e4 e7e5 g1f3", or None if the game has no moves or didn't start from the
+/// initial position
+
+pub fn game_line(d: &Data) -> Option<String> {
+    if d.pos.hply == 0 {
+        return None;
+    }
+    let moves: Vec<String> = d
+        .pos
+        .history()
+        .iter()
+        .map(|h| util::move_str(h.m.bytes()))
         .collect();
-    d.book_lines = lines;
+    let line = moves.join(" ");
+
+    // make sure the game's moves lead from the initial position to this one
+    let mut scratch = scratch_data(d);
+    let mut count = 0;
+    play_line(&mut scratch, &line, |_, _, _| count += 1);
+    if count == moves.len() && scratch.pos.hash == d.pos.hash {
+        Some(line)
+    } else {
+        None
+    }
+}
+
+/// read_book_lines() reads the lines of the opening book file at path, e.g.,
+/// to edit them
+
+#[cfg(feature = "std")]
+pub fn read_book_lines(path: &Path) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+/// write_book_lines() replaces the opening book file at path with lines
+
+#[cfg(feature = "std")]
+pub fn write_book_lines(path: &Path, lines: &[String]) -> io::Result<()> {
+    let mut text = lines.join("\n");
+    text.push('\n');
+    fs::write(path, text)
 }
 
 /// close_book() closes the book file. This is called when the program exits.
 
 pub fn close_book(d: &mut Data) {
-    d.book_lines = Vec::new();
+    d.books.clear();
 }
 
 /// book_move() returns a book move (in integer format) or -1 if there is no
 /// book move.
 
 pub fn book_move(d: &Data) -> Int {
-    if d.hply > 25 {
+    // #rust In C, this variable is just "move", but that is a reserved word in
+    // Rust.
+    let move_ = book_moves(d);
+    let total_count: Int = move_.iter().map(|&(_, count)| count).sum();
+
+    // no book moves (or none with any weight)?
+    if total_count == 0 {
         return -1;
     }
 
-    // #rust In C, this variable is just "move", but that is a reserved word in
-    // Rust.
-    let mut move_: [Int; 50] = [0; 50]; // the possible book moves
-    let mut count: [Int; 50] = [0; 50]; // number of occurrences of each move
-    let mut moves = 0;
-    let mut total_count = 0;
-
-    // line is a string with the current line, e.g., "e2e4 e7e5 g1f3 "
-    let mut line = String::from("");
-    let mut j: Int;
-    for i in 0..d.hply {
-        line = line + &format!("{} ", util::move_str(d.hist_dat[i].m.bytes()));
-    }
-
-    // compare line to each line in the opening book
-    for book_line in d.book_lines.iter() {
-        // #rust The C code has a function book_match() to check whether the
-        // prefix matches, but in Rust we can just call the standard library's
-        // starts_with() method.
-        if book_line.starts_with(&line) {
-            // parse the book move that continues the line
-            let m = util::parse_move(d, &book_line[line.len()..]);
-            if m == -1 {
-                continue;
-            }
-            let m = d.gen_dat[m as usize].m.value();
-
-            // add the book move to the move list, or update the move's count
-            j = 0;
-            while j < moves {
-                if move_[j as usize] == m {
-                    count[j as usize] += 1;
-                    break;
+    // Think of total_count as the set of matching book lines. Randomly pick one
+    // of those lines (j) and figure out which move j "corresponds" to.
+    let mut j = match d.book_selection {
+        BookSelection::Random => rand::rand() % total_count,
+        BookSelection::Seeded(seed) => rand::rand_from(seed ^ d.pos.hash as u64) % total_count,
+        BookSelection::Best => {
+            let mut best = move_[0];
+            for &(m, count) in &move_[1..] {
+                if count > best.1 {
+                    best = (m, count);
                 }
-                j += 1;
             }
-            if j == moves {
-                move_[moves as usize] = m;
-                count[moves as usize] = 1;
-                moves += 1;
+            return best.0.value();
+        }
+    };
+    for (m, count) in move_ {
+        j -= count;
+        if j < 0 {
+            return m.value();
+        }
+    }
+
+    -1
+}
+
+/// book_moves() returns the moves book_move() chooses from in the current
+/// position, with their weights: the moves from the first of the books (for
+/// the side to move) that has any with weight for the position, in the order
+/// they're in the book. It's empty if there are none, or if the game has gone
+/// past the book depth.
+
+pub fn book_moves(d: &Data) -> Vec<(Move, Int)> {
+    if !d.book_depth.allows(d.pos.hply) {
+        return Vec::new();
+    }
+    let side = d.pos.side_to_move();
+    // the moves come from the book with their weights, but we only keep the
+    // ones that are legal here, in case another position has the same hash
+    // value
+    let legal = |m: &Move| d.search.gen_dat[0].iter().any(|g| g.m == *m);
+    for book in d.books.iter() {
+        if book.side().is_none_or(|s| s == side) {
+            let moves: Vec<(Move, Int)> = book
+                .moves(d.pos.hash)
+                .iter()
+                .copied()
+                .filter(|(m, _)| legal(m))
+                .collect();
+            if moves.iter().any(|&(_, count)| count > 0) {
+                return moves;
             }
-            total_count += 1;
         }
     }
+    Vec::new()
+}
 
-    // no book moves?
-    if moves == 0 {
-        return -1;
+/// book_token() splits one of the moves of a book line into the move and its
+/// weight (1 if it doesn't have one). It returns None if the weight isn't a
+/// number from 0 up.
+
+fn book_token(token: &str) -> Option<(&str, Int)> {
+    match token.split_once(':') {
+        None => Some((token, 1)),
+        Some((m, weight)) => match weight.parse::<Int>() {
+            Ok(weight) if weight >= 0 => Some((m, weight)),
+            _ => None,
+        },
     }
+}
 
-    // Think of total_count as the set of matching book lines. Randomly pick one
-    // of those lines (j) and figure out which move j "corresponds" to.
-    unsafe {
-        j = (libc::rand() % (total_count as i32)) as Int;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use alloc::sync::Arc;
+    use alloc::vec;
+
+    fn book_data(text: &str) -> Data {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        d.books = vec![Arc::new(parse_book(text))];
+        d
     }
-    for i in 0..(moves as usize) {
-        j -= count[i];
-        if j < 0 {
-            return move_[i];
+
+    fn book_move_str(d: &Data) -> Option<String> {
+        let m = book_move(d);
+        if m == -1 {
+            return None;
         }
+        let mut book_move = crate::defs::Move::default();
+        book_move.set_value(m);
+        Some(util::move_str(book_move.bytes()))
     }
 
-    -1
+    #[test]
+    fn test_weights() {
+        assert!(book_token("e2e4") == Some(("e2e4", 1)));
+        assert!(book_token("e2e4:30") == Some(("e2e4", 30)));
+        assert!(book_token("e2e4:-1").is_none());
+        assert!(book_token("e2e4:x").is_none());
+
+        // a move with weight 0 is never played, however many lines it's in
+        let mut d = book_data("e2e4:0 e7e5\ne2e4:0 c7c5\nd2d4 d7d5\nc2c4:x\n");
+        for _ in 0..20 {
+            assert_eq!(book_move_str(&d).as_deref(), Some("d2d4"));
+        }
+
+        // later moves are matched without their weights
+        board::try_move(&mut d, "e2e4".parse().unwrap()).unwrap();
+        let m = book_move_str(&d).unwrap();
+        assert!(m == "e7e5" || m == "c7c5");
+
+        let d = book_data("e2e4:0\n");
+        assert!(book_move_str(&d).is_none());
+    }
+
+    #[test]
+    fn test_book_depth() {
+        assert!(BookDepth::default().allows(25));
+        assert!(!BookDepth::default().allows(26));
+        assert!(BookDepth::Moves(3).allows(6));
+        assert!(!BookDepth::Moves(3).allows(7));
+        assert!(BookDepth::Unlimited.allows(1000));
+
+        let mut d = book_data("e2e4 e7e5 g1f3\n");
+        d.book_depth = BookDepth::Ply(1);
+        board::try_move(&mut d, "e2e4".parse().unwrap()).unwrap();
+        assert_eq!(book_move_str(&d).as_deref(), Some("e7e5"));
+        board::try_move(&mut d, "e7e5".parse().unwrap()).unwrap();
+        assert!(book_move_str(&d).is_none());
+        d.book_depth = BookDepth::Unlimited;
+        assert_eq!(book_move_str(&d).as_deref(), Some("g1f3"));
+    }
+
+    #[test]
+    fn test_transpositions() {
+        let text = "g1f3 g8f6 b1c3 d7d5:0\nb1c3 g8f6 g1f3 e7e5\n";
+        // the positions after 1. Nf3 Nf6 2. Nc3 and 1. Nc3 Nf6 2. Nf3 are the
+        // same
+        assert_eq!(parse_book(text).len(), 6);
+
+        let mut d = book_data(text);
+        for m in &["g1f3", "g8f6", "b1c3"] {
+            board::try_move(&mut d, m.parse().unwrap()).unwrap();
+        }
+        assert_eq!(book_move_str(&d).as_deref(), Some("e7e5"));
+
+        // the book's moves aren't played if they aren't legal
+        let mut book = Book::new();
+        book.add(d.pos.hash, Move::default(), 1);
+        d.books = vec![Arc::new(book)];
+        assert!(book_move_str(&d).is_none());
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut d = book_data("");
+        let lines: Vec<String> = ["e2e4 e7e5 g1f3", "d2d4 d7d5", "g1f3 b8c6:2 b1c3", "e2e4"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(lines_through(&d, &lines), [0, 1, 2, 3]);
+        assert!(game_line(&d).is_none());
+
+        for m in &["b1c3", "b8c6", "g1f3"] {
+            board::try_move(&mut d, m.parse().unwrap()).unwrap();
+        }
+        assert_eq!(lines_through(&d, &lines), [2]);
+        assert_eq!(game_line(&d).as_deref(), Some("b1c3 b8c6 g1f3"));
+
+        board::new_game(&mut d, false);
+        board::try_move(&mut d, "e2e4".parse().unwrap()).unwrap();
+        assert_eq!(lines_through(&d, &lines), [0, 3]);
+        assert_eq!(game_line(&d).as_deref(), Some("e2e4"));
+
+        // a game that started from another position can't be added
+        board::set_fen(&mut d, "4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        board::try_move(&mut d, "h1h2".parse().unwrap()).unwrap();
+        assert!(game_line(&d).is_none());
+    }
+
+    #[test]
+    fn test_selection() {
+        let text = "e2e4:2 e7e5\nd2d4:3 d7d5\ng1f3:3\nc2c4:1\n";
+        let mut d = book_data(text);
+        d.book_selection = BookSelection::Best;
+        assert_eq!(book_move_str(&d).as_deref(), Some("d2d4"));
+
+        // the same seed picks the same moves, whatever else uses rand()
+        let picks = |seed| {
+            let mut d = book_data(text);
+            d.book_selection = BookSelection::Seeded(seed);
+            (0..10)
+                .map(|_| {
+                    rand::srand(rand::rand() as u32);
+                    book_move_str(&d).unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        let first = picks(12345);
+        assert!(first.iter().all(|m| *m == first[0]));
+        assert_eq!(picks(12345), first);
+        let all: Vec<_> = (0..100).map(|seed| picks(seed)[0].clone()).collect();
+        assert!(all.iter().any(|m| *m != all[0]));
+    }
+
+    #[test]
+    fn test_book_moves() {
+        let mut d = book_data("e2e4:2 e7e5\nd2d4:3 d7d5\ne2e4 c7c5\nh2h4:0\n");
+        let moves: Vec<(String, Int)> = book_moves(&d)
+            .iter()
+            .map(|&(m, count)| (util::move_str(m.bytes()), count))
+            .collect();
+        assert_eq!(
+            moves,
+            [
+                ("e2e4".to_string(), 3),
+                ("d2d4".to_string(), 3),
+                ("h2h4".to_string(), 0)
+            ]
+        );
+        d.book_depth = BookDepth::Ply(0);
+        board::try_move(&mut d, "e2e4".parse().unwrap()).unwrap();
+        assert!(book_moves(&d).is_empty());
+        d.book_depth = BookDepth::Unlimited;
+        assert_eq!(book_moves(&d).len(), 2);
+    }
+
+    #[test]
+    fn test_books() {
+        let mut d = book_data("e2e4 e7e5\n");
+        d.books
+            .push(Arc::new(parse_book("d2d4 d7d5\ne2e4:0 c7c5\n")));
+
+        // the first book that has moves for the position is used
+        assert_eq!(book_move_str(&d).as_deref(), Some("e2e4"));
+        board::try_move(&mut d, "e2e4".parse().unwrap()).unwrap();
+        assert_eq!(book_move_str(&d).as_deref(), Some("e7e5"));
+        d.books.remove(0);
+        assert_eq!(book_move_str(&d).as_deref(), Some("c7c5"));
+        board::undo_move(&mut d);
+        assert_eq!(book_move_str(&d).as_deref(), Some("d2d4"));
+
+        // a book for one side is only used when that side is to move
+        d.books = vec![
+            Arc::new(parse_book("e2e4 e7e5\n").for_side(Color::Dark)),
+            Arc::new(parse_book("d2d4 d7d5\n").for_side(Color::Light)),
+        ];
+        assert_eq!(book_move_str(&d).as_deref(), Some("d2d4"));
+        board::try_move(&mut d, "e2e4".parse().unwrap()).unwrap();
+        assert_eq!(book_move_str(&d).as_deref(), Some("e7e5"));
+    }
 }
@@ -0,0 +1,189 @@
+// clock.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP has no chess clock; in the console, the computer
+// searches to a fixed depth or for a fixed time per move. ChessClock keeps
+// time for both sides, Fischer style (a base time plus an increment for each
+// move), so a game in the console can be played at a time control, with the
+// computer sharing its time out over the game the way it does in XBoard mode.
+//
+// The clock doesn't read the time itself; it's passed the time in
+// milliseconds (e.g., from util::get_ms()), so it can be tested without
+// waiting.
+
+use alloc::format;
+use alloc::string::String;
+
+use super::defs::{Int, DARK, LIGHT};
+use super::search::SearchLimits;
+
+/// a chess clock for the two sides, with an increment
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChessClock {
+    /// the time each side starts with, and the time added after each move, in
+    /// milliseconds
+    base: Int,
+    inc: Int,
+    /// the milliseconds each side (LIGHT and DARK) has left, not counting the
+    /// time that's passed since its clock started, if it's running
+    left: [Int; 2],
+    /// the milliseconds each side spent on its last move
+    last_move: [Option<Int>; 2],
+    /// the side whose clock is running, and the time it started
+    running: Option<(Int, u128)>,
+}
+
+impl ChessClock {
+    /// new() returns a clock that gives each side base milliseconds, plus inc
+    /// milliseconds for each move. Neither side's clock is running.
+
+    pub fn new(base: Int, inc: Int) -> ChessClock {
+        ChessClock {
+            base,
+            inc,
+            left: [base; 2],
+            last_move: [None; 2],
+            running: None,
+        }
+    }
+
+    /// reset() sets both sides' time back to what they started with, and
+    /// stops the clock, e.g., for a new game
+
+    pub fn reset(&mut self) {
+        *self = ChessClock::new(self.base, self.inc);
+    }
+
+    /// start() starts side's clock at the time now (stopping the other side's,
+    /// without charging the other side for it)
+
+    pub fn start(&mut self, side: Int, now: u128) {
+        self.running = Some((side, now));
+    }
+
+    /// punch() stops the clock of the side that's moving at the time now,
+    /// charges it for the move, and adds the increment if it didn't run out
+    /// of time. It returns the side and the milliseconds the move took, or
+    /// None if neither clock was running.
+
+    pub fn punch(&mut self, now: u128) -> Option<(Int, Int)> {
+        let (side, started) = self.running.take()?;
+        let used = now.saturating_sub(started) as Int;
+        let left = &mut self.left[side as usize];
+        *left -= used;
+        if *left > 0 {
+            *left += self.inc;
+        }
+        self.last_move[side as usize] = Some(used);
+        Some((side, used))
+    }
+
+    /// left() returns the milliseconds side has left at the time now (which
+    /// may be less than 0, if it's run out)
+
+    pub fn left(&self, side: Int, now: u128) -> Int {
+        let mut left = self.left[side as usize];
+        if let Some((running, started)) = self.running {
+            if running == side {
+                left -= now.saturating_sub(started) as Int;
+            }
+        }
+        left
+    }
+
+    /// flagged() returns the side that's run out of time at the time now, if
+    /// one has
+
+    pub fn flagged(&self, now: u128) -> Option<Int> {
+        [LIGHT, DARK]
+            .iter()
+            .copied()
+            .find(|&side| self.left(side, now) <= 0)
+    }
+
+    /// limits() returns the search limits for a search on the clock, so the
+    /// search's time management can work out how long to think
+
+    pub fn limits(&self, now: u128) -> SearchLimits {
+        SearchLimits {
+            wtime: Some(self.left(LIGHT, now).max(0)),
+            btime: Some(self.left(DARK, now).max(0)),
+            winc: self.inc,
+            binc: self.inc,
+            ..SearchLimits::default()
+        }
+    }
+
+    /// status() describes both sides' time at the time now, and the time each
+    /// spent on its last move, e.g.,
+    /// "White 4:58.2 (last move 1.8 s)  Black 5:00.0"
+
+    pub fn status(&self, now: u128) -> String {
+        let mut status = String::new();
+        for (side, name) in [(LIGHT, "White"), (DARK, "Black")] {
+            if !status.is_empty() {
+                status += "  ";
+            }
+            status += &format!("{} {}", name, time_str(self.left(side, now)));
+            if let Some(used) = self.last_move[side as usize] {
+                status += &format!(" (last move {}.{} s)", used / 1000, used / 100 % 10);
+            }
+        }
+        status
+    }
+}
+
+/// time_str() formats a number of milliseconds as "m:ss.t", or "h:mm:ss" for
+/// an hour or more (or "0:00.0" if it's less than 0)
+
+pub fn time_str(ms: Int) -> String {
+    let ms = ms.max(0);
+    let s = ms / 1000;
+    if s >= 3600 {
+        format!("{}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+    } else {
+        format!("{}:{:02}.{}", s / 60, s % 60, ms / 100 % 10)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        let mut clock = ChessClock::new(60_000, 2000);
+        assert_eq!(clock.status(0), "White 1:00.0  Black 1:00.0");
+        assert_eq!(clock.punch(0), None);
+
+        clock.start(LIGHT, 1000);
+        assert_eq!(clock.left(LIGHT, 6000), 55_000);
+        assert_eq!(clock.left(DARK, 6000), 60_000);
+        assert_eq!(clock.punch(6000), Some((LIGHT, 5000)));
+        clock.start(DARK, 6000);
+        assert_eq!(
+            clock.status(6500),
+            "White 0:57.0 (last move 5.0 s)  Black 0:59.5"
+        );
+
+        let limits = clock.limits(6500);
+        assert_eq!((limits.wtime, limits.btime), (Some(57_000), Some(59_500)));
+        assert_eq!((limits.winc, limits.binc), (2000, 2000));
+
+        // running out of time, the increment doesn't help
+        assert_eq!(clock.flagged(60_000), None);
+        assert_eq!(clock.flagged(66_000), Some(DARK));
+        clock.punch(66_000);
+        assert_eq!(clock.flagged(66_000), Some(DARK));
+        assert_eq!(clock.left(DARK, 70_000), 0);
+
+        clock.reset();
+        assert_eq!(clock, ChessClock::new(60_000, 2000));
+        assert_eq!(time_str(3_723_000), "1:02:03");
+    }
+}
@@ -5,20 +5,77 @@
 //
 // Rust port by Kristopher Johnson
 
-use super::defs::{Gen, Hist, Int, Move, DARK, GEN_STACK, HIST_STACK, LIGHT, MAX_PLY};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::AtomicBool;
+
+use super::book::{Book, BookDepth, BookSelection};
+use super::defs::{
+    Color, Gen, Hist, HistoryEntry, Int, Move, Piece, Square, DARK, E1, E8, KNIGHT, LIGHT, MAX_PLY,
+};
+use super::search::{Humanlike, Params, Variety, MAX_SKILL};
+use super::tt::{TranspositionTable, TtStats};
+
+use super::board;
+#[cfg(feature = "serde")]
+use alloc::format;
+#[cfg(feature = "serde")]
+use alloc::string::{String, ToString};
+#[cfg(feature = "serde")]
+use core::convert::TryFrom;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 // #rustc In the original C code, all the elements of the Data struct below are
 // global variables.  In Rust, we wrap them all in a struct so that we don't
 // have to treat them as "unsafe" static mutable data.
+//
+// #rust The board state and the search's scratch space are kept in separate
+// structs, Position and SearchState, so that code that only needs to know
+// about the board (makemove(), takeback(), in_check(), eval(), etc.) can work
+// with a Position on its own.
 
-/// the board representation
+/// an iterator over the squares in a set of squares like Position::pieces,
+/// in order
 
-pub struct Data {
+#[derive(Clone, Copy, Debug)]
+pub struct Squares(pub u64);
+
+impl Iterator for Squares {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            return None;
+        }
+        let sq = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(sq)
+    }
+}
+
+/// the board representation
+// #rust The fields are laid out in this order (hence repr(C), since Rust is
+// otherwise free to rearrange them) so that what makemove(), gen(), and eval()
+// touch at every node, the board and the state that goes with it, comes first
+// and fits in a few cache lines, ahead of the game history and the hash
+// tables. For the same reason the board is stored as i8s rather than Ints;
+// color() and piece() read a square back as an Int.
+
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "PositionState", try_from = "PositionState")
+)]
+#[repr(C)]
+pub struct Position {
     /// LIGHT, DARK, or EMPTY
-    pub color: [Int; 64],
+    pub color: [i8; 64],
 
     /// PAWN, KNIGHT, BISHOP, ROOK, QUEEN, KING, or EMPTY
-    pub piece: [Int; 64],
+    pub piece: [i8; 64],
 
     /// the side to move
     pub side: Int,
@@ -42,49 +99,16 @@ pub struct Data {
     /// a (more or less) unique number that corresponds to the position
     pub hash: Int,
 
-    /// the number of half-moves (ply) since the root of the search tree
-    pub ply: usize,
+    /// the square each side's king is on, so in_check() doesn't have to search
+    /// the board for it
+    pub king_sq: [usize; 2],
 
-    /// h for history; the number of ply since the beginning of the game
-    pub hply: usize,
-
-    /// GEN_DAT is some memory for move lists that are created by the move
-    /// generators. The move list for ply n starts at FIRST_MOVE[n] and ends at
-    /// FIRST_MOVE[n + 1].
-    pub gen_dat: [Gen; GEN_STACK],
-    pub first_move: [usize; MAX_PLY],
-
-    /// the history heuristic array (used for move ordering)
-    pub history: [[Int; 64]; 64],
-
-    /// we need an array of hist_t's so we can take back the moves we make
-    pub hist_dat: [Hist; HIST_STACK],
-    /// the engine will search for max_time milliseconds or until it finishes
-    /// searching max_depth ply.
-    pub max_time: Int,
-    pub max_depth: Int,
-
-    /// the time when the engine starts searching, and when it should stop
-    pub start_time: u128,
-    pub stop_time: u128,
-
-    /// the number of nodes we've searched
-    pub nodes: Int,
-
-    /// a "triangular" PV array; for a good explanation of why a triangular
-    /// array is needed, see "How Computers Play Chess" by Levy and Newborn.
-    pub pv: [[Move; MAX_PLY]; MAX_PLY],
-    pub pv_length: [usize; MAX_PLY],
-    pub follow_pv: bool,
-
-    /// random numbers used to compute hash; see set_hash() in board.rs.
-    /// indexed by piece [color][type][square]
-    pub hash_piece: [[[Int; 64]; 6]; 2],
-    pub hash_side: Int,
-    pub hash_ep: [Int; 64],
-
-    /// opening book
-    pub book_lines: Vec<String>,
+    /// each side's piece list: the squares its pieces are on, so gen() and
+    /// eval() don't have to look at every square of the board. (It's kept
+    /// as a set, with bit n for square n, rather than as an array, so the
+    /// squares come out in order, and the moves are generated in the same
+    /// order as the C version's scan of the board finds them.)
+    pub pieces: [u64; 2],
 
     /// pawn_rank[x][y] is the rank of the least advanced pawn of color x on
     /// file y - 1. There are "buffer files" on the left and right to avoid
@@ -94,18 +118,53 @@ pub struct Data {
     /// evaluation code.
     pub pawn_rank: [[Int; 10]; 2],
 
+    /// the squares of each side's pawns, as a set like pieces, from which
+    /// pawn_rank is worked out
+    pub pawns: [u64; 2],
+
     /// the value of a side's pieces
     pub piece_mat: [Int; 2],
 
     /// the value of a side's pawns
     pub pawn_mat: [Int; 2],
+
+    /// the sum of the piece/square values of a side's pawns, knights, and
+    /// bishops. (The king's piece/square value depends on the game phase, so
+    /// it's still looked up by eval().)
+    ///
+    /// #rust piece_mat, pawn_mat, and pcsq are computed by set_material() when
+    /// the board is set up, and then updated by makemove() and takeback() as
+    /// pieces are added to and removed from the board.
+    pub pcsq: [Int; 2],
+
+    /// h for history; the number of ply since the beginning of the game
+    pub hply: usize,
+
+    /// the number of ply that were played before the game's first position,
+    /// which isn't zero if the game started from a FEN position; used for the
+    /// move number
+    pub start_ply: usize,
+
+    /// we need an array of hist_t's so we can take back the moves we make
+    // #rust This is a Vec rather than a fixed-size array, so there's no limit
+    // on the length of a game.
+    pub hist_dat: Vec<Hist>,
+
+    /// random numbers used to compute hash; see set_hash() in board.rs.
+    /// indexed by piece [color][type][square]
+    pub hash_piece: [[[Int; 64]; 6]; 2],
+    pub hash_side: Int,
+    pub hash_ep: [Int; 64],
+    /// indexed by castle permissions; these aren't part of the hash, only of
+    /// the transposition table's keys (see tt_key() in search.rs)
+    pub hash_castle: [Int; 16],
 }
 
-impl Data {
-    /// create a new instance of Data
+impl Position {
+    /// create a new instance of Position
 
-    pub fn new() -> Data {
-        Data {
+    pub fn new() -> Position {
+        let mut pos = Position {
             color: INIT_COLOR,
             piece: INIT_PIECE,
             side: LIGHT,
@@ -114,27 +173,354 @@ impl Data {
             ep: -1,
             fifty: 0,
             hash: 0,
-            ply: 0,
+            king_sq: [E1, E8],
+            pieces: [0; 2],
+            pawn_rank: [[0; 10]; 2],
+            pawns: [0; 2],
+            piece_mat: [0; 2],
+            pawn_mat: [0; 2],
+            pcsq: [0; 2],
             hply: 0,
-            gen_dat: [Gen::default(); GEN_STACK],
-            first_move: [0; MAX_PLY],
-            history: [[0; 64]; 64],
-            hist_dat: [Hist::default(); HIST_STACK],
-            max_time: 0,
-            max_depth: 0,
+            start_ply: 0,
+            hist_dat: Vec::new(),
+            hash_piece: [[[0; 64]; 6]; 2],
+            hash_side: 0,
+            hash_ep: [0; 64],
+            hash_castle: [0; 16],
+        };
+        // the hash keys are all 0 until init_hash() is called, so the hash
+        // is, too
+        board::set_incremental(&mut pos);
+        pos
+    }
+
+    /// color() returns the color of the piece on square sq: LIGHT, DARK, or
+    /// EMPTY
+    #[inline(always)]
+    pub fn color(&self, sq: usize) -> Int {
+        self.color[sq] as Int
+    }
+
+    /// piece() returns the kind of piece on square sq, or EMPTY
+    #[inline(always)]
+    pub fn piece(&self, sq: usize) -> Int {
+        self.piece[sq] as Int
+    }
+
+    /// history() returns the moves that have been made since the game (or the
+    /// position set up by set_fen()) started, oldest first
+
+    pub fn history(&self) -> &[HistoryEntry] {
+        &self.hist_dat[..self.hply]
+    }
+
+    /// side_to_move() returns the side whose turn it is
+    pub fn side_to_move(&self) -> Color {
+        Color::from_index(self.side).expect("bad side")
+    }
+
+    /// piece_at() returns the color and kind of the piece on square sq, or None
+    /// if the square is empty
+    pub fn piece_at(&self, sq: Square) -> Option<(Color, Piece)> {
+        let i = sq.index();
+        Some((
+            Color::from_index(self.color(i))?,
+            Piece::from_index(self.piece(i))?,
+        ))
+    }
+
+    /// king_square() returns the square side s's king is on
+    pub fn king_square(&self, s: Color) -> Square {
+        Square::from_index(self.king_sq[s.index()]).expect("bad king square")
+    }
+
+    /// en_passant() returns the square a pawn can capture en passant to, or
+    /// None if there isn't one
+    pub fn en_passant(&self) -> Option<Square> {
+        if self.ep == -1 {
+            None
+        } else {
+            Square::from_index(self.ep as usize)
+        }
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// #rust With the "serde" feature, a Position can be serialized, e.g., to save a
+// game and pick it up later. Only the state of the game is written out: the
+// FEN of the position the game started from, and the moves made since. The
+// game is replayed with try_move() when it's read back in, so a history that
+// doesn't add up (which takeback() would trust) is rejected, and the rest (the
+// hash, the material counts, etc.) is recomputed. Note that that means calling
+// init_hash(), which reseeds rand().
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct PositionState {
+    start: String,
+    moves: Vec<Move>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Position> for PositionState {
+    fn from(pos: Position) -> Self {
+        let moves = pos.hist_dat[..pos.hply].iter().map(|h| h.m).collect();
+        let mut start = pos;
+        while start.hply > 0 {
+            board::takeback(&mut start);
+        }
+        PositionState {
+            start: board::fen(&start),
+            moves,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<PositionState> for Position {
+    type Error = String;
+
+    fn try_from(state: PositionState) -> Result<Self, Self::Error> {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::set_fen(&mut d, &state.start).map_err(|e| e.to_string())?;
+        // the move isn't printed: it might not even have squares on the board
+        for (i, m) in state.moves.iter().enumerate() {
+            board::try_move(&mut d, m.bytes())
+                .map_err(|_| format!("illegal move {} in the history", i + 1))?;
+        }
+        Ok(d.pos)
+    }
+}
+
+/// the number of entries in SearchState.cont_history: for each piece and to
+/// square of the previous move, one for each piece and to square
+pub const CONT_HISTORY_LEN: usize = 6 * 64 * 6 * 64;
+
+/// the search's scratch space
+
+pub struct SearchState {
+    /// the number of half-moves (ply) since the root of the search tree
+    pub ply: usize,
+
+    /// GEN_DAT holds the move lists that are created by the move generators.
+    /// The move list for ply n is GEN_DAT[n].
+    // #rust The C version keeps all the move lists in one fixed-size array,
+    // with FIRST_MOVE[n] marking where ply n's list starts, and nothing stops
+    // a dense position from writing past the end of it. A Vec for each ply
+    // grows as needed, and clearing it keeps its capacity, so after the first
+    // few searches the move generators don't allocate.
+    pub gen_dat: Vec<Vec<Gen>>,
+
+    /// the history heuristic array (used for move ordering)
+    // #rust An i32, like the C version's int, rather than an Int: that halves
+    // the table, which is read for every quiet move gen() generates.
+    //
+    // #rust The C version has one table for both sides, indexed by from and
+    // to, so one side's good moves are also ordered high for the other.
+    // There's a table for each side here.
+    pub history: [[[i32; 64]; 64]; 2],
+
+    /// the continuation history: like history, but for a move made right
+    /// after another one, indexed by the piece the previous move moved and
+    /// its to square, then the piece and to square of the move (see
+    /// board::cont_base())
+    // #rust It's a Vec, since at over half a megabyte it's too big to keep
+    // in the struct (which is often on the stack).
+    pub cont_history: Vec<i32>,
+
+    /// where the continuation history of gen()'s moves starts in
+    /// cont_history, i.e., board::cont_base() for the position, which gen()
+    /// works out before it generates any moves
+    pub cont: Option<usize>,
+
+    /// the number of milliseconds think() can search for, and the number of
+    /// nodes, as worked out from its SearchLimits (None if there's no limit)
+    pub move_time: Option<u128>,
+    pub max_nodes: Option<Int>,
+
+    /// clock() returns the current time in milliseconds; think() uses it to
+    /// keep track of its time. It's util::get_ms() by default, but without the
+    /// "std" feature there's no system clock, so it always returns 0 and the
+    /// search is only limited by depth and nodes (and stop), unless the
+    /// caller supplies a clock of its own.
+    pub clock: fn() -> u128,
+
+    /// the time when the engine starts searching, and when it should stop
+    pub start_time: u128,
+    pub stop_time: u128,
+
+    /// the number of nodes we've searched
+    pub nodes: Int,
+
+    /// the number of those nodes that were in the quiescence search
+    pub qnodes: Int,
+
+    /// the deepest ply the search has reached
+    pub seldepth: usize,
+
+    /// set (possibly from another thread or a signal handler) to make the
+    /// search stop as if its time were up. think() doesn't clear it, so that a
+    /// request to stop that comes in just before the search starts isn't lost;
+    /// it's up to the caller to clear it before starting a search.
+    pub stop: Arc<AtomicBool>,
+
+    /// set while the engine is thinking on the opponent's time; the search
+    /// doesn't run out of time until it's cleared
+    pub pondering: Arc<AtomicBool>,
+
+    /// the transposition table, if the search has one. It can be shared with
+    /// other searches. (The console doesn't use one, so its search is the same
+    /// as the C version's.)
+    pub tt: Option<Arc<TranspositionTable>>,
+
+    /// the counts of the current search's probes and stores of tt, which
+    /// think() adds to the table's totals when it's done
+    pub tt_stats: TtStats,
+
+    /// the skill level, from search::MIN_SKILL to search::MAX_SKILL (full
+    /// strength, the default), and the seed for the current search's
+    /// evaluation noise at the lower levels
+    pub skill: Int,
+    pub noise_seed: u64,
+
+    /// how think() picks among the root moves to play more like a person, or
+    /// None to play the best move it finds (the default)
+    pub humanlike: Option<Humanlike>,
+
+    /// the opening variety setting, or None to play the best move (or the
+    /// humanlike one) in the opening too (the default)
+    pub variety: Option<Variety>,
+
+    /// the evaluation's penalties and bonuses and the search's constants
+    /// (Params::DEFAULT unless they're being tuned or tested)
+    pub params: Params,
+
+    /// set to have think() explain its decisions to its observer (see
+    /// SearchObserver::debug()): the book's move, the time it allows itself,
+    /// and why it stopped
+    pub debug: bool,
+
+    /// a "triangular" PV array; for a good explanation of why a triangular
+    /// array is needed, see "How Computers Play Chess" by Levy and Newborn.
+    pub pv: [[Move; MAX_PLY]; MAX_PLY],
+    pub pv_length: [usize; MAX_PLY],
+    pub follow_pv: bool,
+}
+
+impl SearchState {
+    /// create a new instance of SearchState
+
+    pub fn new() -> SearchState {
+        SearchState {
+            ply: 0,
+            gen_dat: vec![Vec::new(); MAX_PLY],
+            history: [[[0; 64]; 64]; 2],
+            cont_history: vec![0; CONT_HISTORY_LEN],
+            cont: None,
+            move_time: None,
+            max_nodes: None,
+            clock: default_clock,
             start_time: 0,
             stop_time: 0,
             nodes: 0,
+            qnodes: 0,
+            seldepth: 0,
+            stop: Arc::new(AtomicBool::new(false)),
+            pondering: Arc::new(AtomicBool::new(false)),
+            tt: None,
+            tt_stats: TtStats::default(),
+            skill: MAX_SKILL,
+            noise_seed: 0,
+            humanlike: None,
+            variety: None,
+            params: Params::DEFAULT,
+            debug: false,
             pv: [[Move::default(); MAX_PLY]; MAX_PLY],
             pv_length: [0; MAX_PLY],
             follow_pv: false,
-            hash_piece: [[[0; 64]; 6]; 2],
-            hash_side: 0,
-            hash_ep: [0; 64],
-            book_lines: Vec::new(),
-            pawn_rank: [[0; 10]; 2],
-            piece_mat: [0; 2],
-            pawn_mat: [0; 2],
+        }
+    }
+
+    /// clear() forgets everything the previous searches left behind: the
+    /// history heuristic, the PV, the node counts, and the time and node
+    /// limits. (It keeps the stop and pondering flags, the clock, the
+    /// transposition table, the skill level, the humanlike and variety
+    /// settings, and the debug flag, which belong to whoever set them up.)
+
+    pub fn clear(&mut self) {
+        self.ply = 0;
+        for moves in self.gen_dat.iter_mut() {
+            moves.clear();
+        }
+        self.history = [[[0; 64]; 64]; 2];
+        self.cont_history.fill(0);
+        self.cont = None;
+        self.move_time = None;
+        self.max_nodes = None;
+        self.start_time = 0;
+        self.stop_time = 0;
+        self.nodes = 0;
+        self.qnodes = 0;
+        self.seldepth = 0;
+        self.pv = [[Move::default(); MAX_PLY]; MAX_PLY];
+        self.pv_length = [0; MAX_PLY];
+        self.follow_pv = false;
+    }
+}
+
+#[cfg(feature = "std")]
+fn default_clock() -> u128 {
+    super::util::get_ms()
+}
+
+#[cfg(not(feature = "std"))]
+fn default_clock() -> u128 {
+    0
+}
+
+impl Default for SearchState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// everything the engine needs to play a game: the position, the search's
+/// scratch space, and the opening book
+
+pub struct Data {
+    /// the board
+    pub pos: Position,
+
+    /// the search's scratch space
+    pub search: SearchState,
+
+    /// opening books, in the order book_move() looks in them (which can be
+    /// shared by the sessions of an Engine)
+    pub books: Vec<Arc<Book>>,
+
+    /// how far into the game the book is used
+    pub book_depth: BookDepth,
+
+    /// how book moves are chosen
+    pub book_selection: BookSelection,
+}
+
+impl Data {
+    /// create a new instance of Data
+
+    pub fn new() -> Data {
+        Data {
+            pos: Position::new(),
+            search: SearchState::new(),
+            books: Vec::new(),
+            book_depth: BookDepth::default(),
+            book_selection: BookSelection::default(),
         }
     }
 }
@@ -153,7 +539,7 @@ impl Default for Data {
 /// doing a lot of annoying work. Sooooo, what we do is figure out a4's mailbox
 /// number, which is 61. Then we subtract 1 from 61 (60) and see what mailbox[60]
 /// is. In this case, it's -1, so it's out of bounds and we can forget it. You
-/// can see how mailbox[] is used in attack() in board.rs.
+/// can see how mailbox[] is used in gen() in board.rs.
 
 #[rustfmt::skip]
 pub const MAILBOX: [Int; 120] = [
@@ -202,6 +588,67 @@ pub const OFFSET: [[Int; 8]; 6] = [
     [-11, -10, -9, -1, 1, 9, 10, 11],
 ];
 
+// #rust The C version's attack() looks at every square for a piece of the
+// attacking side, and walks its offsets through the mailbox to see if it gets
+// to the square. attack() is called at every node (to see if the side that
+// just moved left its king in check), so this version works the other way,
+// outward from the square, with tables worked out (at compile time) from the
+// same mailbox: the squares a knight could attack it from, and the squares
+// in each direction a sliding piece (or a king, one square away) could.
+
+/// the mailbox offsets of the directions in ATTACKS.rays: a bishop's, and
+/// then a rook's
+pub const RAY_OFFSET: [Int; 8] = [-11, -9, 9, 11, -10, -1, 1, 10];
+
+/// the number of directions in ATTACKS.rays that are diagonal
+pub const DIAGONALS: usize = 4;
+
+/// the tables that attack() in board.rs uses
+pub struct AttackTables {
+    /// the squares in each of the RAY_OFFSET directions from each square,
+    /// nearest first, up to the edge of the board, and how many there are
+    pub rays: [[[u8; 7]; 8]; 64],
+    pub ray_len: [[u8; 8]; 64],
+    /// the squares a knight's move away from each square, and how many there
+    /// are
+    pub knight: [[u8; 8]; 64],
+    pub knight_len: [u8; 64],
+}
+
+impl AttackTables {
+    const fn new() -> AttackTables {
+        let mut tables = AttackTables {
+            rays: [[[0; 7]; 8]; 64],
+            ray_len: [[0; 8]; 64],
+            knight: [[0; 8]; 64],
+            knight_len: [0; 64],
+        };
+        let mut sq = 0;
+        while sq < 64 {
+            let mut dir = 0;
+            while dir < 8 {
+                let mut n = MAILBOX[(MAILBOX64[sq] + RAY_OFFSET[dir]) as usize];
+                while n != -1 {
+                    let len = tables.ray_len[sq][dir] as usize;
+                    tables.rays[sq][dir][len] = n as u8;
+                    tables.ray_len[sq][dir] += 1;
+                    n = MAILBOX[(MAILBOX64[n as usize] + RAY_OFFSET[dir]) as usize];
+                }
+                let n = MAILBOX[(MAILBOX64[sq] + OFFSET[KNIGHT as usize][dir]) as usize];
+                if n != -1 {
+                    tables.knight[sq][tables.knight_len[sq] as usize] = n as u8;
+                    tables.knight_len[sq] += 1;
+                }
+                dir += 1;
+            }
+            sq += 1;
+        }
+        tables
+    }
+}
+
+pub static ATTACKS: AttackTables = AttackTables::new();
+
 /// This is the CASTLE_MASK array. We can use it to determine the castling
 /// permissions after a move. What we do is logical-AND the CASTLE bits with the
 /// CASTLE_MASK bits for both of the move's squares. Let's say CASTLE is 1,
@@ -224,10 +671,20 @@ pub const CASTLE_MASK: [Int; 64] = [
 /// the piece letters, for print_board()
 pub const PIECE_CHAR: [char; 6] = ['P', 'N', 'B', 'R', 'Q', 'K'];
 
+/// the Unicode chess symbols for each side's pieces, for print_board()
+pub const PIECE_GLYPH: [[char; 6]; 2] = [
+    [
+        '\u{2659}', '\u{2658}', '\u{2657}', '\u{2656}', '\u{2655}', '\u{2654}',
+    ],
+    [
+        '\u{265f}', '\u{265e}', '\u{265d}', '\u{265c}', '\u{265b}', '\u{265a}',
+    ],
+];
+
 // the initial board state
 
 #[rustfmt::skip]
-pub const INIT_COLOR: [Int; 64] = [
+pub const INIT_COLOR: [i8; 64] = [
     1, 1, 1, 1, 1, 1, 1, 1,
     1, 1, 1, 1, 1, 1, 1, 1,
     6, 6, 6, 6, 6, 6, 6, 6,
@@ -239,7 +696,7 @@ pub const INIT_COLOR: [Int; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const INIT_PIECE: [Int; 64] = [
+pub const INIT_PIECE: [i8; 64] = [
     3, 1, 2, 4, 5, 2, 1, 3,
     0, 0, 0, 0, 0, 0, 0, 0,
     6, 6, 6, 6, 6, 6, 6, 6,
@@ -249,3 +706,83 @@ pub const INIT_PIECE: [Int; 64] = [
     0, 0, 0, 0, 0, 0, 0, 0,
     3, 1, 2, 4, 5, 2, 1, 3
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "serde")]
+    use super::super::defs::MoveBytes;
+
+    #[test]
+    fn test_new() {
+        // the start position, with everything makemove() keeps up to date
+        let pos = Position::new();
+        board::check_incremental(&pos).unwrap();
+        assert_eq!(pos.piece_mat, [3100, 3100]);
+        assert_eq!(pos.hash, 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        let start_hash = d.pos.hash;
+        for &(from, to) in [(52, 36), (12, 28), (62, 45)].iter() {
+            let m = MoveBytes {
+                from,
+                to,
+                ..MoveBytes::default()
+            };
+            board::try_move(&mut d, m).unwrap();
+        }
+
+        let json = serde_json::to_string(&d.pos).unwrap();
+        let mut pos: Position = serde_json::from_str(&json).unwrap();
+        assert_eq!(board::fen(&pos), board::fen(&d.pos));
+        assert_eq!(pos.hash, d.pos.hash);
+        assert_eq!(pos.hply, 3);
+        while pos.hply > 0 {
+            board::takeback(&mut pos);
+        }
+        assert_eq!(pos.hash, start_hash);
+
+        let bad = json.replace(" w KQkq", " x KQkq");
+        assert!(serde_json::from_str::<Position>(&bad).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_bad_history() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        for &(from, to) in [(52, 36), (12, 28)].iter() {
+            let m = MoveBytes {
+                from,
+                to,
+                ..MoveBytes::default()
+            };
+            board::try_move(&mut d, m).unwrap();
+        }
+        let json = serde_json::to_string(&d.pos).unwrap();
+
+        // e7e5 replaced by a move Black can't make, a white move, and nonsense
+        let m = "{\"from\":12,\"to\":28";
+        assert!(json.contains(m));
+        for bad in [
+            "{\"from\":12,\"to\":44",
+            "{\"from\":51,\"to\":35",
+            "{\"from\":99,\"to\":35",
+        ]
+        .iter()
+        {
+            match serde_json::from_str::<Position>(&json.replace(m, bad)) {
+                Err(err) => assert!(err.to_string().contains("illegal move 2")),
+                Ok(_) => panic!("accepted a bad history"),
+            }
+        }
+    }
+}
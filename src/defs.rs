@@ -5,6 +5,14 @@
 //
 // Rust port by Kristopher Johnson
 
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
 // #rust The original C program uses the `int` type extensively.  We'll use a
 // type definition here so we can experiment with performance with different
 // integer sizes, but generally `isize` should be best because the C code often
@@ -14,44 +22,7 @@
 // used as array indexes, or as `Int` otherwise.
 pub type Int = isize;
 
-pub const GEN_STACK: usize = 1120;
 pub const MAX_PLY: usize = 32;
-pub const HIST_STACK: usize = 400;
-
-pub const LIGHT: Int = 0;
-pub const DARK: Int = 1;
-
-pub const ILIGHT: usize = LIGHT as usize;
-pub const IDARK: usize = DARK as usize;
-
-pub const PAWN: Int = 0;
-pub const KNIGHT: Int = 1;
-pub const BISHOP: Int = 2;
-pub const ROOK: Int = 3;
-pub const QUEEN: Int = 4;
-pub const KING: Int = 5;
-
-pub const IPAWN: usize = PAWN as usize;
-
-pub const EMPTY: Int = 6;
-
-// useful squares
-pub const A1: usize = 56;
-pub const B1: usize = 57;
-pub const C1: usize = 58;
-pub const D1: usize = 59;
-pub const E1: usize = 60;
-pub const F1: usize = 61;
-pub const G1: usize = 62;
-pub const H1: usize = 63;
-pub const A8: usize = 0;
-pub const B8: usize = 1;
-pub const C8: usize = 2;
-pub const D8: usize = 3;
-pub const E8: usize = 4;
-pub const F8: usize = 5;
-pub const G8: usize = 6;
-pub const H8: usize = 7;
 
 /// Get the row number for a square
 macro_rules! row {
@@ -67,6 +38,198 @@ macro_rules! col {
     };
 }
 
+/// the two sides
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Color {
+    Light,
+    Dark,
+}
+
+impl Color {
+    /// from_index() returns the Color for a LIGHT or DARK value, or None if i
+    /// is neither
+    pub fn from_index(i: Int) -> Option<Color> {
+        match i {
+            LIGHT => Some(Color::Light),
+            DARK => Some(Color::Dark),
+            _ => None,
+        }
+    }
+
+    /// index() returns the LIGHT or DARK value used in the board arrays
+    #[inline(always)]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// opposite() returns the other side
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::Light => Color::Dark,
+            Color::Dark => Color::Light,
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Color::Light => write!(f, "White"),
+            Color::Dark => write!(f, "Black"),
+        }
+    }
+}
+
+/// the kinds of pieces
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Piece {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl Piece {
+    /// from_index() returns the Piece for a PAWN..KING value, or None if i is
+    /// out of range (e.g., EMPTY)
+    pub fn from_index(i: Int) -> Option<Piece> {
+        match i {
+            PAWN => Some(Piece::Pawn),
+            KNIGHT => Some(Piece::Knight),
+            BISHOP => Some(Piece::Bishop),
+            ROOK => Some(Piece::Rook),
+            QUEEN => Some(Piece::Queen),
+            KING => Some(Piece::King),
+            _ => None,
+        }
+    }
+
+    /// index() returns the PAWN..KING value used in the board arrays
+    #[inline(always)]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// the squares of the board, in the same order as the board arrays (A8 is 0
+/// and H1 is 63)
+#[rustfmt::skip]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Square {
+    A8, B8, C8, D8, E8, F8, G8, H8,
+    A7, B7, C7, D7, E7, F7, G7, H7,
+    A6, B6, C6, D6, E6, F6, G6, H6,
+    A5, B5, C5, D5, E5, F5, G5, H5,
+    A4, B4, C4, D4, E4, F4, G4, H4,
+    A3, B3, C3, D3, E3, F3, G3, H3,
+    A2, B2, C2, D2, E2, F2, G2, H2,
+    A1, B1, C1, D1, E1, F1, G1, H1,
+}
+
+#[rustfmt::skip]
+const SQUARES: [Square; 64] = [
+    Square::A8, Square::B8, Square::C8, Square::D8, Square::E8, Square::F8, Square::G8, Square::H8,
+    Square::A7, Square::B7, Square::C7, Square::D7, Square::E7, Square::F7, Square::G7, Square::H7,
+    Square::A6, Square::B6, Square::C6, Square::D6, Square::E6, Square::F6, Square::G6, Square::H6,
+    Square::A5, Square::B5, Square::C5, Square::D5, Square::E5, Square::F5, Square::G5, Square::H5,
+    Square::A4, Square::B4, Square::C4, Square::D4, Square::E4, Square::F4, Square::G4, Square::H4,
+    Square::A3, Square::B3, Square::C3, Square::D3, Square::E3, Square::F3, Square::G3, Square::H3,
+    Square::A2, Square::B2, Square::C2, Square::D2, Square::E2, Square::F2, Square::G2, Square::H2,
+    Square::A1, Square::B1, Square::C1, Square::D1, Square::E1, Square::F1, Square::G1, Square::H1,
+];
+
+impl Square {
+    /// from_index() returns the Square for a board array index, or None if i
+    /// isn't on the board
+    #[inline(always)]
+    pub fn from_index(i: usize) -> Option<Square> {
+        SQUARES.get(i).copied()
+    }
+
+    /// index() returns the square's index in the board arrays
+    #[inline(always)]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// file() returns the square's file, 0 (the a-file) to 7 (the h-file)
+    pub fn file(self) -> usize {
+        col!(self.index())
+    }
+
+    /// rank() returns the square's rank, 0 (the first rank) to 7 (the eighth
+    /// rank)
+    pub fn rank(self) -> usize {
+        7 - row!(self.index())
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            (b'a' + self.file() as u8) as char,
+            self.rank() + 1
+        )
+    }
+}
+
+impl FromStr for Square {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Square, ()> {
+        match s.as_bytes() {
+            [f @ b'a'..=b'h', r @ b'1'..=b'8'] => {
+                Ok(SQUARES[(f - b'a') as usize + 8 * (b'8' - r) as usize])
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+// #rust The board arrays still hold the C program's Int values, which are
+// the enums' discriminants, so the hot loops don't pay for conversions.
+pub const LIGHT: Int = Color::Light as Int;
+pub const DARK: Int = Color::Dark as Int;
+
+pub const ILIGHT: usize = LIGHT as usize;
+pub const IDARK: usize = DARK as usize;
+
+pub const PAWN: Int = Piece::Pawn as Int;
+pub const KNIGHT: Int = Piece::Knight as Int;
+pub const BISHOP: Int = Piece::Bishop as Int;
+pub const ROOK: Int = Piece::Rook as Int;
+pub const QUEEN: Int = Piece::Queen as Int;
+pub const KING: Int = Piece::King as Int;
+
+pub const IPAWN: usize = PAWN as usize;
+
+pub const EMPTY: Int = 6;
+
+// useful squares
+pub const A1: usize = Square::A1 as usize;
+pub const B1: usize = Square::B1 as usize;
+pub const C1: usize = Square::C1 as usize;
+pub const D1: usize = Square::D1 as usize;
+pub const E1: usize = Square::E1 as usize;
+pub const F1: usize = Square::F1 as usize;
+pub const G1: usize = Square::G1 as usize;
+pub const H1: usize = Square::H1 as usize;
+pub const A8: usize = Square::A8 as usize;
+pub const B8: usize = Square::B8 as usize;
+pub const C8: usize = Square::C8 as usize;
+pub const D8: usize = Square::D8 as usize;
+pub const E8: usize = Square::E8 as usize;
+pub const F8: usize = Square::F8 as usize;
+pub const G8: usize = Square::G8 as usize;
+pub const H8: usize = Square::H8 as usize;
+
 /// This is the basic description of a move. promote is what
 /// piece to promote the pawn to, if the move is a pawn
 /// promotion. bits is a bitfield that describes the move,
@@ -79,10 +242,11 @@ macro_rules! col {
 /// - 16 pawn move
 /// - 32 promote
 ///
-/// It's union'ed with an integer so two moves can easily
-/// be compared with each other.
+/// It's packed into a Move so two moves can easily be
+/// compared with each other.
 
 #[derive(Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MoveBytes {
     pub from: u8,
     pub to: u8,
@@ -90,64 +254,379 @@ pub struct MoveBytes {
     pub bits: u8,
 }
 
-// #rust TODO The use of a union for move representation requires a lot of code
-// to be in "unsafe" blocks.  We provide safe functions to avoid the need for
-// that.
+impl MoveBytes {
+    /// from_sq() returns the square the piece moves from
+    #[inline(always)]
+    pub fn from_sq(self) -> Square {
+        SQUARES[self.from as usize]
+    }
+
+    /// to_sq() returns the square the piece moves to
+    #[inline(always)]
+    pub fn to_sq(self) -> Square {
+        SQUARES[self.to as usize]
+    }
+
+    /// promotion() returns the piece a pawn is promoted to, or None if the
+    /// move isn't a promotion
+    #[inline(always)]
+    pub fn promotion(self) -> Option<Piece> {
+        if self.is_promotion() {
+            Piece::from_index(self.promote as Int)
+        } else {
+            None
+        }
+    }
 
-#[derive(Copy, Clone)]
-pub union Move {
-    pub b: MoveBytes,
-    pub u: Int,
+    /// is_capture() returns true if the move captures a piece (including en
+    /// passant)
+    #[inline(always)]
+    pub fn is_capture(self) -> bool {
+        (self.bits & 1) != 0
+    }
+
+    /// is_castle() returns true if the move is a king's castling move
+    #[inline(always)]
+    pub fn is_castle(self) -> bool {
+        (self.bits & 2) != 0
+    }
+
+    /// is_en_passant() returns true if the move is an en passant capture
+    #[inline(always)]
+    pub fn is_en_passant(self) -> bool {
+        (self.bits & 4) != 0
+    }
+
+    /// is_double_push() returns true if the move pushes a pawn 2 squares
+    #[inline(always)]
+    pub fn is_double_push(self) -> bool {
+        (self.bits & 8) != 0
+    }
+
+    /// is_pawn_move() returns true if the move is made by a pawn
+    #[inline(always)]
+    pub fn is_pawn_move(self) -> bool {
+        (self.bits & 16) != 0
+    }
+
+    /// is_promotion() returns true if the move promotes a pawn
+    #[inline(always)]
+    pub fn is_promotion(self) -> bool {
+        (self.bits & 32) != 0
+    }
 }
 
+/// A move is displayed in coordinate notation, e.g. "e2e4" or "e7e8q".
+
+impl fmt::Display for MoveBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.from_sq(), self.to_sq())?;
+        match self.promotion() {
+            Some(Piece::Knight) => write!(f, "n"),
+            Some(Piece::Bishop) => write!(f, "b"),
+            Some(Piece::Rook) => write!(f, "r"),
+            Some(_) => write!(f, "q"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A move is parsed from coordinate notation. Only the squares and the
+/// promotion piece are filled in; the bits are up to the move generator (see
+/// board::try_move()).
+
+impl FromStr for MoveBytes {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<MoveBytes, ()> {
+        if !s.is_ascii() || s.len() < 4 || s.len() > 5 {
+            return Err(());
+        }
+        let from: Square = s[0..2].parse()?;
+        let to: Square = s[2..4].parse()?;
+        let promote = match s.as_bytes().get(4) {
+            None => 0,
+            Some(b'n') | Some(b'N') => KNIGHT,
+            Some(b'b') | Some(b'B') => BISHOP,
+            Some(b'r') | Some(b'R') => ROOK,
+            Some(b'q') | Some(b'Q') => QUEEN,
+            Some(_) => return Err(()),
+        };
+        Ok(MoveBytes {
+            from: from as u8,
+            to: to as u8,
+            promote: promote as u8,
+            bits: 0,
+        })
+    }
+}
+
+// #rust The C code unions MoveBytes with an int so two moves can be compared
+// with each other. Here Move packs the four bytes into a u32 instead (from in
+// the low byte, bits in the high byte), which can be compared without any
+// "unsafe" code.
+
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Move(u32);
+
 impl Move {
-    /// safely extract MoveBytes variant from a Move
+    /// extract the MoveBytes from a Move
     #[inline(always)]
     pub fn bytes(self: Move) -> MoveBytes {
-        unsafe { self.b }
+        MoveBytes {
+            from: self.0 as u8,
+            to: (self.0 >> 8) as u8,
+            promote: (self.0 >> 16) as u8,
+            bits: (self.0 >> 24) as u8,
+        }
     }
 
-    /// safely set MoveBytes variant in a Move
+    /// set the MoveBytes of a Move
     #[inline(always)]
     pub fn set_bytes(self: &mut Move, m: MoveBytes) {
-        self.b = m
+        *self = Move::from(m);
     }
 
-    /// safely extract 32-bit value from a Move
+    /// extract the integer value of a Move
+    ///
+    /// #rust The value is sign-extended from 32 bits, so the book's -1 ("no
+    /// move") survives a round trip through set_value(). (bits is never more
+    /// than 63, so a real move is never negative.)
     #[inline(always)]
     pub fn value(self: Move) -> Int {
-        unsafe { self.u }
+        self.0 as i32 as Int
     }
 
-    /// safely set 32-bit value for a Move
+    /// set the integer value of a Move
     #[inline(always)]
     pub fn set_value(self: &mut Move, value: Int) {
-        self.u = value;
+        self.0 = value as u32;
     }
 }
 
-impl Default for Move {
-    fn default() -> Self {
-        Move { u: 0 }
+impl From<MoveBytes> for Move {
+    #[inline(always)]
+    fn from(m: MoveBytes) -> Self {
+        Move(m.from as u32 | (m.to as u32) << 8 | (m.promote as u32) << 16 | (m.bits as u32) << 24)
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.bytes().fmt(f)
+    }
+}
+
+// #rust A Move is serialized as its MoveBytes, which is easier to read (and
+// to edit by hand) than the packed value.
+
+#[cfg(feature = "serde")]
+impl Serialize for Move {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.bytes().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Move {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        MoveBytes::deserialize(deserializer).map(Move::from)
     }
 }
 
 /// an element of the move stack. it's just a move with a score, so it can be
 /// sorted by the search functions.
+// #rust The score is an i32, like the C version's int, so an entry is 8 bytes
+// (and 8-byte aligned, so it never straddles a cache line): sort() scans the
+// whole list for every move the search tries. The biggest score, a capture
+// that's the PV move and the table's move too, is about 16,000,000, and the
+// history values are i32s already.
 #[derive(Copy, Clone, Default)]
+#[repr(C, align(8))]
 pub struct Gen {
     pub m: Move,
-    pub score: Int,
+    pub score: i32,
 }
 
 /// an element of the history stack, with the information necessary to take a
 /// move back.
+// #rust capture, castle, and ep are i8s, which they all fit in, to keep the
+// entry (and hist_dat) small; the hash needs the whole Int.
 #[derive(Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Hist {
     pub m: Move,
-    pub capture: Int,
-    pub castle: Int,
-    pub ep: Int,
+    pub capture: i8,
+    pub castle: i8,
+    pub ep: i8,
     pub fifty: Int,
     pub hash: Int,
 }
+
+impl Hist {
+    /// captured() returns the piece the move captured, or None if it wasn't a
+    /// capture. (For an en passant capture, that's the pawn, even though it
+    /// wasn't on the move's to square.)
+    pub fn captured(&self) -> Option<Piece> {
+        if self.m.bytes().is_en_passant() {
+            Some(Piece::Pawn)
+        } else {
+            Piece::from_index(self.capture as Int)
+        }
+    }
+}
+
+/// the name for an element of the history stack in the public API, e.g.,
+/// Position::history()
+pub type HistoryEntry = Hist;
+
+/// the error returned by try_move() when it's asked to make an illegal move
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IllegalMove;
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "illegal move")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IllegalMove {}
+
+/// the error returned by parse_move()
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParseMoveError {
+    /// the string isn't a move in coordinate notation
+    Malformed,
+    /// the string is a move, but not a legal one
+    Illegal,
+    /// the move is a pawn promotion, but the string doesn't say what to
+    /// promote the pawn to
+    AmbiguousPromotion,
+}
+
+impl fmt::Display for ParseMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseMoveError::Malformed => write!(f, "not a move in coordinate notation"),
+            ParseMoveError::Illegal => write!(f, "illegal move"),
+            ParseMoveError::AmbiguousPromotion => {
+                write!(f, "promotion without a piece (e.g., e7e8q)")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseMoveError {}
+
+/// the state of the game, as returned by game_result()
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameResult {
+    /// the game isn't over yet
+    Ongoing,
+    /// the given side has checkmated the other side
+    Checkmate(Color),
+    Stalemate,
+    /// the position has occurred for the third time
+    DrawRepetition,
+    /// fifty moves have been played without a capture or pawn move
+    DrawFifty,
+    /// neither side has enough material left to checkmate
+    DrawMaterial,
+}
+
+/// a problem with the board representation, as found by validate()
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Inconsistency {
+    /// the square has a color but no piece (or vice versa), or a value that's
+    /// out of range
+    BadSquare(Square),
+    /// side and xside aren't LIGHT and DARK (or DARK and LIGHT)
+    BadSide,
+    /// the side has this many kings instead of one
+    KingCount(Color, usize),
+    /// king_sq doesn't hold the square that the side's king is on
+    KingSquare(Color),
+    /// pieces doesn't hold the squares of the side's pieces
+    PieceList(Color),
+    /// there's a pawn on the first or eighth rank
+    PawnOnBackRank(Square),
+    /// ep isn't the square behind a pawn that just moved two squares
+    BadEnPassant(Int),
+    /// the castle permission bit is set, but the king or rook has moved
+    BadCastle(Int),
+    /// the side that isn't to move is in check
+    XsideInCheck,
+    /// hash doesn't match the position
+    BadHash,
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Inconsistency::BadSquare(sq) => write!(f, "bad color or piece on square {}", sq),
+            Inconsistency::BadSide => write!(f, "bad side to move"),
+            Inconsistency::KingCount(s, n) => write!(f, "{} has {} kings", s, n),
+            Inconsistency::KingSquare(s) => write!(f, "wrong king square for {}", s),
+            Inconsistency::PieceList(s) => write!(f, "wrong piece list for {}", s),
+            Inconsistency::PawnOnBackRank(sq) => write!(f, "pawn on back rank square {}", sq),
+            Inconsistency::BadEnPassant(ep) => write!(f, "bad en passant square {}", ep),
+            Inconsistency::BadCastle(bit) => write!(f, "bad castle permission {}", bit),
+            Inconsistency::XsideInCheck => write!(f, "the side that isn't to move is in check"),
+            Inconsistency::BadHash => write!(f, "hash doesn't match the position"),
+        }
+    }
+}
+
+/// the error returned by set_fen()
+#[derive(Clone, Debug, PartialEq)]
+pub enum FenError {
+    /// the string isn't valid FEN; the message says what's wrong with it
+    Syntax(&'static str),
+    /// the string is valid FEN, but the position doesn't make sense
+    Invalid(Vec<Inconsistency>),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::Syntax(msg) => write!(f, "bad FEN: {}", msg),
+            FenError::Invalid(errors) => {
+                write!(f, "bad position:")?;
+                for (i, err) in errors.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { "" } else { "," }, err)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FenError {}
+
+/// the error returned by pgn::parse_pgn() and pgn::replay()
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnError {
+    /// the text isn't valid PGN; the message says what's wrong with it
+    Syntax(&'static str),
+    /// the game's FEN tag isn't a valid position
+    Fen(FenError),
+    /// the move (numbered, e.g., "12... Nf6") isn't legal in the game
+    IllegalMove(String),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Syntax(msg) => write!(f, "bad PGN: {}", msg),
+            PgnError::Fen(err) => write!(f, "bad FEN tag: {}", err),
+            PgnError::IllegalMove(m) => write!(f, "illegal move: {}", m),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PgnError {}
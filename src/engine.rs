@@ -0,0 +1,1615 @@
+// engine.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP is a program, not a library. Engine wraps the
+// engine's Data in a thread of its own, so a GUI or other embedding program
+// can ask it to think without blocking its own thread (or, if it does block,
+// can interrupt it from another thread with stop_thinking()), and so it can
+// think on the opponent's time.
+//
+// Pondering works like this: after the engine moves, start_ponder() makes the
+// move the engine expects the opponent to play and starts searching with no
+// time limit. If the opponent plays that move, ponder_hit() starts the clock
+// and waits for the search to finish, so the time spent pondering is free. If
+// the opponent plays something else, ponder_miss() stops the search and takes
+// the predicted move back.
+//
+// Frontends that want to know what the engine is doing without polling it can
+// subscribe() to its events; ones that would rather poll, e.g., to redraw a
+// progress display, can ask for its status() at any time, even while it's
+// searching.
+//
+// An Engine is configured with an EngineBuilder, which checks the whole
+// configuration before the engine's thread is started. An Engine can have a
+// transposition table of its own, or share one with other Engines.
+//
+// Each of the Session methods that waits for the engine's thread to answer
+// has a try_ version, which gives up after a timeout instead of waiting
+// forever, and returns an EngineError instead of panicking if the thread has
+// exited (e.g., because it panicked).
+//
+// The engine's thread doesn't print anything. Its diagnostics go to a log
+// function, if the engine's LogLevel lets them through; by default, it's Off.
+//
+// An Engine can play several games at once: each new_session() is a game with
+// a board and search state of its own, sharing the engine's thread, opening
+// books, and transposition table. The Engine's own methods work on its first
+// session. The sessions take turns on the thread, so while one of them is
+// thinking (or pondering), the others' commands wait.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::board;
+use super::book::{self, Book, BookDepth, BookSelection};
+use super::rand;
+use super::search;
+
+use super::data::Data;
+use super::defs::{Color, FenError, GameResult, IllegalMove, Int, Move, MoveBytes, MAX_PLY};
+use super::eval::{self, EvalTerms};
+use super::search::{SearchInfo, SearchLimits, SearchResult, MAX_SKILL, MIN_SKILL};
+use super::tt::TranspositionTable;
+use super::util;
+
+/// identifies one of an engine's sessions
+
+pub type SessionId = usize;
+
+/// the commands that Engine sends to its thread
+
+enum Command {
+    NewSession(SessionId, Box<Data>, SearchLimits, Subscribers),
+    EndSession(SessionId),
+    Session(SessionId, SessionCommand),
+    Quit,
+}
+
+/// the commands for one of the engine's sessions
+
+enum SessionCommand {
+    /// start a new game, and clear the transposition table if it's true
+    NewGame(bool),
+    MakeMove(MoveBytes, Sender<Result<(), IllegalMove>>),
+    Think(SearchLimits, Sender<SearchResult>),
+    Ponder(
+        MoveBytes,
+        Sender<Result<(), IllegalMove>>,
+        Sender<Option<Event>>,
+    ),
+    TakeBack,
+    UndoMoves(usize, Sender<Vec<MoveBytes>>),
+    HistorySan(Sender<Vec<String>>),
+    SetPosition(String, Sender<Result<(), FenError>>),
+    Fen(Sender<String>),
+    Evaluate(Sender<(Int, EvalTerms)>),
+    SetSkill(Int),
+    /// make the engine's thread panic, to test how that's handled
+    #[cfg(test)]
+    Panic,
+}
+
+impl fmt::Display for SessionCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionCommand::NewGame(false) => write!(f, "new game"),
+            SessionCommand::NewGame(true) => write!(f, "new game (clear hash)"),
+            SessionCommand::MakeMove(m, _) => write!(f, "make move {}", m),
+            SessionCommand::Think(limits, _) => write!(f, "think {:?}", limits),
+            SessionCommand::Ponder(m, _, _) => write!(f, "ponder on {}", m),
+            SessionCommand::TakeBack => write!(f, "take back"),
+            SessionCommand::UndoMoves(..) => write!(f, "undo moves"),
+            SessionCommand::HistorySan(_) => write!(f, "history"),
+            SessionCommand::SetPosition(fen, _) => write!(f, "set position {}", fen),
+            SessionCommand::Fen(_) => write!(f, "fen"),
+            SessionCommand::Evaluate(_) => write!(f, "evaluate"),
+            SessionCommand::SetSkill(skill) => write!(f, "skill {}", skill),
+            #[cfg(test)]
+            SessionCommand::Panic => write!(f, "panic"),
+        }
+    }
+}
+
+/// the error returned by the try_ methods when the engine's thread doesn't
+/// answer
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EngineError {
+    /// the thread didn't answer before the timeout. (It may still be working
+    /// on the command; if it's a search, stop_thinking() stops it.)
+    Timeout,
+    /// the thread panicked, with this message
+    Panicked(String),
+    /// the thread has exited, because the Engine has been dropped
+    Exited,
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::Timeout => write!(f, "the engine didn't answer in time"),
+            EngineError::Panicked(message) => write!(f, "the engine panicked: {}", message),
+            EngineError::Exited => write!(f, "the engine has exited"),
+        }
+    }
+}
+
+impl Error for EngineError {}
+
+/// how the engine's thread exited (None while it's running), shared with the
+/// sessions so they can tell why it stopped answering
+
+#[derive(Default)]
+struct ThreadExit {
+    error: Mutex<Option<EngineError>>,
+    exited: Condvar,
+}
+
+impl ThreadExit {
+    /// set() records how the thread exited
+    fn set(&self, error: EngineError) {
+        *self.error.lock().unwrap_or_else(|err| err.into_inner()) = Some(error);
+        self.exited.notify_all();
+    }
+
+    /// get() returns how the thread exited. A reply channel is dropped while
+    /// the thread is unwinding, just before it records the panic, so get()
+    /// waits a little while for that.
+    fn get(&self) -> EngineError {
+        let error = self.error.lock().unwrap_or_else(|err| err.into_inner());
+        let (error, _) = self
+            .exited
+            .wait_timeout_while(error, Duration::from_secs(1), |error| error.is_none())
+            .unwrap_or_else(|err| err.into_inner());
+        error.clone().unwrap_or(EngineError::Exited)
+    }
+}
+
+/// the levels of the engine's log messages, from least to most verbose. An
+/// engine logs the messages at its level and the levels before it.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// log nothing
+    Off,
+    Error,
+    Warn,
+    /// sessions starting and ending, and the moves the engine picks
+    Info,
+    /// every command the engine's thread carries out
+    Debug,
+}
+
+impl LogLevel {
+    fn from_index(i: usize) -> LogLevel {
+        match i {
+            1 => LogLevel::Error,
+            2 => LogLevel::Warn,
+            3 => LogLevel::Info,
+            4 => LogLevel::Debug,
+            _ => LogLevel::Off,
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        };
+        f.write_str(s)
+    }
+}
+
+/// the function that an engine's log messages are passed to
+
+pub type LogFn = Arc<dyn Fn(LogLevel, &str) + Send + Sync>;
+
+/// Logger passes the messages at its level (or less verbose) to its LogFn. It's
+/// shared by an Engine and its thread, so the level can be changed while the
+/// engine is running.
+
+#[derive(Clone)]
+struct Logger {
+    level: Arc<AtomicUsize>,
+    log: LogFn,
+}
+
+impl Logger {
+    fn level(&self) -> LogLevel {
+        LogLevel::from_index(self.level.load(Ordering::Relaxed))
+    }
+
+    fn log(&self, level: LogLevel, args: fmt::Arguments) {
+        if level != LogLevel::Off && level <= self.level() {
+            (self.log)(level, &args.to_string());
+        }
+    }
+}
+
+/// log_to_stderr() is the default LogFn
+
+fn log_to_stderr(level: LogLevel, message: &str) {
+    eprintln!("tscp {}: {}", level, message);
+}
+
+/// the events that Engine sends to its subscribers
+
+#[derive(Clone)]
+pub enum Event {
+    /// the search statistics after each iteration of a search
+    SearchInfo(SearchInfo),
+    /// the move that think() or ponder_hit() found by searching
+    BestMove(MoveBytes),
+    /// the move that think() or ponder_hit() found in the opening book
+    BookMove(MoveBytes),
+    /// make_move() or set_position() ended the game
+    GameOver(GameResult),
+}
+
+impl Event {
+    /// the move in a BestMove or BookMove event
+    fn best_move(&self) -> Option<MoveBytes> {
+        match *self {
+            Event::BestMove(m) | Event::BookMove(m) => Some(m),
+            _ => None,
+        }
+    }
+}
+
+/// what the engine's thread is doing, as returned by Engine::status()
+
+#[derive(Clone, Default)]
+pub struct EngineStatus {
+    /// the session whose search is running (whether it's thinking or
+    /// pondering), or None if the engine isn't searching
+    pub session: Option<SessionId>,
+    /// the depth of the last iteration the search finished, and its score
+    pub depth: Int,
+    pub score: Int,
+    /// the number of nodes searched by the finished iterations
+    pub nodes: Int,
+    /// milliseconds since the search started
+    pub time_ms: u128,
+    /// the best move the search has found so far
+    pub best_move: Option<MoveBytes>,
+    /// the last iteration's branching factor, and how long the next one is
+    /// expected to take; see SearchInfo
+    pub branching_factor: Option<f64>,
+    pub next_iteration_ms: Option<u128>,
+}
+
+impl EngineStatus {
+    /// searching() returns true if a search is running
+    pub fn searching(&self) -> bool {
+        self.session.is_some()
+    }
+}
+
+/// the status of the running search (None if there isn't one), which the
+/// engine's thread updates after each iteration, and when it started
+
+#[derive(Clone, Default)]
+struct Progress(Arc<Mutex<Option<(EngineStatus, Instant)>>>);
+
+impl Progress {
+    fn lock(&self) -> MutexGuard<'_, Option<(EngineStatus, Instant)>> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// start() records that session id has started searching
+    fn start(&self, id: SessionId) {
+        let status = EngineStatus {
+            session: Some(id),
+            ..EngineStatus::default()
+        };
+        *self.lock() = Some((status, Instant::now()));
+    }
+
+    /// update() records the results of an iteration
+    fn update(&self, info: &SearchInfo) {
+        if let Some((status, _)) = self.lock().as_mut() {
+            status.depth = info.depth;
+            status.score = info.score;
+            status.nodes = info.nodes;
+            status.best_move = info.pv.first().copied();
+            status.branching_factor = info.branching_factor;
+            status.next_iteration_ms = info.next_iteration;
+        }
+    }
+
+    /// finish() records that the search is over
+    fn finish(&self) {
+        *self.lock() = None;
+    }
+
+    /// status() returns the status, with the time brought up to date
+    fn status(&self) -> EngineStatus {
+        match self.lock().as_ref() {
+            Some((status, start)) => EngineStatus {
+                time_ms: start.elapsed().as_millis(),
+                ..status.clone()
+            },
+            None => EngineStatus::default(),
+        }
+    }
+}
+
+/// the channels of the subscribers to an engine's events, shared by the Engine
+/// and its thread
+
+#[derive(Clone, Default)]
+struct Subscribers(Arc<Mutex<Vec<Sender<Event>>>>);
+
+impl Subscribers {
+    /// send() sends the event to every subscriber, forgetting the ones that
+    /// have hung up
+    fn send(&self, event: Event) {
+        let mut senders = self.0.lock().expect("subscribers poisoned");
+        senders.retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
+
+/// an engine running in a thread of its own. Engine derefs to its first
+/// Session, so the Session methods can be called on the Engine itself.
+
+pub struct Engine {
+    session: Session,
+    next_session: AtomicUsize,
+    book: BookConfig,
+    limits: SearchLimits,
+    tt: Option<Arc<TranspositionTable>>,
+    logger: Logger,
+    progress: Progress,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// the opening books and how they're used, which each session's Data gets a
+/// copy of
+
+#[derive(Clone)]
+struct BookConfig {
+    books: Vec<Arc<Book>>,
+    depth: BookDepth,
+    selection: BookSelection,
+}
+
+/// a game played by an Engine; see Engine::new_session(). A Session can't be
+/// used after its Engine is dropped.
+
+pub struct Session {
+    id: SessionId,
+    commands: Mutex<Sender<Command>>,
+    stop: Arc<AtomicBool>,
+    pondering: Arc<AtomicBool>,
+    ponder_result: Mutex<Option<Receiver<Option<Event>>>>,
+    subscribers: Subscribers,
+    thread_exit: Arc<ThreadExit>,
+}
+
+/// the error returned by EngineBuilder::build() when the configuration isn't
+/// usable
+
+#[derive(Debug)]
+pub enum ConfigError {
+    MaxTime(Int),
+    MaxDepth(Int),
+    HashSize(usize),
+    Threads(usize),
+    Book(PathBuf, io::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::MaxTime(n) => write!(f, "bad max_time {} (must be positive)", n),
+            ConfigError::MaxDepth(n) => {
+                write!(f, "bad max_depth {} (must be 1 to {})", n, MAX_PLY)
+            }
+            ConfigError::HashSize(n) => {
+                write!(f, "bad hash_mb {} (must be at most {})", n, MAX_HASH_MB)
+            }
+            ConfigError::Threads(n) => write!(f, "bad threads {} (only 1 is supported)", n),
+            ConfigError::Book(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Book(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// the largest hash table that EngineBuilder accepts
+
+pub const MAX_HASH_MB: usize = 65536;
+
+/// EngineBuilder collects an Engine's configuration; see Engine::builder()
+
+pub struct EngineBuilder {
+    book_paths: Vec<(PathBuf, Option<Color>)>,
+    book_depth: BookDepth,
+    book_selection: BookSelection,
+    max_time: Int,
+    max_depth: Int,
+    hash_mb: usize,
+    tt: Option<Arc<TranspositionTable>>,
+    threads: usize,
+    log_level: LogLevel,
+    log: LogFn,
+}
+
+impl EngineBuilder {
+    /// book_path() adds an opening book file. By default, there's no book. If
+    /// there's more than one, the engine plays a move from the first one (in
+    /// the order they're added) that has any for the position.
+
+    pub fn book_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.book_paths.push((path.as_ref().to_path_buf(), None));
+        self
+    }
+
+    /// side_book_path() adds an opening book file that's only used when side
+    /// is to move
+
+    pub fn side_book_path<P: AsRef<Path>>(mut self, path: P, side: Color) -> Self {
+        self.book_paths
+            .push((path.as_ref().to_path_buf(), Some(side)));
+        self
+    }
+
+    /// book_depth() sets how far into the game the opening book is used. By
+    /// default, it's 25 ply.
+
+    pub fn book_depth(mut self, depth: BookDepth) -> Self {
+        self.book_depth = depth;
+        self
+    }
+
+    /// book_selection() sets how book moves are chosen. By default, it's
+    /// BookSelection::Random.
+
+    pub fn book_selection(mut self, selection: BookSelection) -> Self {
+        self.book_selection = selection;
+        self
+    }
+
+    /// max_time() sets the number of milliseconds start_ponder() searches for
+    /// after a ponder_hit(), until a think() sets other limits
+
+    pub fn max_time(mut self, max_time: Int) -> Self {
+        self.max_time = max_time;
+        self
+    }
+
+    /// max_depth() sets the number of ply start_ponder() searches, until a
+    /// think() sets other limits
+
+    pub fn max_depth(mut self, max_depth: Int) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// hash_mb() sets the size of the transposition table, in megabytes. By
+    /// default, it's 0, and the engine doesn't use one.
+
+    pub fn hash_mb(mut self, hash_mb: usize) -> Self {
+        self.hash_mb = hash_mb;
+        self
+    }
+
+    /// transposition_table() makes the engine use tt, which may be shared with
+    /// other engines, instead of a table of its own; hash_mb is ignored
+
+    pub fn transposition_table(mut self, tt: Arc<TranspositionTable>) -> Self {
+        self.tt = Some(tt);
+        self
+    }
+
+    /// threads() sets the number of search threads. TSCP's search is single
+    /// threaded, so this must be 1.
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// log_level() sets how much the engine logs. By default, it's Off.
+
+    pub fn log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = level;
+        self
+    }
+
+    /// log() sets the function the engine's log messages are passed to. By
+    /// default, they're written to standard error.
+
+    pub fn log<F: Fn(LogLevel, &str) + Send + Sync + 'static>(mut self, log: F) -> Self {
+        self.log = Arc::new(log);
+        self
+    }
+
+    /// build() checks the configuration, reads the opening book, and starts
+    /// the engine
+
+    pub fn build(self) -> Result<Engine, ConfigError> {
+        if self.max_time <= 0 {
+            return Err(ConfigError::MaxTime(self.max_time));
+        }
+        if !(1..=MAX_PLY as Int).contains(&self.max_depth) {
+            return Err(ConfigError::MaxDepth(self.max_depth));
+        }
+        if self.hash_mb > MAX_HASH_MB {
+            return Err(ConfigError::HashSize(self.hash_mb));
+        }
+        if self.threads != 1 {
+            return Err(ConfigError::Threads(self.threads));
+        }
+        let mut books = Vec::new();
+        for (path, side) in self.book_paths {
+            let book = match book::load_book(&path) {
+                Ok(book) => book,
+                Err(err) => return Err(ConfigError::Book(path, err)),
+            };
+            books.push(Arc::new(match side {
+                Some(side) => book.for_side(side),
+                None => book,
+            }));
+        }
+
+        let tt = match self.tt {
+            Some(tt) => Some(tt),
+            None if self.hash_mb > 0 => Some(Arc::new(TranspositionTable::new(self.hash_mb))),
+            None => None,
+        };
+
+        let (sender, receiver) = channel();
+        let limits = SearchLimits {
+            depth: Some(self.max_depth),
+            movetime: Some(self.max_time),
+            ..SearchLimits::default()
+        };
+        let logger = Logger {
+            level: Arc::new(AtomicUsize::new(self.log_level as usize)),
+            log: self.log,
+        };
+        let thread_logger = logger.clone();
+        let progress = Progress::default();
+        let thread_progress = progress.clone();
+        let thread_exit = Arc::new(ThreadExit::default());
+        let exit = Arc::clone(&thread_exit);
+        let thread = thread::spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                engine_loop(receiver, &thread_logger, &thread_progress)
+            }));
+            match result {
+                Ok(()) => exit.set(EngineError::Exited),
+                Err(payload) => {
+                    let message = match payload.downcast::<String>() {
+                        Ok(message) => *message,
+                        Err(payload) => match payload.downcast::<&str>() {
+                            Ok(message) => message.to_string(),
+                            Err(_) => "(unknown)".to_string(),
+                        },
+                    };
+                    thread_logger.log(LogLevel::Error, format_args!("panicked: {}", message));
+                    exit.set(EngineError::Panicked(message));
+                }
+            }
+        });
+        let book = BookConfig {
+            books,
+            depth: self.book_depth,
+            selection: self.book_selection,
+        };
+        Ok(Engine {
+            session: Session::start(sender, 0, &book, &tt, limits, thread_exit),
+            next_session: AtomicUsize::new(1),
+            book,
+            limits,
+            tt,
+            logger,
+            progress,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Engine {
+    /// create an engine with the default configuration: no opening book, and
+    /// a 4 ply search
+
+    pub fn new() -> Engine {
+        Engine::builder()
+            .build()
+            .expect("the default configuration is valid")
+    }
+
+    /// builder() returns an EngineBuilder with the default configuration
+
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder {
+            book_paths: Vec::new(),
+            book_depth: BookDepth::default(),
+            book_selection: BookSelection::default(),
+            max_time: 1 << 25,
+            max_depth: 4,
+            hash_mb: 0,
+            tt: None,
+            threads: 1,
+            log_level: LogLevel::Off,
+            log: Arc::new(log_to_stderr),
+        }
+    }
+
+    /// transposition_table() returns the engine's transposition table, if it
+    /// has one, e.g., to share it with another engine
+
+    pub fn transposition_table(&self) -> Option<Arc<TranspositionTable>> {
+        self.tt.clone()
+    }
+
+    /// status() returns what the engine is doing: which session is searching,
+    /// if any, and how far its search has got. It doesn't wait for the
+    /// engine's thread.
+
+    pub fn status(&self) -> EngineStatus {
+        self.progress.status()
+    }
+
+    /// log_level() returns how much the engine logs
+
+    pub fn log_level(&self) -> LogLevel {
+        self.logger.level()
+    }
+
+    /// set_log_level() changes how much the engine logs, e.g., to turn logging
+    /// off or on while it's running
+
+    pub fn set_log_level(&self, level: LogLevel) {
+        self.logger.level.store(level as usize, Ordering::Relaxed);
+    }
+
+    /// new_session() starts a new game, independent of the engine's other
+    /// sessions, set up in the starting position. The session ends when the
+    /// Session is dropped.
+
+    pub fn new_session(&self) -> Session {
+        Session::start(
+            self.session.sender(),
+            self.next_session.fetch_add(1, Ordering::Relaxed),
+            &self.book,
+            &self.tt,
+            self.limits,
+            Arc::clone(&self.session.thread_exit),
+        )
+    }
+}
+
+impl Deref for Engine {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        &self.session
+    }
+}
+
+impl Session {
+    /// start() creates the Data for session id, and sends it to the engine's
+    /// thread
+    fn start(
+        commands: Sender<Command>,
+        id: SessionId,
+        book: &BookConfig,
+        tt: &Option<Arc<TranspositionTable>>,
+        limits: SearchLimits,
+        thread_exit: Arc<ThreadExit>,
+    ) -> Session {
+        let mut d = Box::new(Data::new());
+        d.books = book.books.clone();
+        d.book_depth = book.depth;
+        d.book_selection = book.selection;
+        d.search.tt = tt.clone();
+        let session = Session {
+            id,
+            commands: Mutex::new(commands),
+            stop: Arc::clone(&d.search.stop),
+            pondering: Arc::clone(&d.search.pondering),
+            ponder_result: Mutex::new(None),
+            subscribers: Subscribers::default(),
+            thread_exit,
+        };
+        let subscribers = session.subscribers.clone();
+        // (if the engine's thread has exited, the session's commands will
+        // fail with the reason)
+        let _ = session.send_command(Command::NewSession(id, d, limits, subscribers));
+        session
+    }
+
+    /// id() returns the session's id, which is unique among its engine's
+    /// sessions
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    /// new_game() starts a new game: it sets the board up, forgets the moves
+    /// of the last game and what its searches learned, and goes back to the
+    /// search limits the session started with. If clear_tt is true, it also
+    /// empties the transposition table, which is shared by all the engine's
+    /// sessions.
+
+    pub fn new_game(&self, clear_tt: bool) {
+        answer(self.send(SessionCommand::NewGame(clear_tt)));
+    }
+
+    /// subscribe() returns a channel that receives the engine's events from now
+    /// on. Dropping the Receiver unsubscribes.
+
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = channel();
+        self.subscribers
+            .0
+            .lock()
+            .expect("subscribers poisoned")
+            .push(sender);
+        receiver
+    }
+
+    /// set_position() sets up the position described by the FEN string fen, as
+    /// the start of a new game. If the string isn't valid FEN, or the position
+    /// doesn't make sense (see board::validate()), the board doesn't change.
+
+    pub fn set_position(&self, fen: &str) -> Result<(), FenError> {
+        answer(self.request(|r| SessionCommand::SetPosition(fen.to_string(), r), None))
+    }
+
+    /// try_set_position() is set_position() with a timeout
+
+    pub fn try_set_position(
+        &self,
+        fen: &str,
+        timeout: Duration,
+    ) -> Result<Result<(), FenError>, EngineError> {
+        self.request(
+            |r| SessionCommand::SetPosition(fen.to_string(), r),
+            Some(timeout),
+        )
+    }
+
+    /// fen() returns the FEN string for the current position
+
+    pub fn fen(&self) -> String {
+        answer(self.request(SessionCommand::Fen, None))
+    }
+
+    /// try_fen() is fen() with a timeout
+
+    pub fn try_fen(&self, timeout: Duration) -> Result<String, EngineError> {
+        self.request(SessionCommand::Fen, Some(timeout))
+    }
+
+    /// make_move() makes the move m if it's legal
+
+    pub fn make_move(&self, m: MoveBytes) -> Result<(), IllegalMove> {
+        answer(self.request(|r| SessionCommand::MakeMove(m, r), None))
+    }
+
+    /// try_make_move() is make_move() with a timeout
+
+    pub fn try_make_move(
+        &self,
+        m: MoveBytes,
+        timeout: Duration,
+    ) -> Result<Result<(), IllegalMove>, EngineError> {
+        self.request(|r| SessionCommand::MakeMove(m, r), Some(timeout))
+    }
+
+    /// undo_move() takes back the last move of the game, and returns it (or
+    /// None if there are no moves to take back)
+
+    pub fn undo_move(&self) -> Option<MoveBytes> {
+        self.undo_moves(1).pop()
+    }
+
+    /// try_undo_move() is undo_move() with a timeout
+
+    pub fn try_undo_move(&self, timeout: Duration) -> Result<Option<MoveBytes>, EngineError> {
+        Ok(self.try_undo_moves(1, timeout)?.pop())
+    }
+
+    /// undo_moves() takes back the last n moves of the game (or all of them,
+    /// if there are fewer than n), and returns them, the last one first
+
+    pub fn undo_moves(&self, n: usize) -> Vec<MoveBytes> {
+        answer(self.request(|r| SessionCommand::UndoMoves(n, r), None))
+    }
+
+    /// try_undo_moves() is undo_moves() with a timeout
+
+    pub fn try_undo_moves(
+        &self,
+        n: usize,
+        timeout: Duration,
+    ) -> Result<Vec<MoveBytes>, EngineError> {
+        self.request(|r| SessionCommand::UndoMoves(n, r), Some(timeout))
+    }
+
+    /// history_san() returns the moves of the game so far, in Standard
+    /// Algebraic Notation
+
+    pub fn history_san(&self) -> Vec<String> {
+        answer(self.request(SessionCommand::HistorySan, None))
+    }
+
+    /// try_history_san() is history_san() with a timeout
+
+    pub fn try_history_san(&self, timeout: Duration) -> Result<Vec<String>, EngineError> {
+        self.request(SessionCommand::HistorySan, Some(timeout))
+    }
+
+    /// evaluate() returns the static evaluation of the current position, from
+    /// the point of view of the side to move (like the search's scores),
+    /// without searching
+
+    pub fn evaluate(&self) -> Int {
+        self.evaluate_terms().0
+    }
+
+    /// evaluate_terms() is evaluate(), plus the terms that make up the
+    /// evaluation, from white's point of view
+
+    pub fn evaluate_terms(&self) -> (Int, EvalTerms) {
+        answer(self.request(SessionCommand::Evaluate, None))
+    }
+
+    /// try_evaluate_terms() is evaluate_terms() with a timeout
+
+    pub fn try_evaluate_terms(&self, timeout: Duration) -> Result<(Int, EvalTerms), EngineError> {
+        self.request(SessionCommand::Evaluate, Some(timeout))
+    }
+
+    /// set_skill() sets the skill level the session's searches play at, from
+    /// search::MIN_SKILL to search::MAX_SKILL (full strength, the default);
+    /// search::skill_for_elo() gives the level for an Elo rating. A level
+    /// out of that range is clamped to it.
+
+    pub fn set_skill(&self, skill: Int) {
+        answer(self.send(SessionCommand::SetSkill(skill)));
+    }
+
+    /// think() searches until it reaches one of the limits, or until
+    /// stop_thinking() is called, and returns what it found, including the
+    /// best move (which it doesn't make). The best move is None only if there
+    /// are no legal moves.
+
+    pub fn think(&self, limits: SearchLimits) -> SearchResult {
+        self.stop.store(false, Ordering::Relaxed);
+        answer(self.request(|r| SessionCommand::Think(limits, r), None))
+    }
+
+    /// try_think() is think() with a timeout. If it times out, the search
+    /// carries on (and its result is thrown away) until it reaches its limits
+    /// or stop_thinking() is called.
+
+    pub fn try_think(
+        &self,
+        limits: SearchLimits,
+        timeout: Duration,
+    ) -> Result<SearchResult, EngineError> {
+        self.stop.store(false, Ordering::Relaxed);
+        self.request(|r| SessionCommand::Think(limits, r), Some(timeout))
+    }
+
+    /// stop_thinking() makes a think() that's in progress (on another thread)
+    /// return as soon as possible with the best move found so far
+
+    pub fn stop_thinking(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// start_ponder() makes the move predicted_move (which the engine expects
+    /// the opponent to play) and starts searching the resulting position. It
+    /// returns right away; ponder_hit() or ponder_miss() must be called before
+    /// any other command. The search uses the time and depth limits of the
+    /// most recent think().
+
+    pub fn start_ponder(&self, predicted_move: MoveBytes) -> Result<(), IllegalMove> {
+        self.stop.store(false, Ordering::Relaxed);
+        self.pondering.store(true, Ordering::Relaxed);
+        let (started_sender, started_receiver) = channel();
+        let (result_sender, result_receiver) = channel();
+        answer(self.send(SessionCommand::Ponder(
+            predicted_move,
+            started_sender,
+            result_sender,
+        )));
+        let started = started_receiver.recv().map_err(|_| self.thread_exit.get());
+        match answer(started) {
+            Ok(()) => {
+                *self.ponder_result.lock().expect("ponder result poisoned") = Some(result_receiver);
+                Ok(())
+            }
+            Err(err) => {
+                self.pondering.store(false, Ordering::Relaxed);
+                Err(err)
+            }
+        }
+    }
+
+    /// ponder_hit() is called when the opponent plays the predicted move. The
+    /// search carries on, now with the clock running, and ponder_hit() returns
+    /// the move it finds (without making it), like think(). It returns None if
+    /// there are no legal moves or if the engine wasn't pondering.
+
+    pub fn ponder_hit(&self) -> Option<MoveBytes> {
+        let result = self.take_ponder_result()?;
+        self.pondering.store(false, Ordering::Relaxed);
+        // the engine thread doesn't know whether the search will be used, so
+        // it leaves sending its result to us
+        let event = answer(result.recv().map_err(|_| self.thread_exit.get()))?;
+        let m = event.best_move();
+        self.subscribers.send(event);
+        m
+    }
+
+    /// ponder_miss() is called when the opponent doesn't play the predicted
+    /// move. It stops the search and takes the predicted move back, so the
+    /// opponent's actual move can be made with make_move().
+
+    pub fn ponder_miss(&self) {
+        if let Some(result) = self.take_ponder_result() {
+            self.stop_thinking();
+            let _ = result.recv();
+            self.pondering.store(false, Ordering::Relaxed);
+            answer(self.send(SessionCommand::TakeBack));
+        }
+    }
+
+    fn take_ponder_result(&self) -> Option<Receiver<Option<Event>>> {
+        self.ponder_result
+            .lock()
+            .expect("ponder result poisoned")
+            .take()
+    }
+
+    /// request() sends the command that make_command() makes with a reply
+    /// channel, and waits (for up to timeout, if there is one) for the reply
+
+    fn request<T, F: FnOnce(Sender<T>) -> SessionCommand>(
+        &self,
+        make_command: F,
+        timeout: Option<Duration>,
+    ) -> Result<T, EngineError> {
+        let (sender, receiver) = channel();
+        self.send(make_command(sender))?;
+        match timeout {
+            None => receiver.recv().map_err(|_| self.thread_exit.get()),
+            Some(timeout) => receiver.recv_timeout(timeout).map_err(|err| match err {
+                RecvTimeoutError::Timeout => EngineError::Timeout,
+                RecvTimeoutError::Disconnected => self.thread_exit.get(),
+            }),
+        }
+    }
+
+    fn send(&self, command: SessionCommand) -> Result<(), EngineError> {
+        self.send_command(Command::Session(self.id, command))
+    }
+
+    fn send_command(&self, command: Command) -> Result<(), EngineError> {
+        self.commands
+            .lock()
+            .expect("engine command channel poisoned")
+            .send(command)
+            .map_err(|_| self.thread_exit.get())
+    }
+
+    fn sender(&self) -> Sender<Command> {
+        self.commands
+            .lock()
+            .expect("engine command channel poisoned")
+            .clone()
+    }
+}
+
+/// answer() unwraps the answer to a request that doesn't have a timeout, so
+/// the only way it can fail is if the engine's thread has exited
+
+fn answer<T>(result: Result<T, EngineError>) -> T {
+    result.unwrap_or_else(|err| panic!("{}", err))
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.stop_thinking();
+        if let Ok(commands) = self.session.commands.lock() {
+            let _ = commands.send(Command::Quit);
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // (if this is the Engine's first session, the engine's thread has
+        // already quit, and there's nothing to end)
+        self.stop_thinking();
+        if let Ok(commands) = self.commands.lock() {
+            let _ = commands.send(Command::EndSession(self.id));
+        }
+    }
+}
+
+/// what the engine's thread keeps for each session. Pondering uses the limits
+/// of the session's most recent think(), or the ones the session started with
+/// (which a new game goes back to).
+
+struct SessionState {
+    d: Box<Data>,
+    limits: SearchLimits,
+    start_limits: SearchLimits,
+    subscribers: Subscribers,
+}
+
+/// engine_loop() runs on the engine's thread, carrying out commands until it's
+/// told to quit
+
+fn engine_loop(receiver: Receiver<Command>, logger: &Logger, progress: &Progress) {
+    let mut sessions = HashMap::new();
+    for command in receiver {
+        match command {
+            Command::NewSession(id, mut d, limits, subscribers) => {
+                board::init_hash(&mut d.pos);
+                board::init_board(&mut d.pos);
+                if d.books.iter().any(|book| !book.is_empty()) {
+                    // so we play random book moves, as open_book() does. (This
+                    // has to come after init_hash(), which seeds rand() with
+                    // 0.)
+                    rand::srand(util::get_ms() as u32);
+                }
+                board::gen_legal(&mut d);
+                let session = SessionState {
+                    d,
+                    limits,
+                    start_limits: limits,
+                    subscribers,
+                };
+                sessions.insert(id, session);
+                logger.log(LogLevel::Info, format_args!("session {} started", id));
+            }
+            Command::EndSession(id) => {
+                if sessions.remove(&id).is_some() {
+                    logger.log(LogLevel::Info, format_args!("session {} ended", id));
+                }
+            }
+            Command::Session(id, command) => {
+                // (if there's no such session, the command's reply channel is
+                // dropped)
+                match sessions.get_mut(&id) {
+                    Some(session) => {
+                        logger.log(LogLevel::Debug, format_args!("session {}: {}", id, command));
+                        session_command(session, command, id, logger, progress);
+                    }
+                    None => logger.log(
+                        LogLevel::Warn,
+                        format_args!("session {}: no such session for {}", id, command),
+                    ),
+                }
+            }
+            Command::Quit => {
+                logger.log(LogLevel::Debug, format_args!("quit"));
+                break;
+            }
+        }
+    }
+}
+
+/// session_command() carries out a command for one of the sessions
+
+fn session_command(
+    session: &mut SessionState,
+    command: SessionCommand,
+    id: SessionId,
+    logger: &Logger,
+    progress: &Progress,
+) {
+    let d = &mut *session.d;
+    let subscribers = &session.subscribers;
+    match command {
+        SessionCommand::NewGame(clear_tt) => {
+            board::new_game(d, clear_tt);
+            session.limits = session.start_limits;
+        }
+        SessionCommand::MakeMove(m, reply) => {
+            let result = board::try_move(d, m);
+            let ok = result.is_ok();
+            let _ = reply.send(result);
+            if ok {
+                check_game_over(d, subscribers);
+            }
+        }
+        SessionCommand::Think(new_limits, reply) => {
+            session.limits = new_limits;
+            let (result, event) = think(d, &session.limits, subscribers, progress, id);
+            match (&event, result.best_move) {
+                (Some(Event::BookMove(m)), _) => logger.log(
+                    LogLevel::Info,
+                    format_args!("session {}: book move {}", id, m),
+                ),
+                (_, Some(m)) => logger.log(
+                    LogLevel::Info,
+                    format_args!(
+                        "session {}: best move {} (score {}, depth {}, {} nodes, {} ms)",
+                        id, m, result.score, result.depth, result.nodes, result.time_ms
+                    ),
+                ),
+                _ => logger.log(
+                    LogLevel::Info,
+                    format_args!("session {}: no legal moves", id),
+                ),
+            }
+            if let Some(event) = event {
+                subscribers.send(event);
+            }
+            let _ = reply.send(result);
+        }
+        SessionCommand::Ponder(m, started, reply) => {
+            let result = board::try_move(d, m);
+            let ok = result.is_ok();
+            let _ = started.send(result);
+            if ok {
+                let _ = reply.send(think(d, &session.limits, subscribers, progress, id).1);
+            }
+        }
+        SessionCommand::TakeBack => {
+            board::takeback(&mut d.pos);
+            board::gen_legal(d);
+        }
+        SessionCommand::UndoMoves(n, reply) => {
+            let _ = reply.send(board::undo_moves(d, n));
+        }
+        SessionCommand::HistorySan(reply) => {
+            let _ = reply.send(board::history_san(&d.pos));
+        }
+        SessionCommand::SetPosition(fen, reply) => {
+            let result = board::set_fen(d, &fen);
+            let ok = result.is_ok();
+            let _ = reply.send(result);
+            if ok {
+                check_game_over(d, subscribers);
+            }
+        }
+        SessionCommand::Fen(reply) => {
+            let _ = reply.send(board::fen(&d.pos));
+        }
+        SessionCommand::Evaluate(reply) => {
+            let terms = eval::eval_terms(&mut d.pos);
+            let _ = reply.send((eval::eval(&mut d.pos), terms));
+        }
+        SessionCommand::SetSkill(skill) => {
+            d.search.skill = skill.clamp(MIN_SKILL, MAX_SKILL);
+        }
+        #[cfg(test)]
+        SessionCommand::Panic => panic!("told to panic"),
+    }
+}
+
+/// check_game_over() sends a GameOver event if the game is over
+
+fn check_game_over(d: &mut Data, subscribers: &Subscribers) {
+    let result = util::game_result(d);
+    if result != GameResult::Ongoing {
+        subscribers.send(Event::GameOver(result));
+    }
+}
+
+/// think() picks a move, from the opening book if possible, and otherwise by
+/// searching (and sending SearchInfo events and updating progress as it goes,
+/// for session id). If the search was
+/// stopped before it found a move, it just picks the first legal move. It
+/// returns the SearchResult and the BookMove or BestMove event for the move,
+/// or None if there are no legal moves.
+
+fn think(
+    d: &mut Data,
+    limits: &SearchLimits,
+    subscribers: &Subscribers,
+    progress: &Progress,
+    id: SessionId,
+) -> (SearchResult, Option<Event>) {
+    let m = book::book_move(d);
+    if m != -1 {
+        let mut book_move = Move::default();
+        book_move.set_value(m);
+        let m = book_move.bytes();
+        let result = SearchResult {
+            best_move: Some(m),
+            pv: vec![m],
+            ..SearchResult::default()
+        };
+        return (result, Some(Event::BookMove(m)));
+    }
+
+    let mut observer = |info: &SearchInfo| {
+        progress.update(info);
+        subscribers.send(Event::SearchInfo(info.clone()));
+    };
+    progress.start(id);
+    let result = search::think(d, limits, &mut observer);
+    progress.finish();
+    let mut result = result.expect("the observer can't fail");
+    if result.best_move.is_none() {
+        board::gen_legal(d);
+        result.best_move = d.search.gen_dat[0].first().map(|g| g.m.bytes());
+    }
+    let event = result.best_move.map(Event::BestMove);
+    (result, event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::defs::Color;
+
+    use std::time::{Duration, Instant};
+
+    fn limits(movetime: Int, depth: Int) -> SearchLimits {
+        SearchLimits {
+            movetime: Some(movetime),
+            depth: Some(depth),
+            ..SearchLimits::default()
+        }
+    }
+
+    #[test]
+    fn test_stop_thinking() {
+        let engine = Engine::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(200));
+                engine.stop_thinking();
+            });
+            let start = Instant::now();
+            let m = engine.think(limits(60_000, 32)).best_move;
+            assert!(m.is_some());
+            assert!(start.elapsed() < Duration::from_secs(30));
+            engine.make_move(m.unwrap()).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_ponder() {
+        let engine = Engine::new();
+        let e2e4 = MoveBytes {
+            from: 52,
+            to: 36,
+            ..MoveBytes::default()
+        };
+        let e7e5 = MoveBytes {
+            from: 12,
+            to: 28,
+            ..MoveBytes::default()
+        };
+        let d7d5 = MoveBytes {
+            from: 11,
+            to: 27,
+            ..MoveBytes::default()
+        };
+        engine.make_move(e2e4).unwrap();
+        assert!(engine.think(limits(100, 32)).best_move.is_some());
+
+        // the opponent plays something else, so e7e5 gets taken back
+        engine.start_ponder(e7e5).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        engine.ponder_miss();
+        engine.make_move(d7d5).unwrap();
+
+        // this time the opponent plays the move we're pondering on
+        let m = engine.think(limits(100, 32)).best_move.unwrap();
+        engine.make_move(m).unwrap();
+        assert_eq!(engine.start_ponder(e2e4), Err(IllegalMove));
+        engine.start_ponder(e7e5).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        let m = engine.ponder_hit().unwrap();
+        engine.make_move(m).unwrap();
+    }
+
+    #[test]
+    fn test_set_position() {
+        let engine = Engine::new();
+        let start = engine.fen();
+        assert!(engine.set_position("8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+        assert_eq!(engine.fen(), start);
+
+        // white mates in one
+        let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        engine.set_position(fen).unwrap();
+        assert_eq!(engine.fen(), fen);
+        let result = engine.think(limits(1000, 3));
+        let m = result.best_move.unwrap();
+        assert_eq!((m.from, m.to), (56, 0));
+        assert_eq!(result.score, 9999);
+        assert_eq!(result.depth, 2);
+        assert_eq!(result.pv.len(), 1);
+        assert!(result.ponder_move.is_none());
+    }
+
+    #[test]
+    fn test_history() {
+        let engine = Engine::new();
+        assert!(engine.undo_move().is_none());
+        engine.make_move("g1f3".parse().unwrap()).unwrap();
+        engine.make_move("d7d5".parse().unwrap()).unwrap();
+        assert_eq!(engine.history_san(), ["Nf3", "d5"]);
+        assert_eq!(engine.undo_move().unwrap().to_string(), "d7d5");
+        assert_eq!(engine.history_san(), ["Nf3"]);
+
+        // undo_moves() stops at the start of the game
+        engine.make_move("d7d5".parse().unwrap()).unwrap();
+        engine.make_move("d2d4".parse().unwrap()).unwrap();
+        let moves: Vec<String> = engine.undo_moves(2).iter().map(|m| m.to_string()).collect();
+        assert_eq!(moves, ["d2d4", "d7d5"]);
+        assert_eq!(engine.history_san(), ["Nf3"]);
+        assert_eq!(engine.undo_moves(5).len(), 1);
+        assert!(engine.history_san().is_empty());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let engine = Engine::new();
+        assert_eq!(engine.evaluate(), 0);
+        engine.make_move("e2e4".parse().unwrap()).unwrap();
+        let (score, terms) = engine.evaluate_terms();
+        assert!(terms.piece_square > 0);
+        assert_eq!(score, -terms.total());
+    }
+
+    #[test]
+    fn test_sessions() {
+        let engine = Engine::builder().hash_mb(1).build().unwrap();
+        let session = engine.new_session();
+        assert_ne!(session.id(), engine.id());
+        let events = session.subscribe();
+
+        // the sessions' games are independent
+        engine.make_move("e2e4".parse().unwrap()).unwrap();
+        session.make_move("d2d4".parse().unwrap()).unwrap();
+        assert_eq!(engine.history_san(), ["e4"]);
+        assert_eq!(session.history_san(), ["d4"]);
+        assert!(session.think(limits(1 << 25, 2)).best_move.is_some());
+        assert_eq!(session.history_san(), ["d4"]);
+
+        // and so are their events
+        let other_events = engine.subscribe();
+        let m = engine.think(limits(1 << 25, 1)).best_move.unwrap();
+        engine.make_move(m).unwrap();
+        drop(session);
+        assert_eq!(events.iter().count(), 3);
+        assert_eq!(other_events.try_iter().count(), 2);
+
+        // a session that's ended doesn't stop the others
+        let session = engine.new_session();
+        assert_eq!(session.history_san().len(), 0);
+        assert_eq!(engine.history_san().len(), 2);
+    }
+
+    #[test]
+    fn test_log() {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let log_messages = Arc::clone(&messages);
+        let engine = Engine::builder()
+            .log_level(LogLevel::Info)
+            .log(move |level, message| {
+                log_messages
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", level, message))
+            })
+            .build()
+            .unwrap();
+        engine.think(limits(1 << 25, 1));
+        engine.fen();
+        assert_eq!(engine.log_level(), LogLevel::Info);
+        engine.set_log_level(LogLevel::Debug);
+        engine.fen();
+        engine.set_log_level(LogLevel::Off);
+        engine.fen();
+        drop(engine);
+
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0], "info session 0 started");
+        assert!(messages[1].starts_with("info session 0: best move "));
+        assert_eq!(messages[2], "debug session 0: fen");
+    }
+
+    #[test]
+    fn test_new_game() {
+        use crate::tt::{Bound, TtEntry};
+
+        let tt = Arc::new(TranspositionTable::new(1));
+        let engine = Engine::builder()
+            .transposition_table(Arc::clone(&tt))
+            .build()
+            .unwrap();
+        let start = engine.fen();
+        engine.make_move("e2e4".parse().unwrap()).unwrap();
+        engine.think(limits(1000, 3));
+        let entry = TtEntry {
+            m: Move::default(),
+            score: 0,
+            depth: 99,
+            bound: Bound::Exact,
+        };
+        tt.store(12345, entry);
+
+        engine.new_game(false);
+        assert_eq!(engine.fen(), start);
+        assert!(engine.history_san().is_empty());
+        assert!(engine.undo_move().is_none());
+        assert!(tt.probe(12345) == Some(entry));
+
+        engine.new_game(true);
+        assert_eq!(engine.fen(), start);
+        assert!(tt.probe(12345).is_none());
+    }
+
+    #[test]
+    fn test_status() {
+        let engine = Engine::new();
+        let session = engine.new_session();
+        assert!(!engine.status().searching());
+        let infinite = SearchLimits {
+            infinite: true,
+            ..SearchLimits::default()
+        };
+        thread::scope(|s| {
+            s.spawn(|| session.think(infinite));
+            let start = Instant::now();
+            let status = loop {
+                let status = engine.status();
+                if status.depth >= 2 || start.elapsed() > Duration::from_secs(30) {
+                    break status;
+                }
+                thread::sleep(Duration::from_millis(10));
+            };
+            session.stop_thinking();
+            assert_eq!(status.session, Some(session.id()));
+            assert!(status.depth >= 2);
+            assert!(status.nodes > 0);
+            assert!(status.best_move.is_some());
+        });
+        assert!(!engine.status().searching());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let engine = Engine::new();
+        let session = engine.new_session();
+        let infinite = SearchLimits {
+            infinite: true,
+            ..SearchLimits::default()
+        };
+        let timeout = Duration::from_millis(100);
+        assert_eq!(
+            engine.try_think(infinite, timeout).err(),
+            Some(EngineError::Timeout)
+        );
+        // the other sessions wait for the search, too
+        assert_eq!(session.try_fen(timeout), Err(EngineError::Timeout));
+        engine.stop_thinking();
+        assert_eq!(session.try_history_san(Duration::from_secs(10)), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_panic() {
+        let engine = Engine::new();
+        assert!(engine.send(SessionCommand::Panic).is_ok());
+        let err = Err(EngineError::Panicked("told to panic".to_string()));
+        assert_eq!(engine.try_fen(Duration::from_secs(10)), err);
+        assert_eq!(
+            engine.try_undo_move(Duration::from_secs(10)).err(),
+            err.err()
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let engine = Engine::builder()
+            .max_depth(2)
+            .hash_mb(16)
+            .threads(1)
+            .build()
+            .unwrap();
+        engine
+            .make_move(MoveBytes {
+                from: 52,
+                to: 36,
+                ..MoveBytes::default()
+            })
+            .unwrap();
+
+        // an engine can share another engine's transposition table
+        let tt = engine.transposition_table().unwrap();
+        assert_eq!(tt.len(), 1 << 20);
+        assert!(tt.probe(0).is_none());
+        let other = Engine::builder()
+            .transposition_table(Arc::clone(&tt))
+            .build()
+            .unwrap();
+        assert!(Arc::ptr_eq(&other.transposition_table().unwrap(), &tt));
+        assert!(Engine::new().transposition_table().is_none());
+
+        match Engine::builder().max_depth(0).build() {
+            Err(ConfigError::MaxDepth(0)) => {}
+            _ => panic!("max_depth 0 was accepted"),
+        }
+        match Engine::builder().threads(4).build() {
+            Err(ConfigError::Threads(4)) => {}
+            _ => panic!("4 threads were accepted"),
+        }
+        match Engine::builder().book_path("no such book.txt").build() {
+            Err(ConfigError::Book(_, _)) => {}
+            _ => panic!("a missing book was accepted"),
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let engine = Engine::new();
+        let events = engine.subscribe();
+        engine
+            .set_position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1")
+            .unwrap();
+        let m = engine.think(limits(1 << 25, 2)).best_move.unwrap();
+        engine.make_move(m).unwrap();
+        drop(engine);
+
+        let events: Vec<Event> = events.iter().collect();
+        assert_eq!(events.len(), 4);
+        match (&events[0], &events[1]) {
+            (Event::SearchInfo(a), Event::SearchInfo(b)) => {
+                assert_eq!((a.depth, b.depth), (1, 2));
+            }
+            _ => panic!("expected two SearchInfo events"),
+        }
+        match events[2] {
+            Event::BestMove(best) => assert_eq!((best.from, best.to), (m.from, m.to)),
+            _ => panic!("expected a BestMove event"),
+        }
+        match events[3] {
+            Event::GameOver(result) => assert_eq!(result, GameResult::Checkmate(Color::Light)),
+            _ => panic!("expected a GameOver event"),
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// epd.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP doesn't read EPD (Extended Position
+// Description). An EPD record is the first four fields of a FEN, followed by
+// operations, each an opcode and its operands, ending with a semicolon, e.g.:
+//
+//     r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - bm Nxc6; id "WAC.009";
+//
+// Test suites and puzzle collections are usually EPD files, with the best
+// move (or moves) in the "bm" operation.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use super::defs::FenError;
+
+/// an EPD record
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Epd {
+    /// the position, as a FEN for board::set_fen() (with the move counters
+    /// from the "hmvc" and "fmvn" operations, if the record has them)
+    pub fen: String,
+    /// the operations' opcodes and operands, in the order they're given.
+    /// (The quotes around a string operand are removed.)
+    pub operations: Vec<(String, String)>,
+}
+
+impl Epd {
+    /// operation() returns the operands of the operation with the given
+    /// opcode, if the record has one
+
+    pub fn operation(&self, opcode: &str) -> Option<&str> {
+        self.operations
+            .iter()
+            .find(|(op, _)| op == opcode)
+            .map(|(_, operands)| operands.as_str())
+    }
+
+    /// id() returns the record's "id" operation, if it has one
+
+    pub fn id(&self) -> Option<&str> {
+        self.operation("id")
+    }
+
+    /// best_moves() returns the moves in the "bm" operation, in Standard
+    /// Algebraic Notation (there can be more than one)
+
+    pub fn best_moves(&self) -> Vec<&str> {
+        self.operation("bm")
+            .map(|bm| bm.split_whitespace().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for Epd {
+    type Err = FenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim_start();
+        let mut fields = Vec::new();
+        let mut rest = s;
+        for _ in 0..4 {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if end == 0 {
+                return Err(FenError::Syntax("an EPD record needs four fields"));
+            }
+            fields.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+
+        let mut operations = Vec::new();
+        while !rest.is_empty() {
+            let (operation, tail) = split_operation(rest)?;
+            let mut words = operation.splitn(2, char::is_whitespace);
+            let opcode = words.next().unwrap_or_default();
+            let operands = words.next().unwrap_or_default().trim();
+            let operands = operands
+                .strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .unwrap_or(operands);
+            operations.push((String::from(opcode), String::from(operands)));
+            rest = tail.trim_start();
+        }
+
+        let mut epd = Epd {
+            fen: fields.join(" "),
+            operations,
+        };
+        if let (Some(hmvc), Some(fmvn)) = (epd.operation("hmvc"), epd.operation("fmvn")) {
+            epd.fen = format!("{} {} {}", epd.fen, hmvc, fmvn);
+        }
+        Ok(epd)
+    }
+}
+
+/// split_operation() splits the first operation off the operations in s,
+/// and returns it (without its semicolon) and the rest. A semicolon inside
+/// a quoted string doesn't end the operation.
+
+fn split_operation(s: &str) -> Result<(&str, &str), FenError> {
+    let mut quoted = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return Ok((s[..i].trim(), &s[i + 1..])),
+            _ => {}
+        }
+    }
+    if quoted {
+        Err(FenError::Syntax("unterminated string in an EPD operation"))
+    } else {
+        // the last operation's semicolon is sometimes left off
+        Ok((s.trim(), ""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epd() {
+        let epd: Epd =
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            epd.fen,
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - -"
+        );
+        assert_eq!(epd.id(), Some("WAC.001"));
+        assert_eq!(epd.best_moves(), ["Qg6"]);
+        assert_eq!(epd.operation("am"), None);
+
+        let epd: Epd = "4k3/8/8/8/8/8/8/R3K3 w Q - bm Ra8+ O-O-O; c0 \"a; b\"; hmvc 3; fmvn 40"
+            .parse()
+            .unwrap();
+        assert_eq!(epd.fen, "4k3/8/8/8/8/8/8/R3K3 w Q - 3 40");
+        assert_eq!(epd.best_moves(), ["Ra8+", "O-O-O"]);
+        assert_eq!(epd.operation("c0"), Some("a; b"));
+        assert!(epd.id().is_none());
+
+        assert!("4k3/8/8/8 w".parse::<Epd>().is_err());
+        assert!("4k3/8/8/8/8/8/8/R3K3 w - - id \"x;".parse::<Epd>().is_err());
+    }
+}
@@ -5,8 +5,10 @@
 //
 // Rust port by Kristopher Johnson
 
-use super::data::Data;
-use super::defs::{Int, BISHOP, EMPTY, IDARK, ILIGHT, IPAWN, KING, KNIGHT, LIGHT, PAWN, ROOK};
+use super::data::{Position, Squares};
+use super::defs::{
+    Int, BISHOP, DARK, EMPTY, IDARK, ILIGHT, IPAWN, KING, KNIGHT, LIGHT, PAWN, ROOK,
+};
 
 const DOUBLED_PAWN_PENALTY: Int = 10;
 const ISOLATED_PAWN_PENALTY: Int = 20;
@@ -16,6 +18,39 @@ const ROOK_SEMI_OPEN_FILE_BONUS: Int = 10;
 const ROOK_OPEN_FILE_BONUS: Int = 15;
 const ROOK_ON_SEVENTH_BONUS: Int = 20;
 
+/// the penalties and bonuses eval_with() adds to the material and
+/// piece/square values. eval() uses the ones above (EvalParams::DEFAULT); the
+/// tuner (see tune.rs) tries others.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvalParams {
+    pub doubled_pawn_penalty: Int,
+    pub isolated_pawn_penalty: Int,
+    pub backwards_pawn_penalty: Int,
+    pub passed_pawn_bonus: Int,
+    pub rook_semi_open_file_bonus: Int,
+    pub rook_open_file_bonus: Int,
+    pub rook_on_seventh_bonus: Int,
+}
+
+impl EvalParams {
+    pub const DEFAULT: EvalParams = EvalParams {
+        doubled_pawn_penalty: DOUBLED_PAWN_PENALTY,
+        isolated_pawn_penalty: ISOLATED_PAWN_PENALTY,
+        backwards_pawn_penalty: BACKWARDS_PAWN_PENALTY,
+        passed_pawn_bonus: PASSED_PAWN_BONUS,
+        rook_semi_open_file_bonus: ROOK_SEMI_OPEN_FILE_BONUS,
+        rook_open_file_bonus: ROOK_OPEN_FILE_BONUS,
+        rook_on_seventh_bonus: ROOK_ON_SEVENTH_BONUS,
+    };
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
 /// the values of the pieces
 const PIECE_VALUE: [Int; 6] = [100, 300, 300, 500, 900, 0];
 
@@ -97,113 +132,151 @@ const FLIP: [usize; 64] = [
      0,   1,   2,   3,   4,   5,   6,   7
 ];
 
-pub fn eval(d: &mut Data) -> Int {
-    let mut score = [0; 2];
-
-    // this is the first pass: set up d.pawn_rank, d.piece_mat, and d.pawn_mat
-    for i in 0..10 {
-        d.pawn_rank[ILIGHT][i] = 0;
-        d.pawn_rank[IDARK][i] = 7;
+/// set_material() computes piece_mat, pawn_mat, pcsq, pawns, and pawn_rank
+/// from scratch. It's called by set_incremental() when the board is set up;
+/// after that, makemove() and takeback() keep the values up to date.
+
+pub fn set_material(pos: &mut Position) {
+    pos.piece_mat = [0; 2];
+    pos.pawn_mat = [0; 2];
+    pos.pcsq = [0; 2];
+    pos.pawns = [0; 2];
+    pos.pawn_rank = [[0; 10], [7; 10]];
+    for f in 0..8 {
+        set_pawn_file(pos, ILIGHT, f);
+        set_pawn_file(pos, IDARK, f);
     }
-    d.piece_mat[ILIGHT] = 0;
-    d.piece_mat[IDARK] = 0;
-    d.pawn_mat[ILIGHT] = 0;
-    d.pawn_mat[IDARK] = 0;
     for i in 0..64 {
-        if d.color[i] == EMPTY {
-            continue;
+        if pos.color(i) != EMPTY {
+            add_piece_score(pos, pos.color(i), pos.piece(i), i);
         }
-        match d.piece[i] {
-            PAWN => {
-                let row = row!(i as Int);
-                d.pawn_mat[d.color[i] as usize] += PIECE_VALUE[IPAWN];
-                let f = col!(i) + 1; // add 1 because of the extra file in the array
-                match d.color[i] {
-                    LIGHT => {
-                        if d.pawn_rank[ILIGHT][f] < row {
-                            d.pawn_rank[ILIGHT][f] = row;
-                        }
-                    }
-                    _ => {
-                        d.pawn_rank[IDARK][f] = row;
-                    }
-                }
-            }
-            _ => {
-                d.piece_mat[d.color[i] as usize] += PIECE_VALUE[d.piece[i] as usize];
-            }
+    }
+}
+
+/// add_piece_score() adds the material and piece/square value of a piece of
+/// color c and type p on square sq to the running totals (and a pawn to the
+/// pawn structure).
+
+#[inline(always)]
+pub fn add_piece_score(pos: &mut Position, c: Int, p: Int, sq: usize) {
+    let c = c as usize;
+    match p {
+        PAWN => {
+            pos.pawn_mat[c] += PIECE_VALUE[IPAWN];
+            pos.pawns[c] |= 1 << sq;
+            set_pawn_file(pos, c, col!(sq));
         }
+        _ => pos.piece_mat[c] += PIECE_VALUE[p as usize],
     }
+    pos.pcsq[c] += pcsq_value(c, p, sq);
+}
 
-    // this is the second pass: evaluate each piece
-    score[ILIGHT] = d.piece_mat[ILIGHT] + d.pawn_mat[ILIGHT];
-    score[IDARK] = d.piece_mat[IDARK] + d.pawn_mat[IDARK];
-    for i in 0..64 {
-        if d.color[i] == EMPTY {
-            continue;
+/// sub_piece_score() is the opposite of add_piece_score().
+
+#[inline(always)]
+pub fn sub_piece_score(pos: &mut Position, c: Int, p: Int, sq: usize) {
+    let c = c as usize;
+    match p {
+        PAWN => {
+            pos.pawn_mat[c] -= PIECE_VALUE[IPAWN];
+            pos.pawns[c] &= !(1 << sq);
+            set_pawn_file(pos, c, col!(sq));
         }
-        match d.color[i] {
-            LIGHT => match d.piece[i] {
+        _ => pos.piece_mat[c] -= PIECE_VALUE[p as usize],
+    }
+    pos.pcsq[c] -= pcsq_value(c, p, sq);
+}
+
+/// the squares of the a-file, as a set of squares like Position::pawns
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+
+// #rust The C version's eval() starts with a pass over the whole board to
+// find the pawns and work out pawn_rank. This version keeps pawn_rank up to
+// date as pawns come and go instead, working out each file that changes from
+// the set of the side's pawns, so eval() can go straight to the pieces.
+
+/// set_pawn_file() works out pawn_rank for side c's pawns on file f (0 is
+/// the a-file): the highest row (the one nearest White's side) that one of
+/// them is on, the same value the scan of the board used to find for either
+/// side, or 0 for LIGHT and 7 for DARK if there aren't any
+
+#[inline(always)]
+fn set_pawn_file(pos: &mut Position, c: usize, f: usize) {
+    let file = pos.pawns[c] & (FILE_A << f);
+    pos.pawn_rank[c][f + 1] = match file {
+        0 if c == ILIGHT => 0,
+        0 => 7,
+        _ => (63 - file.leading_zeros() as Int) / 8,
+    };
+}
+
+/// pcsq_value() returns the piece/square value of a pawn, knight, or bishop of
+/// color c on square sq, and 0 for the other pieces.
+
+#[inline(always)]
+fn pcsq_value(c: usize, p: Int, sq: usize) -> Int {
+    let sq = if c == ILIGHT { sq } else { FLIP[sq] };
+    match p {
+        PAWN => PAWN_PCSQ[sq],
+        KNIGHT => KNIGHT_PCSQ[sq],
+        BISHOP => BISHOP_PCSQ[sq],
+        _ => 0,
+    }
+}
+
+/// eval() evaluates the position with the C version's penalties and bonuses
+
+pub fn eval(pos: &mut Position) -> Int {
+    eval_with(pos, &EvalParams::DEFAULT)
+}
+
+/// eval_with() evaluates the position with the given penalties and bonuses,
+/// which is what the search does
+
+#[inline]
+pub fn eval_with(pos: &mut Position, params: &EvalParams) -> Int {
+    let mut score = [0; 2];
+
+    // evaluate each piece. (pos.piece_mat, pos.pawn_mat, pos.pcsq, and
+    // pos.pawn_rank are kept up to date by makemove() and takeback().)
+    score[ILIGHT] = pos.piece_mat[ILIGHT] + pos.pawn_mat[ILIGHT] + pos.pcsq[ILIGHT];
+    score[IDARK] = pos.piece_mat[IDARK] + pos.pawn_mat[IDARK] + pos.pcsq[IDARK];
+    #[cfg(not(feature = "simd"))]
+    let others = pos.pieces[ILIGHT] | pos.pieces[IDARK];
+    // the pawns are evaluated all at once, so the loop can skip them
+    #[cfg(feature = "simd")]
+    let others = (pos.pieces[ILIGHT] | pos.pieces[IDARK]) & !(pos.pawns[ILIGHT] | pos.pawns[IDARK]);
+    #[cfg(feature = "simd")]
+    {
+        let pawns = eval_pawns(pos, params);
+        score[ILIGHT] += pawns[ILIGHT];
+        score[IDARK] += pawns[IDARK];
+    }
+    for i in Squares(others) {
+        match pos.color(i) {
+            LIGHT => match pos.piece(i) {
+                #[cfg(not(feature = "simd"))]
                 PAWN => {
-                    score[ILIGHT] += eval_light_pawn(d, i);
-                }
-                KNIGHT => {
-                    score[ILIGHT] += KNIGHT_PCSQ[i];
-                }
-                BISHOP => {
-                    score[ILIGHT] += BISHOP_PCSQ[i];
+                    score[ILIGHT] += eval_light_pawn(pos, i, params);
                 }
                 ROOK => {
-                    let col = col!(i);
-                    if d.pawn_rank[ILIGHT][col + 1] == 0 {
-                        score[ILIGHT] += if d.pawn_rank[IDARK][col + 1] == 7 {
-                            ROOK_OPEN_FILE_BONUS
-                        } else {
-                            ROOK_SEMI_OPEN_FILE_BONUS
-                        }
-                    }
-                    if row!(i) == 1 {
-                        score[ILIGHT] += ROOK_ON_SEVENTH_BONUS;
-                    }
+                    score[ILIGHT] += eval_light_rook(pos, i, params);
                 }
                 KING => {
-                    score[ILIGHT] += if d.piece_mat[IDARK] <= 1200 {
-                        KING_ENDGAME_PCSQ[i]
-                    } else {
-                        eval_light_king(d, i)
-                    }
+                    score[ILIGHT] += eval_light_king(pos, i);
                 }
                 _ => {}
             },
-            _ => match d.piece[i] {
+            _ => match pos.piece(i) {
+                #[cfg(not(feature = "simd"))]
                 PAWN => {
-                    score[IDARK] += eval_dark_pawn(d, i);
-                }
-                KNIGHT => {
-                    score[IDARK] += KNIGHT_PCSQ[FLIP[i]];
-                }
-                BISHOP => {
-                    score[IDARK] += BISHOP_PCSQ[FLIP[i]];
+                    score[IDARK] += eval_dark_pawn(pos, i, params);
                 }
                 ROOK => {
-                    let col = col!(i);
-                    if d.pawn_rank[IDARK][col + 1] == 7 {
-                        score[IDARK] += if d.pawn_rank[ILIGHT][col + 1] == 0 {
-                            ROOK_OPEN_FILE_BONUS
-                        } else {
-                            ROOK_SEMI_OPEN_FILE_BONUS
-                        }
-                    }
-                    if row!(i) == 6 {
-                        score[IDARK] += ROOK_ON_SEVENTH_BONUS;
-                    }
+                    score[IDARK] += eval_dark_rook(pos, i, params);
                 }
                 KING => {
-                    score[IDARK] += if d.piece_mat[ILIGHT] <= 1200 {
-                        KING_ENDGAME_PCSQ[FLIP[i]]
-                    } else {
-                        eval_dark_king(d, i)
-                    }
+                    score[IDARK] += eval_dark_king(pos, i);
                 }
                 _ => {}
             },
@@ -212,14 +285,100 @@ pub fn eval(d: &mut Data) -> Int {
 
     // the score[] array is set, now return the score relative to the side to
     // move
-    match d.side {
+    match pos.side {
         LIGHT => score[ILIGHT] - score[IDARK],
         _ => score[IDARK] - score[ILIGHT],
     }
 }
 
+/// the terms that make up eval()'s score, each from white's point of view
+/// (that is, white's value minus black's)
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EvalTerms {
+    /// the values of the pieces and pawns
+    pub material: Int,
+    /// the piece/square values of the pawns, knights, and bishops
+    pub piece_square: Int,
+    /// doubled, isolated, backwards, and passed pawns
+    pub pawns: Int,
+    /// rooks on open and semi-open files and on the seventh rank
+    pub rooks: Int,
+    /// the kings' piece/square values and king safety
+    pub kings: Int,
+}
+
+impl EvalTerms {
+    /// total() returns the sum of the terms, which is eval()'s score from
+    /// white's point of view
+    pub fn total(&self) -> Int {
+        self.material + self.piece_square + self.pawns + self.rooks + self.kings
+    }
+}
+
+/// eval_terms() is eval() broken down into its terms, for showing the user
+/// why a position is scored the way it is, with the default parameters. The
+/// search only uses eval_with().
+
+pub fn eval_terms(pos: &mut Position) -> EvalTerms {
+    let params = &EvalParams::DEFAULT;
+    let mut terms = EvalTerms {
+        material: pos.piece_mat[ILIGHT] + pos.pawn_mat[ILIGHT]
+            - pos.piece_mat[IDARK]
+            - pos.pawn_mat[IDARK],
+        piece_square: pos.pcsq[ILIGHT] - pos.pcsq[IDARK],
+        ..EvalTerms::default()
+    };
+    for i in Squares(pos.pieces[ILIGHT] | pos.pieces[IDARK]) {
+        match (pos.color(i), pos.piece(i)) {
+            (LIGHT, PAWN) => terms.pawns += eval_light_pawn(pos, i, params),
+            (LIGHT, ROOK) => terms.rooks += eval_light_rook(pos, i, params),
+            (LIGHT, KING) => terms.kings += eval_light_king(pos, i),
+            (DARK, PAWN) => terms.pawns -= eval_dark_pawn(pos, i, params),
+            (DARK, ROOK) => terms.rooks -= eval_dark_rook(pos, i, params),
+            (DARK, KING) => terms.kings -= eval_dark_king(pos, i),
+            _ => {}
+        }
+    }
+    terms
+}
+
+#[inline(always)]
+fn eval_light_rook(pos: &Position, sq: usize, params: &EvalParams) -> Int {
+    let mut r = 0;
+    let col = col!(sq);
+    if pos.pawn_rank[ILIGHT][col + 1] == 0 {
+        r += if pos.pawn_rank[IDARK][col + 1] == 7 {
+            params.rook_open_file_bonus
+        } else {
+            params.rook_semi_open_file_bonus
+        }
+    }
+    if row!(sq) == 1 {
+        r += params.rook_on_seventh_bonus;
+    }
+    r
+}
+
 #[inline(always)]
-fn eval_light_pawn(d: &Data, sq: usize) -> Int {
+fn eval_dark_rook(pos: &Position, sq: usize, params: &EvalParams) -> Int {
+    let mut r = 0;
+    let col = col!(sq);
+    if pos.pawn_rank[IDARK][col + 1] == 7 {
+        r += if pos.pawn_rank[ILIGHT][col + 1] == 0 {
+            params.rook_open_file_bonus
+        } else {
+            params.rook_semi_open_file_bonus
+        }
+    }
+    if row!(sq) == 6 {
+        r += params.rook_on_seventh_bonus;
+    }
+    r
+}
+
+#[inline(always)]
+fn eval_light_pawn(pos: &Position, sq: usize, params: &EvalParams) -> Int {
     // the value to return
     let mut r = 0;
 
@@ -229,36 +388,34 @@ fn eval_light_pawn(d: &Data, sq: usize) -> Int {
     // the pawn's row
     let row = row!(sq as Int);
 
-    r += PAWN_PCSQ[sq];
-
     // if there's a pawn behind this one, it's doubled
-    if d.pawn_rank[ILIGHT][f] > row {
-        r -= DOUBLED_PAWN_PENALTY;
+    if pos.pawn_rank[ILIGHT][f] > row {
+        r -= params.doubled_pawn_penalty;
     }
 
     // if there aren't any friendly pawns on either side of this one, it's
     // isolated
-    if (d.pawn_rank[ILIGHT][f - 1] == 0) && (d.pawn_rank[ILIGHT][f + 1] == 0) {
-        r -= ISOLATED_PAWN_PENALTY;
+    if (pos.pawn_rank[ILIGHT][f - 1] == 0) && (pos.pawn_rank[ILIGHT][f + 1] == 0) {
+        r -= params.isolated_pawn_penalty;
     }
     // if it's not isolated, it might be backwards
-    else if (d.pawn_rank[ILIGHT][f - 1] < row) && (d.pawn_rank[ILIGHT][f + 1] < row) {
-        r -= BACKWARDS_PAWN_PENALTY;
+    else if (pos.pawn_rank[ILIGHT][f - 1] < row) && (pos.pawn_rank[ILIGHT][f + 1] < row) {
+        r -= params.backwards_pawn_penalty;
     }
 
     // add a bonus if the pawn is passed
-    if (d.pawn_rank[IDARK][f - 1] >= row)
-        && (d.pawn_rank[IDARK][f] >= row)
-        && (d.pawn_rank[IDARK][f + 1] >= row)
+    if (pos.pawn_rank[IDARK][f - 1] >= row)
+        && (pos.pawn_rank[IDARK][f] >= row)
+        && (pos.pawn_rank[IDARK][f + 1] >= row)
     {
-        r += (7 - row) * PASSED_PAWN_BONUS;
+        r += (7 - row) * params.passed_pawn_bonus;
     }
 
     r
 }
 
 #[inline(always)]
-fn eval_dark_pawn(d: &Data, sq: usize) -> Int {
+fn eval_dark_pawn(pos: &Position, sq: usize, params: &EvalParams) -> Int {
     // the value to return
     let mut r = 0;
 
@@ -268,36 +425,136 @@ fn eval_dark_pawn(d: &Data, sq: usize) -> Int {
     // the pawn's row
     let row = row!(sq as Int);
 
-    r += PAWN_PCSQ[FLIP[sq]];
-
     // if there's a pawn behind this one, it's doubled
-    if d.pawn_rank[IDARK][f] < row {
-        r -= DOUBLED_PAWN_PENALTY;
+    if pos.pawn_rank[IDARK][f] < row {
+        r -= params.doubled_pawn_penalty;
     }
 
     // if there aren't any friendly pawns on either side of this one, it's
     // isolated
-    if (d.pawn_rank[IDARK][f - 1] == 7) && (d.pawn_rank[IDARK][f + 1] == 7) {
-        r -= ISOLATED_PAWN_PENALTY;
+    if (pos.pawn_rank[IDARK][f - 1] == 7) && (pos.pawn_rank[IDARK][f + 1] == 7) {
+        r -= params.isolated_pawn_penalty;
     }
     // if it's not isolated, it might be backwards
-    else if (d.pawn_rank[IDARK][f - 1] > row) && (d.pawn_rank[IDARK][f + 1] > row) {
-        r -= BACKWARDS_PAWN_PENALTY;
+    else if (pos.pawn_rank[IDARK][f - 1] > row) && (pos.pawn_rank[IDARK][f + 1] > row) {
+        r -= params.backwards_pawn_penalty;
     }
 
     // add a bonus if the pawn is passed
-    if (d.pawn_rank[ILIGHT][f - 1] <= row)
-        && (d.pawn_rank[ILIGHT][f] <= row)
-        && (d.pawn_rank[ILIGHT][f + 1] <= row)
+    if (pos.pawn_rank[ILIGHT][f - 1] <= row)
+        && (pos.pawn_rank[ILIGHT][f] <= row)
+        && (pos.pawn_rank[ILIGHT][f + 1] <= row)
     {
-        r += row * PASSED_PAWN_BONUS;
+        r += row * params.passed_pawn_bonus;
     }
 
     r
 }
 
+// #rust With the "simd" feature, eval() evaluates all of a side's pawns at
+// once instead of one at a time. The material and piece/square values are
+// already running totals, so the pawns are the only loop left worth
+// vectorizing. std::simd is only in nightly Rust, so this works on the sets of
+// squares in pos.pawns instead, 64 squares to a u64 (SIMD within a register):
+// each of eval_light_pawn()'s and eval_dark_pawn()'s tests becomes a set of
+// the squares where the test passes, worked out with shifts and fills, and the
+// penalties and bonuses are counted with count_ones(). The results are exactly
+// the same as the scalar code's, including what it makes of pawn_rank[IDARK]
+// being the row of the side's most advanced pawn on each file, so that a dark
+// pawn is never counted as doubled.
+
+/// the squares of the h-file
+#[cfg(any(feature = "simd", test))]
+const FILE_H: u64 = FILE_A << 7;
+
+/// fill_up() returns the squares of x, and the squares above them (toward
+/// row 0) on the same files
+#[cfg(any(feature = "simd", test))]
+#[inline(always)]
+fn fill_up(mut x: u64) -> u64 {
+    x |= x >> 8;
+    x |= x >> 16;
+    x | x >> 32
+}
+
+/// fill_down() returns the squares of x, and the squares below them (toward
+/// row 7) on the same files
+#[cfg(any(feature = "simd", test))]
 #[inline(always)]
-fn eval_light_king(d: &Data, sq: usize) -> Int {
+fn fill_down(mut x: u64) -> u64 {
+    x |= x << 8;
+    x |= x << 16;
+    x | x << 32
+}
+
+/// neighbors() returns, for each square, whether both of the squares beside
+/// it are in x; a square on the edge of the board only needs the one it has
+#[cfg(any(feature = "simd", test))]
+#[inline(always)]
+fn neighbors(x: u64) -> u64 {
+    (((x << 1) & !FILE_A) | FILE_A) & (((x >> 1) & !FILE_H) | FILE_H)
+}
+
+/// eval_pawns() returns the sum of eval_light_pawn() for LIGHT's pawns and of
+/// eval_dark_pawn() for DARK's
+
+#[cfg(any(feature = "simd", test))]
+fn eval_pawns(pos: &Position, params: &EvalParams) -> [Int; 2] {
+    let light = pos.pawns[ILIGHT];
+    let dark = pos.pawns[IDARK];
+    let light_files = fill_up(fill_down(light));
+    let dark_files = fill_up(fill_down(dark));
+
+    // a pawn with no friendly pawns on the files beside it is isolated
+    let light_isolated = light & !(((light_files << 1) & !FILE_A) | ((light_files >> 1) & !FILE_H));
+    let dark_isolated = dark & !(((dark_files << 1) & !FILE_A) | ((dark_files >> 1) & !FILE_H));
+
+    // a light pawn with a friendly pawn behind it is doubled
+    let light_doubled = light & fill_up(light >> 8);
+
+    // a pawn that isn't isolated is backwards if the friendly pawns beside it
+    // are all ahead of it
+    let light_backwards = light & !light_isolated & neighbors(!fill_up(light));
+    let dark_backwards = dark & !dark_isolated & neighbors(fill_up(dark >> 8) | !dark_files);
+
+    // a pawn is passed if, on its file and the files beside it, the enemy's
+    // most advanced pawn isn't behind it
+    let dark_ahead = fill_up(dark) | !dark_files;
+    let light_passed = light & dark_ahead & neighbors(dark_ahead);
+    let light_ahead = !fill_up(light >> 8);
+    let dark_passed = dark & light_ahead & neighbors(light_ahead);
+
+    // the passed pawn bonus goes up by passed_pawn_bonus a row, so add the
+    // pawns up one row at a time
+    let mut light_rows = 0;
+    let mut dark_rows = 0;
+    for row in 1..8 {
+        light_rows += (light_passed & ((1 << (8 * row)) - 1)).count_ones() as Int;
+        dark_rows += (dark_passed >> (8 * row)).count_ones() as Int;
+    }
+
+    [
+        light_rows * params.passed_pawn_bonus
+            - light_doubled.count_ones() as Int * params.doubled_pawn_penalty
+            - light_isolated.count_ones() as Int * params.isolated_pawn_penalty
+            - light_backwards.count_ones() as Int * params.backwards_pawn_penalty,
+        dark_rows * params.passed_pawn_bonus
+            - dark_isolated.count_ones() as Int * params.isolated_pawn_penalty
+            - dark_backwards.count_ones() as Int * params.backwards_pawn_penalty,
+    ]
+}
+
+#[inline(always)]
+fn eval_light_king(pos: &Position, sq: usize) -> Int {
+    if pos.piece_mat[IDARK] <= 1200 {
+        KING_ENDGAME_PCSQ[sq]
+    } else {
+        eval_light_king_safety(pos, sq)
+    }
+}
+
+#[inline(always)]
+fn eval_light_king_safety(pos: &Position, sq: usize) -> Int {
     // the value to return
     let mut r = KING_PCSQ[sq];
 
@@ -306,18 +563,18 @@ fn eval_light_king(d: &Data, sq: usize) -> Int {
     // if the king is castled, use a special function to evaluate the pawns on
     // the appropriate side
     if col < 3 {
-        r += eval_lkp(d, 1);
-        r += eval_lkp(d, 2);
-        r += eval_lkp(d, 3) / 2; // problems with pawns on the c & f files are not as severe
+        r += eval_lkp(pos, 1);
+        r += eval_lkp(pos, 2);
+        r += eval_lkp(pos, 3) / 2; // problems with pawns on the c & f files are not as severe
     } else if col > 4 {
-        r += eval_lkp(d, 8);
-        r += eval_lkp(d, 7);
-        r += eval_lkp(d, 6) / 2;
+        r += eval_lkp(pos, 8);
+        r += eval_lkp(pos, 7);
+        r += eval_lkp(pos, 6) / 2;
     }
     // otherwise just assess a penalty if there are open files near the king
     else {
         for i in (col as usize)..=(col as usize + 2) {
-            if (d.pawn_rank[ILIGHT][i] == 0) && (d.pawn_rank[IDARK][i] == 7) {
+            if (pos.pawn_rank[ILIGHT][i] == 0) && (pos.pawn_rank[IDARK][i] == 7) {
                 r -= 10;
             }
         }
@@ -326,7 +583,7 @@ fn eval_light_king(d: &Data, sq: usize) -> Int {
     // scale the king safely value according to the opponent's material; the
     // premise is that your king safety can only be bad if the opponent has
     // enough pieces to attack you.
-    r *= d.piece_mat[IDARK];
+    r *= pos.piece_mat[IDARK];
     r /= 3100;
 
     r
@@ -335,10 +592,10 @@ fn eval_light_king(d: &Data, sq: usize) -> Int {
 /// eval_lkp(f) evaluates the Light King Pawn on file f
 
 #[inline(always)]
-fn eval_lkp(d: &Data, f: usize) -> Int {
+fn eval_lkp(pos: &Position, f: usize) -> Int {
     let mut r = 0;
 
-    let rank_light = d.pawn_rank[ILIGHT][f];
+    let rank_light = pos.pawn_rank[ILIGHT][f];
 
     match rank_light {
         6 => (),      // pawn hasn't moved
@@ -347,7 +604,7 @@ fn eval_lkp(d: &Data, f: usize) -> Int {
         _ => r -= 20, // pawn moved more than one square
     }
 
-    let rank_dark = d.pawn_rank[IDARK][f];
+    let rank_dark = pos.pawn_rank[IDARK][f];
 
     match rank_dark {
         7 => r -= 15, // no enemy pawn
@@ -360,37 +617,46 @@ fn eval_lkp(d: &Data, f: usize) -> Int {
 }
 
 #[inline(always)]
-fn eval_dark_king(d: &Data, sq: usize) -> Int {
+fn eval_dark_king(pos: &Position, sq: usize) -> Int {
+    if pos.piece_mat[ILIGHT] <= 1200 {
+        KING_ENDGAME_PCSQ[FLIP[sq]]
+    } else {
+        eval_dark_king_safety(pos, sq)
+    }
+}
+
+#[inline(always)]
+fn eval_dark_king_safety(pos: &Position, sq: usize) -> Int {
     let mut r = KING_PCSQ[FLIP[sq]];
 
     let col = col!(sq as Int);
 
     if col < 3 {
-        r += eval_dkp(d, 1);
-        r += eval_dkp(d, 2);
-        r += eval_dkp(d, 3) / 2;
+        r += eval_dkp(pos, 1);
+        r += eval_dkp(pos, 2);
+        r += eval_dkp(pos, 3) / 2;
     } else if col > 4 {
-        r += eval_dkp(d, 8);
-        r += eval_dkp(d, 7);
-        r += eval_dkp(d, 6) / 2;
+        r += eval_dkp(pos, 8);
+        r += eval_dkp(pos, 7);
+        r += eval_dkp(pos, 6) / 2;
     } else {
         for i in (col as usize)..=(col as usize + 2) {
-            if (d.pawn_rank[ILIGHT][i] == 0) && (d.pawn_rank[IDARK][i] == 7) {
+            if (pos.pawn_rank[ILIGHT][i] == 0) && (pos.pawn_rank[IDARK][i] == 7) {
                 r -= 10;
             }
         }
     }
-    r *= d.piece_mat[ILIGHT];
+    r *= pos.piece_mat[ILIGHT];
     r /= 3100;
 
     r
 }
 
 #[inline(always)]
-fn eval_dkp(d: &Data, f: usize) -> Int {
+fn eval_dkp(pos: &Position, f: usize) -> Int {
     let mut r = 0;
 
-    let rank_dark = d.pawn_rank[IDARK][f];
+    let rank_dark = pos.pawn_rank[IDARK][f];
 
     match rank_dark {
         1 => (),
@@ -399,7 +665,7 @@ fn eval_dkp(d: &Data, f: usize) -> Int {
         _ => r -= 20,
     }
 
-    let rank_light = d.pawn_rank[ILIGHT][f];
+    let rank_light = pos.pawn_rank[ILIGHT][f];
 
     match rank_light {
         0 => r -= 15,
@@ -410,3 +676,87 @@ fn eval_dkp(d: &Data, f: usize) -> Int {
 
     r
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board;
+    use crate::data::Data;
+
+    #[test]
+    fn test_eval_terms() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bq1rk1/pp3ppp/2n1p3/3p4/1b1P4/2NBPN2/PP3PPP/R2QK2R b KQ - 0 9",
+            "6k1/1R3pp1/7p/8/2P5/6P1/5PKP/3r4 w - - 0 40",
+        ] {
+            board::set_fen(&mut d, fen).unwrap();
+            let terms = eval_terms(&mut d.pos);
+            let score = eval(&mut d.pos);
+            if d.pos.side == LIGHT {
+                assert_eq!(terms.total(), score, "{}", fen);
+            } else {
+                assert_eq!(terms.total(), -score, "{}", fen);
+            }
+        }
+
+        // both rooks are on open files, but only white's is on the seventh,
+        // and white's c pawn is passed
+        assert_eq!(eval_terms(&mut d.pos).rooks, ROOK_ON_SEVENTH_BONUS);
+        assert!(eval_terms(&mut d.pos).pawns > 0);
+    }
+
+    #[test]
+    fn test_eval_pawns() {
+        // eval_pawns() has to agree with eval_light_pawn() and eval_dark_pawn()
+        // on every position, so try it on each position two ply from some with
+        // lots of pawn structure
+        fn check(pos: &Position) {
+            let mut scalar = [0; 2];
+            for i in Squares(pos.pawns[ILIGHT]) {
+                scalar[ILIGHT] += eval_light_pawn(pos, i, &EvalParams::DEFAULT);
+            }
+            for i in Squares(pos.pawns[IDARK]) {
+                scalar[IDARK] += eval_dark_pawn(pos, i, &EvalParams::DEFAULT);
+            }
+            assert_eq!(
+                eval_pawns(pos, &EvalParams::DEFAULT),
+                scalar,
+                "{}",
+                board::fen(pos)
+            );
+        }
+
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bq1rk1/pp3ppp/2n1p3/3p4/1b1P4/2NBPN2/PP3PPP/R2QK2R b KQ - 0 9",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "4k3/p1pp1p1p/1p2p3/P2P1P2/1PP3P1/4P3/7P/4K3 w - - 0 1",
+            "k7/1p3P2/P7/2p1p3/2P1P3/p6p/1P3p2/7K b - - 0 1",
+        ] {
+            board::set_fen(&mut d, fen).unwrap();
+            check(&d.pos);
+            board::gen(&mut d);
+            for g in d.search.gen_dat[0].clone() {
+                if !board::makemove(&mut d.pos, g.m.bytes()) {
+                    continue;
+                }
+                check(&d.pos);
+                d.search.ply = 1;
+                board::gen(&mut d);
+                for g in d.search.gen_dat[1].clone() {
+                    if board::makemove(&mut d.pos, g.m.bytes()) {
+                        check(&d.pos);
+                        board::takeback(&mut d.pos);
+                    }
+                }
+                d.search.ply = 0;
+                board::takeback(&mut d.pos);
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+// handicap.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP only plays from the standard starting position.
+// A handicap (or odds) game starts from it with some of one side's pieces
+// taken off, the traditional way for a stronger player to give a weaker one
+// a chance. handicap_fen() makes the FEN of such a position, for
+// board::set_fen().
+//
+// Odds are only given by taking pieces away: giving the other side extra
+// pawns would leave it with more than eight, which no game can reach.
+
+use alloc::string::String;
+use core::fmt;
+use core::str::FromStr;
+
+use super::board;
+use super::data::Position;
+use super::defs::{Int, A1, A8, EMPTY, H1, H8, LIGHT};
+
+/// a piece that can be given as odds
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Odds {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+}
+
+impl Odds {
+    /// files() returns the files (0 is the a-file) of the pieces of this kind
+    /// that are taken off, in the order they're taken: the f-pawn first, as
+    /// in the traditional pawn odds, and the queenside knight, bishop, and
+    /// rook before the kingside ones
+    fn files(self) -> &'static [usize] {
+        match self {
+            Odds::Pawn => &[5, 4, 3, 2, 1, 0, 6, 7],
+            Odds::Knight => &[1, 6],
+            Odds::Bishop => &[2, 5],
+            Odds::Rook => &[0, 7],
+            Odds::Queen => &[3],
+        }
+    }
+}
+
+impl FromStr for Odds {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "p" | "pawn" => Ok(Odds::Pawn),
+            "n" | "knight" => Ok(Odds::Knight),
+            "b" | "bishop" => Ok(Odds::Bishop),
+            "r" | "rook" => Ok(Odds::Rook),
+            "q" | "queen" => Ok(Odds::Queen),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Odds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Odds::Pawn => "pawn",
+            Odds::Knight => "knight",
+            Odds::Bishop => "bishop",
+            Odds::Rook => "rook",
+            Odds::Queen => "queen",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// handicap_fen() returns the FEN of the starting position with the given
+/// pieces taken off side's side of the board (each one given as many times
+/// as there are pieces of its kind to take off), or None if side doesn't
+/// have that many. A rook that's taken off can't castle, of course.
+
+pub fn handicap_fen(side: Int, odds: &[Odds]) -> Option<String> {
+    let mut pos = Position::new();
+    board::init_board(&mut pos);
+    // the ranks (rows, from the top) of side's pieces and pawns
+    let (back, pawns) = if side == LIGHT { (7, 6) } else { (0, 1) };
+    for &piece in odds {
+        let row = if piece == Odds::Pawn { pawns } else { back };
+        let sq = piece
+            .files()
+            .iter()
+            .map(|file| row * 8 + file)
+            .find(|&sq| pos.color(sq) != EMPTY)?;
+        pos.color[sq] = EMPTY as i8;
+        pos.piece[sq] = EMPTY as i8;
+        match sq {
+            H1 => pos.castle &= !1,
+            A1 => pos.castle &= !2,
+            H8 => pos.castle &= !4,
+            A8 => pos.castle &= !8,
+            _ => {}
+        }
+    }
+    Some(board::fen(&pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defs::DARK;
+
+    #[test]
+    fn test_handicap_fen() {
+        assert_eq!(
+            handicap_fen(DARK, &[Odds::Pawn]).unwrap(),
+            "rnbqkbnr/ppppp1pp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert_eq!(
+            handicap_fen(LIGHT, &[Odds::Rook, Odds::Knight]).unwrap(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/2BQKBNR w Kkq - 0 1"
+        );
+        assert_eq!(
+            handicap_fen(DARK, &[Odds::Queen, Odds::Rook, Odds::Rook]).unwrap(),
+            "1nb1kbn1/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1"
+        );
+        assert_eq!(handicap_fen(LIGHT, &[Odds::Queen, Odds::Queen]), None);
+        assert_eq!(handicap_fen(LIGHT, &[Odds::Pawn; 9]), None);
+        assert_eq!("knight".parse(), Ok(Odds::Knight));
+        assert_eq!("q".parse(), Ok(Odds::Queen));
+        assert!("king".parse::<Odds>().is_err());
+    }
+}
@@ -5,28 +5,98 @@
 //
 // Rust port by Kristopher Johnson
 
+// #rust The translated code puts a blank line between a function's doc comment
+// and the function itself, which newer versions of clippy complain about.
+#![allow(clippy::empty_line_after_doc_comments)]
+// #rust Without the "std" feature, the engine core (board, search, eval, and
+// the opening book) is no_std, and only needs an allocator.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "cli")]
 use std::io;
+#[cfg(feature = "cli")]
 use std::io::prelude::*;
-
-extern crate libc;
+#[cfg(feature = "cli")]
+use std::io::IsTerminal;
+#[cfg(feature = "cli")]
+use std::path::{Path, PathBuf};
 
 #[macro_use]
 pub mod defs;
 
+#[cfg(feature = "cli")]
+pub mod analysis;
+#[cfg(feature = "cli")]
+pub mod annotate;
+#[cfg(feature = "cli")]
 pub mod bench;
 pub mod board;
 pub mod book;
+pub mod clock;
 pub mod data;
+#[cfg(feature = "std")]
+pub mod engine;
+pub mod epd;
 pub mod eval;
+pub mod handicap;
+pub mod perft;
+pub mod pgn;
+#[cfg(feature = "cli")]
+pub mod puzzle;
+pub mod rand;
+#[cfg(feature = "cli")]
 pub mod scan;
 pub mod search;
+#[cfg(feature = "cli")]
+pub mod selfplay;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "cli")]
+pub mod sprt;
+#[cfg(feature = "cli")]
+pub mod tournament;
+#[cfg(feature = "cli")]
+pub mod transcript;
+pub mod tt;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "cli")]
+pub mod tune;
+#[cfg(feature = "cli")]
+pub mod uci;
 pub mod util;
+#[cfg(feature = "cli")]
 pub mod xboard;
 
+#[cfg(feature = "cli")]
+use bench::BenchConfig;
+#[cfg(feature = "cli")]
+use clock::ChessClock;
+#[cfg(feature = "cli")]
 use data::Data;
-use defs::EMPTY;
-use search::ThinkOutput::*;
+#[cfg(feature = "cli")]
+use defs::{GameResult, Int, ParseMoveError, DARK, EMPTY, LIGHT, MAX_PLY};
+#[cfg(feature = "cli")]
+use handicap::Odds;
+#[cfg(feature = "cli")]
+use search::{Humanlike, NormalOutput, Resignation, SearchLimits, Variety};
+#[cfg(feature = "cli")]
+use sprt::SprtConfig;
+#[cfg(feature = "cli")]
+use tournament::{Format, Player, TournamentConfig};
+#[cfg(feature = "cli")]
+use tune::TuneConfig;
+#[cfg(feature = "cli")]
+use util::BoardStyle;
 
+// #rust The "cli" feature (on by default) adds the console and XBoard front
+// ends, which read commands from standard input. Without it, the crate is just
+// the engine: the board, search, evaluation, and opening book, plus (with the
+// "std" feature) the Engine API for running them on a thread.
+
+#[cfg(feature = "cli")]
 const BANNER: &str = "\n\
     Tom Kerrigan's Simple Chess Program (TSCP)\n\
     version 1.81c, 2/3/19\n\
@@ -36,150 +106,1318 @@ const BANNER: &str = "\n\
     \n\
     \"help\" displays a list of commands.\n";
 
+#[cfg(feature = "cli")]
 const HELP: &str = "on - computer plays for the side to move\n\
     off - computer stops playing\n\
+    go - computer plays for the side to move, starting now (same as on)\n\
+    force - computer stops playing, so you move for both sides (same as off)\n\
+    switch - computer plays the other side\n\
     st n - search for n seconds per move\n\
     sd n - search n ply per move\n\
-    undo - takes back a move\n\
+    clock m [inc] - play with a clock: m minutes each, plus inc seconds a move\n\
+    clock [off] - show the clock, or stop playing with one (st and sd apply again)\n\
+    skill n - play at skill level n, from 1 (weakest) to 10 (full strength)\n\
+    elo n - play at the skill level for an Elo rating of about n\n\
+    variety [n [cp]|off] - in the first n moves, play any move within cp centipawns (20) of the best\n\
+    human [n|off] - vary the computer's moves like a person, from 1 (loosely) to 10 (closely)\n\
+    hash [n|clear] - show the transposition table's size, set it to n MB (0 for none), or empty it\n\
+    ttstats - show how full the transposition table is, and its hits, collisions, and overwrites\n\
+    resign n - computer resigns when its score stays below -n centipawns (900)\n\
+    resign [off] - show when the computer resigns, or make it never resign\n\
+    undo [n] - takes back a move, or n moves (half-moves, not pairs)\n\
     new - starts a new game\n\
+    handicap piece... [white|black] - new game with pawn, knight, bishop, rook, or queen odds given by a side\n\
     d - display the board\n\
-    bench - run the built-in benchmark\n\
+    hint - suggest a move, with its score and the line that's expected to follow\n\
+    coach - switch coaching on or off: after each of your moves, compare it with the engine's\n\
+    debug [on|off] - switch on or off explanations of the engine's book moves, time, and stopping\n\
+    blunder [n|off] - ask before making a move that's over n centipawns worse than the engine's\n\
+    moves - show the moves of the game so far\n\
+    analyze [depth] - review the game's moves, searching depth ply (4): mistakes and accuracy\n\
+    flip - turn the board around, to see it from the other side\n\
+    unicode - switch between chess symbols and letters for the pieces\n\
+    color - switch between a colored board and a plain one\n\
+    export file.svg [arrow] - save an image of the board, with an arrow for the last move\n\
+    bench [depth [n]] [fen] - run the benchmark, searching depth ply (5) n times (3)\n\
+    bench suite [depth] - run the benchmark on a set of positions, to depth ply (6)\n\
+    regress - check the search's results against the reference results in regress.txt\n\
+    regress save file - save the search's current results as the reference results\n\
+    selfplay [n [file]] - computer plays n games against itself, saving them to file as PGN\n\
+    tournament rr|gauntlet n player... [file.pgn] - n games a pairing between players, e.g., skill=5 name=Deep,depth=6\n\
+    sprt elo0 elo1 n test base - up to n games, until test is shown to be elo1 (or only elo0) stronger than base\n\
+    tune n player - tune the evaluation's and the search's parameters with n SPSA game pairs of player, e.g., depth=3,variety=4\n\
+    puzzle file - solve the puzzles in an EPD file, checked against their best moves (bm)\n\
+    book list - show the book lines that lead through this position\n\
+    book add - add this game's moves to the book as a new line\n\
+    book delete n - delete line n of the book\n\
+    explore - list the book moves for this position\n\
+    perft n - count the leaf nodes of the move tree n ply deep\n\
+    perft bench - time perft on a set of positions, as a benchmark of the move generator\n\
+    divide n - like perft, but shows the count for each move\n\
     bye - exit the program\n\
     xboard - switch to XBoard mode\n\
+    uci - switch to UCI mode\n\
     Enter moves in coordinate notation, e.g., e2e4, e7e8Q";
 
+#[cfg(feature = "cli")]
+pub const USAGE: &str = "usage: tscp [options]\n\
+    \n\
+    --xboard       start in XBoard mode\n\
+    --uci          start in UCI mode\n\
+    --bench        run the benchmark (at --depth, on --fen, if given), and exit\n\
+    --depth n      search n ply per move (like the sd command)\n\
+    --st n         search for n seconds per move (like the st command)\n\
+    --hash n       use an n MB transposition table (like the hash command)\n\
+    --book path    use the opening book at path, instead of book.txt\n\
+    --fen fen      start from the position fen (quoted, since it has spaces)\n\
+    --log path     write everything that's read and written to the file at path\n\
+    --debug        explain the engine's decisions (like the debug command)\n\
+    --help         show this message";
+
+/// the options that can be given on the command line; see parse_args()
+
+#[cfg(feature = "cli")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    /// start in XBoard mode, as though the "xboard" command had been entered
+    pub xboard: bool,
+    /// start in UCI mode, as though the "uci" command had been entered
+    pub uci: bool,
+    /// run the benchmark and exit
+    pub bench: bool,
+    /// the search depth, and the seconds per move; if neither is given, the
+    /// console searches 4 ply
+    pub depth: Option<Int>,
+    pub st: Option<Int>,
+    /// the size of the transposition table, in MB (0 or None for no table)
+    pub hash: Option<usize>,
+    /// the opening book file, instead of book::BOOK_FILE
+    pub book: Option<PathBuf>,
+    /// the position to start from, in FEN
+    pub fen: Option<String>,
+    /// the file to write a transcript to, of all the input and output
+    pub log: Option<PathBuf>,
+    /// explain the engine's decisions, as though "debug on" had been entered
+    pub debug: bool,
+    /// show the usage message and exit
+    pub help: bool,
+}
+
+/// parse_args() reads the command line arguments (without the program's
+/// name) into Options. It returns a message saying what's wrong if they don't
+/// make sense.
+
+#[cfg(feature = "cli")]
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_ref() {
+            "--xboard" => options.xboard = true,
+            "--uci" => options.uci = true,
+            "--bench" => options.bench = true,
+            "--debug" => options.debug = true,
+            "--help" | "-h" => options.help = true,
+            "--depth" | "--st" => {
+                let n = value()?;
+                let n = match n.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("bad {} value: {}", arg, n)),
+                };
+                if arg == "--depth" {
+                    if n > MAX_PLY as Int {
+                        return Err(format!(
+                            "bad {} value: {} (the most is {})",
+                            arg, n, MAX_PLY
+                        ));
+                    }
+                    options.depth = Some(n);
+                } else {
+                    options.st = Some(n);
+                }
+            }
+            "--hash" => {
+                let n = value()?;
+                match n.parse() {
+                    Ok(mb) if mb <= engine::MAX_HASH_MB => options.hash = Some(mb),
+                    _ => return Err(format!("bad {} value: {}", arg, n)),
+                }
+            }
+            "--book" => options.book = Some(PathBuf::from(value()?)),
+            "--fen" => options.fen = Some(value()?),
+            "--log" => options.log = Some(PathBuf::from(value()?)),
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+    if options.xboard && options.uci {
+        return Err(String::from("--xboard and --uci can't be used together"));
+    }
+    if options.xboard && options.fen.is_some() {
+        // XBoard sets the position up itself
+        return Err(String::from("--fen can't be used with --xboard"));
+    }
+    if options.uci && options.fen.is_some() {
+        // so does a UCI GUI
+        return Err(String::from("--fen can't be used with --uci"));
+    }
+    Ok(options)
+}
+
+/// run() is the console interface (the C version's main()), which plays on
+/// standard output.
+
+#[cfg(feature = "cli")]
 pub fn run() {
-    println!("{}", BANNER);
+    run_with(&Options::default());
+}
+
+/// run_with() is run() with the given options, e.g., from the command line
+
+#[cfg(feature = "cli")]
+pub fn run_with(options: &Options) {
+    if let Err(err) = console_with(options, &mut io::stdout()) {
+        eprintln!("output error: {}", err);
+    }
+}
+
+/// console() is run() with the output going to out instead of standard output.
+
+#[cfg(feature = "cli")]
+pub fn console(out: &mut dyn Write) -> io::Result<()> {
+    console_with(&Options::default(), out)
+}
+
+/// console_with() is run_with() with the output going to out instead of
+/// standard output.
+
+#[cfg(feature = "cli")]
+pub fn console_with(options: &Options, out: &mut dyn Write) -> io::Result<()> {
+    if options.help {
+        return writeln!(out, "{}", USAGE);
+    }
+    if let Some(path) = &options.log {
+        if let Err(err) = transcript::start(path) {
+            writeln!(out, "unable to write {}: {}", path.display(), err)?;
+            return Ok(());
+        }
+    }
+    let result = play(options, &mut transcript::Logged(out));
+    transcript::stop();
+    result
+}
+
+/// play() is console_with() after the transcript is set up
+
+#[cfg(feature = "cli")]
+fn play(options: &Options, out: &mut dyn Write) -> io::Result<()> {
+    // #rust A GUI that starts TSCP sends "xboard" (or "uci") before anything
+    // else, and the C version only switches to XBoard mode when the console
+    // reads it, after the banner and a prompt have gone to the GUI. When
+    // standard input isn't a terminal, the first command is read before
+    // anything is written, to see which protocol the other end speaks; a
+    // person at a terminal gets the banner straight away, and can still type
+    // "xboard" or "uci".
+    let mut first = None;
+    let mut xboard = options.xboard;
+    let mut uci = options.uci;
+    // greet is set if the "uci" command has been read, and needs an answer
+    let mut greet = false;
+    if !options.xboard && !options.uci && !options.bench && !io::stdin().is_terminal() {
+        match scan::scan_token_line() {
+            Ok((s, _)) if s == "xboard" => xboard = true,
+            Ok((s, _)) if s == "uci" => {
+                uci = true;
+                greet = true;
+            }
+            token => first = Some(token),
+        }
+    }
+    if !xboard && !uci {
+        writeln!(out, "{}", BANNER)?;
+    }
 
+    let book_path = options
+        .book
+        .as_deref()
+        .unwrap_or(Path::new(book::BOOK_FILE));
     let mut d = Data::new();
-    board::init_hash(&mut d);
-    board::init_board(&mut d);
-    book::open_book(&mut d);
-    board::gen(&mut d);
+    board::init_hash(&mut d.pos);
+    board::init_board(&mut d.pos);
+    book::open_book(&mut d, book_path);
+    board::gen_legal(&mut d);
     let mut computer_side = EMPTY;
-    d.max_time = 1 << 25;
-    d.max_depth = 4;
+    let mut style = BoardStyle::default();
+    let mut clock: Option<ChessClock> = None;
+    let mut resignation = Resignation::default();
+    let mut coach = false;
+    let mut blunder_check: Option<Int> = None;
+    // the opening book, while it's put aside for a handicap game
+    let mut saved_books = None;
+    let mut limits = SearchLimits {
+        depth: options.depth,
+        movetime: options.st.map(|n| n * 1000),
+        ..SearchLimits::default()
+    };
+    if options.depth.is_none() && options.st.is_none() {
+        limits.depth = Some(4);
+    }
+    if let Some(mb) = options.hash {
+        util::set_hash_mb(&mut d, mb).expect("checked by parse_args()");
+    }
+    d.search.debug = options.debug;
+    if let Some(fen) = &options.fen {
+        if let Err(err) = board::set_fen(&mut d, fen) {
+            writeln!(out, "Bad FEN ({}): {}", err, fen)?;
+            return Ok(());
+        }
+    }
+    if options.bench {
+        let config = BenchConfig {
+            depth: options.depth.unwrap_or(5),
+            fen: options.fen.clone(),
+            ..BenchConfig::default()
+        };
+        return bench::bench_with(&mut d, &config, out);
+    }
+    if xboard {
+        return xboard::xboard(&mut d, limits, out);
+    }
+    if uci {
+        return uci::uci(&mut d, greet, out);
+    }
+    scan::start_line_editing();
     loop {
-        if d.side == computer_side {
+        if d.pos.side == computer_side {
             // computer's turn
 
-            // think about the move and make it
-            search::think(&mut d, NormalOutput);
-            if d.pv[0][0].value() == 0 {
-                println!("(no legal moves");
+            // think about the move and make it (on the clock, if there is
+            // one)
+            let clock_limits = clock.as_ref().map(|clock| clock.limits(util::get_ms()));
+            let limits = clock_limits.as_ref().unwrap_or(&limits);
+            let result = search::think(&mut d, limits, &mut NormalOutput(out))?;
+            let m = match result.best_move {
+                Some(m) => m,
+                None => {
+                    writeln!(out, "(no legal moves")?;
+                    computer_side = EMPTY;
+                    continue;
+                }
+            };
+            if resignation.update(&result) {
+                writeln!(out, "TSCP resigns")?;
+                if d.pos.side == LIGHT {
+                    writeln!(out, "0-1 {{White resigns}}")?;
+                } else {
+                    writeln!(out, "1-0 {{Black resigns}}")?;
+                }
                 computer_side = EMPTY;
                 continue;
             }
-            let m = d.pv[0][0].bytes();
-            println!("Computer's move: {}", util::move_str(m));
-            board::makemove(&mut d, m);
-            d.ply = 0;
-            board::gen(&mut d);
-            util::print_result(&mut d);
+            writeln!(out, "Computer's move: {}", util::move_str(m))?;
+            board::makemove(&mut d.pos, m);
+            board::gen_legal(&mut d);
+            if !punch_clock(clock.as_mut(), &mut d, out)? {
+                computer_side = EMPTY;
+            }
+            util::print_result(&mut d, out)?;
             continue;
         }
 
         // get user input
-        print!("tscp> ");
-        io::stdout().flush().expect("unable to flush prompt output");
-        let s = match scan::scan_token() {
-            Ok(s) => s,
+        scan::prompt("tscp> ", out)?;
+        let (s, line_ended) = match first.take().unwrap_or_else(scan::scan_token_line) {
+            Ok(token) => token,
             Err(err) => {
-                println!("input error: {}", err);
-                return;
+                writeln!(out, "input error: {}", err)?;
+                return Ok(());
             }
         };
         if s.is_empty() {
             // EOF
-            return;
+            return Ok(());
         }
         match s.as_ref() {
-            "on" => {
-                computer_side = d.side;
+            "on" | "go" => {
+                computer_side = d.pos.side;
                 continue;
             }
-            "off" => {
+            "off" | "force" => {
                 computer_side = EMPTY;
                 continue;
             }
+            "switch" => {
+                // if the computer wasn't playing, it takes the side that isn't
+                // to move, so the user moves next
+                computer_side = match computer_side {
+                    LIGHT => DARK,
+                    DARK => LIGHT,
+                    _ => d.pos.xside,
+                };
+                continue;
+            }
             "st" => {
                 let n = match scan::scan_int() {
                     Ok(n) => n,
                     Err(err) => {
-                        println!("unable to read st argument: {}", err);
-                        return;
+                        writeln!(out, "unable to read st argument: {}", err)?;
+                        return Ok(());
                     }
                 };
-                d.max_time = n * 1000;
-                d.max_depth = 32;
+                limits = SearchLimits {
+                    movetime: Some(n * 1000),
+                    ..SearchLimits::default()
+                };
                 continue;
             }
             "sd" => {
                 let n = match scan::scan_int() {
                     Ok(n) => n,
                     Err(err) => {
-                        println!("unable to read sd argument: {}", err);
-                        return;
+                        writeln!(out, "unable to read sd argument: {}", err)?;
+                        return Ok(());
                     }
                 };
-                d.max_depth = n;
-                d.max_time = 1 << 25;
+                limits = SearchLimits {
+                    depth: Some(n),
+                    ..SearchLimits::default()
+                };
+                continue;
+            }
+            "skill" | "elo" => {
+                let n = match scan::scan_int() {
+                    Ok(n) => n,
+                    Err(err) => {
+                        writeln!(out, "unable to read {} argument: {}", s, err)?;
+                        return Ok(());
+                    }
+                };
+                d.search.skill = if s == "elo" {
+                    search::skill_for_elo(n)
+                } else {
+                    n.clamp(search::MIN_SKILL, search::MAX_SKILL)
+                };
+                writeln!(out, "Skill level {}", d.search.skill)?;
+                continue;
+            }
+            "hash" => {
+                let args = scan::scan_args(line_ended)?;
+                match args.first().map(String::as_str) {
+                    None => {}
+                    Some("clear") => {
+                        if let Some(tt) = &d.search.tt {
+                            tt.clear();
+                        }
+                    }
+                    Some(arg) => {
+                        let result = match arg.parse::<usize>() {
+                            Ok(mb) => util::set_hash_mb(&mut d, mb),
+                            Err(_) => Err(format!("Bad hash argument: {}", arg)),
+                        };
+                        if let Err(err) = result {
+                            writeln!(out, "{}", err)?;
+                            continue;
+                        }
+                    }
+                }
+                writeln!(out, "{}", util::hash_str(&d))?;
+                continue;
+            }
+            "ttstats" => {
+                util::print_tt_stats(&d, out)?;
+                continue;
+            }
+            "human" => {
+                let args = scan::scan_args(line_ended)?;
+                match args.first().map(String::as_str) {
+                    None => {}
+                    Some("off") => d.search.humanlike = None,
+                    Some(arg) => match arg.parse::<Int>() {
+                        Ok(n)
+                            if (search::MIN_HUMAN_LEVEL..=search::MAX_HUMAN_LEVEL).contains(&n) =>
+                        {
+                            d.search.humanlike = Some(Humanlike::level(n))
+                        }
+                        _ => {
+                            writeln!(out, "Bad human argument: {}", arg)?;
+                            continue;
+                        }
+                    },
+                }
+                match d.search.humanlike {
+                    Some(humanlike) => writeln!(
+                        out,
+                        "Humanlike play: moves within {} centipawns of the best, {}% inaccuracies",
+                        humanlike.margin, humanlike.inaccuracy
+                    )?,
+                    None => writeln!(out, "Humanlike play off")?,
+                }
+                continue;
+            }
+            "variety" => {
+                let args = scan::scan_args(line_ended)?;
+                match args.first().map(String::as_str) {
+                    None => {}
+                    Some("off") => d.search.variety = None,
+                    Some(_) => match variety_args(&args) {
+                        Some(variety) => d.search.variety = Some(variety),
+                        None => {
+                            writeln!(out, "Bad variety arguments: {}", args.join(" "))?;
+                            continue;
+                        }
+                    },
+                }
+                match d.search.variety {
+                    Some(variety) => writeln!(
+                        out,
+                        "Opening variety: the first {} moves are within {} centipawns of the best",
+                        variety.moves, variety.margin
+                    )?,
+                    None => writeln!(out, "Opening variety off")?,
+                }
+                continue;
+            }
+            "resign" => {
+                let arg = if line_ended {
+                    String::new()
+                } else {
+                    match scan::scan_token() {
+                        Ok(arg) => arg,
+                        Err(err) => {
+                            writeln!(out, "unable to read resign argument: {}", err)?;
+                            return Ok(());
+                        }
+                    }
+                };
+                if arg == "off" {
+                    resignation.threshold = None;
+                } else if !arg.is_empty() {
+                    match arg.parse::<Int>() {
+                        // it's a score to be below, whichever way it's given
+                        Ok(n) => resignation.threshold = Some(-n.abs()),
+                        Err(_) => {
+                            writeln!(out, "Bad resign argument: {}", arg)?;
+                            continue;
+                        }
+                    }
+                    resignation.reset();
+                }
+                match resignation.threshold {
+                    Some(threshold) => writeln!(
+                        out,
+                        "The computer resigns after {} moves below {}",
+                        resignation.moves, threshold
+                    )?,
+                    None => writeln!(out, "The computer doesn't resign")?,
+                }
+                continue;
+            }
+            "coach" => {
+                coach = !coach;
+                writeln!(out, "Coach {}", if coach { "on" } else { "off" })?;
+                continue;
+            }
+            "debug" => {
+                let args = scan::scan_args(line_ended)?;
+                match args.first().map(String::as_str) {
+                    None => d.search.debug = !d.search.debug,
+                    Some("on") => d.search.debug = true,
+                    Some("off") => d.search.debug = false,
+                    Some(arg) => {
+                        writeln!(out, "Bad debug argument: {}", arg)?;
+                        continue;
+                    }
+                }
+                writeln!(out, "Debug {}", if d.search.debug { "on" } else { "off" })?;
+                continue;
+            }
+            "blunder" => {
+                let args = scan::scan_args(line_ended)?;
+                match args.first().map(String::as_str) {
+                    None => {}
+                    Some("off") => blunder_check = None,
+                    Some(arg) => match arg.parse::<Int>() {
+                        Ok(n) if n > 0 => blunder_check = Some(n),
+                        _ => {
+                            writeln!(out, "Bad blunder argument: {}", arg)?;
+                            continue;
+                        }
+                    },
+                }
+                match blunder_check {
+                    Some(n) => writeln!(
+                        out,
+                        "Moves that are more than {} centipawns worse than the engine's choice need confirming",
+                        n
+                    )?,
+                    None => writeln!(out, "Blunder check off")?,
+                }
                 continue;
             }
             "undo" => {
-                if d.hply == 0 {
-                    continue;
+                let mut n = 1;
+                if !line_ended {
+                    n = match scan::scan_int() {
+                        Ok(n) if n > 0 => n as usize,
+                        Ok(_) => continue,
+                        Err(err) => {
+                            writeln!(out, "unable to read undo argument: {}", err)?;
+                            return Ok(());
+                        }
+                    };
+                }
+                // if that leaves the computer to move, it stops playing, so it
+                // doesn't just play the move again; otherwise it keeps its side
+                if !board::undo_moves(&mut d, n).is_empty() && d.pos.side == computer_side {
+                    computer_side = EMPTY;
+                }
+                // the time that was spent isn't given back
+                if let Some(clock) = clock.as_mut() {
+                    clock.start(d.pos.side, util::get_ms());
                 }
-                computer_side = EMPTY;
-                board::takeback(&mut d);
-                d.ply = 0;
-                board::gen(&mut d);
                 continue;
             }
             "new" => {
                 computer_side = EMPTY;
-                board::init_board(&mut d);
-                board::gen(&mut d);
+                if let Some(books) = saved_books.take() {
+                    d.books = books;
+                }
+                board::new_game(&mut d, true);
+                resignation.reset();
+                if let Some(clock) = clock.as_mut() {
+                    clock.reset();
+                    clock.start(d.pos.side, util::get_ms());
+                }
+                continue;
+            }
+            "handicap" => {
+                let args = scan::scan_args(line_ended)?;
+                let mut side = if computer_side == EMPTY {
+                    DARK
+                } else {
+                    computer_side
+                };
+                let mut odds = Vec::new();
+                let mut bad = None;
+                for arg in &args {
+                    match arg.as_str() {
+                        "white" => side = LIGHT,
+                        "black" => side = DARK,
+                        _ => match arg.parse::<Odds>() {
+                            Ok(piece) => odds.push(piece),
+                            Err(()) => bad = Some(arg),
+                        },
+                    }
+                }
+                if let Some(arg) = bad {
+                    writeln!(out, "Bad handicap argument: {}", arg)?;
+                    continue;
+                }
+                if odds.is_empty() {
+                    writeln!(
+                        out,
+                        "usage: handicap pawn|knight|bishop|rook|queen... [white|black]"
+                    )?;
+                    continue;
+                }
+                let fen = match handicap::handicap_fen(side, &odds) {
+                    Some(fen) => fen,
+                    None => {
+                        writeln!(out, "There aren't that many pieces to give")?;
+                        continue;
+                    }
+                };
+                board::new_game(&mut d, true);
+                board::set_fen(&mut d, &fen).expect("handicap_fen() made a bad FEN");
+                // the book is for the standard starting position
+                if saved_books.is_none() {
+                    saved_books = Some(std::mem::take(&mut d.books));
+                }
+                resignation.reset();
+                if let Some(clock) = clock.as_mut() {
+                    clock.reset();
+                    clock.start(d.pos.side, util::get_ms());
+                }
+                let names: Vec<String> = odds.iter().map(Odds::to_string).collect();
+                writeln!(
+                    out,
+                    "{} gives odds of {} (\"new\" starts a normal game)",
+                    if side == LIGHT { "White" } else { "Black" },
+                    names.join(", ")
+                )?;
+                util::print_board(&d.pos, &style, out)?;
+                continue;
+            }
+            "clock" => {
+                let args = scan::scan_args(line_ended)?;
+                let now = util::get_ms();
+                match args.first().map(String::as_str) {
+                    None => match &clock {
+                        Some(clock) => writeln!(out, "{}", clock.status(now))?,
+                        None => writeln!(out, "No clock.")?,
+                    },
+                    Some("off") => clock = None,
+                    Some(_) => match clock_args(&args) {
+                        Some((base, inc)) => {
+                            let mut new_clock = ChessClock::new(base, inc);
+                            new_clock.start(d.pos.side, now);
+                            writeln!(out, "{}", new_clock.status(now))?;
+                            clock = Some(new_clock);
+                        }
+                        None => writeln!(out, "Bad clock arguments: {}", args.join(" "))?,
+                    },
+                }
+                continue;
+            }
+            "hint" => {
+                match util::hint(&mut d)? {
+                    Some((_, description)) => writeln!(out, "Hint: {}", description)?,
+                    None => writeln!(out, "No legal moves.")?,
+                }
+                continue;
+            }
+            "moves" => {
+                util::print_moves(&d.pos, out)?;
+                continue;
+            }
+            "flip" => {
+                style.flipped = !style.flipped;
+                util::print_board(&d.pos, &style, out)?;
+                continue;
+            }
+            "unicode" => {
+                if style.unicode {
+                    style.unicode = false;
+                } else if util::unicode_supported() {
+                    style.unicode = true;
+                } else {
+                    writeln!(
+                        out,
+                        "This terminal doesn't seem to support Unicode, so the board stays in letters."
+                    )?;
+                    continue;
+                }
+                util::print_board(&d.pos, &style, out)?;
+                continue;
+            }
+            "color" => {
+                if style.color {
+                    style.color = false;
+                } else if util::color_supported() {
+                    style.color = true;
+                } else {
+                    writeln!(
+                        out,
+                        "The output isn't a terminal, so the board stays plain."
+                    )?;
+                    continue;
+                }
+                util::print_board(&d.pos, &style, out)?;
                 continue;
             }
             "d" => {
-                util::print_board(&d);
+                util::print_board(&d.pos, &style, out)?;
+                continue;
+            }
+            "export" => {
+                let args = scan::scan_args(line_ended)?;
+                let path = match args.first() {
+                    Some(path) => path,
+                    None => {
+                        writeln!(out, "usage: export file.svg [arrow]")?;
+                        continue;
+                    }
+                };
+                let options = board::SvgOptions {
+                    flipped: style.flipped,
+                    last_move: args.get(1).map(String::as_str) == Some("arrow"),
+                };
+                match std::fs::write(path, board::to_svg_with(&d, &options)) {
+                    Ok(()) => writeln!(out, "Wrote {}", path)?,
+                    Err(err) => writeln!(out, "unable to write {}: {}", path, err)?,
+                }
                 continue;
             }
             "bench" => {
                 computer_side = EMPTY;
-                bench::bench(&mut d);
+                let args = scan::scan_args(line_ended)?;
+                if args.first().map(String::as_str) == Some("suite") {
+                    let depth = match args.get(1).map(|arg| arg.parse::<Int>()) {
+                        None => bench::SUITE_DEPTH,
+                        Some(Ok(n)) if (1..MAX_PLY as Int).contains(&n) => n,
+                        Some(_) => {
+                            writeln!(out, "Bad bench argument: {}", args[1])?;
+                            continue;
+                        }
+                    };
+                    bench::bench_suite(&mut d, depth, out)?;
+                    continue;
+                }
+                match bench_args(&args) {
+                    Ok(config) => bench::bench_with(&mut d, &config, out)?,
+                    Err(err) => writeln!(out, "{}", err)?,
+                }
+                continue;
+            }
+            "regress" => {
+                computer_side = EMPTY;
+                let args = scan::scan_args(line_ended)?;
+                match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+                    [] => {
+                        bench::regress(&mut d, bench::REFERENCES, out)?;
+                    }
+                    ["save", path] => {
+                        match bench::regress_save(&mut d, bench::REFERENCES, Path::new(path)) {
+                            Ok(()) => writeln!(out, "Saved the results to {}", path)?,
+                            Err(err) => writeln!(out, "Unable to save {}: {}", path, err)?,
+                        }
+                    }
+                    _ => writeln!(out, "Usage: regress [save file]")?,
+                }
+                continue;
+            }
+            "perft" => {
+                let arg = scan::scan_token()?;
+                if arg == "bench" {
+                    perft::perft_bench(out)?;
+                    continue;
+                }
+                let n = match arg.parse::<Int>() {
+                    Ok(n) => n,
+                    Err(err) => {
+                        writeln!(out, "unable to read perft argument: {}", err)?;
+                        return Ok(());
+                    }
+                };
+                let start_time = util::get_ms();
+                let nodes = if n > 0 {
+                    util::perft_counts(&d, n).iter().map(|&(_, n)| n).sum()
+                } else {
+                    board::perft(&mut d, n)
+                };
+                writeln!(out, "Nodes: {}", nodes)?;
+                writeln!(out, "Time: {} ms", util::get_ms() - start_time)?;
+                continue;
+            }
+            "divide" => {
+                let n = match scan::scan_int() {
+                    Ok(n) => n,
+                    Err(err) => {
+                        writeln!(out, "unable to read divide argument: {}", err)?;
+                        return Ok(());
+                    }
+                };
+                util::divide(&mut d, n, out)?;
+                continue;
+            }
+            "book" => {
+                book_command(&mut d, book_path, out)?;
+                continue;
+            }
+            "explore" => {
+                explore(&d, out)?;
+                continue;
+            }
+            "selfplay" => {
+                computer_side = EMPTY;
+                let mut games = 1;
+                let mut pgn_path = None;
+                if !line_ended {
+                    let (arg, line_ended) = match scan::scan_token_line() {
+                        Ok(token) => token,
+                        Err(err) => {
+                            writeln!(out, "unable to read selfplay argument: {}", err)?;
+                            return Ok(());
+                        }
+                    };
+                    games = match arg.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => {
+                            writeln!(out, "unable to read selfplay argument: {}", arg)?;
+                            continue;
+                        }
+                    };
+                    if !line_ended {
+                        pgn_path = match scan::scan_token() {
+                            Ok(path) => Some(path),
+                            Err(err) => {
+                                writeln!(out, "unable to read selfplay argument: {}", err)?;
+                                return Ok(());
+                            }
+                        };
+                    }
+                }
+                selfplay::selfplay(
+                    &mut d,
+                    &limits,
+                    games,
+                    pgn_path.as_deref().map(Path::new),
+                    out,
+                )?;
+                continue;
+            }
+            "analyze" => {
+                let args = scan::scan_args(line_ended)?;
+                let depth = match args.first() {
+                    None => util::ASSESS_DEPTH,
+                    Some(arg) => match arg.parse::<Int>() {
+                        Ok(n) if (1..MAX_PLY as Int).contains(&n) => n,
+                        _ => {
+                            writeln!(out, "Bad analyze argument: {}", arg)?;
+                            continue;
+                        }
+                    },
+                };
+                if d.pos.hply == 0 {
+                    writeln!(out, "No moves to analyze")?;
+                    continue;
+                }
+                let limits = SearchLimits {
+                    depth: Some(depth),
+                    ..SearchLimits::default()
+                };
+                let analysis = analysis::analyze_game(&d.pos, &limits)?;
+                analysis::print_analysis(&analysis, out)?;
+                continue;
+            }
+            "tournament" => {
+                computer_side = EMPTY;
+                let args = scan::scan_args(line_ended)?;
+                match tournament_args(&args, &limits) {
+                    Ok(config) => {
+                        tournament::tournament(&d, &config, out)?;
+                        board::new_game(&mut d, false);
+                    }
+                    Err(err) => writeln!(out, "{}", err)?,
+                }
+                continue;
+            }
+            "sprt" => {
+                computer_side = EMPTY;
+                let args = scan::scan_args(line_ended)?;
+                match sprt_args(&args, &limits) {
+                    Ok(config) => {
+                        sprt::sprt(&d, &config, out)?;
+                        board::new_game(&mut d, false);
+                    }
+                    Err(err) => writeln!(out, "{}", err)?,
+                }
+                continue;
+            }
+            "tune" => {
+                computer_side = EMPTY;
+                let args = scan::scan_args(line_ended)?;
+                match tune_args(&args, &limits) {
+                    Ok(config) => {
+                        tune::tune(&d, &config, out)?;
+                        board::new_game(&mut d, false);
+                    }
+                    Err(err) => writeln!(out, "{}", err)?,
+                }
+                continue;
+            }
+            "puzzle" => {
+                computer_side = EMPTY;
+                let args = scan::scan_args(line_ended)?;
+                match args.first() {
+                    Some(path) => puzzle::puzzle(&mut d, Path::new(path), out)?,
+                    None => writeln!(out, "usage: puzzle file")?,
+                }
                 continue;
             }
             "bye" => {
-                println!("Share and enjoy!");
+                writeln!(out, "Share and enjoy!")?;
                 break;
             }
             "xboard" => {
-                xboard::xboard(&mut d);
+                xboard::xboard(&mut d, limits, out)?;
+                break;
+            }
+            "uci" => {
+                uci::uci(&mut d, true, out)?;
                 break;
             }
             "help" => {
-                println!("{}", HELP);
+                writeln!(out, "{}", HELP)?;
                 continue;
             }
             _ => {
                 // maybe the user entered a move?
-                let m = util::parse_move(&d, &s);
-                if m == -1 {
-                    println!("Illegal move.");
-                } else {
-                    let m = d.gen_dat[m as usize].m.bytes();
-                    if !board::makemove(&mut d, m) {
-                        println!("Illegal move.");
-                    } else {
-                        d.ply = 0;
-                        board::gen(&mut d);
-                        util::print_result(&mut d);
+                match util::parse_move(&d, &s) {
+                    Ok(m) => {
+                        let m = d.search.gen_dat[0][m].m.bytes();
+                        // the coach and the blunder check look at the position
+                        // before the move
+                        let assessment = if coach || blunder_check.is_some() {
+                            util::assess_move(&mut d, m)?
+                        } else {
+                            None
+                        };
+                        if let (Some(threshold), Some(assessment)) = (blunder_check, &assessment) {
+                            if assessment.loss() > threshold && !confirm_blunder(assessment, out)? {
+                                writeln!(out, "Move not made.")?;
+                                continue;
+                            }
+                        }
+                        if board::try_move(&mut d, m).is_err() {
+                            writeln!(out, "Illegal move.")?;
+                        } else {
+                            if let (true, Some(assessment)) = (coach, assessment) {
+                                writeln!(out, "Coach: {}", assessment.describe())?;
+                            }
+                            if !punch_clock(clock.as_mut(), &mut d, out)? {
+                                computer_side = EMPTY;
+                            }
+                            util::print_result(&mut d, out)?;
+                        }
                     }
+                    Err(ParseMoveError::Illegal) => writeln!(out, "Illegal move.")?,
+                    Err(err) => writeln!(out, "Illegal move ({}).", err)?,
                 }
             }
         }
     }
     book::close_book(&mut d);
+    Ok(())
+}
+
+/// punch_clock() stops the clock of the side that's just moved (if there's a
+/// clock) and prints both sides' time. If that side has run out of time, it
+/// says so and returns false, and the clocks stay stopped; otherwise, unless
+/// the game's over, the other side's clock starts.
+
+#[cfg(feature = "cli")]
+fn punch_clock(
+    clock: Option<&mut ChessClock>,
+    d: &mut Data,
+    out: &mut dyn Write,
+) -> io::Result<bool> {
+    let clock = match clock {
+        Some(clock) => clock,
+        None => return Ok(true),
+    };
+    let now = util::get_ms();
+    clock.punch(now);
+    writeln!(out, "Clock: {}", clock.status(now))?;
+    match clock.flagged(now) {
+        Some(LIGHT) => {
+            writeln!(out, "0-1 {{White loses on time}}")?;
+            return Ok(false);
+        }
+        Some(_) => {
+            writeln!(out, "1-0 {{Black loses on time}}")?;
+            return Ok(false);
+        }
+        None => {}
+    }
+    if util::game_result(d) == GameResult::Ongoing {
+        clock.start(d.pos.side, now);
+    }
+    Ok(true)
+}
+
+/// confirm_blunder() tells the user how much worse a move is than the
+/// engine's choice, and asks whether to make it anyway. It returns true if
+/// the answer is yes.
+
+#[cfg(feature = "cli")]
+fn confirm_blunder(assessment: &util::Assessment, out: &mut dyn Write) -> io::Result<bool> {
+    writeln!(out, "Careful: {}.", assessment.describe())?;
+    scan::prompt("Are you sure? (y/n) ", out)?;
+    let (answer, line_ended) = scan::scan_token_line()?;
+    scan::scan_args(line_ended)?;
+    Ok(matches!(answer.to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+/// clock_args() reads a "clock" command's minutes and (optional) increment
+/// in seconds, and returns them in milliseconds, or None if they don't make
+/// sense
+
+#[cfg(feature = "cli")]
+fn clock_args(args: &[String]) -> Option<(Int, Int)> {
+    let minutes: f64 = args.first()?.parse().ok()?;
+    let inc: f64 = match args.get(1) {
+        Some(arg) => arg.parse().ok()?,
+        None => 0.0,
+    };
+    if args.len() > 2 || minutes.is_nan() || minutes <= 0.0 || inc.is_nan() || inc < 0.0 {
+        return None;
+    }
+    Some(((minutes * 60_000.0) as Int, (inc * 1000.0) as Int))
+}
+
+/// tournament_args() works out what a "tournament" command's arguments ask
+/// for: the format, the number of games each pair plays, the players (whose
+/// limits start out as the given ones), and the PGN file to save the games
+/// to, if there's one. It returns a message if they don't make sense.
+
+#[cfg(feature = "cli")]
+fn tournament_args(args: &[String], limits: &SearchLimits) -> Result<TournamentConfig, String> {
+    const USAGE: &str = "usage: tournament rr|gauntlet n player... [file.pgn]";
+    let format = match args.first().map(String::as_str) {
+        Some("rr") => Format::RoundRobin,
+        Some("gauntlet") => Format::Gauntlet,
+        _ => return Err(String::from(USAGE)),
+    };
+    let games = match args.get(1).map(|arg| arg.parse::<usize>()) {
+        Some(Ok(n)) if n > 0 => n,
+        _ => return Err(String::from(USAGE)),
+    };
+    let mut specs = &args[2..];
+    let mut pgn_path = None;
+    if let Some((last, rest)) = specs.split_last() {
+        if last.ends_with(".pgn") {
+            pgn_path = Some(PathBuf::from(last));
+            specs = rest;
+        }
+    }
+    if specs.len() < 2 {
+        return Err(String::from("a tournament needs at least two players"));
+    }
+    let players = specs
+        .iter()
+        .map(|spec| Player::parse(spec, limits))
+        .collect::<Result<Vec<Player>, String>>()?;
+    Ok(TournamentConfig {
+        format,
+        games,
+        players,
+        pgn_path,
+    })
+}
+
+/// sprt_args() works out what an "sprt" command's arguments ask for: the
+/// Elo bounds, the most games to play, and the test and base players (whose
+/// limits start out as the given ones). The error rates are both 5%. It
+/// returns a message if the arguments don't make sense.
+
+#[cfg(feature = "cli")]
+fn sprt_args(args: &[String], limits: &SearchLimits) -> Result<SprtConfig, String> {
+    const USAGE: &str = "usage: sprt elo0 elo1 n test base";
+    if args.len() != 5 {
+        return Err(String::from(USAGE));
+    }
+    let (elo0, elo1) = match (args[0].parse::<f64>(), args[1].parse::<f64>()) {
+        (Ok(elo0), Ok(elo1)) if elo0 < elo1 => (elo0, elo1),
+        _ => {
+            return Err(String::from(
+                "elo0 and elo1 must be numbers, with elo0 < elo1",
+            ))
+        }
+    };
+    let max_games = match args[2].parse::<usize>() {
+        Ok(n) if n > 0 => n,
+        _ => return Err(String::from(USAGE)),
+    };
+    Ok(SprtConfig {
+        test: Player::parse(&args[3], limits)?,
+        base: Player::parse(&args[4], limits)?,
+        elo0,
+        elo1,
+        alpha: 0.05,
+        beta: 0.05,
+        max_games,
+    })
+}
+
+/// tune_args() works out what a "tune" command's arguments ask for: the
+/// number of iterations, and the player whose parameters are tuned (whose
+/// limits start out as the given ones). It returns a message if the arguments
+/// don't make sense.
+
+#[cfg(feature = "cli")]
+fn tune_args(args: &[String], limits: &SearchLimits) -> Result<TuneConfig, String> {
+    const USAGE: &str = "usage: tune n player";
+    if args.len() != 2 {
+        return Err(String::from(USAGE));
+    }
+    let iterations = match args[0].parse::<usize>() {
+        Ok(n) if n > 0 => n,
+        _ => return Err(String::from(USAGE)),
+    };
+    Ok(TuneConfig {
+        player: Player::parse(&args[1], limits)?,
+        iterations,
+    })
+}
+
+/// variety_args() works out the opening variety setting that a "variety"
+/// command's arguments ask for: the number of moves, and the margin, if it's
+/// given. It returns None if they don't make sense.
+
+#[cfg(feature = "cli")]
+fn variety_args(args: &[String]) -> Option<Variety> {
+    let moves: Int = args.first()?.parse().ok()?;
+    let margin: Int = match args.get(1) {
+        Some(arg) => arg.parse().ok()?,
+        None => search::VARIETY_MARGIN,
+    };
+    if args.len() > 2 || moves < 1 || margin < 0 {
+        return None;
+    }
+    Some(Variety { moves, margin })
+}
+
+/// bench_args() works out what a "bench" command's arguments ask for: the
+/// depth and the number of iterations, if they're given, and then the FEN of
+/// the position, if it's given. It returns a message if they don't make sense.
+
+#[cfg(feature = "cli")]
+fn bench_args(args: &[String]) -> Result<BenchConfig, String> {
+    let mut config = BenchConfig::default();
+    let mut numbers = 0;
+    let mut fen = Vec::new();
+    for arg in args {
+        match arg.parse::<Int>() {
+            Ok(n) if fen.is_empty() && numbers < 2 => {
+                let ok = if numbers == 0 {
+                    config.depth = n;
+                    (1..MAX_PLY as Int).contains(&n)
+                } else {
+                    config.iterations = n as usize;
+                    n >= 1
+                };
+                if !ok {
+                    return Err(format!("Bad bench argument: {}", n));
+                }
+                numbers += 1;
+            }
+            _ => fen.push(arg.as_str()),
+        }
+    }
+    if !fen.is_empty() {
+        config.fen = Some(fen.join(" "));
+    }
+    Ok(config)
+}
+
+/// explore() lists the moves the opening book has for the current position,
+/// with their weights and how often book_move() would play them
+
+#[cfg(feature = "cli")]
+fn explore(d: &Data, out: &mut dyn Write) -> io::Result<()> {
+    let moves = book::book_moves(d);
+    if moves.is_empty() {
+        return writeln!(out, "No book moves.");
+    }
+    let total: Int = moves.iter().map(|&(_, weight)| weight).sum();
+    writeln!(out, "move   weight  percent")?;
+    for (m, weight) in moves {
+        writeln!(
+            out,
+            "{:<6} {:6}  {:6.1}",
+            util::move_str(m.bytes()),
+            weight,
+            (weight * 100) as f64 / total as f64
+        )?;
+    }
+    Ok(())
+}
+
+/// book_command() reads the rest of a "book" command and carries it out on
+/// the opening book file at path, reloading the book if the file is changed. The
+/// lines are numbered as they are in the file, starting from 1.
+
+#[cfg(feature = "cli")]
+fn book_command(d: &mut Data, path: &Path, out: &mut dyn Write) -> io::Result<()> {
+    let command = scan::scan_token()?;
+    let mut lines = match book::read_book_lines(path) {
+        Ok(lines) => lines,
+        Err(err) if err.kind() == io::ErrorKind::NotFound && command == "add" => Vec::new(),
+        Err(err) => {
+            writeln!(out, "unable to read {}: {}", path.display(), err)?;
+            return Ok(());
+        }
+    };
+    match command.as_ref() {
+        "list" => {
+            for i in book::lines_through(d, &lines) {
+                writeln!(out, "{}: {}", i + 1, lines[i])?;
+            }
+            return Ok(());
+        }
+        "add" => match book::game_line(d) {
+            Some(line) => {
+                writeln!(out, "{}: {}", lines.len() + 1, line)?;
+                lines.push(line);
+            }
+            None => {
+                writeln!(out, "This game can't be added to the book.")?;
+                return Ok(());
+            }
+        },
+        "delete" => {
+            let n = match scan::scan_int() {
+                Ok(n) if n >= 1 && n as usize <= lines.len() => n as usize,
+                Ok(n) => {
+                    writeln!(out, "The book has no line {}.", n)?;
+                    return Ok(());
+                }
+                Err(err) => {
+                    writeln!(out, "unable to read book delete argument: {}", err)?;
+                    return Ok(());
+                }
+            };
+            lines.remove(n - 1);
+        }
+        _ => {
+            writeln!(out, "Error (unknown book command): {}", command)?;
+            return Ok(());
+        }
+    }
+    if let Err(err) = book::write_book_lines(path, &lines) {
+        writeln!(out, "unable to write {}: {}", path.display(), err)?;
+        return Ok(());
+    }
+    book::open_book(d, path);
+    Ok(())
+}
+
+#[cfg(all(test, feature = "cli"))]
+mod tests {
+    use super::*;
+
+    fn args(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(args(&[])), Ok(Options::default()));
+        let options = parse_args(args(&[
+            "--xboard", "--depth", "6", "--st", "2", "--book", "my.txt", "--log", "log.txt",
+            "--hash", "64", "--debug",
+        ]))
+        .unwrap();
+        assert!(options.xboard);
+        assert!(options.debug);
+        assert_eq!(options.depth, Some(6));
+        assert_eq!(options.st, Some(2));
+        assert_eq!(options.book, Some(PathBuf::from("my.txt")));
+        assert_eq!(options.log, Some(PathBuf::from("log.txt")));
+        assert_eq!(options.hash, Some(64));
+
+        assert!(parse_args(args(&["--uci"])).unwrap().uci);
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1";
+        assert_eq!(
+            parse_args(args(&["--fen", fen])).unwrap().fen.as_deref(),
+            Some(fen)
+        );
+        for bad in [
+            &["--depth"][..],
+            &["--depth", "0"],
+            &["--depth", "99"],
+            &["--st", "x"],
+            &["--hash", "-1"],
+            &["--hash", "1000000"],
+            &["--xboard", "--uci"],
+            &["--xboard", "--fen", fen],
+            &["--uci", "--fen", fen],
+            &["xboard"],
+        ]
+        .iter()
+        {
+            assert!(parse_args(args(bad)).is_err(), "{:?}", bad);
+        }
+    }
 }
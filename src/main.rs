@@ -5,6 +5,16 @@
 //
 // Rust port by Kristopher Johnson
 
+use std::env;
+use std::process;
+
 fn main() {
-    tscp::run();
+    let options = match tscp::parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("tscp: {}\n\n{}", err, tscp::USAGE);
+            process::exit(2);
+        }
+    };
+    tscp::run_with(&options);
 }
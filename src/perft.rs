@@ -0,0 +1,359 @@
+// perft.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP doesn't have perft; board::perft() is the plain
+// recursive version, which is what checks the move generator in the tests.
+// This module makes perft fast enough to run deep: a table of the counts of
+// subtrees that have already been walked (the same position is reached by
+// many move orders), and, with the "std" feature, splitting the moves from
+// the root between threads. With the "cli" feature, perft_bench() times
+// board::perft() on a set of positions whose counts are well known, as a
+// benchmark of the move generator itself.
+//
+// The table works like the transposition table (see tt.rs): each slot is a
+// pair of 64-bit atomics, the count and the key XORed with the count, so the
+// threads can share it without locks, and a torn slot reads as a miss. The
+// keys aren't the positions' hash values, though. Those leave out the castle
+// permissions (see set_hash() in board.rs), which is fine for spotting
+// repetitions, but a perft count has to be exact, and positions that differ
+// only in their castle permissions have different counts. So the table has
+// Zobrist keys of its own.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::board;
+
+use super::data::{Data, Squares};
+use super::defs::{Int, DARK, MAX_PLY};
+
+#[cfg(feature = "std")]
+use super::data::Position;
+#[cfg(feature = "std")]
+use super::defs::MoveBytes;
+
+/// the random numbers the table's keys are made of, indexed like the
+/// Position hash tables, with ones for the castle permissions and the depth
+
+struct Keys {
+    piece: [[[u64; 64]; 6]; 2],
+    side: u64,
+    castle: [u64; 16],
+    ep: [u64; 64],
+    depth: [u64; MAX_PLY],
+}
+
+impl Keys {
+    /// the numbers come from splitmix64, which is simple enough to run at
+    /// compile time
+    const fn new() -> Keys {
+        let mut keys = Keys {
+            piece: [[[0; 64]; 6]; 2],
+            side: 0,
+            castle: [0; 16],
+            ep: [0; 64],
+            depth: [0; MAX_PLY],
+        };
+        let mut state = 0;
+        let mut i = 0;
+        while i < 2 * 6 * 64 {
+            keys.piece[i / 384][i / 64 % 6][i % 64] = splitmix64(&mut state);
+            i += 1;
+        }
+        keys.side = splitmix64(&mut state);
+        i = 0;
+        while i < 64 {
+            if i < 16 {
+                keys.castle[i] = splitmix64(&mut state);
+            }
+            if i < MAX_PLY {
+                keys.depth[i] = splitmix64(&mut state);
+            }
+            keys.ep[i] = splitmix64(&mut state);
+            i += 1;
+        }
+        keys
+    }
+}
+
+const fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+static KEYS: Keys = Keys::new();
+
+#[derive(Default)]
+struct Slot {
+    /// the key XORed with count
+    key: AtomicU64,
+    /// the count plus 1, so that an empty slot (0) doesn't look like a
+    /// subtree with no leaves
+    count: AtomicU64,
+}
+
+/// a fixed-size, lock-free table of perft counts, indexed by position and
+/// depth
+
+pub struct PerftTable {
+    slots: Vec<Slot>,
+}
+
+impl PerftTable {
+    /// create a table that uses at most mb megabytes (but has at least one
+    /// slot). The number of slots is rounded down to a power of two.
+
+    pub fn new(mb: usize) -> PerftTable {
+        let bytes = mb.saturating_mul(1 << 20);
+        let mut len = 1;
+        while len * 2 * core::mem::size_of::<Slot>() <= bytes {
+            len *= 2;
+        }
+        let mut slots = Vec::with_capacity(len);
+        slots.resize_with(len, Slot::default);
+        PerftTable { slots }
+    }
+
+    /// key() returns the key for the position in d searched to depth. It's
+    /// worked out from scratch, but it's only needed for the positions that
+    /// aren't next to the leaves, which are few.
+    fn key(d: &Data, depth: Int) -> u64 {
+        let pos = &d.pos;
+        let mut key = KEYS.castle[pos.castle as usize] ^ KEYS.depth[depth as usize];
+        for sq in Squares(pos.pieces[0] | pos.pieces[1]) {
+            key ^= KEYS.piece[pos.color(sq) as usize][pos.piece(sq) as usize][sq];
+        }
+        if pos.side == DARK {
+            key ^= KEYS.side;
+        }
+        if pos.ep != -1 {
+            key ^= KEYS.ep[pos.ep as usize];
+        }
+        key
+    }
+
+    #[inline(always)]
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[key as usize & (self.slots.len() - 1)]
+    }
+
+    fn probe(&self, key: u64) -> Option<u64> {
+        let slot = self.slot(key);
+        let count = slot.count.load(Ordering::Relaxed);
+        if count == 0 || slot.key.load(Ordering::Relaxed) ^ count != key {
+            return None;
+        }
+        Some(count - 1)
+    }
+
+    fn store(&self, key: u64, count: u64) {
+        let slot = self.slot(key);
+        let count = count + 1;
+        slot.key.store(key ^ count, Ordering::Relaxed);
+        slot.count.store(count, Ordering::Relaxed);
+    }
+}
+
+/// perft_hashed() is board::perft() with a table of counts: a subtree whose
+/// count is in the table isn't walked again
+
+pub fn perft_hashed(d: &mut Data, depth: Int, table: &PerftTable) -> u64 {
+    // below depth 2, looking the count up costs more than working it out
+    if depth < 2 {
+        return board::perft(d, depth);
+    }
+    let key = PerftTable::key(d, depth);
+    if let Some(count) = table.probe(key) {
+        return count;
+    }
+    let mut nodes = 0;
+    board::gen_legal(d);
+    for i in 0..d.search.gen_dat[d.search.ply].len() {
+        board::makemove(&mut d.pos, d.search.gen_dat[d.search.ply][i].m.bytes());
+        d.search.ply += 1;
+        nodes += perft_hashed(d, depth - 1, table);
+        board::takeback(&mut d.pos);
+        d.search.ply -= 1;
+    }
+    table.store(key, nodes);
+    nodes
+}
+
+/// perft_divide() returns the perft count of each legal move in pos, to the
+/// given depth (so the counts add up to perft of pos), in the order
+/// gen_legal() generates them. The moves are shared out between the given
+/// number of threads as they go, and the table, if there is one, is shared
+/// by all of them.
+
+#[cfg(feature = "std")]
+pub fn perft_divide(
+    pos: &Position,
+    depth: Int,
+    threads: usize,
+    table: Option<&PerftTable>,
+) -> Vec<(MoveBytes, u64)> {
+    use core::sync::atomic::AtomicUsize;
+    use std::sync::Mutex;
+
+    let mut d = Data::new();
+    d.pos = pos.clone();
+    board::gen_legal(&mut d);
+    let moves: Vec<MoveBytes> = d.search.gen_dat[0].iter().map(|g| g.m.bytes()).collect();
+    let counts = Mutex::new(alloc::vec![0; moves.len()]);
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, moves.len().max(1)) {
+            scope.spawn(|| {
+                let mut d = Data::new();
+                d.pos = pos.clone();
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let m = match moves.get(i) {
+                        Some(&m) => m,
+                        None => break,
+                    };
+                    board::makemove(&mut d.pos, m);
+                    d.search.ply = 1;
+                    let nodes = match table {
+                        Some(table) => perft_hashed(&mut d, depth - 1, table),
+                        None => board::perft(&mut d, depth - 1),
+                    };
+                    board::takeback(&mut d.pos);
+                    d.search.ply = 0;
+                    counts.lock().unwrap()[i] = nodes;
+                }
+            });
+        }
+    });
+    moves
+        .into_iter()
+        .zip(counts.into_inner().unwrap())
+        .collect()
+}
+
+/// the positions perft_bench() runs: a FEN, the depth, and the count it
+/// should come to
+
+#[cfg(feature = "cli")]
+const BENCH_POSITIONS: [(&str, Int, u64); 5] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        5,
+        4_865_609,
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        4,
+        4_085_603,
+    ),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 6, 11_030_083),
+    (
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        5,
+        15_833_292,
+    ),
+    (
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        4,
+        2_103_487,
+    ),
+];
+
+/// perft_bench() runs board::perft() on a set of well-known positions, on one
+/// thread and without a table, and prints the node count, the time, and the
+/// speed in millions of nodes per second for each, and in all. A count that
+/// isn't what it should be is flagged, since it means the move generator is
+/// broken.
+
+#[cfg(feature = "cli")]
+pub fn perft_bench(out: &mut dyn std::io::Write) -> std::io::Result<()> {
+    use super::util::get_ms;
+
+    let mut d = Data::new();
+    board::init_hash(&mut d.pos);
+    let mut total_nodes = 0;
+    let mut total_time = 0;
+    for (fen, depth, expected) in BENCH_POSITIONS.iter() {
+        board::set_fen(&mut d, fen).expect("bad perft bench position");
+        let start_time = get_ms();
+        let nodes = board::perft(&mut d, *depth);
+        let time = get_ms() - start_time;
+        total_nodes += nodes;
+        total_time += time;
+        write!(
+            out,
+            "{:>10} nodes {:>6} ms {:>7.2} Mnps  depth {}  {}",
+            nodes,
+            time,
+            mnps(nodes, time),
+            depth,
+            fen
+        )?;
+        if nodes != *expected {
+            write!(out, "  WRONG (should be {})", expected)?;
+        }
+        writeln!(out)?;
+    }
+    board::new_game(&mut d, false);
+    writeln!(
+        out,
+        "{:>10} nodes {:>6} ms {:>7.2} Mnps  in all",
+        total_nodes,
+        total_time,
+        mnps(total_nodes, total_time)
+    )
+}
+
+/// mnps() works out a speed in millions of nodes per second
+
+#[cfg(feature = "cli")]
+fn mnps(nodes: u64, ms: u128) -> f64 {
+    nodes as f64 / ms.max(1) as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn test_perft_hashed() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::set_fen(&mut d, KIWIPETE).unwrap();
+        let table = PerftTable::new(1);
+        assert_eq!(perft_hashed(&mut d, 3, &table), 97862);
+        // the second time, the counts come from the table
+        assert_eq!(perft_hashed(&mut d, 3, &table), 97862);
+        assert_eq!(perft_hashed(&mut d, 2, &table), 2039);
+
+        // a tiny table is overwritten all the time, but still right
+        let table = PerftTable::new(0);
+        assert_eq!(perft_hashed(&mut d, 3, &table), 97862);
+        assert_eq!(board::fen(&d.pos), KIWIPETE);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_perft_divide() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::set_fen(&mut d, KIWIPETE).unwrap();
+        let table = PerftTable::new(1);
+        for (threads, table) in [(1, None), (4, None), (4, Some(&table))] {
+            let counts = perft_divide(&d.pos, 3, threads, table);
+            assert_eq!(counts.len(), 48);
+            assert_eq!(counts.iter().map(|&(_, n)| n).sum::<u64>(), 97862);
+            let castle = counts.iter().find(|(m, _)| m.to_string() == "e1g1");
+            assert_eq!(castle.map(|&(_, n)| n), Some(2059));
+        }
+    }
+}
@@ -0,0 +1,410 @@
+// pgn.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP doesn't read or write PGN (Portable Game
+// Notation). This module writes a game in PGN's export format, so games can be
+// looked at in other chess programs, and reads the games in a PGN file (their
+// tags and main lines; comments and variations are skipped).
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::board;
+use super::data::{Data, Position};
+use super::defs::{Color, GameResult, Int, PgnError};
+
+/// the FEN of the standard starting position, which a PGN game doesn't need a
+/// FEN tag for
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// movetext lines are wrapped to be no longer than this
+const LINE_WIDTH: usize = 79;
+
+/// result_str() returns the PGN result token for a game result: "1-0",
+/// "0-1", "1/2-1/2", or "*" if the game isn't over
+
+pub fn result_str(result: GameResult) -> &'static str {
+    match result {
+        GameResult::Ongoing => "*",
+        GameResult::Checkmate(Color::Light) => "1-0",
+        GameResult::Checkmate(_) => "0-1",
+        _ => "1/2-1/2",
+    }
+}
+
+/// an annotation for a move in annotated_pgn(): a NAG (Numeric Annotation
+/// Glyph, e.g., 4 for "??") and a comment, either of which can be left out
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MoveNote {
+    pub nag: Option<Int>,
+    pub comment: Option<String>,
+}
+
+/// game_pgn() returns the game in pos's history as PGN: the given tags (in
+/// the given order), then the Result tag, and FEN and SetUp tags if the game
+/// didn't start from the standard starting position, then the moves, ending
+/// with the result.
+
+pub fn game_pgn(pos: &Position, tags: &[(&str, &str)], result: GameResult) -> String {
+    annotated_pgn(pos, tags, result_str(result), &[])
+}
+
+/// annotated_pgn() is game_pgn() with the result given as a PGN result token
+/// (e.g., "1-0"), and with notes[i] after the game's ith move (there can be
+/// fewer notes than moves)
+
+pub fn annotated_pgn(
+    pos: &Position,
+    tags: &[(&str, &str)],
+    result: &str,
+    notes: &[MoveNote],
+) -> String {
+    let mut pgn = String::new();
+    for (name, value) in tags {
+        pgn += &format!("[{} \"{}\"]\n", name, escape(value));
+    }
+    pgn += &format!("[Result \"{}\"]\n", result);
+    let mut start = pos.clone();
+    while start.hply > 0 {
+        board::takeback(&mut start);
+    }
+    let fen = board::fen(&start);
+    if fen != START_FEN {
+        pgn += &format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", fen);
+    }
+    pgn.push('\n');
+
+    // the movetext, as tokens: move numbers, moves, and the result
+    let mut tokens = Vec::new();
+    for (i, san) in board::history_san(pos).into_iter().enumerate() {
+        let ply = pos.start_ply + i;
+        if ply.is_multiple_of(2) {
+            tokens.push(format!("{}.", ply / 2 + 1));
+        } else if i == 0 {
+            tokens.push(format!("{}...", ply / 2 + 1));
+        }
+        tokens.push(san);
+        if let Some(note) = notes.get(i) {
+            if let Some(nag) = note.nag {
+                tokens.push(format!("${}", nag));
+            }
+            if let Some(comment) = &note.comment {
+                // a comment's words can be wrapped like the other tokens
+                let comment = format!("{{{}}}", comment.replace('}', ")"));
+                tokens.extend(comment.split_whitespace().map(String::from));
+            }
+        }
+    }
+    tokens.push(String::from(result));
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+            pgn += &line;
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line += &token;
+    }
+    pgn += &line;
+    pgn.push('\n');
+    pgn
+}
+
+/// a game read from PGN by parse_pgn()
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PgnGame {
+    /// the tags' names and values, in the order they're given
+    pub tags: Vec<(String, String)>,
+    /// the moves of the main line, in SAN (without move numbers, NAGs, or
+    /// comments)
+    pub moves: Vec<String>,
+    /// the result token at the end of the movetext ("*" if there isn't one)
+    pub result: String,
+}
+
+impl PgnGame {
+    /// tag() returns the value of the tag with the given name, if the game
+    /// has one
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// the tokens that end a game's movetext
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// parse_pgn() reads the games in a PGN file's text
+
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '[' => {
+                // a tag after the movetext starts the next game
+                if !game.moves.is_empty() {
+                    games.push(finish(game));
+                    game = PgnGame::default();
+                }
+                game.tags.push(parse_tag(&mut chars)?);
+            }
+            '{' => {
+                chars
+                    .find(|&(_, c)| c == '}')
+                    .ok_or(PgnError::Syntax("unterminated comment"))?;
+            }
+            // a comment to the end of the line, or an escaped line
+            ';' | '%' => while chars.next_if(|&(_, c)| c != '\n').is_some() {},
+            '(' => skip_variation(&mut chars)?,
+            ')' => return Err(PgnError::Syntax("unmatched \")\"")),
+            '$' => while chars.next_if(|(_, c)| c.is_ascii_digit()).is_some() {},
+            _ => {
+                let mut end = i + c.len_utf8();
+                while let Some(&(j, c)) = chars.peek() {
+                    if c.is_whitespace() || "[]{}();$".contains(c) {
+                        break;
+                    }
+                    end = j + c.len_utf8();
+                    chars.next();
+                }
+                let token = &text[i..end];
+                if RESULTS.contains(&token) {
+                    game.result = String::from(token);
+                    games.push(game);
+                    game = PgnGame::default();
+                    continue;
+                }
+                // a move number can be run together with its move ("1.e4")
+                let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                if !san.is_empty() {
+                    game.moves.push(String::from(san));
+                }
+            }
+        }
+    }
+    if !game.tags.is_empty() || !game.moves.is_empty() {
+        games.push(finish(game));
+    }
+    Ok(games)
+}
+
+/// finish() gives a game without a result token the result "*"
+
+fn finish(mut game: PgnGame) -> PgnGame {
+    if game.result.is_empty() {
+        game.result = String::from("*");
+    }
+    game
+}
+
+/// parse_tag() reads a tag, e.g., [Event "F/S Return Match"], after its "["
+
+fn parse_tag<I: Iterator<Item = (usize, char)>>(
+    chars: &mut core::iter::Peekable<I>,
+) -> Result<(String, String), PgnError> {
+    let mut name = String::new();
+    while let Some((_, c)) = chars.next_if(|&(_, c)| c != '"' && c != ']') {
+        if !c.is_whitespace() {
+            name.push(c);
+        }
+    }
+    let mut value = String::new();
+    if chars.next_if(|&(_, c)| c == '"').is_some() {
+        loop {
+            match chars.next() {
+                Some((_, '"')) => break,
+                Some((_, '\\')) => value.extend(chars.next().map(|(_, c)| c)),
+                Some((_, c)) => value.push(c),
+                None => return Err(PgnError::Syntax("unterminated tag value")),
+            }
+        }
+    }
+    chars
+        .find(|&(_, c)| c == ']')
+        .ok_or(PgnError::Syntax("unterminated tag"))?;
+    if name.is_empty() {
+        return Err(PgnError::Syntax("tag without a name"));
+    }
+    Ok((name, value))
+}
+
+/// skip_variation() skips a variation (which can have variations and
+/// comments in it), after its "("
+
+fn skip_variation<I: Iterator<Item = (usize, char)>>(
+    chars: &mut core::iter::Peekable<I>,
+) -> Result<(), PgnError> {
+    let mut depth = 1;
+    while depth > 0 {
+        match chars.next() {
+            Some((_, '(')) => depth += 1,
+            Some((_, ')')) => depth -= 1,
+            Some((_, '{')) => {
+                chars
+                    .find(|&(_, c)| c == '}')
+                    .ok_or(PgnError::Syntax("unterminated comment"))?;
+            }
+            Some(_) => {}
+            None => return Err(PgnError::Syntax("unterminated variation")),
+        }
+    }
+    Ok(())
+}
+
+/// replay() sets the board up for the game (from its FEN tag, if it has
+/// one) and makes its moves
+
+pub fn replay(d: &mut Data, game: &PgnGame) -> Result<(), PgnError> {
+    board::init_hash(&mut d.pos);
+    let fen = game.tag("FEN").unwrap_or(START_FEN);
+    board::set_fen(d, fen).map_err(PgnError::Fen)?;
+    for san in &game.moves {
+        let m = board::parse_san(d, san).ok_or_else(|| {
+            let ply = d.pos.start_ply + d.pos.hply;
+            let dots = if ply.is_multiple_of(2) { "." } else { "..." };
+            PgnError::IllegalMove(format!("{}{} {}", ply / 2 + 1, dots, san))
+        })?;
+        board::try_move(d, m).map_err(|_| PgnError::IllegalMove(san.clone()))?;
+    }
+    Ok(())
+}
+
+/// escape() escapes the quotes and backslashes in a tag value
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+    use crate::util;
+
+    fn play(d: &mut Data, moves: &[&str]) {
+        for s in moves {
+            let m = util::parse_move(d, s).unwrap();
+            let m = d.search.gen_dat[0][m].m.bytes();
+            board::try_move(d, m).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_game_pgn() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        play(&mut d, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        let result = util::game_result(&mut d);
+        assert_eq!(
+            game_pgn(&d.pos, &[("Event", "\"Fool's\" mate")], result),
+            "[Event \"\\\"Fool's\\\" mate\"]\n\
+             [Result \"0-1\"]\n\
+             \n\
+             1. f3 e5 2. g4 Qh4# 0-1\n"
+        );
+
+        board::set_fen(&mut d, "4k3/8/8/8/8/8/8/R3K3 b Q - 0 40").unwrap();
+        play(&mut d, &["e8d7", "e1c1"]);
+        assert_eq!(
+            game_pgn(&d.pos, &[], GameResult::Ongoing),
+            "[Result \"*\"]\n\
+             [SetUp \"1\"]\n\
+             [FEN \"4k3/8/8/8/8/8/8/R3K3 b Q - 0 40\"]\n\
+             \n\
+             40... Kd7 41. O-O-O+ *\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_pgn() {
+        let text = "[Event \"Casual \\\"game\\\"\"]\n\
+                    [Site \"?\"]\n\
+                    \n\
+                    1. f3 {a bad move} e5 2.g4?? (2. e4 Nc6 (2... Bc5)) $4 Qh4# 0-1\n\
+                    \n\
+                    [Event \"Set up\"]\n\
+                    [FEN \"4k3/8/8/8/8/8/8/R3K3 b Q - 0 40\"]\n\
+                    \n\
+                    ; a comment\n\
+                    40... Kd7 41. O-O-O+\n";
+        let games = parse_pgn(text).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("Casual \"game\""));
+        assert_eq!(games[0].tag("Site"), Some("?"));
+        assert_eq!(games[0].moves, ["f3", "e5", "g4??", "Qh4#"]);
+        assert_eq!(games[0].result, "0-1");
+        assert_eq!(games[1].moves, ["Kd7", "O-O-O+"]);
+        assert_eq!(games[1].result, "*");
+
+        let mut d = Data::new();
+        replay(&mut d, &games[1]).unwrap();
+        assert_eq!(board::fen(&d.pos), "8/3k4/8/8/8/8/8/2KR4 b - - 2 41");
+        replay(&mut d, &games[0]).unwrap();
+        assert_eq!(
+            util::game_result(&mut d),
+            GameResult::Checkmate(Color::Dark)
+        );
+
+        let notes = [
+            MoveNote::default(),
+            MoveNote {
+                nag: None,
+                comment: Some(String::from("+0.10 best")),
+            },
+            MoveNote {
+                nag: Some(4),
+                comment: None,
+            },
+        ];
+        assert_eq!(
+            annotated_pgn(&d.pos, &[], "0-1", &notes),
+            "[Result \"0-1\"]\n\
+             \n\
+             1. f3 e5 {+0.10 best} 2. g4 $4 Qh4# 0-1\n"
+        );
+
+        let mut game = games[0].clone();
+        game.moves[2] = String::from("g5");
+        assert_eq!(
+            replay(&mut d, &game),
+            Err(PgnError::IllegalMove(String::from("2. g5")))
+        );
+        assert!(parse_pgn("1. e4 {oops").is_err());
+        assert!(parse_pgn("1. e4 (1. d4").is_err());
+        assert!(parse_pgn("[Event \"x").is_err());
+    }
+
+    #[test]
+    fn test_line_width() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        for _ in 0..10 {
+            play(&mut d, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        }
+        let pgn = game_pgn(&d.pos, &[], GameResult::Ongoing);
+        let lines: Vec<&str> = pgn.lines().skip(2).collect();
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.len() <= LINE_WIDTH));
+        assert_eq!(lines.join(" ").split(' ').count(), 40 + 20 + 1);
+    }
+}
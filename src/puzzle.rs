@@ -0,0 +1,181 @@
+// puzzle.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP doesn't have a puzzle mode. It shows the user
+// the positions in an EPD file one at a time, and checks the moves the user
+// enters against each position's best moves (its "bm" operation, or, if it
+// doesn't have one, the move the engine finds), keeping score.
+
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::path::Path;
+
+use super::board;
+use super::scan;
+use super::search;
+use super::util;
+
+use super::data::Data;
+use super::defs::{Int, MoveBytes, LIGHT};
+use super::epd::Epd;
+use super::search::{NoOutput, SearchLimits};
+use super::util::BoardStyle;
+
+/// how long the engine searches for the answer to a puzzle that doesn't have
+/// a "bm" operation, in milliseconds
+const SOLVE_MS: Int = 5000;
+
+/// what the user answers to a puzzle
+enum Answer {
+    Move(MoveBytes),
+    Skip,
+    Quit,
+}
+
+/// puzzle() reads the EPD file at path and presents its positions as
+/// puzzles, until they run out or the user quits, and then prints the score.
+/// Afterwards, the board is set up for a new game.
+
+pub fn puzzle(d: &mut Data, path: &Path, out: &mut dyn Write) -> io::Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            writeln!(out, "unable to read {}: {}", path.display(), err)?;
+            return Ok(());
+        }
+    };
+    let mut puzzles = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.parse::<Epd>() {
+            Ok(epd) => puzzles.push(epd),
+            Err(err) => writeln!(out, "{}, line {}: {}", path.display(), i + 1, err)?,
+        }
+    }
+
+    // the opening book would answer the puzzles that it knows the position of
+    let books = mem::take(&mut d.books);
+    let result = run(d, &puzzles, out);
+    board::init_board(&mut d.pos);
+    d.books = books;
+    board::gen_legal(d);
+    result
+}
+
+/// run() presents the puzzles and keeps score
+
+fn run(d: &mut Data, puzzles: &[Epd], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "Enter your move (e.g., Nf3 or g1f3), \"skip\" for the answer, or \"quit\"."
+    )?;
+    let (mut solved, mut tried) = (0, 0);
+    for (i, epd) in puzzles.iter().enumerate() {
+        if let Err(err) = board::set_fen(d, &epd.fen) {
+            writeln!(out, "Puzzle {}: {}", i + 1, err)?;
+            continue;
+        }
+        let (answers, from_engine) = solutions(d, epd)?;
+        if answers.is_empty() {
+            writeln!(out, "Puzzle {}: there are no legal moves", i + 1)?;
+            continue;
+        }
+
+        writeln!(out)?;
+        write!(out, "Puzzle {} of {}", i + 1, puzzles.len())?;
+        if let Some(id) = epd.id() {
+            write!(out, " ({})", id)?;
+        }
+        writeln!(out)?;
+        util::print_board(&d.pos, &BoardStyle::default(), out)?;
+        let side = if d.pos.side == LIGHT {
+            "White"
+        } else {
+            "Black"
+        };
+        writeln!(out, "{} to move", side)?;
+
+        let answer = match read_answer(d, out)? {
+            Answer::Quit => break,
+            answer => answer,
+        };
+        tried += 1;
+        if let Answer::Move(m) = answer {
+            if answers
+                .iter()
+                .any(|&a| util::move_str(a) == util::move_str(m))
+            {
+                writeln!(out, "Correct!")?;
+                solved += 1;
+                continue;
+            }
+            writeln!(out, "Wrong.")?;
+        }
+        let answers: Vec<String> = answers.iter().filter_map(|&m| board::san(d, m)).collect();
+        if from_engine {
+            writeln!(out, "The engine plays {}.", answers.join(" or "))?;
+        } else {
+            writeln!(out, "The answer is {}.", answers.join(" or "))?;
+        }
+        if let Some((_, description)) = util::hint(d)? {
+            writeln!(out, "Engine: {}", description)?;
+        }
+    }
+    writeln!(out)?;
+    writeln!(out, "Solved {} of {}", solved, tried)
+}
+
+/// solutions() returns the moves that solve the puzzle: its best moves, if
+/// it has any that are legal, or else the move the engine finds (in which
+/// case the bool is true)
+
+fn solutions(d: &mut Data, epd: &Epd) -> io::Result<(Vec<MoveBytes>, bool)> {
+    let answers: Vec<MoveBytes> = epd
+        .best_moves()
+        .iter()
+        .filter_map(|san| board::parse_san(d, san))
+        .collect();
+    if !answers.is_empty() {
+        return Ok((answers, false));
+    }
+    let limits = SearchLimits {
+        movetime: Some(SOLVE_MS),
+        ..SearchLimits::default()
+    };
+    let result = search::think(d, &limits, &mut NoOutput)?;
+    Ok((result.best_move.into_iter().collect(), true))
+}
+
+/// read_answer() reads the user's answer to a puzzle, asking again if it's
+/// an illegal move
+
+fn read_answer(d: &mut Data, out: &mut dyn Write) -> io::Result<Answer> {
+    loop {
+        scan::prompt("Your move: ", out)?;
+        let (s, line_ended) = scan::scan_token_line()?;
+        // anything else on the line is ignored
+        scan::scan_args(line_ended)?;
+        match s.as_ref() {
+            "" | "quit" => return Ok(Answer::Quit),
+            "skip" => return Ok(Answer::Skip),
+            _ => {}
+        }
+        board::gen_legal(d);
+        if let Ok(i) = util::parse_move(d, &s) {
+            return Ok(Answer::Move(d.search.gen_dat[0][i].m.bytes()));
+        }
+        if let Some(m) = board::parse_san(d, &s) {
+            return Ok(Answer::Move(m));
+        }
+        writeln!(out, "Illegal move.")?;
+    }
+}
@@ -0,0 +1,47 @@
+// rand.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::defs::Int;
+
+// #rust The C program uses the C library's srand() and rand(). These are
+// replacements, so the engine doesn't need libc. Like the C functions, they
+// share one generator across the whole program, and rand() returns 31-bit
+// numbers (as glibc's does). The generator is Knuth's MMIX linear
+// congruential generator, which returns the high bits of its state.
+
+static STATE: AtomicU64 = AtomicU64::new(1);
+
+/// srand() seeds the random number generator
+
+pub fn srand(seed: u32) {
+    STATE.store(seed as u64, Ordering::Relaxed);
+}
+
+/// rand() returns a random number from 0 to 2^31 - 1
+
+pub fn rand() -> Int {
+    // #rust fetch_update() only fails if the closure returns None
+    let prev = STATE
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |s| Some(next(s)))
+        .unwrap();
+    (next(prev) >> 33) as Int
+}
+
+/// rand_from() returns the number rand() would return after srand(seed), but
+/// without touching the shared generator, so the result only depends on seed
+
+pub fn rand_from(seed: u64) -> Int {
+    (next(seed) >> 33) as Int
+}
+
+#[inline(always)]
+fn next(s: u64) -> u64 {
+    s.wrapping_mul(6_364_136_223_846_793_005)
+        .wrapping_add(1_442_695_040_888_963_407)
+}
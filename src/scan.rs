@@ -12,8 +12,252 @@
 
 use std::io;
 use std::io::prelude::*;
+#[cfg(feature = "readline")]
+use std::io::IsTerminal;
+#[cfg(feature = "readline")]
+use std::mem;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+
+#[cfg(feature = "readline")]
+use rustyline::error::ReadlineError;
+#[cfg(feature = "readline")]
+use rustyline::DefaultEditor;
 
 use super::defs::Int;
+use super::transcript;
+
+// #rust With the "readline" feature, once start_line_editing() has been
+// called, the functions that read from stdin read from a line editor instead,
+// which gets a line at a time from the terminal, with editing and history.
+// The scanning itself is the same either way.
+
+#[cfg(feature = "readline")]
+static LINE_EDITOR: Mutex<Option<LineEditor>> = Mutex::new(None);
+
+/// a line editor that can be read from like stdin, one line at a time
+#[cfg(feature = "readline")]
+struct LineEditor {
+    editor: DefaultEditor,
+    /// the prompt for the next line, which is only shown once
+    prompt: String,
+    /// the line being read, and how much of it has been read
+    line: Vec<u8>,
+    pos: usize,
+}
+
+#[cfg(feature = "readline")]
+impl Read for LineEditor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.line.len() {
+            let prompt = mem::take(&mut self.prompt);
+            match self.editor.readline(&prompt) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        let _ = self.editor.add_history_entry(line.as_str());
+                    }
+                    self.line = line.into_bytes();
+                    self.line.push(b'\n');
+                    self.pos = 0;
+                }
+                // Ctrl-C throws away the line, and starts a new one
+                Err(ReadlineError::Interrupted) => self.prompt = prompt,
+                Err(ReadlineError::Eof) => return Ok(0),
+                Err(ReadlineError::Io(err)) => return Err(err),
+                Err(err) => return Err(io::Error::other(err)),
+            }
+        }
+        let n = buf.len().min(self.line.len() - self.pos);
+        buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// #rust Once start_input_thread() has been called, stdin is read by a thread
+// of its own, a line at a time, and the functions that read from stdin read
+// the lines it passes on. That way a command that comes in while the engine
+// is searching is seen right away (by the thread's on_line), instead of only
+// when the search is over and the next command is read.
+
+static INPUT_THREAD: Mutex<Option<InputThread>> = Mutex::new(None);
+
+/// the lines read by an input thread, which can be read from like stdin
+pub struct InputThread {
+    lines: Receiver<Vec<u8>>,
+    /// the line being read, and how much of it has been read
+    line: Vec<u8>,
+    pos: usize,
+}
+
+impl InputThread {
+    /// spawn() starts a thread that reads reader a line at a time, and calls
+    /// on_line with each line as soon as it's read (before it's read from
+    /// the InputThread)
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Cursor;
+    /// use std::sync::mpsc;
+    /// use tscp::scan::{scan_token_from, InputThread};
+    ///
+    /// let (sender, seen) = mpsc::channel();
+    /// let reader = Cursor::new("?\nsd 5\n");
+    /// let mut input = InputThread::spawn(reader, move |line| {
+    ///     sender.send(line.to_string()).unwrap();
+    /// });
+    /// // both lines are seen before any of the input is read
+    /// assert_eq!(seen.recv().unwrap(), "?\n");
+    /// assert_eq!(seen.recv().unwrap(), "sd 5\n");
+    /// assert_eq!(scan_token_from(&mut input).unwrap(), "?");
+    /// assert_eq!(scan_token_from(&mut input).unwrap(), "sd");
+    /// assert_eq!(scan_token_from(&mut input).unwrap(), "5");
+    /// assert_eq!(scan_token_from(&mut input).unwrap(), "");
+    /// ```
+    pub fn spawn<R, F>(reader: R, on_line: F) -> InputThread
+    where
+        R: BufRead + Send + 'static,
+        F: Fn(&str) + Send + 'static,
+    {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = reader;
+            loop {
+                let mut line = Vec::new();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {}
+                }
+                on_line(&String::from_utf8_lossy(&line));
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        InputThread {
+            lines,
+            line: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for InputThread {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.line.len() {
+            match self.lines.recv() {
+                Ok(line) => {
+                    self.line = line;
+                    self.pos = 0;
+                }
+                // the thread has reached the end of the input
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.line.len() - self.pos);
+        buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// start_input_thread() makes the functions that read from stdin read
+/// through an InputThread, which calls on_line with each line as soon as it
+/// comes in. It returns false (and on_line isn't used) if it's already been
+/// called.
+
+pub fn start_input_thread<F: Fn(&str) + Send + 'static>(on_line: F) -> bool {
+    let mut input_thread = INPUT_THREAD.lock().unwrap_or_else(|e| e.into_inner());
+    if input_thread.is_some() {
+        return false;
+    }
+    let stdin = io::BufReader::new(Tee(io::stdin()));
+    *input_thread = Some(InputThread::spawn(stdin, on_line));
+    true
+}
+
+/// start_line_editing() makes the functions that read from stdin read
+/// through a line editor, with editing and history, if stdin and stdout are
+/// terminals. It returns true if they do. Without the "readline" feature, it
+/// always returns false.
+
+pub fn start_line_editing() -> bool {
+    #[cfg(feature = "readline")]
+    {
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return false;
+        }
+        let mut line_editor = LINE_EDITOR.lock().unwrap_or_else(|e| e.into_inner());
+        if line_editor.is_none() {
+            let editor = match DefaultEditor::new() {
+                Ok(editor) => editor,
+                Err(_) => return false,
+            };
+            *line_editor = Some(LineEditor {
+                editor,
+                prompt: String::new(),
+                line: Vec::new(),
+                pos: 0,
+            });
+        }
+        true
+    }
+    #[cfg(not(feature = "readline"))]
+    false
+}
+
+/// prompt() shows the prompt for the next line of input: the line editor
+/// shows it, if it's in use, and otherwise it's written to out
+
+pub fn prompt(prompt: &str, out: &mut dyn Write) -> io::Result<()> {
+    #[cfg(feature = "readline")]
+    {
+        let mut line_editor = LINE_EDITOR.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(line_editor) = line_editor.as_mut() {
+            // (if there's more on the current line, there won't be a prompt)
+            line_editor.prompt = prompt.to_string();
+            return out.flush();
+        }
+    }
+    write!(out, "{}", prompt)?;
+    out.flush()
+}
+
+/// with_input() calls f with the reader for stdin: the input thread or the
+/// line editor, if one is in use, or stdin itself (through a Tee to the
+/// transcript)
+
+fn with_input<T>(f: fn(&mut dyn Read) -> io::Result<T>) -> io::Result<T> {
+    {
+        // (the thread has already passed the input on to the transcript)
+        let mut input_thread = INPUT_THREAD.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(input_thread) = input_thread.as_mut() {
+            return f(input_thread);
+        }
+    }
+    #[cfg(feature = "readline")]
+    {
+        let mut line_editor = LINE_EDITOR.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(line_editor) = line_editor.as_mut() {
+            return f(&mut Tee(line_editor));
+        }
+    }
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    f(&mut Tee(&mut reader))
+}
+
+/// Tee passes what's read through it on to the transcript, if there is one
+struct Tee<R: Read>(R);
+
+impl<R: Read> Read for Tee<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        transcript::received(&buf[..n]);
+        Ok(n)
+    }
+}
 
 /// reads a whitespace-delimited token from stdin. returns an empty string on
 /// EOF. assumes input is 7-bit ASCII, and does not recognize Unicode whitespace
@@ -24,9 +268,7 @@ use super::defs::Int;
 /// Returns error if unable to read for a reason other than EOF.
 
 pub fn scan_token() -> io::Result<String> {
-    let stdin = io::stdin();
-    let mut reader = stdin.lock();
-    scan_token_from(&mut reader)
+    with_input(scan_token_from)
 }
 
 /// reads a whitespace-delimited token from a reader. returns an empty string on
@@ -50,6 +292,44 @@ pub fn scan_token() -> io::Result<String> {
 /// ```
 
 pub fn scan_token_from(reader: &mut dyn Read) -> io::Result<String> {
+    Ok(scan_token_line_from(reader)?.0)
+}
+
+/// reads a whitespace-delimited token from stdin, like scan_token(), and also
+/// returns true if the token was the last one on its line, so that a command
+/// can tell whether it was given any arguments.
+///
+/// # Errors
+///
+/// Returns error if unable to read for a reason other than EOF.
+
+pub fn scan_token_line() -> io::Result<(String, bool)> {
+    with_input(scan_token_line_from)
+}
+
+/// reads a whitespace-delimited token from a reader, like scan_token_from(),
+/// and also returns true if the token was the last one on its line (or in the
+/// input). Whitespace after the token isn't looked at beyond the first
+/// character, so trailing spaces make it look like more is coming.
+///
+/// # Errors
+///
+/// Returns error if unable to read for a reason other than EOF.
+///
+/// # Example
+/// ```
+/// use tscp::scan::scan_token_line_from;
+///
+/// let s = String::from("one two\r\nthree\n\nfour");
+/// let mut reader = s.as_bytes();
+/// assert_eq!(scan_token_line_from(&mut reader).unwrap(), ("one".into(), false));
+/// assert_eq!(scan_token_line_from(&mut reader).unwrap(), ("two".into(), true));
+/// assert_eq!(scan_token_line_from(&mut reader).unwrap(), ("three".into(), true));
+/// assert_eq!(scan_token_line_from(&mut reader).unwrap(), ("four".into(), true));
+/// assert_eq!(scan_token_line_from(&mut reader).unwrap(), ("".into(), true));
+/// ```
+
+pub fn scan_token_line_from(reader: &mut dyn Read) -> io::Result<(String, bool)> {
     let mut bytes: Vec<u8> = Vec::new();
 
     // skip leading whitespace
@@ -62,7 +342,7 @@ pub fn scan_token_from(reader: &mut dyn Read) -> io::Result<String> {
                 }
             }
             ReadByteResult::Eof => {
-                return Ok(String::new());
+                return Ok((String::new(), true));
             }
             ReadByteResult::Err(err) => {
                 return Err(err);
@@ -71,15 +351,18 @@ pub fn scan_token_from(reader: &mut dyn Read) -> io::Result<String> {
     }
 
     // copy bytes until whitespace or EOF
+    let end_of_line;
     loop {
         match read_byte(reader) {
             ReadByteResult::Ok(byte) => {
                 if is_whitespace(byte) {
+                    end_of_line = byte == b'\n' || byte == b'\r';
                     break;
                 }
                 bytes.push(byte);
             }
             ReadByteResult::Eof => {
+                end_of_line = true;
                 break;
             }
             ReadByteResult::Err(err) => {
@@ -93,7 +376,28 @@ pub fn scan_token_from(reader: &mut dyn Read) -> io::Result<String> {
         Ok(s) => s,
         Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
     };
-    Ok(s)
+    Ok((s, end_of_line))
+}
+
+/// reads the rest of the tokens on the current line from stdin, e.g., a
+/// command's arguments. line_ended is what scan_token_line() returned for the
+/// token before them; if it's true, there's nothing more to read.
+///
+/// # Errors
+///
+/// Returns error if unable to read for a reason other than EOF.
+
+pub fn scan_args(line_ended: bool) -> io::Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut line_ended = line_ended;
+    while !line_ended {
+        let (token, ended) = scan_token_line()?;
+        line_ended = ended;
+        if !token.is_empty() {
+            args.push(token);
+        }
+    }
+    Ok(args)
 }
 
 /// reads a whitespace-delimited integer value from stdin.
@@ -103,9 +407,7 @@ pub fn scan_token_from(reader: &mut dyn Read) -> io::Result<String> {
 /// Returns error at EOF or if otherwise unable to read an integer value.
 
 pub fn scan_int() -> io::Result<Int> {
-    let stdin = io::stdin();
-    let mut reader = stdin.lock();
-    scan_int_from(&mut reader)
+    with_input(scan_int_from)
 }
 
 /// reads a whitespace-delimited integer value from a reader.
@@ -7,184 +7,1214 @@
 
 use super::board;
 use super::book;
-use super::data::Data;
-use super::defs::{Int, Move, HIST_STACK, MAX_PLY};
+use super::data::{Data, Position};
+use super::defs::{Color, Int, Move, MoveBytes, MAX_PLY};
 use super::eval;
+use super::eval::EvalParams;
+use super::rand;
+use super::tt::{Bound, TtEntry};
+#[cfg(feature = "cli")]
 use super::util;
 
-use std::io;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::sync::atomic::Ordering;
+#[cfg(feature = "cli")]
 use std::io::Write;
 
+/// the error a SearchObserver can return to stop think(). With the "std"
+/// feature, it's an I/O error, so an observer that writes the statistics
+/// somewhere can pass its write errors along; without it, it's core's
+/// formatting error.
+#[cfg(feature = "std")]
+pub type ObserverError = std::io::Error;
+#[cfg(not(feature = "std"))]
+pub type ObserverError = core::fmt::Error;
+
 /// #rust The original C code uses setjmp/longjmp to unwind the stack and exit
 /// if thinking-time expires during search().  Rust doesn't make it easy to use
 /// setjmp/longjmp, so instead our search() will return Timeout in that case.
 
 #[derive(Copy, Clone)]
-enum SearchResult {
+enum Outcome {
     Value(Int),
     Timeout,
 }
 
-/// output options for think()
+/// the milliseconds a search on a clock leaves for sending its move, and for
+/// the GUI to stop the clock
+
+const MOVE_OVERHEAD: Int = 50;
+
+/// the limits on a search. The frontends each fill in the ones their protocol
+/// has, and leave the rest at their defaults; with no limits at all, think()
+/// searches until it's stopped (or finds a mate).
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// the number of ply to search
+    pub depth: Option<Int>,
+    /// the number of nodes to search. (think() only checks every 1024 nodes,
+    /// so it may search a few more.)
+    pub nodes: Option<Int>,
+    /// the number of milliseconds to search
+    pub movetime: Option<Int>,
+    /// the milliseconds white and black have left on their clocks, and their
+    /// increments per move. They're only used if movetime isn't set.
+    pub wtime: Option<Int>,
+    pub btime: Option<Int>,
+    pub winc: Int,
+    pub binc: Int,
+    /// the number of moves to the next time control. If it isn't set, the
+    /// clock time is shared out as though there are 30 moves to go.
+    pub movestogo: Option<Int>,
+    /// search until stopped, even if a mate is found; the other limits are
+    /// ignored
+    pub infinite: bool,
+    /// look for a mate in this many moves
+    pub mate: Option<Int>,
+}
+
+impl SearchLimits {
+    /// max_depth() returns the number of ply to search
+    pub fn max_depth(&self) -> Int {
+        let mut depth = MAX_PLY as Int;
+        if !self.infinite {
+            if let Some(n) = self.depth {
+                depth = depth.min(n);
+            }
+            if let Some(n) = self.mate {
+                // a mate in n moves is found by a 2n ply search
+                depth = depth.min(n * 2);
+            }
+        }
+        depth.max(1)
+    }
+
+    /// time_for() returns the number of milliseconds side s can spend on this
+    /// move, or None if there's no time limit. On a clock, it never spends
+    /// more than half the time left, however few moves there are to go or
+    /// however big the increment, and it leaves MOVE_OVERHEAD for sending the
+    /// move, since the search only stops once it's used up its time.
+    pub fn time_for(&self, s: Color) -> Option<Int> {
+        if self.infinite {
+            return None;
+        }
+        if let Some(t) = self.movetime {
+            return Some(t.max(0));
+        }
+        let (time, inc) = match s {
+            Color::Light => (self.wtime?, self.winc),
+            Color::Dark => (self.btime?, self.binc),
+        };
+        let moves = self.movestogo.unwrap_or(30).max(1);
+        Some(
+            (time / moves + inc)
+                .min(time / 2)
+                .min(time - MOVE_OVERHEAD)
+                .max(0),
+        )
+    }
+}
+
+/// the weakest and the strongest skill levels. At full strength (the
+/// default), the search is TSCP's own. Below it, think() searches less deeply
+/// and fewer nodes, and adds a random amount (the same for each position
+/// during a search) to the evaluation, so the engine misses things the way a
+/// weaker player would, instead of just blundering the way a 1 ply search does.
+
+pub const MIN_SKILL: Int = 1;
+pub const MAX_SKILL: Int = 10;
+
+/// skill_for_elo() returns the skill level that plays at about the given Elo
+/// rating. TSCP at full strength is rated about 1700, and each level below
+/// that is taken to be worth 100 points, which is a rough guess.
+
+pub fn skill_for_elo(elo: Int) -> Int {
+    (MAX_SKILL - (1700 - elo) / 100).clamp(MIN_SKILL, MAX_SKILL)
+}
+
+/// skill_limits() returns the depth and the number of nodes that a search at
+/// the given skill level is limited to, and the most its evaluation noise can
+/// be (either way, in centipawns), or None at full strength
+
+fn skill_limits(skill: Int) -> Option<(Int, Int, Int)> {
+    if skill >= MAX_SKILL {
+        return None;
+    }
+    let skill = skill.max(MIN_SKILL);
+    Some((skill + 1, 1000 << skill, (MAX_SKILL - skill) * 25))
+}
+
+/// how think() picks the move to play among the root moves, so the engine
+/// plays more like a person than just a weaker engine does (see
+/// Humanlike::level()): it scores each of them with a short search
+/// (HUMAN_DEPTH ply), and picks at random among the ones that are nearly as
+/// good as the best
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Humanlike {
+    /// the moves whose scores are within this many centipawns of the best
+    /// one's are the candidates
+    pub margin: Int,
+    /// how evenly the candidates are picked among: one that's loss
+    /// centipawns worse than the best is weighted by temperature divided by
+    /// temperature plus loss, so the higher the temperature, the more evenly
+    /// (and a temperature of 0 picks among them evenly)
+    pub temperature: Int,
+    /// the chance, in percent, that the move is a deliberate inaccuracy,
+    /// picked from the moves that aren't candidates but are within three
+    /// times the margin of the best
+    pub inaccuracy: Int,
+}
+
+/// the weakest and the strongest humanlike levels
+pub const MIN_HUMAN_LEVEL: Int = 1;
+pub const MAX_HUMAN_LEVEL: Int = 10;
+
+/// the depth each root move is scored to when the engine plays humanlike
+const HUMAN_DEPTH: Int = 3;
+
+impl Humanlike {
+    /// level() returns the humanlike setting for a level, from
+    /// MIN_HUMAN_LEVEL (which often plays a move 100 centipawns worse than
+    /// the best, and is sometimes deliberately inaccurate) to
+    /// MAX_HUMAN_LEVEL (which only varies among moves within 10 centipawns)
+    pub fn level(level: Int) -> Humanlike {
+        let level = level.clamp(MIN_HUMAN_LEVEL, MAX_HUMAN_LEVEL);
+        let margin = (MAX_HUMAN_LEVEL + 1 - level) * 10;
+        Humanlike {
+            margin,
+            temperature: margin,
+            inaccuracy: (4 - level).max(0) * 10,
+        }
+    }
+
+    /// pick() picks a move from the scored moves, using roll (a random
+    /// number that's at least 0), or returns None if there aren't any
+    fn pick(&self, scores: &[(MoveBytes, Int)], roll: Int) -> Option<(MoveBytes, Int)> {
+        let best = scores.iter().map(|&(_, score)| score).max()?;
+        let within = |lo: Int, hi: Int| -> Vec<(MoveBytes, Int)> {
+            scores
+                .iter()
+                .copied()
+                .filter(|&(_, score)| best - score >= lo && best - score <= hi)
+                .collect()
+        };
+        let mut candidates = within(0, self.margin);
+        if roll % 100 < self.inaccuracy {
+            let inaccuracies = within(self.margin + 1, self.margin * 3);
+            if !inaccuracies.is_empty() {
+                candidates = inaccuracies;
+            }
+        }
+        // the weights, in thousandths
+        let weight = |score: Int| match self.temperature {
+            0 => 1000,
+            t => 1000 * t / (t + best - score),
+        };
+        let total: Int = candidates.iter().map(|&(_, score)| weight(score)).sum();
+        let mut j = (roll / 100) % total.max(1);
+        for &(m, score) in &candidates {
+            j -= weight(score);
+            if j < 0 {
+                return Some((m, score));
+            }
+        }
+        candidates.last().copied()
+    }
+}
+
+/// a setting for playing a variety of openings: in the game's first moves
+/// moves, think() plays any of the root moves whose scores (from a short
+/// search, as for Humanlike) are within margin centipawns of the best one's,
+/// picked evenly, so games that leave the book don't all go the same way
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Variety {
+    pub moves: Int,
+    pub margin: Int,
+}
+
+/// the margin for Variety, if it isn't given
+pub const VARIETY_MARGIN: Int = 20;
+
+impl Variety {
+    /// humanlike() returns the Humanlike setting that picks the move in pos,
+    /// or None if the game is past the first moves moves
+    fn humanlike(&self, pos: &Position) -> Option<Humanlike> {
+        let move_number = ((pos.start_ply + pos.hply) / 2 + 1) as Int;
+        if move_number > self.moves {
+            return None;
+        }
+        Some(Humanlike {
+            margin: self.margin,
+            temperature: 0,
+            inaccuracy: 0,
+        })
+    }
+}
+
+/// eval_noise() returns the random amount to add to the evaluation of the
+/// current position, which is 0 at full strength. It's worked out from the
+/// position's hash, so the position gets the same amount wherever it turns up
+/// in the search tree.
+
+fn eval_noise(d: &Data) -> Int {
+    let noise = match skill_limits(d.search.skill) {
+        Some((_, _, noise)) => noise,
+        None => return 0,
+    };
+    let x = ((d.pos.hash as u64) ^ d.search.noise_seed).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32;
+    (x % (2 * noise + 1) as u64) as Int - noise
+}
+
+/// what think() found. If the search ran out of time, the score, depth, and
+/// PV are the last completed iteration's, and the best move is the best one
+/// found so far (which may come from the unfinished iteration).
+
+#[derive(Clone, Default)]
+pub struct SearchResult {
+    /// the move to play, or None if there are no legal moves (or the search
+    /// was stopped before it found one)
+    pub best_move: Option<MoveBytes>,
+    /// the reply the engine expects, to ponder on
+    pub ponder_move: Option<MoveBytes>,
+    pub score: Int,
+    /// the number of ply searched (0 for a book move)
+    pub depth: Int,
+    /// the deepest ply the search reached, counting quiescence search
+    pub seldepth: Int,
+    pub nodes: Int,
+    /// the number of those nodes that were in the quiescence search
+    pub qnodes: Int,
+    /// milliseconds spent searching
+    pub time_ms: u128,
+    /// the principal variation
+    pub pv: Vec<MoveBytes>,
+}
+
+/// the score below which the engine resigns, by default, and the number of
+/// its moves in a row that the score has to be below it
+pub const RESIGN_SCORE: Int = -900;
+pub const RESIGN_MOVES: usize = 3;
+
+/// Resignation decides when the engine should resign: when its searches'
+/// scores have been below a threshold for several of its moves in a row, so
+/// it doesn't play out a hopeless position (e.g., a lone king against a king
+/// and queen) to the end.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resignation {
+    /// the score to resign below, or None if the engine never resigns
+    pub threshold: Option<Int>,
+    /// the number of moves in a row the score has to be below it
+    pub moves: usize,
+    /// the number of moves in a row the score has been below it so far
+    count: usize,
+}
+
+impl Default for Resignation {
+    fn default() -> Self {
+        Resignation {
+            threshold: Some(RESIGN_SCORE),
+            moves: RESIGN_MOVES,
+            count: 0,
+        }
+    }
+}
+
+impl Resignation {
+    /// update() counts the result of the search for the engine's move, and
+    /// returns true if it's time to resign instead of making it. (A book
+    /// move's score doesn't count against it.)
+
+    pub fn update(&mut self, result: &SearchResult) -> bool {
+        match self.threshold {
+            Some(threshold) if result.depth > 0 && result.score < threshold => self.count += 1,
+            _ => self.count = 0,
+        }
+        self.threshold.is_some() && self.count >= self.moves.max(1)
+    }
+
+    /// reset() forgets the scores that have been counted, e.g., for a new
+    /// game
+
+    pub fn reset(&mut self) {
+        self.count = 0;
+    }
+}
+
+/// the search statistics that think() reports after each iteration
+
+#[derive(Clone)]
+pub struct SearchInfo {
+    /// the number of ply searched
+    pub depth: Int,
+    pub score: Int,
+    pub nodes: Int,
+    /// milliseconds since the search started
+    pub time: u128,
+    /// the deepest ply the search reached, counting the quiescence search
+    pub seldepth: Int,
+    /// the principal variation
+    pub pv: Vec<MoveBytes>,
+    /// how full the transposition table is, in permill (None if there isn't
+    /// one)
+    pub hashfull: Option<Int>,
+    /// the effective branching factor: the number of nodes this iteration
+    /// searched divided by the number the one before it searched (None after
+    /// the first iteration)
+    pub branching_factor: Option<f64>,
+    /// an estimate of how many milliseconds the next iteration will take, if
+    /// there's a branching factor to base it on: this iteration's time times
+    /// the branching factor
+    pub next_iteration: Option<u128>,
+    /// the number of milliseconds left before the search runs out of time
+    /// (None if it has no time limit, or is pondering)
+    pub time_left: Option<u128>,
+}
+
+impl SearchInfo {
+    /// next_iteration_fits() returns false if the next iteration is expected
+    /// not to finish in the time that's left. (think() doesn't start an
+    /// iteration that won't finish, since it would just be thrown away.)
+
+    pub fn next_iteration_fits(&self) -> bool {
+        match (self.next_iteration, self.time_left) {
+            (Some(next), Some(left)) => next <= left,
+            _ => true,
+        }
+    }
+}
+
+/// how often think() reports its progress during an iteration, in
+/// milliseconds
+pub const PROGRESS_INTERVAL: u128 = 1000;
+
+/// think() divides the history heuristic values the last search left by this
+/// before it starts
+const HISTORY_AGING: i32 = 4;
+
+/// the most captures quiesce() searches in a row
+const MAX_QDEPTH: usize = 16;
+
+/// the numbers the search plays with that aren't rules of chess: the
+/// evaluation's penalties and bonuses, how much think() ages the history
+/// heuristic by, and how many captures quiesce() searches in a row. The
+/// defaults are the ones above; the tuner (see tune.rs) looks for better ones.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Params {
+    pub eval: EvalParams,
+    pub history_aging: Int,
+    pub max_qdepth: Int,
+}
+
+/// the name of a parameter, as Player::parse() and the tuner know it, and
+/// the smallest and largest values that make sense for it
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParamInfo {
+    pub name: &'static str,
+    pub min: Int,
+    pub max: Int,
+}
+
+/// the parameters, in the order Params::get() and Params::set() number them
+pub const PARAM_INFO: [ParamInfo; 9] = [
+    ParamInfo {
+        name: "doubled_pawn_penalty",
+        min: 0,
+        max: 100,
+    },
+    ParamInfo {
+        name: "isolated_pawn_penalty",
+        min: 0,
+        max: 100,
+    },
+    ParamInfo {
+        name: "backwards_pawn_penalty",
+        min: 0,
+        max: 100,
+    },
+    ParamInfo {
+        name: "passed_pawn_bonus",
+        min: 0,
+        max: 100,
+    },
+    ParamInfo {
+        name: "rook_semi_open_file_bonus",
+        min: 0,
+        max: 100,
+    },
+    ParamInfo {
+        name: "rook_open_file_bonus",
+        min: 0,
+        max: 100,
+    },
+    ParamInfo {
+        name: "rook_on_seventh_bonus",
+        min: 0,
+        max: 100,
+    },
+    ParamInfo {
+        name: "history_aging",
+        min: 1,
+        max: 64,
+    },
+    ParamInfo {
+        name: "max_qdepth",
+        min: 1,
+        max: MAX_PLY as Int,
+    },
+];
+
+impl Params {
+    pub const DEFAULT: Params = Params {
+        eval: EvalParams::DEFAULT,
+        history_aging: HISTORY_AGING as Int,
+        max_qdepth: MAX_QDEPTH as Int,
+    };
+
+    /// find() returns the number of the parameter with the given name
+    pub fn find(name: &str) -> Option<usize> {
+        PARAM_INFO.iter().position(|info| info.name == name)
+    }
+
+    /// get() returns parameter i's value
+    pub fn get(&self, i: usize) -> Int {
+        match i {
+            0 => self.eval.doubled_pawn_penalty,
+            1 => self.eval.isolated_pawn_penalty,
+            2 => self.eval.backwards_pawn_penalty,
+            3 => self.eval.passed_pawn_bonus,
+            4 => self.eval.rook_semi_open_file_bonus,
+            5 => self.eval.rook_open_file_bonus,
+            6 => self.eval.rook_on_seventh_bonus,
+            7 => self.history_aging,
+            8 => self.max_qdepth,
+            _ => panic!("no parameter {}", i),
+        }
+    }
+
+    /// set() sets parameter i, kept within its PARAM_INFO range
+    pub fn set(&mut self, i: usize, value: Int) {
+        *self.value_mut(i) = value.clamp(PARAM_INFO[i].min, PARAM_INFO[i].max);
+    }
+
+    fn value_mut(&mut self, i: usize) -> &mut Int {
+        match i {
+            0 => &mut self.eval.doubled_pawn_penalty,
+            1 => &mut self.eval.isolated_pawn_penalty,
+            2 => &mut self.eval.backwards_pawn_penalty,
+            3 => &mut self.eval.passed_pawn_bonus,
+            4 => &mut self.eval.rook_semi_open_file_bonus,
+            5 => &mut self.eval.rook_open_file_bonus,
+            6 => &mut self.eval.rook_on_seventh_bonus,
+            7 => &mut self.history_aging,
+            8 => &mut self.max_qdepth,
+            _ => panic!("no parameter {}", i),
+        }
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl fmt::Display for Params {
+    /// the parameters as settings for Player::parse(), e.g.,
+    /// "doubled_pawn_penalty=10,isolated_pawn_penalty=20,..."
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, info) in PARAM_INFO.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}={}", info.name, self.get(i))?;
+        }
+        Ok(())
+    }
+}
+
+/// what think() reports every PROGRESS_INTERVAL during a long search, so that
+/// a search that takes a while to finish an iteration doesn't look like it's
+/// hung
+
+#[derive(Clone)]
+pub struct SearchProgress {
+    /// the iteration that's being searched
+    pub depth: Int,
+    pub nodes: Int,
+    /// milliseconds since the search started
+    pub time: u128,
+    /// the root move that's being searched, its number (counting from 1), and
+    /// the number of legal moves at the root
+    pub current_move: Option<MoveBytes>,
+    pub move_number: usize,
+    pub moves: usize,
+    /// how full the transposition table is, in permill (None if the search
+    /// doesn't have one)
+    pub hashfull: Option<Int>,
+}
+
+impl SearchProgress {
+    /// nps() returns the number of nodes searched per second
+    pub fn nps(&self) -> Int {
+        (self.nodes as u128 * 1000)
+            .checked_div(self.time)
+            .unwrap_or(0) as Int
+    }
+}
+
+/// a SearchObserver receives the search statistics from think(). Closures that
+/// take a &SearchInfo are SearchObservers, too.
 
-#[derive(PartialEq, Copy, Clone)]
-pub enum ThinkOutput {
-    NoOutput,
-    NormalOutput,
-    XboardOutput,
+pub trait SearchObserver {
+    /// start() is called once, before the first iteration
+    fn start(&mut self) -> Result<(), ObserverError> {
+        Ok(())
+    }
+
+    /// info() is called after each iteration
+    fn info(&mut self, info: &SearchInfo) -> Result<(), ObserverError>;
+
+    /// progress() is called every PROGRESS_INTERVAL while an iteration is
+    /// being searched
+    fn progress(&mut self, _progress: &SearchProgress) -> Result<(), ObserverError> {
+        Ok(())
+    }
+
+    /// current_move() is called as the search of each root move begins, once
+    /// the search has been going for PROGRESS_INTERVAL (before then, the moves
+    /// go by too fast to be worth showing). The time is as of the last time
+    /// the search looked at the clock, which is at most 1024 nodes ago.
+    fn current_move(&mut self, _progress: &SearchProgress) -> Result<(), ObserverError> {
+        Ok(())
+    }
+
+    /// debug() is called, if SearchState::debug is set, with a message that
+    /// explains a decision think() has made
+    fn debug(&mut self, _message: &str) -> Result<(), ObserverError> {
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&SearchInfo)> SearchObserver for F {
+    fn info(&mut self, info: &SearchInfo) -> Result<(), ObserverError> {
+        self(info);
+        Ok(())
+    }
 }
 
-/// think() calls search() iteratively. Search statistics are printed depending
-/// on the value of output.
+/// NoOutput ignores the search statistics
+
+pub struct NoOutput;
 
-pub fn think(d: &mut Data, output: ThinkOutput) {
+impl SearchObserver for NoOutput {
+    fn info(&mut self, _info: &SearchInfo) -> Result<(), ObserverError> {
+        Ok(())
+    }
+}
+
+/// NormalOutput prints the search statistics in the console's format
+
+#[cfg(feature = "cli")]
+pub struct NormalOutput<'a>(pub &'a mut dyn Write);
+
+#[cfg(feature = "cli")]
+impl SearchObserver for NormalOutput<'_> {
+    fn start(&mut self) -> Result<(), ObserverError> {
+        writeln!(self.0, "ply      nodes  score  pv")
+    }
+
+    fn info(&mut self, info: &SearchInfo) -> Result<(), ObserverError> {
+        let line = format!(
+            "{:3}  {:9}  {:5} {}\n",
+            info.depth,
+            info.nodes,
+            info.score,
+            pv_str(&info.pv)
+        );
+        send(self.0, &line)
+    }
+
+    fn progress(&mut self, progress: &SearchProgress) -> Result<(), ObserverError> {
+        let mut line = format!(
+            "{:3}  {:9}    ...  {}/{}",
+            progress.depth, progress.nodes, progress.move_number, progress.moves
+        );
+        if let Some(m) = progress.current_move {
+            line += &format!(" {}", util::move_str(m));
+        }
+        line += &format!(
+            " ({}.{} s, {} nps",
+            progress.time / 1000,
+            progress.time / 100 % 10,
+            progress.nps()
+        );
+        if let Some(hashfull) = progress.hashfull {
+            line += &format!(", hash {}.{}% full", hashfull / 10, hashfull % 10);
+        }
+        line += ")\n";
+        send(self.0, &line)
+    }
+
+    fn current_move(&mut self, progress: &SearchProgress) -> Result<(), ObserverError> {
+        self.progress(progress)
+    }
+
+    fn debug(&mut self, message: &str) -> Result<(), ObserverError> {
+        send(self.0, &format!("debug: {}\n", message))
+    }
+}
+
+/// XboardOutput prints the search statistics in the format XBoard expects
+/// when "post" is on
+
+#[cfg(feature = "cli")]
+pub struct XboardOutput<'a>(pub &'a mut dyn Write);
+
+#[cfg(feature = "cli")]
+impl SearchObserver for XboardOutput<'_> {
+    fn info(&mut self, info: &SearchInfo) -> Result<(), ObserverError> {
+        let line = format!(
+            "{} {} {} {}{}\n",
+            info.depth,
+            info.score,
+            info.time / 10,
+            info.nodes,
+            pv_str(&info.pv)
+        );
+        send(self.0, &line)
+    }
+
+    /// XBoard's "stat01" line is meant to be the answer to the "." command in
+    /// analysis mode, but XBoard shows it whenever it comes, so it's sent
+    /// unasked, too
+    fn progress(&mut self, progress: &SearchProgress) -> Result<(), ObserverError> {
+        let mut line = format!(
+            "stat01: {} {} {} {} {}",
+            progress.time / 10,
+            progress.nodes,
+            progress.depth,
+            progress.moves.saturating_sub(progress.move_number),
+            progress.moves
+        );
+        if let Some(m) = progress.current_move {
+            line += &format!(" {}", util::move_str(m));
+        }
+        line += "\n";
+        send(self.0, &line)
+    }
+
+    /// XBoard shows the move in its status line, so it's told about each one
+    fn current_move(&mut self, progress: &SearchProgress) -> Result<(), ObserverError> {
+        self.progress(progress)
+    }
+
+    /// XBoard ignores lines that start with "#", but keeps them in its debug
+    /// log
+    fn debug(&mut self, message: &str) -> Result<(), ObserverError> {
+        send(self.0, &format!("# {}\n", message))
+    }
+}
+
+/// UciOutput prints the search statistics as UCI "info" lines
+
+#[cfg(feature = "cli")]
+pub struct UciOutput<'a>(pub &'a mut dyn Write);
+
+#[cfg(feature = "cli")]
+impl SearchObserver for UciOutput<'_> {
+    fn info(&mut self, info: &SearchInfo) -> Result<(), ObserverError> {
+        // a mate is given in moves, negative if the engine is getting mated
+        let score = match info.score {
+            x if x > 9000 => format!("mate {}", (10000 - x + 1) / 2),
+            x if x < -9000 => format!("mate -{}", (10000 + x) / 2),
+            x => format!("cp {}", x),
+        };
+        let nps = (info.nodes as u128 * 1000)
+            .checked_div(info.time)
+            .unwrap_or(0);
+        let mut line = format!(
+            "info depth {} seldepth {} score {} nodes {} time {} nps {}",
+            info.depth, info.seldepth, score, info.nodes, info.time, nps
+        );
+        if let Some(hashfull) = info.hashfull {
+            line += &format!(" hashfull {}", hashfull);
+        }
+        line += &format!(" pv{}\n", pv_str(&info.pv));
+        send(self.0, &line)
+    }
+
+    fn progress(&mut self, progress: &SearchProgress) -> Result<(), ObserverError> {
+        let mut line = format!(
+            "info depth {} nodes {} time {} nps {}",
+            progress.depth,
+            progress.nodes,
+            progress.time,
+            progress.nps()
+        );
+        if let Some(hashfull) = progress.hashfull {
+            line += &format!(" hashfull {}", hashfull);
+        }
+        line += "\n";
+        send(self.0, &line)
+    }
+
+    fn current_move(&mut self, progress: &SearchProgress) -> Result<(), ObserverError> {
+        match progress.current_move {
+            Some(m) => send(
+                self.0,
+                &format!(
+                    "info depth {} currmove {} currmovenumber {}\n",
+                    progress.depth,
+                    util::move_str(m),
+                    progress.move_number
+                ),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// the GUI shows "info string" lines to the user (or logs them), so they
+    /// only come when it's asked for them with "debug on"
+    fn debug(&mut self, message: &str) -> Result<(), ObserverError> {
+        send(self.0, &format!("info string {}\n", message))
+    }
+}
+
+/// pv_str() returns the moves of a principal variation, each with a space in
+/// front of it
+
+#[cfg(feature = "cli")]
+pub(crate) fn pv_str(pv: &[MoveBytes]) -> String {
+    pv.iter()
+        .map(|&m| format!(" {}", util::move_str(m)))
+        .collect()
+}
+
+// #rust The C version prints each line of the search's output a piece at a
+// time, and flushes it after the principal variation. With "post" on, and a
+// fast search, that's a lot of small writes (and locks of standard output, and
+// writes to the transcript, if there is one) for each iteration, so the
+// observers put each line together first, and send() writes it in one go.
+
+/// send() writes a whole message to out at once, and flushes it
+
+#[cfg(feature = "cli")]
+fn send(out: &mut dyn Write, message: &str) -> Result<(), ObserverError> {
+    out.write_all(message.as_bytes())?;
+    out.flush()
+}
+
+/// Reporter is what search() needs to report think()'s progress: the
+/// observer, when the clock was last read and when the next report is due,
+/// and what's being searched at the root. If the observer returns an error,
+/// it's kept here, and the search stops.
+
+struct Reporter<'a> {
+    observer: &'a mut dyn SearchObserver,
+    now: u128,
+    next_report: u128,
+    depth: Int,
+    current_move: Option<MoveBytes>,
+    move_number: usize,
+    moves: usize,
+    error: Option<ObserverError>,
+}
+
+impl Reporter<'_> {
+    /// report() passes the search's progress to the observer, and works out
+    /// when the next report is due
+    fn report(&mut self, d: &Data, now: u128) {
+        if let Err(err) = self.observer.progress(&self.progress(d, now)) {
+            self.error = Some(err);
+        }
+        self.next_report = now + PROGRESS_INTERVAL;
+    }
+
+    /// root_move() notes that the search of root move m is beginning, and
+    /// passes it on to the observer if the search has been going for long
+    /// enough. (It doesn't read the clock, which would slow the search down.)
+    fn root_move(&mut self, d: &Data, m: MoveBytes) {
+        self.current_move = Some(m);
+        self.move_number += 1;
+        if self.now >= d.search.start_time + PROGRESS_INTERVAL {
+            if let Err(err) = self.observer.current_move(&self.progress(d, self.now)) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    /// progress() returns what's being searched, as of now
+    fn progress(&self, d: &Data, now: u128) -> SearchProgress {
+        SearchProgress {
+            depth: self.depth,
+            nodes: d.search.nodes,
+            time: now - d.search.start_time,
+            current_move: self.current_move,
+            move_number: self.move_number,
+            moves: self.moves,
+            hashfull: d.search.tt.as_ref().map(|tt| tt.hashfull()),
+        }
+    }
+}
+
+/// think() calls search() iteratively, until it reaches one of the limits.
+/// Search statistics are passed to observer after each iteration.
+
+pub fn think(
+    d: &mut Data,
+    limits: &SearchLimits,
+    observer: &mut dyn SearchObserver,
+) -> Result<SearchResult, ObserverError> {
     // try the opening book first
-    d.pv[0][0].set_value(book::book_move(d));
-    if d.pv[0][0].value() != -1 {
-        return;
+    d.search.pv[0][0].set_value(book::book_move(d));
+    if d.search.pv[0][0].value() != -1 {
+        let m = d.search.pv[0][0].bytes();
+        if d.search.debug {
+            observer.debug(&format!("book move {}", m))?;
+        }
+        return Ok(SearchResult {
+            best_move: Some(m),
+            pv: vec![m],
+            ..SearchResult::default()
+        });
+    }
+    if d.search.debug && !d.books.is_empty() {
+        observer.debug("no book move")?;
     }
 
-    d.start_time = util::get_ms();
-    d.stop_time = d.start_time + d.max_time as u128;
+    d.search.move_time = limits.time_for(d.pos.side_to_move()).map(|t| t as u128);
+    d.search.max_nodes = if limits.infinite { None } else { limits.nodes };
+    let mut max_depth = limits.max_depth();
+    if let (false, Some((depth, nodes, _))) = (limits.infinite, skill_limits(d.search.skill)) {
+        max_depth = max_depth.min(depth);
+        d.search.max_nodes = Some(d.search.max_nodes.map_or(nodes, |n| n.min(nodes)));
+        // a new seed for each search, so the engine doesn't make the same
+        // mistakes every time
+        d.search.noise_seed = rand::rand() as u64;
+    }
+    if d.search.debug {
+        debug_limits(d, limits, max_depth, observer)?;
+    }
+    d.search.start_time = (d.search.clock)();
+    set_stop_time(d);
 
-    d.ply = 0;
-    d.nodes = 0;
+    d.search.ply = 0;
+    d.search.nodes = 0;
+    d.search.qnodes = 0;
+    d.search.seldepth = 0;
 
     for i in 0..MAX_PLY {
         for j in 0..MAX_PLY {
-            d.pv[i][j] = Move::default();
+            d.search.pv[i][j] = Move::default();
         }
     }
-    for i in 0..64 {
-        for j in 0..64 {
-            d.history[i][j] = 0;
-        }
+    // #rust The C version clears the history heuristic for every search.
+    // Here it's kept from one search to the next, since the moves that caused
+    // cutoffs a move ago mostly still do, but aged, so the new search's own
+    // cutoffs soon count for more. Only SearchState::clear() (i.e., a new
+    // game) forgets it.
+    let aging = d.search.params.history_aging.max(1) as i32;
+    for h in d.search.history.iter_mut().flatten().flatten() {
+        *h /= aging;
     }
-    if output == ThinkOutput::NormalOutput {
-        println!("ply      nodes  score  pv");
+    for h in d.search.cont_history.iter_mut() {
+        *h /= aging;
     }
-    for i in 1..=d.max_depth {
-        d.follow_pv = true;
-        match search(d, -10000, 10000, i) {
-            SearchResult::Timeout => {
+    // (the legal moves are only counted for the progress reports; search()
+    // generates the root's moves again)
+    board::gen_legal(d);
+    let mut r = Reporter {
+        observer,
+        now: d.search.start_time,
+        next_report: d.search.start_time + PROGRESS_INTERVAL,
+        depth: 0,
+        current_move: None,
+        move_number: 0,
+        moves: d.search.gen_dat[0].len(),
+        error: None,
+    };
+    r.observer.start()?;
+    let mut result = SearchResult::default();
+    let mut last_nodes = 0;
+    let mut stopped = "reached the depth limit";
+    for i in 1..=max_depth {
+        d.search.follow_pv = true;
+        let iteration_start = (d.search.clock)();
+        let start_nodes = d.search.nodes;
+        r.depth = i;
+        r.move_number = 0;
+        match search(d, &mut r, -10000, 10000, i) {
+            Outcome::Timeout => {
                 // make sure to take back the line we were searching
-                while d.ply != 0 {
-                    board::takeback(d);
+                while d.search.ply != 0 {
+                    board::takeback(&mut d.pos);
+                    d.search.ply -= 1;
+                }
+                if let Some(err) = r.error.take() {
+                    return Err(err);
                 }
-                return;
+                stopped = if d.search.stop.load(Ordering::Relaxed) {
+                    "was told to stop"
+                } else if d.search.max_nodes.is_some_and(|n| d.search.nodes >= n) {
+                    "reached the node limit"
+                } else {
+                    "ran out of time"
+                };
+                break;
             }
-            SearchResult::Value(x) => {
-                match output {
-                    ThinkOutput::NoOutput => {}
-                    ThinkOutput::NormalOutput => {
-                        print!("{:3}  {:9}  {:5} ", i, d.nodes, x);
-                    }
-                    ThinkOutput::XboardOutput => {
-                        print!(
-                            "{} {} {} {}",
-                            i,
-                            x,
-                            (util::get_ms() - d.start_time) / 10,
-                            d.nodes
-                        );
-                    }
+            Outcome::Value(x) => {
+                result.score = x;
+                result.depth = i;
+                result.pv = d.search.pv[0][..d.search.pv_length[0]]
+                    .iter()
+                    .map(|m| m.bytes())
+                    .collect();
+                let now = (d.search.clock)();
+                let nodes = d.search.nodes - start_nodes;
+                let branching_factor = if last_nodes > 0 {
+                    Some(nodes as f64 / last_nodes as f64)
+                } else {
+                    None
+                };
+                last_nodes = nodes;
+                let time_left =
+                    if d.search.move_time.is_none() || d.search.pondering.load(Ordering::Relaxed) {
+                        None
+                    } else {
+                        Some(d.search.stop_time.saturating_sub(now))
+                    };
+                let info = SearchInfo {
+                    depth: i,
+                    score: x,
+                    nodes: d.search.nodes,
+                    time: now - d.search.start_time,
+                    seldepth: d.search.seldepth as Int,
+                    pv: result.pv.clone(),
+                    hashfull: d.search.tt.as_ref().map(|tt| tt.hashfull()),
+                    branching_factor,
+                    next_iteration: branching_factor
+                        .map(|b| ((now - iteration_start) as f64 * b) as u128),
+                    time_left,
+                };
+                r.observer.info(&info)?;
+                if !(-9000..=9000).contains(&x) && !limits.infinite {
+                    stopped = "found a mate";
+                    break;
                 }
-                if output != ThinkOutput::NoOutput {
-                    for j in 0..d.pv_length[0] {
-                        print!(" {}", util::move_str(d.pv[0][j].bytes()));
-                    }
-                    println!();
-                    io::stdout().flush().expect("flush");
+                if !info.next_iteration_fits() {
+                    stopped = "expects the next iteration not to finish in time";
+                    break;
                 }
-                if x > 9000 || x < -9000 {
-                    return;
+            }
+        }
+    }
+    if d.search.debug {
+        r.observer.debug(&format!(
+            "search {} after {} ply, {} nodes",
+            stopped, result.depth, d.search.nodes
+        ))?;
+    }
+    if d.search.pv[0][0].value() != 0 {
+        result.best_move = Some(d.search.pv[0][0].bytes());
+    }
+    // (if the search ran out of time after changing its mind about the best
+    // move, the last completed iteration's PV doesn't start with it)
+    if result.pv.len() > 1 && Move::from(result.pv[0]) == d.search.pv[0][0] {
+        result.ponder_move = Some(result.pv[1]);
+    }
+    // the opening variety setting takes precedence in the opening
+    let humanlike = d
+        .search
+        .variety
+        .and_then(|variety| variety.humanlike(&d.pos))
+        .or(d.search.humanlike);
+    if let (Some(humanlike), Some(best)) = (humanlike, result.best_move) {
+        // a mate is played (or fought off) the way the search found
+        if result.depth > 0 && (-9000..=9000).contains(&result.score) {
+            let scores = root_scores(d, &mut r, result.depth.min(HUMAN_DEPTH));
+            if let Some(err) = r.error.take() {
+                return Err(err);
+            }
+            let picked = scores.and_then(|scores| humanlike.pick(&scores, rand::rand()));
+            if let Some((m, score)) = picked {
+                if Move::from(m) != Move::from(best) {
+                    result.best_move = Some(m);
+                    result.score = score;
+                    result.pv = vec![m];
+                    result.ponder_move = None;
                 }
             }
         }
     }
+    result.seldepth = d.search.seldepth as Int;
+    result.nodes = d.search.nodes;
+    result.qnodes = d.search.qnodes;
+    result.time_ms = (d.search.clock)() - d.search.start_time;
+    // (a search that's cut short by an observer error leaves its counts for
+    // the next one to add)
+    if let Some(tt) = &d.search.tt {
+        tt.add_stats(&mem::take(&mut d.search.tt_stats));
+    }
+    Ok(result)
+}
+
+/// root_scores() scores each of the legal moves at the root of the search
+/// with a search depth ply deep (counting the move), for a humanlike pick.
+/// It's only stopped by the stop flag (or an observer error), since the time
+/// and node limits are used up by then, in which case it returns None.
+
+fn root_scores(d: &mut Data, r: &mut Reporter, depth: Int) -> Option<Vec<(MoveBytes, Int)>> {
+    d.search.stop_time = u128::MAX;
+    d.search.max_nodes = None;
+    d.search.follow_pv = false;
+    board::gen_legal(d);
+    let moves: Vec<MoveBytes> = d.search.gen_dat[0].iter().map(|g| g.m.bytes()).collect();
+    let mut scores = Vec::new();
+    for m in moves {
+        board::makemove(&mut d.pos, m);
+        d.search.ply = 1;
+        let outcome = search(d, r, -10000, 10000, depth - 1);
+        // (after a timeout, the line we were searching has to be taken back)
+        while d.search.ply != 0 {
+            board::takeback(&mut d.pos);
+            d.search.ply -= 1;
+        }
+        match outcome {
+            Outcome::Value(x) => scores.push((m, -x)),
+            Outcome::Timeout => return None,
+        }
+    }
+    Some(scores)
 }
 
 /// search() does just that, in negamax fashion
 
 #[allow(clippy::manual_memcpy)]
-fn search(d: &mut Data, alpha: Int, beta: Int, depth: Int) -> SearchResult {
+fn search(d: &mut Data, r: &mut Reporter, alpha: Int, beta: Int, depth: Int) -> Outcome {
     // we're as deep as we want to be; call quiesce() to get a reasonable score
     // and return it
     if depth == 0 {
-        return quiesce(d, alpha, beta);
+        return quiesce(d, r, alpha, beta, 0);
+    }
+    d.search.nodes += 1;
+    if d.search.ply > d.search.seldepth {
+        d.search.seldepth = d.search.ply;
     }
-    d.nodes += 1;
 
     // do some housekeeping every 1024 nodes
-    if (d.nodes & 1023) == 0 && !checkup(d) {
-        return SearchResult::Timeout;
+    if (d.search.nodes & 1023) == 0 && !checkup(d, r) {
+        return Outcome::Timeout;
     }
 
-    d.pv_length[d.ply] = d.ply;
+    d.search.pv_length[d.search.ply] = d.search.ply;
 
     // if this isn't the root of the search tree (where we have to pick a move
     // and can't simply return 0) then check to see if the position is a repeat.
     // if so, we can assume that this line is a draw and return 0.
-    if d.ply != 0 && reps(d) != 0 {
-        return SearchResult::Value(0);
+    if d.search.ply != 0 && reps(&d.pos) != 0 {
+        return Outcome::Value(0);
     }
 
     // are we too deep?
-    if d.ply >= MAX_PLY - 1 {
-        return SearchResult::Value(eval::eval(d));
-    }
-    if d.hply >= HIST_STACK - 1 {
-        return SearchResult::Value(eval::eval(d));
+    if d.search.ply >= MAX_PLY - 1 {
+        return Outcome::Value(eval::eval_with(&mut d.pos, &d.search.params.eval));
     }
 
     // are we in check? if so, we want to search deeper
     let mut depth = depth;
-    let c = board::in_check(d, d.side);
+    let c = board::side_in_check(&d.pos, d.pos.side);
     if c {
         depth += 1;
     }
+
+    // have we already searched this position deep enough?
+    let tt_move = match probe_tt(d, alpha, beta, depth) {
+        Ok(x) => return Outcome::Value(x),
+        Err(m) => m,
+    };
+
     board::gen(d);
-    if d.follow_pv {
+    if d.search.follow_pv {
         // are we following the PV?
         sort_pv(d);
     }
+    if let Some(m) = tt_move {
+        sort_tt(d, m);
+    }
     let mut f = false;
+    let original_alpha = alpha;
     let mut alpha = alpha;
+    let mut best = Move::default();
     let mut x;
 
     // loop through the moves
-    for i in d.first_move[d.ply]..d.first_move[d.ply + 1] {
+    for i in 0..d.search.gen_dat[d.search.ply].len() {
         sort(d, i);
-        if !board::makemove(d, d.gen_dat[i].m.bytes()) {
+        if !board::makemove(&mut d.pos, d.search.gen_dat[d.search.ply][i].m.bytes()) {
             continue;
         }
+        // the child will probe the table for its position, so start loading
+        // its slot now
+        if let Some(tt) = &d.search.tt {
+            tt.prefetch(tt_key(&d.pos));
+        }
+        if d.search.ply == 0 {
+            r.root_move(d, d.search.gen_dat[0][i].m.bytes());
+        }
+        d.search.ply += 1;
         f = true;
-        match search(d, -beta, -alpha, depth - 1) {
-            SearchResult::Timeout => {
-                return SearchResult::Timeout;
+        match search(d, r, -beta, -alpha, depth - 1) {
+            Outcome::Timeout => {
+                return Outcome::Timeout;
             }
-            SearchResult::Value(value) => {
+            Outcome::Value(value) => {
                 x = -value;
-                board::takeback(d);
+                board::takeback(&mut d.pos);
+                d.search.ply -= 1;
                 if x > alpha {
                     // this move caused a cutoff, so increase the history value
                     // so it gets ordered high next time so we can search it
-                    d.history[d.gen_dat[i].m.bytes().from as usize]
-                        [d.gen_dat[i].m.bytes().to as usize] += depth;
+                    let m = d.search.gen_dat[d.search.ply][i].m.bytes();
+                    let (from, to) = (m.from as usize, m.to as usize);
+                    d.search.history[d.pos.side as usize][from][to] += depth as i32;
+                    // (and the same after the move that led here)
+                    if let Some(base) = board::cont_base(&d.pos) {
+                        let p = d.pos.piece(from) as usize;
+                        d.search.cont_history[base + p * 64 + to] += depth as i32;
+                    }
                     if x >= beta {
-                        return SearchResult::Value(beta);
+                        store_tt(
+                            d,
+                            d.search.gen_dat[d.search.ply][i].m,
+                            beta,
+                            depth,
+                            Bound::Lower,
+                        );
+                        return Outcome::Value(beta);
                     }
                     alpha = x;
+                    best = d.search.gen_dat[d.search.ply][i].m;
 
                     // update the PV
-                    d.pv[d.ply][d.ply] = d.gen_dat[i].m;
+                    d.search.pv[d.search.ply][d.search.ply] = d.search.gen_dat[d.search.ply][i].m;
                     // #rust TODO: use split_at_mut/clone_from_slice instead of
                     // manual element-by-element copy here.  (And remove the
                     // #[allow(clippy::manual_memcpy)] annotation.)
-                    for j in (d.ply + 1)..d.pv_length[d.ply + 1] {
-                        d.pv[d.ply][j] = d.pv[d.ply + 1][j];
+                    for j in (d.search.ply + 1)..d.search.pv_length[d.search.ply + 1] {
+                        d.search.pv[d.search.ply][j] = d.search.pv[d.search.ply + 1][j];
                     }
-                    d.pv_length[d.ply] = d.pv_length[d.ply + 1];
+                    d.search.pv_length[d.search.ply] = d.search.pv_length[d.search.ply + 1];
                 }
             }
         }
@@ -193,17 +1223,98 @@ fn search(d: &mut Data, alpha: Int, beta: Int, depth: Int) -> SearchResult {
     // no legal moves? then we're in checkmate or stalemate
     if !f {
         if c {
-            return SearchResult::Value(-10000 + (d.ply as Int));
+            return Outcome::Value(-10000 + (d.search.ply as Int));
         } else {
-            return SearchResult::Value(0);
+            return Outcome::Value(0);
         }
     }
 
-    if d.fifty >= 100 {
-        return SearchResult::Value(0);
+    if d.pos.fifty >= 100 {
+        return Outcome::Value(0);
     }
 
-    SearchResult::Value(alpha)
+    if alpha > original_alpha {
+        store_tt(d, best, alpha, depth, Bound::Exact);
+    } else {
+        store_tt(d, best, alpha, depth, Bound::Upper);
+    }
+    Outcome::Value(alpha)
+}
+
+/// tt_key() returns the position's key in the transposition table: its hash
+/// with the castle permissions XORed in. The hash leaves them out, since
+/// they don't matter to repetitions, but positions that differ in them can
+/// have different moves, so one's score can't stand in for the other's.
+
+fn tt_key(pos: &Position) -> u64 {
+    (pos.hash ^ pos.hash_castle[pos.castle as usize]) as u64
+}
+
+/// probe_tt() looks the current position up in the transposition table. If
+/// the stored result was searched at least depth ply deep and settles the
+/// score given alpha and beta, it returns Ok(score). Otherwise, it returns
+/// the stored move, if any, to be searched first.
+///
+/// #rust The root position is never cut off, so think() always gets a PV.
+
+fn probe_tt(d: &mut Data, alpha: Int, beta: Int, depth: Int) -> Result<Int, Option<Move>> {
+    // below full strength, the table isn't used; its scores don't have the
+    // search's evaluation noise, and the search's scores would mislead the
+    // other searches that share the table
+    let tt = match &d.search.tt {
+        Some(tt) if d.search.skill >= MAX_SKILL => tt,
+        _ => return Err(None),
+    };
+    let e = match tt.probe_counted(tt_key(&d.pos), &mut d.search.tt_stats) {
+        Some(e) => e,
+        None => return Err(None),
+    };
+    if d.search.ply != 0 && e.depth >= depth {
+        // mate scores are stored relative to the position, not the root
+        let x = match e.score {
+            x if x > 9000 => x - d.search.ply as Int,
+            x if x < -9000 => x + d.search.ply as Int,
+            x => x,
+        };
+        match e.bound {
+            Bound::Exact => return Ok(x),
+            Bound::Lower if x >= beta => return Ok(beta),
+            Bound::Upper if x <= alpha => return Ok(alpha),
+            _ => {}
+        }
+    }
+    if e.m == Move::default() {
+        Err(None)
+    } else {
+        Err(Some(e.m))
+    }
+}
+
+/// store_tt() stores the result of searching the current position in the
+/// transposition table, if there is one (and the search is at full strength;
+/// see probe_tt())
+
+fn store_tt(d: &mut Data, m: Move, score: Int, depth: Int, bound: Bound) {
+    if d.search.skill < MAX_SKILL {
+        return;
+    }
+    if let Some(tt) = &d.search.tt {
+        let score = match score {
+            x if x > 9000 => x + d.search.ply as Int,
+            x if x < -9000 => x - d.search.ply as Int,
+            x => x,
+        };
+        tt.store_counted(
+            tt_key(&d.pos),
+            TtEntry {
+                m,
+                score,
+                depth,
+                bound,
+            },
+            &mut d.search.tt_stats,
+        );
+    }
 }
 
 /// quiesce() is a recursive minimax search function with alpha-beta cutoffs. In
@@ -211,84 +1322,117 @@ fn search(d: &mut Data, alpha: Int, beta: Int, depth: Int) -> SearchResult {
 /// allows the evaluation function to cut the search off (and set alpha) The
 /// idea is to find a position where there isn't a lot going on so the static
 /// evaluation function will work.
+///
+/// #rust qdepth is the number of captures since search() called quiesce().
+/// Past max_qdepth of them (see Params), the evaluation is taken as it is:
+/// in positions with a lot of pieces hanging, the capture sequences multiply
+/// until they take up most of the search. Its results are also stored in
+/// (and looked up in) the transposition table, with a depth of 0.
 
 #[allow(clippy::manual_memcpy)]
-fn quiesce(d: &mut Data, alpha: Int, beta: Int) -> SearchResult {
-    d.nodes += 1;
+fn quiesce(d: &mut Data, r: &mut Reporter, alpha: Int, beta: Int, qdepth: usize) -> Outcome {
+    d.search.nodes += 1;
+    d.search.qnodes += 1;
+    if d.search.ply > d.search.seldepth {
+        d.search.seldepth = d.search.ply;
+    }
 
     // do some housekeeping every 1024 nodes
-    if (d.nodes & 1023) == 0 && !checkup(d) {
-        return SearchResult::Timeout;
+    if (d.search.nodes & 1023) == 0 && !checkup(d, r) {
+        return Outcome::Timeout;
     }
 
-    d.pv_length[d.ply] = d.ply;
+    d.search.pv_length[d.search.ply] = d.search.ply;
 
     // are we too deep?
-    if d.ply >= MAX_PLY - 1 {
-        return SearchResult::Value(eval::eval(d));
-    }
-    if d.hply >= HIST_STACK - 1 {
-        return SearchResult::Value(eval::eval(d));
+    if d.search.ply >= MAX_PLY - 1 {
+        return Outcome::Value(eval::eval_with(&mut d.pos, &d.search.params.eval));
     }
 
+    let tt_move = match probe_tt(d, alpha, beta, 0) {
+        Ok(x) => return Outcome::Value(x),
+        Err(m) => m,
+    };
+
     // check with the evaluation function
-    let mut x = eval::eval(d);
+    let mut x = eval::eval_with(&mut d.pos, &d.search.params.eval) + eval_noise(d);
     if x >= beta {
-        return SearchResult::Value(beta);
+        return Outcome::Value(beta);
     }
+    let original_alpha = alpha;
     let mut alpha = alpha;
     if x > alpha {
         alpha = x;
     }
+    if qdepth as Int >= d.search.params.max_qdepth {
+        return Outcome::Value(alpha);
+    }
 
     board::gen_caps(d);
-    if d.follow_pv {
+    if d.search.follow_pv {
         // are we following the PV?
         sort_pv(d);
     }
+    if let Some(m) = tt_move {
+        sort_tt(d, m);
+    }
+    let mut best = Move::default();
 
     // loop through the moves
-    for i in d.first_move[d.ply]..d.first_move[d.ply + 1] {
+    for i in 0..d.search.gen_dat[d.search.ply].len() {
         sort(d, i);
-        if !board::makemove(d, d.gen_dat[i].m.bytes()) {
+        if !board::makemove(&mut d.pos, d.search.gen_dat[d.search.ply][i].m.bytes()) {
             continue;
         }
-        match quiesce(d, -beta, -alpha) {
-            SearchResult::Timeout => {
-                return SearchResult::Timeout;
+        d.search.ply += 1;
+        match quiesce(d, r, -beta, -alpha, qdepth + 1) {
+            Outcome::Timeout => {
+                return Outcome::Timeout;
             }
-            SearchResult::Value(value) => {
+            Outcome::Value(value) => {
                 x = -value;
-                board::takeback(d);
+                board::takeback(&mut d.pos);
+                d.search.ply -= 1;
                 if x > alpha {
+                    let m = d.search.gen_dat[d.search.ply][i].m;
                     if x >= beta {
-                        return SearchResult::Value(beta);
+                        store_tt(d, m, beta, 0, Bound::Lower);
+                        return Outcome::Value(beta);
                     }
                     alpha = x;
+                    best = m;
 
                     // update the PV
-                    d.pv[d.ply][d.ply] = d.gen_dat[i].m;
+                    d.search.pv[d.search.ply][d.search.ply] = d.search.gen_dat[d.search.ply][i].m;
                     // #rust TODO: use split_at_mut/clone_from_slice instead of
                     // manual element-by-element copy here.  (And remove the
                     // #[allow(clippy::manual_memcpy)] annotation.)
-                    for j in (d.ply + 1)..d.pv_length[d.ply + 1] {
-                        d.pv[d.ply][j] = d.pv[d.ply + 1][j];
+                    for j in (d.search.ply + 1)..d.search.pv_length[d.search.ply + 1] {
+                        d.search.pv[d.search.ply][j] = d.search.pv[d.search.ply + 1][j];
                     }
-                    d.pv_length[d.ply] = d.pv_length[d.ply + 1];
+                    d.search.pv_length[d.search.ply] = d.search.pv_length[d.search.ply + 1];
                 }
             }
         }
     }
-    SearchResult::Value(alpha)
+    // (a stand pat score isn't stored; it's as quick to work out again)
+    if best != Move::default() {
+        store_tt(d, best, alpha, 0, Bound::Exact);
+    } else if alpha == original_alpha {
+        store_tt(d, best, alpha, 0, Bound::Upper);
+    }
+    Outcome::Value(alpha)
 }
 
 /// reps() returns the number of times the current position has been repeated.
 /// It compares the current value of hash to previous values.
 
-pub fn reps(d: &Data) -> Int {
+pub fn reps(pos: &Position) -> Int {
     let mut r = 0;
-    for i in (d.hply - d.fifty as usize)..d.hply {
-        if d.hist_dat[i].hash == d.hash {
+    // #rust If the game started from a FEN position, fifty can be more than the
+    // number of moves we have in the history.
+    for i in pos.hply.saturating_sub(pos.fifty as usize)..pos.hply {
+        if pos.hist_dat[i].hash == pos.hash {
             r += 1;
         }
     }
@@ -302,11 +1446,25 @@ pub fn reps(d: &Data) -> Int {
 /// stops calling sort_pv().
 
 fn sort_pv(d: &mut Data) {
-    d.follow_pv = false;
-    for i in d.first_move[d.ply]..d.first_move[d.ply + 1] {
-        if d.gen_dat[i].m.value() == d.pv[0][d.ply].value() {
-            d.follow_pv = true;
-            d.gen_dat[i].score += 10_000_000;
+    d.search.follow_pv = false;
+    for i in 0..d.search.gen_dat[d.search.ply].len() {
+        if d.search.gen_dat[d.search.ply][i].m == d.search.pv[0][d.search.ply] {
+            d.search.follow_pv = true;
+            d.search.gen_dat[d.search.ply][i].score += 10_000_000;
+            return;
+        }
+    }
+}
+
+/// sort_tt() adds 5,000,000 to the score of the move the transposition table
+/// suggests, so it's searched before everything but the PV move. (It may not
+/// be in the move list, if the table entry is for another position with the
+/// same hash.)
+
+fn sort_tt(d: &mut Data, m: Move) {
+    for g in d.search.gen_dat[d.search.ply].iter_mut() {
+        if g.m == m {
+            g.score += 5_000_000;
             return;
         }
     }
@@ -317,25 +1475,682 @@ fn sort_pv(d: &mut Data) {
 /// so the move with the highest score gets searched next, and hopefully
 /// produces a cutoff.
 
+#[allow(clippy::needless_range_loop)]
 fn sort(d: &mut Data, from: usize) {
     let mut bs = -1; // best score
     let mut bi = from; // best i
-    for i in from..d.first_move[d.ply + 1] {
-        if d.gen_dat[i].score > bs {
-            bs = d.gen_dat[i].score;
+    let moves = &mut d.search.gen_dat[d.search.ply];
+    for i in from..moves.len() {
+        if moves[i].score > bs {
+            bs = moves[i].score;
             bi = i;
         }
     }
-    d.gen_dat.swap(from, bi);
+    moves.swap(from, bi);
 }
 
 // checkup() is called once in a while during the search. If it returns false,
 // the search time is up.
 
-fn checkup(d: &Data) -> bool {
-    // is the engine's time up? if so, unwind back to think()
-    if util::get_ms() >= d.stop_time {
+fn checkup(d: &mut Data, r: &mut Reporter) -> bool {
+    // has someone asked the engine to stop? if so, unwind back to think()
+    if d.search.stop.load(Ordering::Relaxed) {
         return false;
     }
-    true
+
+    // is it time to report on the search's progress? (or has the observer
+    // failed since the last checkup?)
+    let now = (d.search.clock)();
+    r.now = now;
+    if now >= r.next_report {
+        r.report(d, now);
+    }
+    if r.error.is_some() {
+        return false;
+    }
+
+    // if we're pondering, the clock doesn't start until the opponent makes the
+    // move we're pondering on
+    if d.search.pondering.load(Ordering::Relaxed) {
+        set_stop_time(d);
+        return true;
+    }
+
+    // have we searched as many nodes as we're allowed to?
+    if let Some(n) = d.search.max_nodes {
+        if d.search.nodes >= n {
+            return false;
+        }
+    }
+
+    // is the engine's time up?
+    now < d.search.stop_time
+}
+
+/// debug_limits() explains to the observer how long think() is going to search
+/// for, and how deep
+
+fn debug_limits(
+    d: &Data,
+    limits: &SearchLimits,
+    max_depth: Int,
+    observer: &mut dyn SearchObserver,
+) -> Result<(), ObserverError> {
+    let side = d.pos.side_to_move();
+    let (time, inc) = match side {
+        Color::Light => (limits.wtime, limits.winc),
+        Color::Dark => (limits.btime, limits.binc),
+    };
+    let time = match (d.search.move_time, limits.movetime, time) {
+        (None, _, _) => String::from("no time limit"),
+        (Some(t), Some(_), _) => format!("{} ms a move", t),
+        (Some(t), None, time) => format!(
+            "{} ms of the {} ms left ({} ms increment, {} moves to go)",
+            t,
+            time.unwrap_or(0),
+            inc,
+            limits.movestogo.unwrap_or(30)
+        ),
+    };
+    let nodes = match d.search.max_nodes {
+        Some(n) => format!("{} nodes", n),
+        None => String::from("no node limit"),
+    };
+    observer.debug(&format!("{}, up to {} ply, {}", time, max_depth, nodes))
+}
+
+/// set_stop_time() sets the time when the search should stop to move_time
+/// from now
+
+fn set_stop_time(d: &mut Data) {
+    d.search.stop_time = match d.search.move_time {
+        Some(t) => (d.search.clock)() + t,
+        None => u128::MAX,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defs::{DARK, LIGHT};
+    use crate::tt::TranspositionTable;
+    use alloc::string::{String, ToString};
+    use alloc::sync::Arc;
+
+    #[test]
+    fn test_skill() {
+        assert_eq!(skill_for_elo(2500), MAX_SKILL);
+        assert_eq!(skill_for_elo(1700), MAX_SKILL);
+        assert_eq!(skill_for_elo(1250), 6);
+        assert_eq!(skill_for_elo(0), MIN_SKILL);
+
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        assert_eq!(eval_noise(&d), 0);
+
+        // the noise is bounded, and the same each time for a position
+        d.search.skill = 4;
+        let noise = eval_noise(&d);
+        assert!(noise.abs() <= 150);
+        assert_eq!(eval_noise(&d), noise);
+
+        // the search is limited
+        let limits = SearchLimits {
+            depth: Some(8),
+            ..SearchLimits::default()
+        };
+        let result = think(&mut d, &limits, &mut NoOutput).unwrap();
+        assert!(result.best_move.is_some());
+        assert!(result.depth <= 5);
+        assert!(result.nodes <= (1000 << 4) + 1024);
+    }
+
+    #[test]
+    fn test_humanlike() {
+        assert_eq!(
+            Humanlike::level(MAX_HUMAN_LEVEL),
+            Humanlike {
+                margin: 10,
+                temperature: 10,
+                inaccuracy: 0
+            }
+        );
+        assert_eq!(Humanlike::level(0).inaccuracy, 30);
+
+        let m = |s: &str| s.parse::<MoveBytes>().unwrap();
+        let scores = [
+            (m("e2e4"), 30),
+            (m("d2d4"), 25),
+            (m("a2a3"), -10),
+            (m("g2g4"), -80),
+        ];
+        let humanlike = Humanlike::level(MAX_HUMAN_LEVEL);
+        let picks: Vec<Int> = (0..1000)
+            .map(|roll| humanlike.pick(&scores, roll * 7919).unwrap().1)
+            .collect();
+        assert!(picks.contains(&30) && picks.contains(&25));
+        assert!(picks.iter().all(|&score| score >= 25));
+        // the best move is picked most often
+        let best = picks.iter().filter(|&&score| score == 30).count();
+        assert!(best > 500 && best < 1000);
+
+        // an inaccuracy (when the roll is under 30) is worse than the margin
+        let humanlike = Humanlike {
+            margin: 20,
+            ..Humanlike::level(MIN_HUMAN_LEVEL)
+        };
+        assert_eq!(humanlike.pick(&scores, 5).unwrap().1, -10);
+        assert!(humanlike.pick(&scores, 50).unwrap().1 >= 25);
+        assert!(humanlike.pick(&[], 0).is_none());
+
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        d.search.humanlike = Some(Humanlike::level(MIN_HUMAN_LEVEL));
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let result = think(&mut d, &limits, &mut NoOutput).unwrap();
+        let best = result.best_move.unwrap();
+        assert!(board::try_move(&mut d, best).is_ok());
+        assert!(result.score > -300);
+    }
+
+    #[test]
+    fn test_variety() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+
+        // with a wide enough margin, the first move varies
+        d.search.variety = Some(Variety {
+            moves: 1,
+            margin: 100,
+        });
+        let mut moves = Vec::new();
+        for _ in 0..10 {
+            let result = think(&mut d, &limits, &mut NoOutput).unwrap();
+            moves.push(result.best_move.unwrap().to_string());
+        }
+        moves.sort();
+        moves.dedup();
+        assert!(moves.len() > 1);
+
+        // but not after the first moves moves
+        for m in ["e2e4", "e7e5"] {
+            board::try_move(&mut d, m.parse().unwrap()).unwrap();
+        }
+        let variety = d.search.variety.unwrap();
+        assert!(variety.humanlike(&d.pos).is_none());
+        d.search.variety = None;
+        let expected = think(&mut d, &limits, &mut NoOutput)
+            .unwrap()
+            .best_move
+            .unwrap();
+        d.search.variety = Some(variety);
+        let m = think(&mut d, &limits, &mut NoOutput)
+            .unwrap()
+            .best_move
+            .unwrap();
+        assert!(Move::from(m) == Move::from(expected));
+    }
+
+    #[test]
+    fn test_resignation() {
+        let losing = SearchResult {
+            depth: 5,
+            score: -1000,
+            ..SearchResult::default()
+        };
+        let book = SearchResult::default();
+
+        let mut resignation = Resignation::default();
+        assert!(!resignation.update(&losing));
+        assert!(!resignation.update(&losing));
+        assert!(!resignation.update(&book));
+        assert!(!resignation.update(&losing));
+        assert!(!resignation.update(&losing));
+        assert!(resignation.update(&losing));
+        resignation.reset();
+        assert!(!resignation.update(&losing));
+
+        resignation.threshold = None;
+        for _ in 0..RESIGN_MOVES {
+            assert!(!resignation.update(&losing));
+        }
+    }
+
+    #[test]
+    fn test_search_observer() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+
+        let mut infos = Vec::new();
+        let result = think(&mut d, &limits, &mut |info: &SearchInfo| {
+            infos.push(info.clone())
+        })
+        .unwrap();
+        assert_eq!(infos.len(), 3);
+        for (i, info) in infos.iter().enumerate() {
+            assert_eq!(info.depth, i as Int + 1);
+            assert_eq!(info.pv.len(), info.depth as usize);
+        }
+
+        // the result is the last iteration's
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.score, infos[2].score);
+        assert_eq!(result.nodes, infos[2].nodes);
+        assert!(result.seldepth >= 3);
+        let pv: Vec<String> = result.pv.iter().map(|m| m.to_string()).collect();
+        let info_pv: Vec<String> = infos[2].pv.iter().map(|m| m.to_string()).collect();
+        assert_eq!(pv, info_pv);
+        assert_eq!(result.best_move.unwrap().to_string(), pv[0]);
+        assert_eq!(result.ponder_move.unwrap().to_string(), pv[1]);
+    }
+
+    #[test]
+    fn test_tt() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        d.search.tt = Some(Arc::new(TranspositionTable::new(1)));
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        };
+
+        // the second search finds the first one's results in the table
+        let first = think(&mut d, &limits, &mut NoOutput).unwrap();
+        let second = think(&mut d, &limits, &mut NoOutput).unwrap();
+        assert!(second.nodes < first.nodes / 2);
+        assert_eq!(
+            second.best_move.unwrap().to_string(),
+            first.best_move.unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_tt_hashfull() {
+        // a table that's too small for the search fills up, odd slots and
+        // all: if the hash were always even, hashfull() couldn't pass 500
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        let tt = Arc::new(TranspositionTable::new(1));
+        d.search.tt = Some(tt.clone());
+        let limits = SearchLimits {
+            depth: Some(7),
+            ..SearchLimits::default()
+        };
+        think(&mut d, &limits, &mut NoOutput).unwrap();
+        assert!(tt.hashfull() > 500);
+        assert!(d.pos.hash as u64 >> 32 != 0);
+    }
+
+    #[test]
+    fn test_tt_castle() {
+        // the same pieces, with and without Black's right to castle long:
+        // the hash is the same, but a score for one isn't a score for the
+        // other
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        d.search.tt = Some(Arc::new(TranspositionTable::new(1)));
+        board::set_fen(&mut d, "r3k3/8/8/8/8/8/8/4K3 b q - 0 1").unwrap();
+        d.search.ply = 1;
+        let (hash, key) = (d.pos.hash, tt_key(&d.pos));
+        store_tt(&mut d, Move::default(), 50, 4, Bound::Exact);
+        assert!(probe_tt(&mut d, -100, 100, 4) == Ok(50));
+
+        board::set_fen(&mut d, "r3k3/8/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        d.search.ply = 1;
+        assert_eq!(d.pos.hash, hash);
+        assert_ne!(tt_key(&d.pos), key);
+        assert!(probe_tt(&mut d, -100, 100, 4) == Err(None));
+    }
+
+    #[test]
+    fn test_history_aging() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+
+        // a8b8 can't be played, so only the aging changes its value
+        d.search.history[LIGHT as usize][0][1] = 4000;
+        d.search.cont_history[0] = 4000;
+        think(&mut d, &limits, &mut NoOutput).unwrap();
+        assert_eq!(d.search.history[LIGHT as usize][0][1], 4000 / HISTORY_AGING);
+        assert_eq!(d.search.cont_history[0], 4000 / HISTORY_AGING);
+        for side in [LIGHT, DARK] {
+            let history = d.search.history[side as usize].iter().flatten();
+            assert!(history.clone().any(|&h| h > 0));
+        }
+        assert!(d.search.cont_history.iter().any(|&h| h > 0));
+
+        // a new game forgets it
+        d.search.clear();
+        assert!(d.search.history.iter().flatten().flatten().all(|&h| h == 0));
+        assert!(d.search.cont_history.iter().all(|&h| h == 0));
+    }
+
+    #[test]
+    fn test_quiesce() {
+        // queens that can take each other all over the board
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::set_fen(&mut d, "qqqqk3/8/8/8/8/8/8/QQQQK3 w - - 0 1").unwrap();
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..SearchLimits::default()
+        };
+        let result = think(&mut d, &limits, &mut NoOutput).unwrap();
+        assert!(result.qnodes > 0 && result.qnodes < result.nodes);
+        assert!(result.seldepth as usize <= 2 + MAX_QDEPTH);
+
+        // the table's results for the quiescence search give the same result
+        d.search.tt = Some(Arc::new(TranspositionTable::new(1)));
+        let hashed = think(&mut d, &limits, &mut NoOutput).unwrap();
+        assert_eq!(hashed.score, result.score);
+        assert!(hashed.nodes < result.nodes);
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        let limits = SearchLimits {
+            depth: Some(4),
+            movetime: Some(1),
+            ..SearchLimits::default()
+        };
+
+        // the clock never moves, so the search can't run out of time
+        d.search.clock = || 1000;
+        let mut depth = 0;
+        think(&mut d, &limits, &mut |info: &SearchInfo| depth = info.depth).unwrap();
+        assert_eq!(depth, 4);
+        assert_eq!(d.search.start_time, 1000);
+    }
+
+    #[test]
+    fn test_next_iteration() {
+        use core::sync::atomic::AtomicU64;
+
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        let limits = SearchLimits {
+            depth: Some(20),
+            movetime: Some(100),
+            ..SearchLimits::default()
+        };
+
+        // a clock that ticks once each time it's read, i.e., about every 1024
+        // nodes
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        d.search.clock = || NOW.fetch_add(1, Ordering::Relaxed) as u128;
+        let mut infos = Vec::new();
+        let result = think(&mut d, &limits, &mut |info: &SearchInfo| {
+            infos.push(info.clone())
+        })
+        .unwrap();
+
+        // the search stopped because the next iteration wouldn't have
+        // finished, not because it ran out of time partway through it
+        let last = infos.last().unwrap();
+        assert_eq!(result.depth, last.depth);
+        assert!(last.depth < 20);
+        assert!(last.time_left.unwrap() > 0);
+        assert!(!last.next_iteration_fits());
+        assert!(infos[..infos.len() - 1]
+            .iter()
+            .all(SearchInfo::next_iteration_fits));
+        assert!(infos[0].branching_factor.is_none());
+        assert!(infos[1].branching_factor.unwrap() > 1.0);
+    }
+
+    #[test]
+    fn test_progress() {
+        use core::sync::atomic::AtomicU64;
+
+        struct Reports(Vec<SearchProgress>);
+
+        impl SearchObserver for Reports {
+            fn info(&mut self, _info: &SearchInfo) -> Result<(), ObserverError> {
+                Ok(())
+            }
+
+            fn progress(&mut self, progress: &SearchProgress) -> Result<(), ObserverError> {
+                self.0.push(progress.clone());
+                Ok(())
+            }
+        }
+
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        d.search.tt = Some(Arc::new(TranspositionTable::new(1)));
+        let limits = SearchLimits {
+            depth: Some(5),
+            ..SearchLimits::default()
+        };
+
+        // a clock that moves half a second each time it's read, so there's a
+        // report about every 2 * 1024 nodes
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        d.search.clock = || NOW.fetch_add(500, Ordering::Relaxed) as u128;
+        let mut reports = Reports(Vec::new());
+        let result = think(&mut d, &limits, &mut reports).unwrap();
+        let reports = reports.0;
+        assert!(reports.len() > 1);
+        assert!(reports.len() <= result.nodes as usize / 2048 + 1);
+        for pair in reports.windows(2) {
+            assert!(pair[1].time >= pair[0].time + PROGRESS_INTERVAL);
+            assert!(pair[1].nodes > pair[0].nodes);
+        }
+        for progress in &reports {
+            assert_eq!(progress.moves, 20);
+            assert!((1..=20).contains(&progress.move_number));
+            assert!(progress.current_move.is_some());
+            assert!((1..=5).contains(&progress.depth));
+            assert!(progress.hashfull.unwrap() > 0);
+            assert!(progress.nps() > 0);
+        }
+    }
+
+    #[test]
+    fn test_debug() {
+        struct Messages(Vec<String>);
+
+        impl SearchObserver for Messages {
+            fn info(&mut self, _info: &SearchInfo) -> Result<(), ObserverError> {
+                Ok(())
+            }
+
+            fn debug(&mut self, message: &str) -> Result<(), ObserverError> {
+                self.0.push(message.to_string());
+                Ok(())
+            }
+        }
+
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let mut messages = Messages(Vec::new());
+        think(&mut d, &limits, &mut messages).unwrap();
+        assert!(messages.0.is_empty());
+
+        d.search.debug = true;
+        think(&mut d, &limits, &mut messages).unwrap();
+        assert_eq!(messages.0.len(), 2);
+        assert_eq!(messages.0[0], "no time limit, up to 3 ply, no node limit");
+        assert!(messages.0[1].starts_with("search reached the depth limit after 3 ply, "));
+
+        messages.0.clear();
+        let limits = SearchLimits {
+            nodes: Some(1000),
+            wtime: Some(60_000),
+            winc: 1000,
+            ..SearchLimits::default()
+        };
+        think(&mut d, &limits, &mut messages).unwrap();
+        assert_eq!(
+            messages.0[0],
+            "3000 ms of the 60000 ms left (1000 ms increment, 30 moves to go), up to 32 ply, 1000 nodes"
+        );
+        assert!(messages.0[1].starts_with("search reached the node limit"));
+    }
+
+    #[test]
+    fn test_current_move() {
+        use core::sync::atomic::AtomicU64;
+
+        struct Moves(Vec<SearchProgress>);
+
+        impl SearchObserver for Moves {
+            fn info(&mut self, _info: &SearchInfo) -> Result<(), ObserverError> {
+                Ok(())
+            }
+
+            fn current_move(&mut self, progress: &SearchProgress) -> Result<(), ObserverError> {
+                self.0.push(progress.clone());
+                Ok(())
+            }
+        }
+
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        let limits = SearchLimits {
+            depth: Some(5),
+            ..SearchLimits::default()
+        };
+
+        // the same clock as test_progress(): by the last iteration, the search
+        // has been going for long enough that every root move is reported
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        d.search.clock = || NOW.fetch_add(500, Ordering::Relaxed) as u128;
+        let mut moves = Moves(Vec::new());
+        think(&mut d, &limits, &mut moves).unwrap();
+        let moves = moves.0;
+        assert!(moves.iter().all(|m| m.time >= PROGRESS_INTERVAL));
+        let last: Vec<_> = moves.iter().filter(|m| m.depth == 5).collect();
+        assert_eq!(
+            last.iter().map(|m| m.move_number).collect::<Vec<_>>(),
+            (1..=20).collect::<Vec<_>>()
+        );
+        for m in &last {
+            assert_eq!(m.moves, 20);
+            assert!(m.current_move.is_some());
+        }
+
+        #[cfg(feature = "cli")]
+        {
+            let mut out = Vec::new();
+            UciOutput(&mut out).current_move(last[2]).unwrap();
+            let line = String::from_utf8(out).unwrap();
+            let expected = format!(
+                "currmove {} currmovenumber 3\n",
+                last[2].current_move.unwrap()
+            );
+            assert!(
+                line.starts_with("info depth 5 ") && line.ends_with(&expected),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = SearchLimits {
+            depth: Some(10),
+            mate: Some(2),
+            wtime: Some(60_000),
+            winc: 1000,
+            btime: Some(30_000),
+            movestogo: Some(20),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits.max_depth(), 4);
+        assert_eq!(limits.time_for(Color::Light), Some(4000));
+        assert_eq!(limits.time_for(Color::Dark), Some(1500));
+
+        // with one move to go, or an increment bigger than the clock, the
+        // engine still keeps time in hand
+        let last_move = SearchLimits {
+            wtime: Some(10_000),
+            movestogo: Some(1),
+            ..SearchLimits::default()
+        };
+        assert_eq!(last_move.time_for(Color::Light), Some(5000));
+        let low_clock = SearchLimits {
+            btime: Some(80),
+            binc: 2000,
+            ..SearchLimits::default()
+        };
+        assert_eq!(low_clock.time_for(Color::Dark), Some(30));
+        let flagged = SearchLimits {
+            btime: Some(0),
+            ..low_clock
+        };
+        assert_eq!(flagged.time_for(Color::Dark), Some(0));
+
+        let limits = SearchLimits {
+            movetime: Some(500),
+            ..limits
+        };
+        assert_eq!(limits.time_for(Color::Dark), Some(500));
+        let limits = SearchLimits {
+            infinite: true,
+            ..limits
+        };
+        assert_eq!(limits.max_depth(), MAX_PLY as Int);
+        assert_eq!(limits.time_for(Color::Dark), None);
+        assert_eq!(SearchLimits::default().time_for(Color::Light), None);
+
+        // the node limit is checked every 1024 nodes
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        let limits = SearchLimits {
+            nodes: Some(5000),
+            ..SearchLimits::default()
+        };
+        let result = think(&mut d, &limits, &mut NoOutput).unwrap();
+        assert!(result.nodes >= 5000 && result.nodes < 5000 + 1024);
+        assert!(result.best_move.is_some());
+    }
 }
@@ -0,0 +1,99 @@
+// selfplay.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP doesn't have a self-play mode. It's a quick way
+// to check that a change to the engine hasn't broken anything, without
+// setting up a GUI to play games against it.
+
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+use super::board;
+use super::pgn;
+use super::search;
+use super::util;
+
+use super::data::Data;
+use super::defs::{Color, GameResult, Int};
+use super::search::{NoOutput, SearchLimits};
+
+/// selfplay() has the computer play games against itself from the starting
+/// position, searching with the given limits, and prints each game's moves
+/// and result and then the totals. If pgn_path is given, each game is
+/// appended to that file as it finishes. The opening book (if there is one)
+/// is what makes the games differ from each other.
+
+pub fn selfplay(
+    d: &mut Data,
+    limits: &SearchLimits,
+    games: Int,
+    pgn_path: Option<&Path>,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let mut pgn_file = match pgn_path {
+        Some(path) => match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Some(file),
+            Err(err) => {
+                writeln!(out, "unable to open {}: {}", path.display(), err)?;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    // white wins, black wins, and draws
+    let mut totals = [0; 3];
+    for game in 1..=games {
+        board::new_game(d, true);
+        let result = loop {
+            let result = util::game_result(d);
+            if result != GameResult::Ongoing {
+                break result;
+            }
+            match search::think(d, limits, &mut NoOutput)?.best_move {
+                Some(m) => {
+                    board::makemove(&mut d.pos, m);
+                }
+                None => break result,
+            }
+        };
+
+        writeln!(out, "Game {}:", game)?;
+        util::print_moves(&d.pos, out)?;
+        util::print_result(d, out)?;
+        writeln!(out)?;
+        match result {
+            GameResult::Checkmate(Color::Light) => totals[0] += 1,
+            GameResult::Checkmate(_) => totals[1] += 1,
+            _ => totals[2] += 1,
+        }
+
+        if let (Some(path), Some(file)) = (pgn_path, pgn_file.as_mut()) {
+            let round = game.to_string();
+            let tags = [
+                ("Event", "TSCP self-play"),
+                ("Site", "?"),
+                ("Date", "????.??.??"),
+                ("Round", round.as_str()),
+                ("White", "TSCP"),
+                ("Black", "TSCP"),
+            ];
+            let text = pgn::game_pgn(&d.pos, &tags, result);
+            if let Err(err) = writeln!(file, "{}", text) {
+                writeln!(out, "unable to write {}: {}", path.display(), err)?;
+                pgn_file = None;
+            }
+        }
+    }
+    writeln!(
+        out,
+        "Total: White won {}, Black won {}, {} drawn",
+        totals[0], totals[1], totals[2]
+    )
+}
@@ -0,0 +1,643 @@
+// server.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP only talks to a console or an XBoard-compatible
+// GUI. This is the tscp-server executable's code: an HTTP server that lets a
+// web front end play and analyze games, with a JSON API:
+//
+//     POST   /games               start a game: {"fen": ..., "moves": [...]}
+//                                 (both optional)
+//     GET    /games               the ids of the games
+//     GET    /games/{id}          the game's position, moves, and result
+//     PUT    /games/{id}/position set the position up: {"fen": ..., "moves": [...]}
+//     POST   /games/{id}/moves    make moves (in SAN or coordinate notation):
+//                                 {"moves": [...]}
+//     POST   /games/{id}/undo     take moves back: {"n": ...} (1 by default)
+//     POST   /games/{id}/go       search, and return the best move: {"depth",
+//                                 "movetime", "nodes", "wtime", "btime",
+//                                 "winc", "binc"} (all optional), and
+//                                 {"play": true} to make the move too
+//     POST   /games/{id}/stop     make a search that's in progress move now
+//     DELETE /games/{id}          end the game
+//     GET    /games/{id}/ws       a WebSocket that streams the game's search
+//                                 info, best moves, and results as JSON
+//
+// Each game is an Engine session (see engine.rs), so games are independent
+// (though they share the engine's thread, so one game's search holds up the
+// others' commands). Like tscp-tui, the server keeps its own copy of each
+// game, so looking at a game doesn't wait for a search to finish.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, StatusCode};
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use super::board;
+use super::pgn;
+use super::util;
+
+use super::data::Data;
+use super::defs::{Int, MoveBytes, LIGHT, MAX_PLY};
+use super::engine::{Engine, Event, Session};
+use super::search::{SearchInfo, SearchLimits};
+
+pub const USAGE: &str = "usage: tscp-server [options]\n\
+    \n\
+    Serves an HTTP and WebSocket API for playing and analyzing games.\n\
+    \n\
+    --listen addr  listen on addr (127.0.0.1:8080)\n\
+    --st n         search n seconds when a request doesn't give a limit (1)\n\
+    --book path    use the opening book at path\n\
+    --hash n       use an n MB transposition table\n\
+    --help         show this message";
+
+/// the address the server listens on if there's no --listen
+const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+
+/// the seconds a search takes if the request doesn't give a limit, and
+/// there's no --st
+const DEFAULT_ST: f64 = 1.0;
+
+/// how long a WebSocket waits for an event before pinging the client, to
+/// find out if it's still there
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+
+/// the options that can be given on tscp-server's command line; see
+/// parse_args()
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    /// the address to listen on
+    pub listen: Option<String>,
+    /// the seconds to search when a request doesn't give a limit
+    pub st: Option<f64>,
+    pub book: Option<PathBuf>,
+    /// the size of the transposition table, in MB
+    pub hash_mb: usize,
+    /// show the usage message and exit
+    pub help: bool,
+}
+
+/// parse_args() reads tscp-server's command line arguments (without the
+/// program's name) into Options. It returns a message saying what's wrong if
+/// they don't make sense.
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_ref() {
+            "--help" | "-h" => options.help = true,
+            "--listen" => options.listen = Some(value()?),
+            "--book" => options.book = Some(PathBuf::from(value()?)),
+            "--st" => {
+                let n = value()?;
+                options.st = match n.parse::<f64>() {
+                    Ok(st) if st.is_finite() && st > 0.0 => Some(st),
+                    _ => return Err(format!("bad {} value: {}", arg, n)),
+                };
+            }
+            "--hash" => {
+                let n = value()?;
+                options.hash_mb = n.parse().map_err(|_| format!("bad {} value: {}", arg, n))?;
+            }
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+/// run() serves requests until the process is killed. It returns a message
+/// if the engine can't be started or the address can't be listened on.
+
+pub fn run(options: &Options) -> Result<(), String> {
+    let server = Arc::new(Server::new(options)?);
+    let listen = options.listen.as_deref().unwrap_or(DEFAULT_LISTEN);
+    let http = tiny_http::Server::http(listen)
+        .map_err(|err| format!("unable to listen on {}: {}", listen, err))?;
+    eprintln!("tscp-server: listening on http://{}", listen);
+    for request in http.incoming_requests() {
+        let server = Arc::clone(&server);
+        // a search can take a while, so each request gets its own thread
+        thread::spawn(move || server.respond(request));
+    }
+    Ok(())
+}
+
+/// a game being played through the server
+struct Game {
+    session: Session,
+    /// the server's copy of the game
+    d: Mutex<Data>,
+}
+
+/// the server's games, and the engine that plays them
+
+pub struct Server {
+    engine: Engine,
+    games: Mutex<BTreeMap<usize, Arc<Game>>>,
+    next_id: AtomicUsize,
+    /// the limits for a search whose request doesn't give any
+    default_limits: SearchLimits,
+}
+
+/// the response to a request that went wrong: the HTTP status, and a
+/// message saying why
+type Failure = (u16, String);
+
+impl Server {
+    /// new() starts the engine
+    pub fn new(options: &Options) -> Result<Server, String> {
+        let mut builder = Engine::builder()
+            .max_depth(MAX_PLY as Int - 1)
+            .hash_mb(options.hash_mb);
+        if let Some(path) = &options.book {
+            builder = builder.book_path(path);
+        }
+        Ok(Server {
+            engine: builder.build().map_err(|err| err.to_string())?,
+            games: Mutex::new(BTreeMap::new()),
+            next_id: AtomicUsize::new(1),
+            default_limits: SearchLimits {
+                movetime: Some((options.st.unwrap_or(DEFAULT_ST) * 1000.0) as Int),
+                ..SearchLimits::default()
+            },
+        })
+    }
+
+    /// respond() answers an HTTP request, upgrading it to a WebSocket if it
+    /// asks for one
+    fn respond(&self, mut request: Request) {
+        let path = request.url().split('?').next().unwrap_or("").to_string();
+        let websocket_key = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+            .map(|h| h.value.as_str().to_string());
+        if let (Some(key), Some(id)) = (websocket_key, path.strip_suffix("/ws")) {
+            match self.game(id.trim_start_matches("/games/")) {
+                Ok(game) => return stream_events(request, &key, &game),
+                Err((status, message)) => {
+                    let _ = request.respond(json_response(status, &json!({ "error": message })));
+                    return;
+                }
+            }
+        }
+
+        let method = request.method().as_str().to_string();
+        let mut body = String::new();
+        let (status, value) = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) if method == Method::Options.as_str() => (204, Value::Null),
+            Ok(_) => self.handle(&method, &path, &body),
+            Err(err) => (400, json!({ "error": err.to_string() })),
+        };
+        let _ = request.respond(json_response(status, &value));
+    }
+
+    /// handle() answers a request for the JSON API (see the top of the file),
+    /// with the HTTP status and the JSON to send back
+    pub fn handle(&self, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let body = if body.trim().is_empty() {
+            Ok(json!({}))
+        } else {
+            serde_json::from_str::<Value>(body).map_err(|err| (400, format!("bad JSON: {}", err)))
+        };
+        let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let result = body.and_then(|body| match (method, &parts[..]) {
+            ("POST", ["games"]) => self.new_game(&body).map(|value| (201, value)),
+            ("GET", ["games"]) => {
+                let ids: Vec<usize> = self.games().keys().copied().collect();
+                Ok((200, json!({ "games": ids })))
+            }
+            ("GET", ["games", id]) => self.game(id).map(|game| (200, game_json(id, &game))),
+            ("DELETE", ["games", id]) => {
+                let game = self.game(id)?;
+                game.session.stop_thinking();
+                self.games().retain(|_, g| !Arc::ptr_eq(g, &game));
+                Ok((200, json!({})))
+            }
+            ("PUT", ["games", id, "position"]) => {
+                let game = self.game(id)?;
+                set_position(&game, &body)?;
+                Ok((200, game_json(id, &game)))
+            }
+            ("POST", ["games", id, "moves"]) => {
+                let game = self.game(id)?;
+                make_moves(&game, &body["moves"])?;
+                Ok((200, game_json(id, &game)))
+            }
+            ("POST", ["games", id, "undo"]) => {
+                let game = self.game(id)?;
+                let n = match &body["n"] {
+                    Value::Null => 1,
+                    n => n.as_u64().ok_or((400, "bad n".to_string()))? as usize,
+                };
+                let mut d = game.d.lock().expect("game poisoned");
+                let n = n.min(d.pos.hply);
+                game.session.undo_moves(n);
+                for _ in 0..n {
+                    board::takeback(&mut d.pos);
+                }
+                drop(d);
+                Ok((200, game_json(id, &game)))
+            }
+            ("POST", ["games", id, "go"]) => {
+                let game = self.game(id)?;
+                let value = self.go(&game, &body)?;
+                Ok((200, value))
+            }
+            ("POST", ["games", id, "stop"]) => {
+                self.game(id)?.session.stop_thinking();
+                Ok((200, json!({})))
+            }
+            (_, ["games"]) | (_, ["games", _]) | (_, ["games", _, _]) => {
+                Err((405, format!("{} isn't allowed for {}", method, path)))
+            }
+            _ => Err((404, format!("not found: {}", path))),
+        });
+        match result {
+            Ok(answer) => answer,
+            Err((status, message)) => (status, json!({ "error": message })),
+        }
+    }
+
+    /// games() locks the table of games
+    fn games(&self) -> std::sync::MutexGuard<'_, BTreeMap<usize, Arc<Game>>> {
+        self.games.lock().expect("games poisoned")
+    }
+
+    /// game() looks up the game whose id is in a request's path
+    fn game(&self, id: &str) -> Result<Arc<Game>, Failure> {
+        id.parse()
+            .ok()
+            .and_then(|id| self.games().get(&id).cloned())
+            .ok_or((404, format!("no such game: {}", id)))
+    }
+
+    /// new_game() starts a game in the position that body gives, if any
+    fn new_game(&self, body: &Value) -> Result<Value, Failure> {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        let game = Arc::new(Game {
+            session: self.engine.new_session(),
+            d: Mutex::new(d),
+        });
+        set_position(&game, body)?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.games().insert(id, Arc::clone(&game));
+        Ok(game_json(&id.to_string(), &game))
+    }
+
+    /// go() searches the game's position with the limits body gives, and
+    /// makes the best move if body asks for it
+    fn go(&self, game: &Game, body: &Value) -> Result<Value, Failure> {
+        let mut limits = SearchLimits::default();
+        let mut limited = false;
+        for (name, limit) in [
+            ("depth", &mut limits.depth),
+            ("movetime", &mut limits.movetime),
+            ("nodes", &mut limits.nodes),
+            ("wtime", &mut limits.wtime),
+            ("btime", &mut limits.btime),
+        ] {
+            if let Some(n) = int_field(body, name)? {
+                *limit = Some(n);
+                limited = true;
+            }
+        }
+        limits.winc = int_field(body, "winc")?.unwrap_or(0);
+        limits.binc = int_field(body, "binc")?.unwrap_or(0);
+        if limits
+            .depth
+            .is_some_and(|depth| depth < 1 || depth >= MAX_PLY as Int)
+        {
+            return Err((400, "bad depth".to_string()));
+        }
+        if !limited {
+            limits = self.default_limits;
+        }
+
+        let result = game.session.think(limits);
+        let mut value = json!({
+            "bestmove": result.best_move.map(util::move_str),
+            "ponder": result.ponder_move.map(util::move_str),
+            "score": result.score,
+            "depth": result.depth,
+            "nodes": result.nodes,
+            "qnodes": result.qnodes,
+            "time": result.time_ms as u64,
+            "pv": result.pv.iter().map(|&m| util::move_str(m)).collect::<Vec<_>>(),
+        });
+        if let (Some(m), Some(true)) = (result.best_move, body["play"].as_bool()) {
+            // the position may have changed while the engine was thinking
+            let mut d = game.d.lock().expect("game poisoned");
+            board::try_move(&mut d, m)
+                .map_err(|_| (409, "the position changed during the search".to_string()))?;
+            // the session is updated under the lock, as make_moves() does,
+            // so the two can't get out of step
+            if game.session.make_move(m).is_err() {
+                board::takeback(&mut d.pos);
+                return Err((409, format!("the engine rejected {}", m)));
+            }
+            value["played"] = json!(true);
+        }
+        Ok(value)
+    }
+}
+
+/// set_position() sets the game up in the position given by body's "fen"
+/// (or the starting position), followed by its "moves"
+
+fn set_position(game: &Game, body: &Value) -> Result<(), Failure> {
+    let mut d = game.d.lock().expect("game poisoned");
+    let fen = match &body["fen"] {
+        Value::Null => None,
+        Value::String(fen) => Some(fen.as_str()),
+        _ => return Err((400, "bad fen".to_string())),
+    };
+    // check the position before changing the game
+    let mut scratch = Data::new();
+    board::init_hash(&mut scratch.pos);
+    board::set_fen(&mut scratch, fen.unwrap_or(pgn::START_FEN))
+        .map_err(|err| (400, format!("bad fen: {}", err)))?;
+    check_moves(&mut scratch, &body["moves"])?;
+
+    game.session.stop_thinking();
+    game.session.new_game(false);
+    if let Some(fen) = fen {
+        let _ = game.session.set_position(fen);
+    }
+    board::set_fen(&mut d, fen.unwrap_or(pgn::START_FEN)).expect("the FEN was checked");
+    drop(d);
+    make_moves(game, &body["moves"])
+}
+
+/// make_moves() makes the moves in the JSON array moves (if it's there),
+/// either all of them or, if one's illegal, none
+
+fn make_moves(game: &Game, moves: &Value) -> Result<(), Failure> {
+    let mut d = game.d.lock().expect("game poisoned");
+    let ms = check_moves(&mut d, moves)?;
+    for &m in &ms {
+        board::makemove(&mut d.pos, m);
+        game.session
+            .make_move(m)
+            .map_err(|_| (409, format!("the engine rejected {}", m)))?;
+    }
+    Ok(())
+}
+
+/// check_moves() parses the moves in the JSON array moves, in SAN or
+/// coordinate notation, and checks that they can be played in d's position
+/// (which doesn't change). It returns the moves, or says which one is wrong.
+
+fn check_moves(d: &mut Data, moves: &Value) -> Result<Vec<MoveBytes>, Failure> {
+    let list = match moves {
+        Value::Null => return Ok(Vec::new()),
+        Value::Array(list) => list,
+        _ => return Err((400, "moves must be an array".to_string())),
+    };
+    let mut ms = Vec::new();
+    let mut illegal = None;
+    for s in list {
+        let m = s.as_str().and_then(|s| {
+            s.parse::<MoveBytes>()
+                .ok()
+                .or_else(|| board::parse_san(d, s))
+        });
+        match m {
+            Some(m) if board::try_move(d, m).is_ok() => ms.push(m),
+            _ => {
+                illegal = Some(s.as_str().map_or_else(|| s.to_string(), String::from));
+                break;
+            }
+        }
+    }
+    for _ in 0..ms.len() {
+        board::takeback(&mut d.pos);
+    }
+    match illegal {
+        Some(s) => Err((400, format!("illegal move: {}", s))),
+        None => Ok(ms),
+    }
+}
+
+/// int_field() returns body's integer field name, if it has one
+
+fn int_field(body: &Value, name: &str) -> Result<Option<Int>, Failure> {
+    match &body[name] {
+        Value::Null => Ok(None),
+        n => n
+            .as_i64()
+            .map(|n| Some(n as Int))
+            .ok_or((400, format!("bad {}", name))),
+    }
+}
+
+/// game_json() describes a game: its id, position, moves (in SAN), the side
+/// to move, and the result ("*" while it's being played)
+
+fn game_json(id: &str, game: &Game) -> Value {
+    let mut d = game.d.lock().expect("game poisoned");
+    let result = util::game_result(&mut d);
+    json!({
+        "id": id.parse::<usize>().unwrap_or(0),
+        "fen": board::fen(&d.pos),
+        "moves": board::history_san(&d.pos),
+        "side": if d.pos.side == LIGHT { "white" } else { "black" },
+        "result": pgn::result_str(result),
+    })
+}
+
+/// event_json() describes an engine event for a WebSocket client, e.g.,
+/// {"type": "bestmove", "move": "e2e4"}
+
+pub fn event_json(event: &Event) -> Value {
+    match event {
+        Event::SearchInfo(info) => info_json(info),
+        Event::BestMove(m) => json!({ "type": "bestmove", "move": util::move_str(*m) }),
+        Event::BookMove(m) => json!({ "type": "bookmove", "move": util::move_str(*m) }),
+        Event::GameOver(result) => {
+            json!({ "type": "gameover", "result": pgn::result_str(*result) })
+        }
+    }
+}
+
+/// info_json() describes a search's progress after an iteration
+
+fn info_json(info: &SearchInfo) -> Value {
+    json!({
+        "type": "info",
+        "depth": info.depth,
+        "score": info.score,
+        "nodes": info.nodes,
+        "time": info.time as u64,
+        "pv": info.pv.iter().map(|&m| util::move_str(m)).collect::<Vec<_>>(),
+    })
+}
+
+/// json_response() makes an HTTP response with a JSON body, which any web
+/// page may read
+
+fn json_response(status: u16, value: &Value) -> Response<io::Cursor<Vec<u8>>> {
+    let body = if value.is_null() {
+        String::new()
+    } else {
+        value.to_string()
+    };
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+        .with_header(header("Access-Control-Allow-Origin", "*"))
+        .with_header(header(
+            "Access-Control-Allow-Methods",
+            "GET, POST, PUT, DELETE",
+        ))
+        .with_header(header("Access-Control-Allow-Headers", "Content-Type"))
+}
+
+/// header() makes an HTTP header (whose name and value are known to be valid)
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("valid header")
+}
+
+/// stream_events() upgrades request to a WebSocket, and sends the game's
+/// events to it as JSON until the client goes away or the game ends
+
+fn stream_events(request: Request, key: &str, game: &Game) {
+    let events = game.session.subscribe();
+    let response = Response::empty(StatusCode(101))
+        .with_header(header("Upgrade", "websocket"))
+        .with_header(header("Connection", "Upgrade"))
+        .with_header(header(
+            "Sec-WebSocket-Accept",
+            &tungstenite::handshake::derive_accept_key(key.as_bytes()),
+        ));
+    let stream = request.upgrade("websocket", response);
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    loop {
+        let message = match events.recv_timeout(PING_INTERVAL) {
+            Ok(event) => Message::text(event_json(&event).to_string()),
+            Err(RecvTimeoutError::Timeout) => Message::Ping(Vec::new()),
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if socket.send(message).is_err() {
+            return;
+        }
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> Server {
+        Server::new(&Options::default()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(
+            "--listen 0.0.0.0:9000 --st 2 --hash 16"
+                .split_whitespace()
+                .map(String::from),
+        )
+        .unwrap();
+        assert_eq!(options.listen.as_deref(), Some("0.0.0.0:9000"));
+        assert_eq!((options.st, options.hash_mb), (Some(2.0), 16));
+        assert!(parse_args(vec!["--st".to_string()]).is_err());
+        assert!(parse_args(vec!["--uci".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_handle() {
+        let server = server();
+        let (status, game) = server.handle("POST", "/games", r#"{"moves": ["e4", "e7e5"]}"#);
+        assert_eq!(status, 201, "{}", game);
+        assert_eq!(game["id"], 1);
+        assert_eq!(game["moves"], json!(["e4", "e5"]));
+        assert_eq!(game["side"], "white");
+        assert_eq!(game["result"], "*");
+
+        let (status, game) = server.handle("POST", "/games/1/moves", r#"{"moves": ["f4"]}"#);
+        assert_eq!(status, 200, "{}", game);
+        assert_eq!(game["moves"], json!(["e4", "e5", "f4"]));
+        let (status, error) =
+            server.handle("POST", "/games/1/moves", r#"{"moves": ["Nc6", "Ke3"]}"#);
+        assert_eq!(status, 400);
+        assert_eq!(error["error"], "illegal move: Ke3");
+        assert_eq!(
+            server.handle("GET", "/games/1", "").1["moves"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+
+        let (status, answer) =
+            server.handle("POST", "/games/1/go", r#"{"depth": 2, "play": true}"#);
+        assert_eq!(status, 200, "{}", answer);
+        assert_eq!(answer["depth"], 2);
+        assert_eq!(answer["played"], true);
+        let (_, game) = server.handle("GET", "/games/1", "");
+        assert_eq!(game["moves"].as_array().unwrap().len(), 4);
+        let (_, game) = server.handle("POST", "/games/1/undo", r#"{"n": 2}"#);
+        assert_eq!(game["moves"], json!(["e4", "e5"]));
+
+        let (status, game) = server.handle(
+            "PUT",
+            "/games/1/position",
+            r#"{"fen": "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2", "moves": ["Qh4#"]}"#,
+        );
+        assert_eq!(status, 200, "{}", game);
+        assert_eq!(game["result"], "0-1");
+        assert_eq!(
+            server
+                .handle("PUT", "/games/1/position", r#"{"fen": "x"}"#)
+                .0,
+            400
+        );
+
+        assert_eq!(
+            server.handle("GET", "/games", "").1,
+            json!({ "games": [1] })
+        );
+        assert_eq!(server.handle("GET", "/games/2", "").0, 404);
+        assert_eq!(server.handle("GET", "/games/1/go", "").0, 405);
+        assert_eq!(server.handle("POST", "/games", "{").0, 400);
+        assert_eq!(server.handle("GET", "/", "").0, 404);
+        assert_eq!(server.handle("DELETE", "/games/1", "").0, 200);
+        assert_eq!(server.handle("GET", "/games", "").1, json!({ "games": [] }));
+    }
+
+    #[test]
+    fn test_event_json() {
+        let m: MoveBytes = "e2e4".parse().unwrap();
+        assert_eq!(
+            event_json(&Event::BestMove(m)),
+            json!({ "type": "bestmove", "move": "e2e4" })
+        );
+        assert_eq!(
+            event_json(&Event::GameOver(super::super::defs::GameResult::Stalemate)),
+            json!({ "type": "gameover", "result": "1/2-1/2" })
+        );
+    }
+}
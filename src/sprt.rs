@@ -0,0 +1,264 @@
+// sprt.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP has no way to tell whether a change makes it
+// play better. This module plays a match between two configurations of TSCP,
+// the test player and the base player, the way tournament() plays its games,
+// and after each game applies a sequential probability ratio test (SPRT): it
+// works out the log-likelihood ratio (LLR) of the hypothesis that the test
+// player is elo1 Elo stronger than the base player (H1) against the one that
+// it's only elo0 stronger (H0), and stops as soon as the LLR crosses one of
+// the bounds, so a clear result only takes as many games as it needs. The
+// bounds come from the error rates that are acceptable: alpha, the chance of
+// accepting H1 when H0 is true, and beta, the chance of accepting H0 when H1
+// is.
+//
+// The LLR is the normal approximation of the trinomial (win, draw, loss)
+// model, which is plenty accurate for the small Elo differences SPRT is used
+// to tell apart.
+
+use std::io;
+use std::io::Write;
+
+use super::board;
+use super::tournament;
+
+use super::data::Data;
+use super::tournament::{Game, Player};
+
+/// what sprt() plays: the players, the Elo bounds of the hypotheses, the
+/// error rates, and the most games to play if neither hypothesis is accepted
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SprtConfig {
+    pub test: Player,
+    pub base: Player,
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    pub max_games: usize,
+}
+
+/// the test player's wins, draws, and losses against the base player
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Score {
+    /// games() returns the number of games played
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    /// elo() returns the Elo difference the score suggests (positive if the
+    /// test player is stronger), or None if it's all wins or all losses
+    pub fn elo(&self) -> Option<f64> {
+        let x = (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64;
+        if x > 0.0 && x < 1.0 {
+            Some(-400.0 * (1.0 / x - 1.0).log10())
+        } else {
+            None
+        }
+    }
+}
+
+/// the outcome of the test
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// H1 was accepted: the test player is stronger (the change passes)
+    Accepted,
+    /// H0 was accepted: the test player isn't stronger (the change fails)
+    Rejected,
+    /// max_games were played without accepting either hypothesis
+    Inconclusive,
+}
+
+/// bounds() returns the lower and upper bounds of the LLR for the given error
+/// rates; H0 is accepted at the lower one, and H1 at the upper one
+
+pub fn bounds(alpha: f64, beta: f64) -> (f64, f64) {
+    ((beta / (1.0 - alpha)).ln(), ((1.0 - beta) / alpha).ln())
+}
+
+/// llr() returns the log-likelihood ratio of H1 (the test player is elo1
+/// stronger) against H0 (it's elo0 stronger), given the score so far. It's 0
+/// until the games have different results, since there's no variance to go
+/// on before then.
+
+pub fn llr(score: &Score, elo0: f64, elo1: f64) -> f64 {
+    let n = score.games() as f64;
+    if n == 0.0 {
+        return 0.0;
+    }
+    // the mean and variance of a game's result (1, 1/2, or 0)
+    let x = (score.wins as f64 + score.draws as f64 / 2.0) / n;
+    let var = (score.wins as f64 * (1.0 - x).powi(2)
+        + score.draws as f64 * (0.5 - x).powi(2)
+        + score.losses as f64 * x.powi(2))
+        / n;
+    if var == 0.0 {
+        return 0.0;
+    }
+    // the expected results under the two hypotheses
+    let s0 = 1.0 / (1.0 + 10f64.powf(-elo0 / 400.0));
+    let s1 = 1.0 / (1.0 + 10f64.powf(-elo1 / 400.0));
+    n * (s1 - s0) * (2.0 * x - s0 - s1) / (2.0 * var)
+}
+
+/// sprt() plays games between the test and base players, alternating
+/// colors, until the LLR crosses one of the bounds or max_games have been
+/// played, printing the score and the LLR after each game, and returns the
+/// final score and the verdict. The players use d's opening books, which are
+/// what make their games differ from each other.
+
+pub fn sprt(d: &Data, config: &SprtConfig, out: &mut dyn Write) -> io::Result<(Score, Verdict)> {
+    let players = [config.test.clone(), config.base.clone()];
+    let mut engines: Vec<Data> = players
+        .iter()
+        .map(|player| tournament::engine(d, player))
+        .collect();
+    let mut board = Data::new();
+    board::init_hash(&mut board.pos);
+
+    let (lower, upper) = bounds(config.alpha, config.beta);
+    writeln!(
+        out,
+        "SPRT {} vs {}: elo0 {}, elo1 {}, alpha {}, beta {}, LLR bounds ({:.2}, {:.2})",
+        players[0].name,
+        players[1].name,
+        config.elo0,
+        config.elo1,
+        config.alpha,
+        config.beta,
+        lower,
+        upper
+    )?;
+    let mut score = Score::default();
+    for round in 1..=config.max_games {
+        // the test player is player 0, and has White in the odd games
+        let (white, black) = if round % 2 == 1 { (0, 1) } else { (1, 0) };
+        let result = tournament::play_game(&mut board, &mut engines, [white, black], &players)?;
+        let game = Game {
+            round,
+            white,
+            black,
+            result,
+        };
+        match game.points(0) {
+            Some(2) => score.wins += 1,
+            Some(0) => score.losses += 1,
+            _ => score.draws += 1,
+        }
+        let x = llr(&score, config.elo0, config.elo1);
+        writeln!(
+            out,
+            "Game {}: +{} ={} -{}, LLR {:.2}",
+            round, score.wins, score.draws, score.losses, x
+        )?;
+        if x >= upper || x <= lower {
+            let verdict = if x >= upper {
+                Verdict::Accepted
+            } else {
+                Verdict::Rejected
+            };
+            print_verdict(&score, verdict, out)?;
+            return Ok((score, verdict));
+        }
+    }
+    print_verdict(&score, Verdict::Inconclusive, out)?;
+    Ok((score, Verdict::Inconclusive))
+}
+
+/// print_verdict() prints the verdict, and the Elo difference the score
+/// suggests
+
+fn print_verdict(score: &Score, verdict: Verdict, out: &mut dyn Write) -> io::Result<()> {
+    let message = match verdict {
+        Verdict::Accepted => "H1 accepted: the test player passes",
+        Verdict::Rejected => "H0 accepted: the test player fails",
+        Verdict::Inconclusive => "No verdict: the games ran out first",
+    };
+    match score.elo() {
+        Some(elo) => writeln!(
+            out,
+            "{} after {} games (Elo difference {:+.1})",
+            message,
+            score.games(),
+            elo
+        ),
+        None => writeln!(out, "{} after {} games", message, score.games()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::SearchLimits;
+
+    #[test]
+    fn test_llr() {
+        let (lower, upper) = bounds(0.05, 0.05);
+        assert!((lower + 2.944).abs() < 0.001 && (upper - 2.944).abs() < 0.001);
+
+        let score = Score {
+            wins: 60,
+            draws: 20,
+            losses: 20,
+        };
+        assert!((llr(&score, 0.0, 10.0) - 1.734).abs() < 0.001);
+        let reversed = Score {
+            wins: 20,
+            draws: 20,
+            losses: 60,
+        };
+        assert!(llr(&reversed, 0.0, 10.0) < 0.0);
+        assert_eq!(llr(&Score::default(), 0.0, 10.0), 0.0);
+        assert_eq!(
+            llr(
+                &Score {
+                    wins: 0,
+                    draws: 5,
+                    losses: 0
+                },
+                0.0,
+                10.0
+            ),
+            0.0
+        );
+
+        assert_eq!(score.elo().map(|elo| elo.round()), Some(147.0));
+        assert_eq!(Score::default().elo(), None);
+    }
+
+    #[test]
+    fn test_sprt() {
+        let limits = SearchLimits::default();
+        let config = SprtConfig {
+            test: Player::parse("name=Two,depth=2", &limits).unwrap(),
+            base: Player::parse("name=One,depth=1", &limits).unwrap(),
+            elo0: 0.0,
+            elo1: 10.0,
+            alpha: 0.05,
+            beta: 0.05,
+            max_games: 2,
+        };
+        let mut out = Vec::new();
+        let (score, verdict) = sprt(&Data::new(), &config, &mut out).unwrap();
+        assert_eq!(score.games(), 2);
+        assert_eq!(verdict, Verdict::Inconclusive);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("SPRT Two vs One: "), "{}", out);
+        assert!(out.contains("Game 2: "), "{}", out);
+        assert!(out.contains("No verdict"), "{}", out);
+    }
+}
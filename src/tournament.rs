@@ -0,0 +1,566 @@
+// tournament.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP doesn't run tournaments. This module plays
+// games, the way selfplay() does, between TSCP configured in different ways
+// (its skill level and its search limits), either as a round robin, where
+// each player plays each other one, or as a gauntlet, where the first player
+// plays each of the others. It reports each game's result as it finishes,
+// and then the standings, a crosstable, and the results of each pairing, and
+// can save all the games as PGN.
+//
+// Each player has its own Data, so nothing that one learns in a search (the
+// history heuristic, and the transposition table, if there is one) helps
+// the other. The players can only be TSCP; there's no UCI support for
+// playing against other engines.
+
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::board;
+use super::pgn;
+use super::search;
+use super::util;
+
+use super::data::Data;
+use super::defs::{Color, GameResult, Int, MAX_PLY};
+use super::search::{NoOutput, Params, SearchLimits, Variety};
+
+/// who plays whom
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// each player plays each of the others
+    RoundRobin,
+    /// the first player plays each of the others
+    Gauntlet,
+}
+
+/// a player: TSCP, at a skill level, searching with the given limits
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Player {
+    pub name: String,
+    pub skill: Int,
+    pub limits: SearchLimits,
+    /// the opening variety setting, so that the players' games differ even
+    /// without a book
+    pub variety: Option<Variety>,
+    /// the evaluation's penalties and bonuses and the search's constants
+    pub params: Params,
+}
+
+impl Player {
+    /// parse() reads a player from a specification: settings separated by
+    /// commas, each of which is one of "skill=n", "elo=n", "depth=n",
+    /// "nodes=n", "st=n" (seconds per move), "variety=n" (moves, with the
+    /// default margin), "name=s", or one of the parameters in PARAM_INFO, e.g.,
+    /// "passed_pawn_bonus=25". The player's limits start out as the given
+    /// ones, and its name as the specification.
+
+    pub fn parse(spec: &str, limits: &SearchLimits) -> Result<Player, String> {
+        let mut player = Player {
+            name: String::from(spec),
+            skill: search::MAX_SKILL,
+            limits: *limits,
+            variety: None,
+            params: Params::DEFAULT,
+        };
+        for setting in spec.split(',') {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or(format!("bad player setting: {}", setting))?;
+            if key == "name" {
+                player.name = String::from(value);
+                continue;
+            }
+            if let Some(i) = Params::find(key) {
+                let info = &search::PARAM_INFO[i];
+                match value.parse::<Int>() {
+                    Ok(n) if (info.min..=info.max).contains(&n) => player.params.set(i, n),
+                    _ => return Err(format!("bad player setting: {}", setting)),
+                }
+                continue;
+            }
+            let n = match value.parse::<Int>() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(format!("bad player setting: {}", setting)),
+            };
+            match key {
+                "variety" => {
+                    player.variety = Some(Variety {
+                        moves: n,
+                        margin: search::VARIETY_MARGIN,
+                    })
+                }
+                "skill" => player.skill = n.clamp(search::MIN_SKILL, search::MAX_SKILL),
+                "elo" => player.skill = search::skill_for_elo(n),
+                "depth" if n < MAX_PLY as Int => {
+                    player.limits = SearchLimits {
+                        depth: Some(n),
+                        ..SearchLimits::default()
+                    }
+                }
+                "nodes" => {
+                    player.limits = SearchLimits {
+                        nodes: Some(n),
+                        ..SearchLimits::default()
+                    }
+                }
+                "st" => {
+                    player.limits = SearchLimits {
+                        movetime: Some(n * 1000),
+                        ..SearchLimits::default()
+                    }
+                }
+                _ => return Err(format!("bad player setting: {}", setting)),
+            }
+        }
+        Ok(player)
+    }
+}
+
+/// what tournament() plays: the format, the number of games each pair of
+/// players plays, the players, and the file to save the games to
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TournamentConfig {
+    pub format: Format,
+    pub games: usize,
+    pub players: Vec<Player>,
+    /// the PGN file the games are appended to, if there's one
+    pub pgn_path: Option<PathBuf>,
+}
+
+/// a game of the tournament, and how it ended
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Game {
+    pub round: usize,
+    /// the players (their indexes) who played White and Black
+    pub white: usize,
+    pub black: usize,
+    pub result: GameResult,
+}
+
+impl Game {
+    /// points() returns the number of half-points the given player scored in
+    /// the game (2 for a win, 1 for a draw), or None if it didn't play in it
+    pub fn points(&self, player: usize) -> Option<Int> {
+        let color = if player == self.white {
+            Color::Light
+        } else if player == self.black {
+            Color::Dark
+        } else {
+            return None;
+        };
+        Some(match self.result {
+            GameResult::Checkmate(winner) if winner == color => 2,
+            GameResult::Checkmate(_) => 0,
+            _ => 1,
+        })
+    }
+}
+
+/// schedule() returns the pairings for a tournament of the given number of
+/// players, in rounds, each pairing as the indexes of the players who play
+/// White and Black. Each pair plays games games, with the colors alternating,
+/// and no player plays more than once in a round. A round robin is scheduled
+/// with the circle method.
+
+pub fn schedule(players: usize, format: Format, games: usize) -> Vec<Vec<(usize, usize)>> {
+    // the rounds in which each pair plays once
+    let mut cycle: Vec<Vec<(usize, usize)>> = Vec::new();
+    match format {
+        Format::Gauntlet => {
+            for opponent in 1..players {
+                if opponent % 2 == 1 {
+                    cycle.push(vec![(0, opponent)]);
+                } else {
+                    cycle.push(vec![(opponent, 0)]);
+                }
+            }
+        }
+        Format::RoundRobin => {
+            // with an odd number of players, the one paired with the dummy
+            // player n has a bye
+            let n = players + players % 2;
+            let mut circle: Vec<usize> = (0..n).collect();
+            for round in 0..n.saturating_sub(1) {
+                let mut pairs = Vec::new();
+                for i in 0..n / 2 {
+                    let (a, b) = (circle[i], circle[n - 1 - i]);
+                    if a >= players || b >= players {
+                        continue;
+                    }
+                    // the player who stays put alternates colors, and so do
+                    // the boards
+                    if (i == 0 && round % 2 == 0) || (i > 0 && i % 2 == 1) {
+                        pairs.push((a, b));
+                    } else {
+                        pairs.push((b, a));
+                    }
+                }
+                if !pairs.is_empty() {
+                    cycle.push(pairs);
+                }
+                circle[1..].rotate_right(1);
+            }
+        }
+    }
+
+    let mut rounds = Vec::new();
+    for game in 0..games {
+        for pairs in &cycle {
+            rounds.push(
+                pairs
+                    .iter()
+                    .map(|&(white, black)| {
+                        if game % 2 == 0 {
+                            (white, black)
+                        } else {
+                            (black, white)
+                        }
+                    })
+                    .collect(),
+            );
+        }
+    }
+    rounds
+}
+
+/// tournament() plays the tournament, printing each game's result as it
+/// finishes, and then the report (see print_report()). Each game is appended
+/// to the PGN file, if there's one, as it finishes. The players use d's
+/// opening books, which are what make their games differ from each other.
+
+pub fn tournament(
+    d: &Data,
+    config: &TournamentConfig,
+    out: &mut dyn Write,
+) -> io::Result<Vec<Game>> {
+    let players = &config.players;
+    let pgn_path = config.pgn_path.as_deref();
+    let mut pgn_file = match pgn_path {
+        Some(path) => match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Some(file),
+            Err(err) => {
+                writeln!(out, "unable to open {}: {}", path.display(), err)?;
+                return Ok(Vec::new());
+            }
+        },
+        None => None,
+    };
+
+    let mut engines: Vec<Data> = players.iter().map(|player| engine(d, player)).collect();
+    let mut board = Data::new();
+    board::init_hash(&mut board.pos);
+
+    let rounds = schedule(players.len(), config.format, config.games);
+    let mut results = Vec::new();
+    for (round, pairs) in rounds.iter().enumerate() {
+        for &(white, black) in pairs {
+            let result = play_game(&mut board, &mut engines, [white, black], players)?;
+            let game = Game {
+                round: round + 1,
+                white,
+                black,
+                result,
+            };
+            writeln!(
+                out,
+                "Round {}: {} - {} {}",
+                game.round,
+                players[white].name,
+                players[black].name,
+                pgn::result_str(result)
+            )?;
+            results.push(game);
+
+            if let (Some(path), Some(file)) = (pgn_path, pgn_file.as_mut()) {
+                let round = game.round.to_string();
+                let tags = [
+                    ("Event", "TSCP tournament"),
+                    ("Site", "?"),
+                    ("Date", "????.??.??"),
+                    ("Round", round.as_str()),
+                    ("White", players[white].name.as_str()),
+                    ("Black", players[black].name.as_str()),
+                ];
+                let text = pgn::game_pgn(&board.pos, &tags, result);
+                if let Err(err) = writeln!(file, "{}", text) {
+                    writeln!(out, "unable to write {}: {}", path.display(), err)?;
+                    pgn_file = None;
+                }
+            }
+        }
+    }
+    writeln!(out)?;
+    print_report(players, &results, out)?;
+    Ok(results)
+}
+
+/// engine() returns a new engine for player, using d's opening books
+
+pub(crate) fn engine(d: &Data, player: &Player) -> Data {
+    let mut engine = Data::new();
+    engine.books = d.books.clone();
+    engine.book_depth = d.book_depth;
+    engine.book_selection = d.book_selection;
+    engine.search.skill = player.skill;
+    engine.search.variety = player.variety;
+    engine.search.params = player.params;
+    engine
+}
+
+/// play_game() plays a game on board between the players with the given
+/// indexes (White and Black), each searching on its own engine, and returns
+/// the result
+
+pub(crate) fn play_game(
+    board: &mut Data,
+    engines: &mut [Data],
+    sides: [usize; 2],
+    players: &[Player],
+) -> io::Result<GameResult> {
+    board::new_game(board, true);
+    for &i in &sides {
+        board::new_game(&mut engines[i], true);
+    }
+    loop {
+        let result = util::game_result(board);
+        if result != GameResult::Ongoing {
+            return Ok(result);
+        }
+        let i = sides[board.pos.side as usize];
+        let engine = &mut engines[i];
+        engine.pos = board.pos.clone();
+        match search::think(engine, &players[i].limits, &mut NoOutput)?.best_move {
+            Some(m) => {
+                board::makemove(&mut board.pos, m);
+            }
+            None => return Ok(result),
+        }
+    }
+}
+
+/// points_str() formats a number of half-points, e.g., "2.5"
+
+fn points_str(points: Int) -> String {
+    if points % 2 == 0 {
+        format!("{}", points / 2)
+    } else {
+        format!("{}.5", points / 2)
+    }
+}
+
+/// print_report() prints the standings (the players in order of their
+/// scores), a crosstable of the points each scored against each other, and
+/// the wins, draws, and losses of each pairing
+
+pub fn print_report(players: &[Player], games: &[Game], out: &mut dyn Write) -> io::Result<()> {
+    let n = players.len();
+    // points[i][j] is what player i scored against player j
+    let mut points = vec![vec![0; n]; n];
+    let mut played = vec![vec![0; n]; n];
+    for game in games {
+        for (player, opponent) in [(game.white, game.black), (game.black, game.white)] {
+            points[player][opponent] += game.points(player).unwrap_or_default();
+            played[player][opponent] += 1;
+        }
+    }
+    let totals: Vec<Int> = points.iter().map(|row| row.iter().sum()).collect();
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by_key(|&i| -totals[i]);
+
+    let width = players
+        .iter()
+        .map(|player| player.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+    write!(out, "    {:<width$} Score  Games", "Player", width = width)?;
+    for rank in 1..=n {
+        write!(out, " {:>4}", rank)?;
+    }
+    writeln!(out)?;
+    for (rank, &i) in order.iter().enumerate() {
+        let games: Int = played[i].iter().sum();
+        write!(
+            out,
+            "{:>2}. {:<width$} {:>5}  {:>5}",
+            rank + 1,
+            players[i].name,
+            points_str(totals[i]),
+            games,
+            width = width
+        )?;
+        for &j in &order {
+            if i == j {
+                write!(out, "    *")?;
+            } else if played[i][j] == 0 {
+                write!(out, "     ")?;
+            } else {
+                write!(out, " {:>4}", points_str(points[i][j]))?;
+            }
+        }
+        writeln!(out)?;
+    }
+
+    writeln!(out)?;
+    for i in 0..n {
+        for j in i + 1..n {
+            let pair: Vec<&Game> = games
+                .iter()
+                .filter(|game| game.points(i).is_some() && game.points(j).is_some())
+                .collect();
+            if pair.is_empty() {
+                continue;
+            }
+            let count = |points| {
+                pair.iter()
+                    .filter(|game| game.points(i) == Some(points))
+                    .count()
+            };
+            writeln!(
+                out,
+                "{} vs {}: {}-{} (+{} ={} -{})",
+                players[i].name,
+                players[j].name,
+                points_str(points[i][j]),
+                points_str(points[j][i]),
+                count(2),
+                count(1),
+                count(0)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule() {
+        for players in 2..8 {
+            let rounds = schedule(players, Format::RoundRobin, 2);
+            let mut met = vec![vec![0; players]; players];
+            for pairs in &rounds {
+                let mut playing = vec![false; players];
+                for &(white, black) in pairs {
+                    assert!(!playing[white] && !playing[black]);
+                    playing[white] = true;
+                    playing[black] = true;
+                    met[white][black] += 1;
+                }
+            }
+            for (i, row) in met.iter().enumerate() {
+                for (j, &n) in row.iter().enumerate() {
+                    // each pair plays once with each color
+                    assert_eq!(n, if i == j { 0 } else { 1 });
+                }
+            }
+        }
+
+        let rounds = schedule(4, Format::Gauntlet, 1);
+        assert_eq!(rounds, [vec![(0, 1)], vec![(2, 0)], vec![(0, 3)]]);
+        assert_eq!(schedule(1, Format::RoundRobin, 1).len(), 0);
+    }
+
+    #[test]
+    fn test_player() {
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        };
+        let player = Player::parse("skill=3", &limits).unwrap();
+        assert_eq!(player.name, "skill=3");
+        assert_eq!(player.skill, 3);
+        assert_eq!(player.limits, limits);
+
+        let player = Player::parse("name=Fast,st=2,elo=800", &limits).unwrap();
+        assert_eq!(player.name, "Fast");
+        assert_eq!(player.skill, search::MIN_SKILL);
+        assert_eq!(player.limits.movetime, Some(2000));
+        assert_eq!(player.limits.depth, None);
+        assert_eq!(player.variety, None);
+        let player = Player::parse("variety=6", &limits).unwrap();
+        assert_eq!(player.variety.map(|variety| variety.moves), Some(6));
+        assert_eq!(player.params, Params::DEFAULT);
+        let player = Player::parse("passed_pawn_bonus=25,max_qdepth=8", &limits).unwrap();
+        assert_eq!(player.params.eval.passed_pawn_bonus, 25);
+        assert_eq!(player.params.max_qdepth, 8);
+
+        assert!(Player::parse("tscp", &limits).is_err());
+        assert!(Player::parse("history_aging=0", &limits).is_err());
+        assert!(Player::parse("depth=0", &limits).is_err());
+        assert!(Player::parse("depth=99", &limits).is_err());
+        assert!(Player::parse("hash=16", &limits).is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let limits = SearchLimits::default();
+        let players: Vec<Player> = ["name=A", "name=B", "name=C"]
+            .iter()
+            .map(|spec| Player::parse(spec, &limits).unwrap())
+            .collect();
+        let game = |round, white, black, result| Game {
+            round,
+            white,
+            black,
+            result,
+        };
+        let games = [
+            game(1, 1, 2, GameResult::Checkmate(Color::Dark)),
+            game(2, 0, 2, GameResult::Stalemate),
+            game(3, 1, 0, GameResult::Checkmate(Color::Light)),
+        ];
+        assert_eq!(games[0].points(2), Some(2));
+        assert_eq!(games[0].points(0), None);
+
+        let mut out = Vec::new();
+        print_report(&players, &games, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "    Player Score  Games    1    2    3\n\
+             \x201. C        1.5      2    *    1  0.5\n\
+             \x202. B          1      2    0    *    1\n\
+             \x203. A        0.5      2  0.5    0    *\n\
+             \n\
+             A vs B: 0-1 (+0 =0 -1)\n\
+             A vs C: 0.5-0.5 (+0 =1 -0)\n\
+             B vs C: 0-1 (+0 =0 -1)\n"
+        );
+    }
+
+    #[test]
+    fn test_tournament() {
+        let limits = SearchLimits::default();
+        let players: Vec<Player> = ["name=One,depth=1", "name=Two,depth=2"]
+            .iter()
+            .map(|spec| Player::parse(spec, &limits).unwrap())
+            .collect();
+        let config = TournamentConfig {
+            format: Format::RoundRobin,
+            games: 2,
+            players,
+            pgn_path: None,
+        };
+        let mut out = Vec::new();
+        let games = tournament(&Data::new(), &config, &mut out).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!((games[0].white, games[1].white), (0, 1));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Round 2: Two - One"), "{}", out);
+        assert!(out.contains("One vs Two: "), "{}", out);
+    }
+}
@@ -0,0 +1,189 @@
+// transcript.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP doesn't have this. A transcript is a log of
+// everything the console or XBoard mode reads and writes, a line at a time,
+// with the time of each line, so when the engine misbehaves in a GUI, there's
+// a record of what the two of them said to each other. The lines are written
+// to the file as soon as they're complete, so a transcript of a session that
+// hangs is complete up to the hang.
+//
+// There's one transcript for the whole program, as there's one stdin: scan.rs
+// passes what it reads to received(), and the frontends write their output
+// through Logged.
+
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use super::util;
+
+static TRANSCRIPT: Mutex<Option<Transcript>> = Mutex::new(None);
+
+/// set while there's a transcript, so output doesn't have to take the lock
+/// when there isn't
+static LOGGING: AtomicBool = AtomicBool::new(false);
+
+struct Transcript {
+    file: File,
+    /// the unfinished lines that have been received and sent
+    received: Vec<u8>,
+    sent: Vec<u8>,
+}
+
+impl Transcript {
+    /// write_line() writes a line to the file, with the time of day (UTC) and
+    /// the direction: "<" for received, ">" for sent
+    fn write_line(&mut self, direction: char, line: &[u8]) {
+        let ms = util::get_ms() % (24 * 60 * 60 * 1000);
+        let line = String::from_utf8_lossy(line);
+        // #rust a transcript that can't be written isn't worth stopping for
+        let _ = writeln!(
+            self.file,
+            "{:02}:{:02}:{:02}.{:03} {} {}",
+            ms / 3_600_000,
+            ms / 60_000 % 60,
+            ms / 1000 % 60,
+            ms % 1000,
+            direction,
+            line.trim_end_matches('\r')
+        );
+    }
+
+    /// add() adds bytes to one direction's unfinished line, and writes the
+    /// line when it's finished
+    fn add(&mut self, direction: char, bytes: &[u8]) {
+        for &byte in bytes {
+            let line = if direction == '<' {
+                &mut self.received
+            } else {
+                &mut self.sent
+            };
+            if byte != b'\n' {
+                line.push(byte);
+                continue;
+            }
+            let line = std::mem::take(line);
+            self.write_line(direction, &line);
+        }
+    }
+
+    /// finish_sent() writes the unfinished line that's been sent, if there is
+    /// one, e.g., the prompt that the input is the answer to
+    fn finish_sent(&mut self) {
+        if !self.sent.is_empty() {
+            let line = std::mem::take(&mut self.sent);
+            self.write_line('>', &line);
+        }
+    }
+}
+
+/// start() starts a transcript in a new file at path (replacing the file, if
+/// there is one). If there's already a transcript, it's finished first.
+
+pub fn start(path: &Path) -> io::Result<()> {
+    let file = File::create(path)?;
+    stop();
+    *lock() = Some(Transcript {
+        file,
+        received: Vec::new(),
+        sent: Vec::new(),
+    });
+    LOGGING.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// stop() finishes the transcript, if there is one, writing its unfinished
+/// lines
+
+pub fn stop() {
+    LOGGING.store(false, Ordering::Relaxed);
+    if let Some(mut transcript) = lock().take() {
+        transcript.finish_sent();
+        if !transcript.received.is_empty() {
+            let line = std::mem::take(&mut transcript.received);
+            transcript.write_line('<', &line);
+        }
+    }
+}
+
+/// received() adds input to the transcript
+
+pub fn received(bytes: &[u8]) {
+    if LOGGING.load(Ordering::Relaxed) {
+        if let Some(transcript) = lock().as_mut() {
+            // what was sent before this came in goes first
+            transcript.finish_sent();
+            transcript.add('<', bytes);
+        }
+    }
+}
+
+/// sent() adds output to the transcript
+
+pub fn sent(bytes: &[u8]) {
+    if LOGGING.load(Ordering::Relaxed) {
+        if let Some(transcript) = lock().as_mut() {
+            transcript.add('>', bytes);
+        }
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<Transcript>> {
+    TRANSCRIPT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Logged writes to the writer it wraps, and adds what it writes to the
+/// transcript
+
+pub struct Logged<'a>(pub &'a mut dyn Write);
+
+impl Write for Logged<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.0.write(buf)?;
+        sent(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_transcript() {
+        let path = std::env::temp_dir().join(format!("tscp-transcript-{}.txt", std::process::id()));
+        start(&path).unwrap();
+        let mut out = Vec::new();
+        {
+            let mut logged = Logged(&mut out);
+            write!(logged, "tscp> ").unwrap();
+            received(b"e2e4\r\nd");
+            writeln!(logged, "Computer's move: e7e5").unwrap();
+        }
+        stop();
+        received(b"not logged\n");
+        assert_eq!(out, b"tscp> Computer's move: e7e5\n");
+
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = text.lines().map(|line| &line[13..]).collect();
+        assert_eq!(
+            lines,
+            ["> tscp> ", "< e2e4", "> Computer's move: e7e5", "< d"]
+        );
+        assert!(text.lines().all(|line| line.as_bytes()[2] == b':'));
+    }
+}
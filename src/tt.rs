@@ -0,0 +1,437 @@
+// tt.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP doesn't have a transposition table. This one is
+// meant to be shared: it's only ever used through a shared reference, so it can
+// be wrapped in an Arc and handed to several searches at once (search threads,
+// or the searches of several Engines), and it doesn't use any locks.
+//
+// Each slot is a pair of 64-bit atomics: the entry's data, and its key XORed
+// with its data (the "lockless hashing" trick from Hyatt and Mann). If two
+// threads store to the same slot at the same time, a reader may see the key
+// of one store and the data of the other, but then key ^ data won't be the
+// hash the reader is looking for, so the torn entry is treated as a miss.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::defs::{Int, Move};
+
+/// what a stored score says about the position's real score
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// the score is exact
+    Exact,
+    /// the real score is at least the score (the search failed high)
+    Lower,
+    /// the real score is at most the score (the search failed low)
+    Upper,
+}
+
+/// the result of searching a position, as stored in the table
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct TtEntry {
+    /// the best move found, or Move::default() if there wasn't one
+    pub m: Move,
+    pub score: Int,
+    /// the depth the position was searched to
+    pub depth: Int,
+    pub bound: Bound,
+}
+
+impl TtEntry {
+    /// pack() packs the entry into 64 bits: the move in the low 32, then the
+    /// score, the depth, and the bound. The bound is never 0, so a packed entry
+    /// is never 0, which is what an empty slot holds.
+    fn pack(self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+        self.m.value() as u32 as u64
+            | (self.score as i16 as u16 as u64) << 32
+            | (self.depth.clamp(0, 255) as u64) << 48
+            | bound << 56
+    }
+
+    /// unpack() is the reverse of pack(); it returns None for an empty slot
+    fn unpack(data: u64) -> Option<TtEntry> {
+        let bound = match data >> 56 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+        let mut m = Move::default();
+        m.set_value(data as u32 as i32 as Int);
+        Some(TtEntry {
+            m,
+            score: (data >> 32) as u16 as i16 as Int,
+            depth: ((data >> 48) & 0xff) as Int,
+            bound,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    /// the position's hash XORed with data
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// counts of a table's probes and stores, for seeing how well it's working:
+/// a low hit rate with a lot of collisions and overwrites means the table is
+/// too small for the searches
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TtStats {
+    /// the number of lookups, and how many found the position
+    pub probes: u64,
+    pub hits: u64,
+    /// the number of lookups that found another position's entry instead
+    pub collisions: u64,
+    /// the number of entries stored, and how many of them replaced another
+    /// position's entry
+    pub stores: u64,
+    pub overwrites: u64,
+}
+
+impl TtStats {
+    /// hit_rate() returns the percentage of probes that were hits (0 if there
+    /// weren't any probes)
+    pub fn hit_rate(&self) -> f64 {
+        match self.probes {
+            0 => 0.0,
+            n => self.hits as f64 * 100.0 / n as f64,
+        }
+    }
+
+    /// add() adds other's counts to these
+    pub fn add(&mut self, other: &TtStats) {
+        self.probes += other.probes;
+        self.hits += other.hits;
+        self.collisions += other.collisions;
+        self.stores += other.stores;
+        self.overwrites += other.overwrites;
+    }
+}
+
+/// a fixed-size, lock-free hash table of search results, indexed by the
+/// positions' hash values
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    /// the TtStats of the searches that have used the table since it was
+    /// created or cleared, in the order of TtStats's fields
+    // #rust A search counts its own probes and stores, and adds them to
+    // these when it's done, so searches that share the table don't fight
+    // over the counters' cache line.
+    totals: [AtomicU64; 5],
+}
+
+impl TranspositionTable {
+    /// create a table that uses at most mb megabytes (but has at least one
+    /// slot). The number of slots is rounded down to a power of two.
+
+    pub fn new(mb: usize) -> TranspositionTable {
+        let bytes = mb.saturating_mul(1 << 20);
+        let mut len = 1;
+        while len * 2 * core::mem::size_of::<Slot>() <= bytes {
+            len *= 2;
+        }
+        let mut slots = Vec::with_capacity(len);
+        slots.resize_with(len, Slot::default);
+        TranspositionTable {
+            slots,
+            totals: Default::default(),
+        }
+    }
+
+    /// len() returns the number of entries the table can hold
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// bytes() returns the size of the table's slots, in bytes
+    pub fn bytes(&self) -> usize {
+        self.slots.len() * core::mem::size_of::<Slot>()
+    }
+
+    /// is_empty() is always false; a table has at least one slot
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    #[inline(always)]
+    fn slot(&self, hash: u64) -> &Slot {
+        &self.slots[hash as usize & (self.slots.len() - 1)]
+    }
+
+    /// prefetch() starts loading the slot for the position with the given hash
+    /// into the CPU's cache, so that a probe() of it a little later doesn't
+    /// have to wait for memory. (It's only a hint, and on CPUs other than
+    /// x86-64 it does nothing.)
+    #[inline(always)]
+    pub fn prefetch(&self, hash: u64) {
+        #[cfg(target_arch = "x86_64")]
+        // #rust _mm_prefetch() only reads the address (it's never
+        // dereferenced, and can't fault), and SSE is always there on x86-64.
+        unsafe {
+            use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            _mm_prefetch::<_MM_HINT_T0>(self.slot(hash) as *const Slot as *const i8);
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = hash;
+    }
+
+    /// probe() returns the entry stored for the position with the given hash,
+    /// if there is one
+
+    pub fn probe(&self, hash: u64) -> Option<TtEntry> {
+        self.probe_counted(hash, &mut TtStats::default())
+    }
+
+    /// probe_counted() is probe(), counting the probe (and whether it was a
+    /// hit or a collision) in stats
+    #[inline(always)]
+    pub fn probe_counted(&self, hash: u64, stats: &mut TtStats) -> Option<TtEntry> {
+        let slot = self.slot(hash);
+        let data = slot.data.load(Ordering::Relaxed);
+        stats.probes += 1;
+        if slot.key.load(Ordering::Relaxed) ^ data != hash {
+            if data != 0 {
+                stats.collisions += 1;
+            }
+            return None;
+        }
+        let e = TtEntry::unpack(data);
+        if e.is_some() {
+            stats.hits += 1;
+        }
+        e
+    }
+
+    /// store() stores an entry for the position with the given hash. An entry
+    /// for a different position is always replaced; an entry for the same
+    /// position is only replaced by one that's at least as deep.
+
+    pub fn store(&self, hash: u64, entry: TtEntry) {
+        self.store_counted(hash, entry, &mut TtStats::default())
+    }
+
+    /// store_counted() is store(), counting the store (if the entry is
+    /// stored, and whether it replaced another position's entry) in stats
+    #[inline(always)]
+    pub fn store_counted(&self, hash: u64, entry: TtEntry, stats: &mut TtStats) {
+        let slot = self.slot(hash);
+        let old = slot.data.load(Ordering::Relaxed);
+        if slot.key.load(Ordering::Relaxed) ^ old == hash {
+            if let Some(old) = TtEntry::unpack(old) {
+                if old.depth > entry.depth {
+                    return;
+                }
+            }
+        } else if old != 0 {
+            stats.overwrites += 1;
+        }
+        stats.stores += 1;
+        let data = entry.pack();
+        slot.key.store(hash ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    /// hashfull() returns how full the table is, in permill, as estimated
+    /// from its first thousand slots (or all of them, if it has fewer)
+
+    pub fn hashfull(&self) -> Int {
+        let sample = &self.slots[..self.slots.len().min(1000)];
+        let used = sample
+            .iter()
+            .filter(|slot| slot.data.load(Ordering::Relaxed) != 0)
+            .count();
+        (used * 1000 / sample.len()) as Int
+    }
+
+    /// clear() empties the table. It shouldn't be called while a search is
+    /// using the table (nothing bad happens, but the search might not see it
+    /// as empty).
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        for total in &self.totals {
+            total.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// stats() returns the counts of the probes and stores since the table
+    /// was created or cleared, as added by add_stats()
+
+    pub fn stats(&self) -> TtStats {
+        let [probes, hits, collisions, stores, overwrites] = self
+            .totals
+            .each_ref()
+            .map(|total| total.load(Ordering::Relaxed));
+        TtStats {
+            probes,
+            hits,
+            collisions,
+            stores,
+            overwrites,
+        }
+    }
+
+    /// add_stats() adds a search's counts of its probes and stores to the
+    /// table's totals
+
+    pub fn add_stats(&self, stats: &TtStats) {
+        let counts = [
+            stats.probes,
+            stats.hits,
+            stats.collisions,
+            stats.stores,
+            stats.overwrites,
+        ];
+        for (total, n) in self.totals.iter().zip(counts) {
+            total.fetch_add(n, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defs::MoveBytes;
+
+    fn entry(m: &str, score: Int, depth: Int, bound: Bound) -> TtEntry {
+        TtEntry {
+            m: Move::from(m.parse::<MoveBytes>().unwrap()),
+            score,
+            depth,
+            bound,
+        }
+    }
+
+    #[test]
+    fn test_probe_and_store() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.len(), 1 << 16);
+        assert!(tt.probe(12345).is_none());
+
+        let e = entry("e2e4", -9990, 7, Bound::Lower);
+        tt.prefetch(12345);
+        tt.store(12345, e);
+        assert!(tt.probe(12345) == Some(e));
+
+        // a position that maps to the same slot misses
+        assert!(tt.probe(12345 + tt.len() as u64).is_none());
+
+        // a shallower entry for the same position doesn't replace a deeper
+        // one, but one for another position does
+        tt.store(12345, entry("d2d4", 10, 3, Bound::Exact));
+        assert!(tt.probe(12345) == Some(e));
+        let e = entry("g1f3", 25, 1, Bound::Upper);
+        tt.store(12345 + tt.len() as u64, e);
+        assert!(tt.probe(12345 + tt.len() as u64) == Some(e));
+        assert!(tt.probe(12345).is_none());
+
+        tt.clear();
+        assert!(tt.probe(12345 + tt.len() as u64).is_none());
+    }
+
+    #[test]
+    fn test_hashfull() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+        for hash in 0..100 {
+            tt.store(hash, entry("e2e4", 0, 1, Bound::Exact));
+        }
+        assert_eq!(tt.hashfull(), 100);
+        tt.clear();
+        assert_eq!(tt.hashfull(), 0);
+    }
+
+    #[test]
+    fn test_stats() {
+        let tt = TranspositionTable::new(1);
+        let mut stats = TtStats::default();
+        assert!(tt.probe_counted(12345, &mut stats).is_none());
+        tt.store_counted(12345, entry("e2e4", 0, 3, Bound::Exact), &mut stats);
+        assert!(tt.probe_counted(12345, &mut stats).is_some());
+        // shallower, so it isn't stored
+        tt.store_counted(12345, entry("e2e4", 0, 2, Bound::Exact), &mut stats);
+        let other = 12345 + tt.len() as u64;
+        assert!(tt.probe_counted(other, &mut stats).is_none());
+        tt.store_counted(other, entry("d2d4", 0, 1, Bound::Exact), &mut stats);
+        let expected = TtStats {
+            probes: 3,
+            hits: 1,
+            collisions: 1,
+            stores: 2,
+            overwrites: 1,
+        };
+        assert_eq!(stats, expected);
+        assert!((stats.hit_rate() - 100.0 / 3.0).abs() < 1e-9);
+        assert_eq!(TtStats::default().hit_rate(), 0.0);
+
+        assert_eq!(tt.stats(), TtStats::default());
+        tt.add_stats(&stats);
+        tt.add_stats(&stats);
+        let mut twice = stats;
+        twice.add(&stats);
+        assert_eq!(tt.stats(), twice);
+        assert_eq!(twice.hits, 2);
+        tt.clear();
+        assert_eq!(tt.stats(), TtStats::default());
+    }
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(TranspositionTable::new(0).len(), 1);
+        assert_eq!(TranspositionTable::new(16).len(), 1 << 20);
+        assert_eq!(TranspositionTable::new(16).bytes(), 16 << 20);
+        assert_eq!(TranspositionTable::new(5).bytes(), 4 << 20);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_shared() {
+        use alloc::sync::Arc;
+        use std::thread;
+
+        let tt = Arc::new(TranspositionTable::new(1));
+        let threads: Vec<_> = (0..4u64)
+            .map(|t| {
+                let tt = Arc::clone(&tt);
+                thread::spawn(move || {
+                    for i in 0..10_000u64 {
+                        let hash = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                        let score = (i % 1000) as Int;
+                        tt.store(hash, entry("e2e4", score, t as Int, Bound::Exact));
+                        // whatever's there is some thread's whole entry for
+                        // this position, never a mix of two
+                        if let Some(e) = tt.probe(hash) {
+                            assert_eq!(e.score, score);
+                            assert!(e.depth < 4);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
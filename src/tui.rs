@@ -0,0 +1,670 @@
+// tui.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP only has its console prompt. This is the
+// tscp-tui executable's code: a full-screen terminal front end, drawn with
+// ratatui, that shows the board, both clocks, the moves of the game, and the
+// engine's evaluation and principal variation as its search goes deeper.
+//
+// It's written against the Engine API (see engine.rs), the way any other
+// front end would be: the engine thinks on its own thread, a think() call
+// waits for it on a helper thread so the screen keeps updating, and the
+// search's progress comes from the SearchInfo events it sends to
+// subscribers. The front end keeps its own copy of the game (in a Data) to
+// draw the board, check the user's moves, and write them in SAN.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use ratatui::crossterm::event;
+use ratatui::crossterm::event::{Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color as TermColor, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use super::board;
+use super::book;
+use super::clock;
+use super::util;
+
+use super::clock::ChessClock;
+use super::data::Data;
+use super::defs::{Color, GameResult, Int, MoveBytes, DARK, EMPTY, KING, LIGHT, MAX_PLY, PAWN};
+use super::engine::{Engine, Event};
+use super::search::{SearchInfo, SearchLimits, SearchResult};
+
+pub const USAGE: &str = "usage: tscp-tui [options]\n\
+    \n\
+    Plays a game against TSCP in a full-screen terminal interface.\n\
+    \n\
+    --clock m inc  give each side m minutes, plus inc seconds a move (5 3)\n\
+    --st n         make the engine think n seconds a move, instead of using its clock\n\
+    --depth n      make the engine search n ply a move, instead of using its clock\n\
+    --black        play Black (the engine plays White)\n\
+    --book path    use the opening book at path (book.txt, if there is one)\n\
+    --no-book      don't use an opening book\n\
+    --help         show this message";
+
+/// the clock if there's no --clock: 5 minutes a side, plus 3 seconds a move
+const DEFAULT_CLOCK: (Int, Int) = (5 * 60 * 1000, 3000);
+
+/// how often the screen is redrawn while nothing happens, so the clocks run
+const TICK: Duration = Duration::from_millis(100);
+
+/// the commands that can be typed instead of a move
+const COMMANDS: &str = "go, stop, off, undo, new, flip, quit";
+
+/// the options that can be given on tscp-tui's command line; see
+/// parse_args()
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    /// the time each side starts with, and the increment, in milliseconds
+    pub clock: Option<(Int, Int)>,
+    /// the engine's time and depth limits for each move, instead of its clock
+    pub st: Option<f64>,
+    pub depth: Option<Int>,
+    /// the user plays Black
+    pub black: bool,
+    /// the opening book, and whether not to look for one
+    pub book: Option<PathBuf>,
+    pub no_book: bool,
+    /// show the usage message and exit
+    pub help: bool,
+}
+
+/// parse_args() reads tscp-tui's command line arguments (without the
+/// program's name) into Options. It returns a message saying what's wrong if
+/// they don't make sense.
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_ref() {
+            "--help" | "-h" => options.help = true,
+            "--black" => options.black = true,
+            "--no-book" => options.no_book = true,
+            "--book" => options.book = Some(PathBuf::from(value()?)),
+            "--clock" => {
+                let minutes = value()?;
+                let inc = value()?;
+                options.clock = match (minutes.parse::<f64>(), inc.parse::<f64>()) {
+                    (Ok(m), Ok(inc))
+                        if m > 0.0 && m.is_finite() && inc >= 0.0 && inc.is_finite() =>
+                    {
+                        Some(((m * 60000.0) as Int, (inc * 1000.0) as Int))
+                    }
+                    _ => return Err(format!("bad {} value: {} {}", arg, minutes, inc)),
+                };
+            }
+            "--st" => {
+                let n = value()?;
+                options.st = match n.parse::<f64>() {
+                    Ok(st) if st.is_finite() && st > 0.0 => Some(st),
+                    _ => return Err(format!("bad {} value: {}", arg, n)),
+                };
+            }
+            "--depth" => {
+                let n = value()?;
+                options.depth = match n.parse::<usize>() {
+                    Ok(n) if n > 0 && n < MAX_PLY => Some(n as Int),
+                    _ => return Err(format!("bad {} value: {}", arg, n)),
+                };
+            }
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+    if options.st.is_some() && options.depth.is_some() {
+        return Err(String::from("--st and --depth can't both be given"));
+    }
+    if options.book.is_some() && options.no_book {
+        return Err(String::from("--book and --no-book can't both be given"));
+    }
+    Ok(options)
+}
+
+/// run() plays games in the terminal until the user quits. It returns a
+/// message if the engine can't be started or the terminal can't be used.
+
+pub fn run(options: &Options) -> Result<(), String> {
+    let mut options = options.clone();
+    if options.book.is_none() && !options.no_book && Path::new(book::BOOK_FILE).exists() {
+        options.book = Some(PathBuf::from(book::BOOK_FILE));
+    }
+    let mut app = App::new(&options)?;
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result.map_err(|err| format!("terminal error: {}", err))
+}
+
+/// the state of the front end: the game, the engine, and what's on the screen
+
+pub struct App {
+    /// the front end's copy of the game, and its moves in SAN
+    d: Data,
+    moves: Vec<String>,
+    engine: Arc<Engine>,
+    /// the engine's SearchInfo and BookMove events
+    events: Receiver<Event>,
+    /// the results of the engine's searches, sent by the threads that wait
+    /// for them
+    results: Receiver<SearchResult>,
+    result_sender: Sender<SearchResult>,
+    /// the side the engine plays, if it plays one
+    computer: Option<Int>,
+    /// the engine's search limits, if it doesn't use its clock
+    limits: Option<SearchLimits>,
+    clock: ChessClock,
+    /// the side the engine is thinking for, while it's thinking
+    thinking: Option<Int>,
+    /// the latest SearchInfo from the engine, with its principal variation in
+    /// SAN, and the side it's for
+    info: Option<(SearchInfo, String, Int)>,
+    book_move: bool,
+    /// the board is drawn from Black's side
+    flipped: bool,
+    /// the line being typed, and the message under it
+    input: String,
+    message: String,
+    /// how the game ended, if it has
+    over: Option<String>,
+    quit: bool,
+}
+
+impl App {
+    /// new() starts the engine and sets up a new game
+    pub fn new(options: &Options) -> Result<App, String> {
+        let mut builder = Engine::builder().max_depth(MAX_PLY as Int - 1);
+        if let Some(path) = &options.book {
+            builder = builder.book_path(path);
+        }
+        let engine = builder.build().map_err(|err| err.to_string())?;
+        let events = engine.subscribe();
+        let (result_sender, results) = channel();
+        let limits = match (options.depth, options.st) {
+            (Some(depth), _) => Some(SearchLimits {
+                depth: Some(depth),
+                ..SearchLimits::default()
+            }),
+            (None, Some(st)) => Some(SearchLimits {
+                movetime: Some((st * 1000.0) as Int),
+                ..SearchLimits::default()
+            }),
+            (None, None) => None,
+        };
+        let (base, inc) = options.clock.unwrap_or(DEFAULT_CLOCK);
+        let mut app = App {
+            d: Data::new(),
+            moves: Vec::new(),
+            engine: Arc::new(engine),
+            events,
+            results,
+            result_sender,
+            computer: Some(if options.black { LIGHT } else { DARK }),
+            limits,
+            clock: ChessClock::new(base, inc),
+            thinking: None,
+            info: None,
+            book_move: false,
+            flipped: options.black,
+            input: String::new(),
+            message: String::new(),
+            over: None,
+            quit: false,
+        };
+        board::init_hash(&mut app.d.pos);
+        app.new_game();
+        Ok(app)
+    }
+
+    /// run() draws the screen and handles the user's keys and the engine's
+    /// events until the user quits
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(TICK)? {
+                if let TermEvent::Key(key) = event::read()? {
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
+                    match key.code {
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            self.command("quit")
+                        }
+                        KeyCode::Char(c) => self.input.push(c),
+                        KeyCode::Backspace => {
+                            self.input.pop();
+                        }
+                        KeyCode::Esc => self.input.clear(),
+                        KeyCode::Enter => {
+                            let line = std::mem::take(&mut self.input);
+                            self.command(line.trim());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            self.update();
+        }
+        Ok(())
+    }
+
+    /// new_game() sets the board up, and starts the clock, and the engine if
+    /// it plays White
+    fn new_game(&mut self) {
+        self.engine.new_game(false);
+        board::init_board(&mut self.d.pos);
+        self.moves.clear();
+        self.clock.reset();
+        self.info = None;
+        self.book_move = false;
+        self.over = None;
+        self.next_turn();
+    }
+
+    /// next_turn() starts the clock of the side to move, and the engine if
+    /// it's the engine's turn, unless the game is over
+    fn next_turn(&mut self) {
+        if let GameResult::Ongoing = util::game_result(&mut self.d) {
+            self.clock.start(self.d.pos.side, util::get_ms());
+            if self.computer == Some(self.d.pos.side) {
+                self.think();
+            }
+        } else {
+            self.over = Some(result_str(util::game_result(&mut self.d)).to_string());
+        }
+    }
+
+    /// think() starts the engine searching for a move for the side to move,
+    /// on a thread that sends the result to self.results when it's found
+    fn think(&mut self) {
+        let limits = self
+            .limits
+            .unwrap_or_else(|| self.clock.limits(util::get_ms()));
+        let engine = Arc::clone(&self.engine);
+        let results = self.result_sender.clone();
+        thread::spawn(move || {
+            let _ = results.send(engine.think(limits));
+        });
+        self.thinking = Some(self.d.pos.side);
+        self.info = None;
+        self.book_move = false;
+    }
+
+    /// play() makes the move m, which must be legal, for the side to move
+    fn play(&mut self, m: MoveBytes) {
+        let san = board::san(&mut self.d, m).unwrap_or_else(|| m.to_string());
+        if board::try_move(&mut self.d, m).is_err() {
+            return;
+        }
+        self.engine
+            .make_move(m)
+            .expect("the front end and the engine agree on the position");
+        self.moves.push(san);
+        self.clock.punch(util::get_ms());
+        self.next_turn();
+    }
+
+    /// update() handles the engine's events and search results, and checks
+    /// the clock
+    pub fn update(&mut self) {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Event::SearchInfo(info) => {
+                    if let Some(side) = self.thinking {
+                        let pv = board::line_san(&mut self.d, &info.pv).join(" ");
+                        self.info = Some((info, pv, side));
+                    }
+                }
+                Event::BookMove(_) => self.book_move = true,
+                _ => {}
+            }
+        }
+        while let Ok(result) = self.results.try_recv() {
+            self.thinking = None;
+            match result.best_move {
+                Some(m) if self.over.is_none() => self.play(m),
+                _ => {}
+            }
+        }
+        if self.over.is_none() {
+            if let Some(side) = self.clock.flagged(util::get_ms()) {
+                self.clock.punch(util::get_ms());
+                self.engine.stop_thinking();
+                self.over = Some(if side == LIGHT {
+                    "0-1 {White loses on time}".to_string()
+                } else {
+                    "1-0 {Black loses on time}".to_string()
+                });
+            }
+        }
+    }
+
+    /// command() handles a line the user typed: a move (in SAN or coordinate
+    /// notation), or one of the COMMANDS
+    pub fn command(&mut self, line: &str) {
+        self.message.clear();
+        match line {
+            "" => {}
+            "quit" | "exit" | "bye" => {
+                self.engine.stop_thinking();
+                self.quit = true;
+            }
+            "stop" => self.engine.stop_thinking(),
+            "flip" => self.flipped = !self.flipped,
+            _ if self.thinking.is_some() => {
+                self.message = "The engine is thinking (\"stop\" makes it move now)".to_string()
+            }
+            "new" => self.new_game(),
+            "off" => {
+                self.computer = None;
+                self.message = "The engine won't move until you type \"go\"".to_string();
+            }
+            "go" => {
+                if self.over.is_some() {
+                    self.message = "The game is over".to_string();
+                } else {
+                    self.computer = Some(self.d.pos.side);
+                    self.think();
+                }
+            }
+            "undo" => {
+                // take back the engine's reply too, so it's the user's turn
+                let mut n = 1;
+                if self.computer.is_some() && self.computer != Some(self.d.pos.side) {
+                    n = 2;
+                }
+                let n = n.min(self.d.pos.hply);
+                if n == 0 {
+                    self.message = "There are no moves to take back".to_string();
+                    return;
+                }
+                self.engine.undo_moves(n);
+                for _ in 0..n {
+                    board::takeback(&mut self.d.pos);
+                    self.moves.pop();
+                }
+                self.clock.punch(util::get_ms());
+                self.over = None;
+                self.info = None;
+                self.clock.start(self.d.pos.side, util::get_ms());
+            }
+            _ if self.over.is_some() => {
+                self.message = "The game is over (\"new\" starts another)".to_string()
+            }
+            _ => {
+                let m = line
+                    .parse::<MoveBytes>()
+                    .ok()
+                    .or_else(|| board::parse_san(&mut self.d, line));
+                match m {
+                    Some(m) if board::try_move(&mut self.d, m).is_ok() => {
+                        board::takeback(&mut self.d.pos);
+                        self.play(m);
+                    }
+                    _ => {
+                        self.message = format!("Illegal move or unknown command: {}", line);
+                    }
+                }
+            }
+        }
+    }
+
+    /// draw() draws the whole screen
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let [main, input, status] = Layout::vertical([
+            Constraint::Min(14),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Length(29), Constraint::Min(20)]).areas(main);
+        let [board_area, clock_area] =
+            Layout::vertical([Constraint::Length(11), Constraint::Min(4)]).areas(left);
+        let [moves_area, engine_area] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(right);
+
+        frame.render_widget(self.board_widget(), board_area);
+        frame.render_widget(self.clock_widget(), clock_area);
+        frame.render_widget(self.moves_widget(moves_area), moves_area);
+        frame.render_widget(self.engine_widget(), engine_area);
+        frame.render_widget(
+            Paragraph::new(format!("> {}_", self.input)).block(Block::bordered().title(" Move ")),
+            input,
+        );
+        let status_line = match &self.over {
+            Some(over) if self.message.is_empty() => over.clone(),
+            _ if self.message.is_empty() => {
+                format!("Type a move (e4, Nf3, e2e4) or: {}", COMMANDS)
+            }
+            _ => self.message.clone(),
+        };
+        frame.render_widget(
+            Paragraph::new(status_line).style(Style::default().add_modifier(Modifier::BOLD)),
+            status,
+        );
+    }
+
+    /// board_widget() draws the board, with the last move's squares
+    /// highlighted
+    fn board_widget(&self) -> Paragraph<'static> {
+        const GLYPHS: [&str; 6] = ["♟", "♞", "♝", "♜", "♛", "♚"];
+        let last = self.d.pos.history().last().map(|h| h.m.bytes());
+        let mut lines = Vec::new();
+        for row in 0..8 {
+            let row = if self.flipped { 7 - row } else { row };
+            let mut spans = vec![Span::raw(format!(" {} ", 8 - row))];
+            for col in 0..8 {
+                let col = if self.flipped { 7 - col } else { col };
+                let sq = row * 8 + col;
+                let highlighted =
+                    last.is_some_and(|m| m.from as usize == sq || m.to as usize == sq);
+                let bg = match ((row + col) % 2 == 0, highlighted) {
+                    (true, false) => TermColor::Rgb(240, 217, 181),
+                    (false, false) => TermColor::Rgb(181, 136, 99),
+                    (true, true) => TermColor::Rgb(205, 210, 106),
+                    (false, true) => TermColor::Rgb(170, 162, 58),
+                };
+                let (glyph, fg) = match self.d.pos.color(sq) {
+                    EMPTY => (" ", TermColor::Black),
+                    color => {
+                        let piece = self.d.pos.piece(sq);
+                        debug_assert!((PAWN..=KING).contains(&piece));
+                        let fg = if color == LIGHT {
+                            TermColor::White
+                        } else {
+                            TermColor::Black
+                        };
+                        (GLYPHS[piece as usize], fg)
+                    }
+                };
+                spans.push(Span::styled(
+                    format!(" {} ", glyph),
+                    Style::default().fg(fg).bg(bg).add_modifier(Modifier::BOLD),
+                ));
+            }
+            lines.push(Line::from(spans));
+        }
+        let files: String = (0..8)
+            .map(|col| {
+                let col = if self.flipped { 7 - col } else { col };
+                format!(" {} ", (b'a' + col as u8) as char)
+            })
+            .collect();
+        lines.push(Line::from(format!("   {}", files)));
+        Paragraph::new(lines).block(Block::bordered().title(" TSCP "))
+    }
+
+    /// clock_widget() draws both sides' clocks, with the running one in bold
+    fn clock_widget(&self) -> Paragraph<'static> {
+        let now = util::get_ms();
+        let lines: Vec<Line> = [(LIGHT, "White"), (DARK, "Black")]
+            .iter()
+            .map(|&(side, name)| {
+                let who = match self.computer {
+                    Some(computer) if computer == side => " (TSCP)",
+                    _ => "",
+                };
+                let text = format!(
+                    "{:<13}{:>10}",
+                    format!("{}{}", name, who),
+                    clock::time_str(self.clock.left(side, now))
+                );
+                if self.over.is_none() && self.d.pos.side == side {
+                    Line::styled(text, Style::default().add_modifier(Modifier::BOLD))
+                } else {
+                    Line::raw(text)
+                }
+            })
+            .collect();
+        Paragraph::new(lines).block(Block::bordered().title(" Clock "))
+    }
+
+    /// moves_widget() draws the moves of the game, two to a line, scrolled so
+    /// the last ones fit in area
+    fn moves_widget(&self, area: Rect) -> Paragraph<'static> {
+        let lines: Vec<Line> = self
+            .moves
+            .chunks(2)
+            .enumerate()
+            .map(|(i, pair)| Line::from(format!("{:>3}. {}", i + 1, pair.join(" "))))
+            .collect();
+        let height = area.height.saturating_sub(2) as usize;
+        let first = lines.len().saturating_sub(height);
+        Paragraph::new(lines[first..].to_vec()).block(Block::bordered().title(" Moves "))
+    }
+
+    /// engine_widget() draws what the engine is doing, and the latest
+    /// depth, score, and principal variation of its search
+    fn engine_widget(&self) -> Paragraph<'static> {
+        let mut lines = Vec::new();
+        lines.push(Line::from(match self.thinking {
+            Some(_) => "Thinking...",
+            None if self.book_move => "Book move",
+            None => "Waiting",
+        }));
+        if let Some((info, pv, side)) = &self.info {
+            let side = if *side == LIGHT { "White" } else { "Black" };
+            let nps = info.nodes as u128 * 1000 / info.time.max(1);
+            lines.push(Line::from(format!(
+                "Depth {}  Score {} for {}",
+                info.depth,
+                util::score_str(info.score),
+                side
+            )));
+            lines.push(Line::from(format!(
+                "Nodes {}  Time {}.{} s  {} nps",
+                info.nodes,
+                info.time / 1000,
+                info.time / 100 % 10,
+                nps
+            )));
+            lines.push(Line::from(format!("PV {}", pv)));
+        }
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().title(" Engine "))
+    }
+}
+
+/// result_str() describes how a game that's over ended, the way
+/// util::print_result() does
+
+fn result_str(result: GameResult) -> &'static str {
+    match result {
+        GameResult::Checkmate(Color::Light) => "1-0 {White mates}",
+        GameResult::Checkmate(_) => "0-1 {Black mates}",
+        GameResult::Stalemate => "1/2-1/2 {Stalemate}",
+        GameResult::DrawRepetition => "1/2-1/2 {Draw by repetition}",
+        GameResult::DrawFifty => "1/2-1/2 {Draw by fifty move rule}",
+        GameResult::DrawMaterial => "1/2-1/2 {Insufficient material}",
+        GameResult::Ongoing => "*",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn args(s: &str) -> Result<Options, String> {
+        parse_args(s.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = args("--clock 3 2 --black --no-book").unwrap();
+        assert_eq!(options.clock, Some((180000, 2000)));
+        assert!(options.black && options.no_book);
+        assert_eq!(args("--depth 4").unwrap().depth, Some(4));
+        assert_eq!(args("--st 0.5").unwrap().st, Some(0.5));
+        assert_eq!(args("").unwrap(), Options::default());
+
+        assert!(args("--clock 3").is_err());
+        assert!(args("--clock 0 2").is_err());
+        assert!(args("--depth 40").is_err());
+        assert!(args("--st 1 --depth 3").is_err());
+        assert!(args("--book b.txt --no-book").is_err());
+        assert!(args("--uci").is_err());
+    }
+
+    #[test]
+    fn test_app() {
+        let mut app = App::new(&args("--depth 2").unwrap()).unwrap();
+        app.command("e5");
+        assert!(app.message.starts_with("Illegal move"));
+        app.command("e4");
+        assert_eq!(app.moves, ["e4"]);
+        // wait for the engine's reply
+        for _ in 0..100 {
+            app.update();
+            if app.thinking.is_none() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(app.moves.len(), 2);
+        assert_eq!(app.d.pos.side, LIGHT);
+        assert!(app.info.is_some());
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(
+            screen.contains(&format!("1. e4 {}", app.moves[1])),
+            "{}",
+            screen
+        );
+        assert!(screen.contains("White"), "{}", screen);
+        assert!(screen.contains("Depth 2"), "{}", screen);
+        assert!(screen.contains("♚"), "{}", screen);
+
+        app.command("undo");
+        assert!(app.moves.is_empty());
+        assert_eq!(app.engine.history_san(), Vec::<String>::new());
+        app.command("quit");
+        assert!(app.quit);
+    }
+}
@@ -0,0 +1,319 @@
+// tune.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP's penalties and bonuses were picked by hand.
+// This module tunes them, along with the search's constants (see Params),
+// with simultaneous perturbation stochastic approximation (SPSA), the way
+// engine testing frameworks like Fishtest do. Each iteration nudges every
+// parameter at once, up or down at random, to make two players: one with the
+// values plus the nudges, and one with them minus. They play a pair of games,
+// one with each color, the way tournament() plays its games, and then every
+// parameter takes a step toward the player that did better. The nudges and
+// the steps shrink as the tuning goes on.
+//
+// The gains are Fishtest's: at iteration k (of n), parameter i is nudged by
+// c_i / k^0.101, with c_i chosen so the last nudge is PERTURBATION[i], and
+// steps by a_i / (A + k)^0.602 times the result over its nudge, where A is n
+// / 10 and a_i is chosen so the last step is R_END times the last nudge
+// squared. A game pair is a very noisy measurement, so it takes thousands of
+// iterations to get anywhere; the sprt command can then tell whether the
+// tuned values really are better.
+//
+// A tuning takes hours, so besides the console's "tune" command, it's the
+// tscp-tune executable's code, which can be left running on its own.
+
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::board;
+use super::book;
+use super::rand;
+use super::tournament;
+
+use super::data::Data;
+use super::defs::Int;
+use super::search::{Params, SearchLimits, PARAM_INFO};
+use super::tournament::{Game, Player};
+
+pub const USAGE: &str = "usage: tscp-tune [options] n player\n\
+    \n\
+    Tunes the evaluation's and the search's parameters with n iterations of\n\
+    SPSA, each a pair of games between two copies of player (e.g.,\n\
+    depth=3,variety=4), and prints the tuned values, as player settings.\n\
+    \n\
+    --book path  start the games from the opening book at path (book.txt)\n\
+    --help       show this message";
+
+/// the search depth of the players whose settings don't limit their searches
+/// (the console's default)
+const DEFAULT_DEPTH: Int = 4;
+
+/// the options that can be given on tscp-tune's command line; see
+/// parse_args()
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    /// the number of iterations
+    pub iterations: usize,
+    /// the player whose parameters are tuned, as the tournament command
+    /// takes it, e.g., "depth=3,variety=4"
+    pub player: String,
+    /// the opening book file, instead of book::BOOK_FILE
+    pub book: Option<PathBuf>,
+    /// show the usage message and exit
+    pub help: bool,
+}
+
+impl Options {
+    /// config() returns what to tune, or a message if the player's settings
+    /// don't make sense
+    pub fn config(&self) -> Result<TuneConfig, String> {
+        let limits = SearchLimits {
+            depth: Some(DEFAULT_DEPTH),
+            ..SearchLimits::default()
+        };
+        Ok(TuneConfig {
+            player: Player::parse(&self.player, &limits)?,
+            iterations: self.iterations,
+        })
+    }
+}
+
+/// parse_args() reads tscp-tune's command line arguments (without the
+/// program's name) into Options. It returns a message saying what's wrong if
+/// they don't make sense.
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--help" | "-h" => options.help = true,
+            "--book" => {
+                let path = args.next().ok_or(format!("{} needs a value", arg))?;
+                options.book = Some(PathBuf::from(path));
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+    if options.help {
+        return Ok(options);
+    }
+    if positional.len() != 2 {
+        return Err(String::from(
+            "the number of iterations and the player are needed",
+        ));
+    }
+    options.iterations = match positional[0].parse::<usize>() {
+        Ok(n) if n > 0 => n,
+        _ => return Err(format!("bad number of iterations: {}", positional[0])),
+    };
+    options.player = positional.remove(1);
+    options.config()?;
+    Ok(options)
+}
+
+/// run() loads the opening book and tunes the parameters the options ask
+/// for, printing its progress on standard output. It returns a message if
+/// the output can't be written.
+
+pub fn run(options: &Options) -> Result<(), String> {
+    let mut d = Data::new();
+    let path = options
+        .book
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(book::BOOK_FILE));
+    book::open_book(&mut d, &path);
+    let config = options.config()?;
+    let mut out = io::stdout();
+    tune(&d, &config, &mut out)
+        .and_then(|_| out.flush())
+        .map_err(|err| format!("output error: {}", err))
+}
+
+/// how far each parameter in PARAM_INFO is nudged at the last iteration
+const PERTURBATION: [f64; 9] = [2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 1.0, 2.0];
+
+/// the last iteration's step, over the last nudge squared
+const R_END: f64 = 0.002;
+
+/// the exponents the steps and the nudges shrink by
+const ALPHA: f64 = 0.602;
+const GAMMA: f64 = 0.101;
+
+/// how often tune() prints the values so far, in iterations
+const REPORT_INTERVAL: usize = 10;
+
+/// what tune() does: the player whose parameters are tuned (its limits,
+/// skill, and variety are the ones the games are played with, and its
+/// parameters are where the tuning starts), and the number of iterations
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TuneConfig {
+    pub player: Player,
+    pub iterations: usize,
+}
+
+/// tune() runs SPSA for the given number of iterations, printing the result
+/// of each iteration's games, and the values so far every REPORT_INTERVAL
+/// iterations, and returns the tuned parameters. The players use d's opening
+/// books. The nudges' directions come from a fixed sequence, so a tuning can
+/// be repeated.
+
+pub fn tune(d: &Data, config: &TuneConfig, out: &mut dyn Write) -> io::Result<Params> {
+    let n = config.iterations as f64;
+    let big_a = n / 10.0;
+    let c: Vec<f64> = PERTURBATION.iter().map(|&c| c * n.powf(GAMMA)).collect();
+    let a: Vec<f64> = PERTURBATION
+        .iter()
+        .map(|&c| R_END * c * c * (big_a + n).powf(ALPHA))
+        .collect();
+
+    let mut theta: Vec<f64> = (0..PARAM_INFO.len())
+        .map(|i| config.player.params.get(i) as f64)
+        .collect();
+    let mut board = Data::new();
+    board::init_hash(&mut board.pos);
+    let mut state = 0;
+
+    writeln!(
+        out,
+        "SPSA {} iterations of {}, from {}",
+        config.iterations, config.player.name, config.player.params
+    )?;
+    for iteration in 1..=config.iterations {
+        let k = iteration as f64;
+        // players[0] gets the values plus the nudges, and players[1] minus
+        let mut players = [config.player.clone(), config.player.clone()];
+        let mut nudges = Vec::new();
+        for (i, &c) in c.iter().enumerate() {
+            state += 1;
+            let sign = if rand::rand_from(state) & 1 == 0 {
+                1.0
+            } else {
+                -1.0
+            };
+            let nudge = sign * c / k.powf(GAMMA);
+            players[0].params.set(i, (theta[i] + nudge).round() as Int);
+            players[1].params.set(i, (theta[i] - nudge).round() as Int);
+            nudges.push(nudge);
+        }
+        let mut engines: Vec<Data> = players
+            .iter()
+            .map(|player| tournament::engine(d, player))
+            .collect();
+
+        // the plus player has White in the first game, and Black in the
+        // second
+        let mut points = 0;
+        for (round, &(white, black)) in [(0, 1), (1, 0)].iter().enumerate() {
+            let result = tournament::play_game(&mut board, &mut engines, [white, black], &players)?;
+            let game = Game {
+                round: round + 1,
+                white,
+                black,
+                result,
+            };
+            points += game.points(0).unwrap_or_default();
+        }
+        // the plus player's score less the minus player's, from -2 to 2
+        let result = (points - 2) as f64;
+        for (i, info) in PARAM_INFO.iter().enumerate() {
+            let step = a[i] / (big_a + k).powf(ALPHA);
+            theta[i] =
+                (theta[i] + step * result / nudges[i]).clamp(info.min as f64, info.max as f64);
+        }
+
+        writeln!(
+            out,
+            "Iteration {}: plus {}, minus {}",
+            iteration,
+            points as f64 / 2.0,
+            2.0 - points as f64 / 2.0
+        )?;
+        if iteration % REPORT_INTERVAL == 0 && iteration < config.iterations {
+            writeln!(out, "Values: {}", params(&theta, &config.player.params))?;
+        }
+    }
+
+    let tuned = params(&theta, &config.player.params);
+    writeln!(out, "Tuned: {}", tuned)?;
+    Ok(tuned)
+}
+
+/// params() returns base with the parameters set to the values in theta,
+/// rounded
+
+fn params(theta: &[f64], base: &Params) -> Params {
+    let mut params = *base;
+    for (i, &value) in theta.iter().enumerate() {
+        params.set(i, value.round() as Int);
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::SearchLimits;
+
+    fn args(s: &str) -> Result<Options, String> {
+        parse_args(s.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = args("--book b.txt 100 depth=2,passed_pawn_bonus=25").unwrap();
+        assert_eq!(options.book, Some(PathBuf::from("b.txt")));
+        let config = options.config().unwrap();
+        assert_eq!(config.iterations, 100);
+        assert_eq!(config.player.limits.depth, Some(2));
+        assert_eq!(config.player.params.eval.passed_pawn_bonus, 25);
+
+        let config = args("10 variety=4").unwrap().config().unwrap();
+        assert_eq!(config.player.limits.depth, Some(DEFAULT_DEPTH));
+        assert!(args("--help").unwrap().help);
+
+        assert!(args("").is_err());
+        assert!(args("0 depth=2").is_err());
+        assert!(args("10 depth=2 extra").is_err());
+        assert!(args("10 bogus").is_err());
+        assert!(args("--book").is_err());
+        assert!(args("--uci 10 depth=2").is_err());
+    }
+
+    #[test]
+    fn test_tune() {
+        let limits = SearchLimits::default();
+        let config = TuneConfig {
+            player: Player::parse("depth=1,variety=4", &limits).unwrap(),
+            iterations: 2,
+        };
+        let mut out = Vec::new();
+        let tuned = tune(&Data::new(), &config, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.starts_with(
+                "SPSA 2 iterations of depth=1,variety=4, from doubled_pawn_penalty=10,"
+            ),
+            "{}",
+            out
+        );
+        assert!(out.contains("Iteration 2: plus "), "{}", out);
+        assert!(out.ends_with(&format!("Tuned: {}\n", tuned)), "{}", out);
+
+        // two iterations' steps are far too small to move a parameter a
+        // whole unit
+        assert_eq!(tuned, Params::DEFAULT);
+        for (i, info) in PARAM_INFO.iter().enumerate() {
+            assert!((info.min..=info.max).contains(&tuned.get(i)));
+        }
+    }
+}
@@ -0,0 +1,306 @@
+// uci.rs
+// Tom Kerrigan's Simple Chess Program (TSCP)
+//
+// Copyright 1997 Tom Kerrigan
+//
+// Rust port by Kristopher Johnson
+
+// #rust The C version of TSCP only speaks XBoard's protocol. This is a
+// minimal front end for the Universal Chess Interface, which most GUIs (and
+// engine testing tools) use instead: enough to set up a position, search it
+// within the usual limits, and be stopped. The engine has no UCI options
+// other than Hash, and doesn't ponder. Like xboard(), it reads the commands
+// on a thread of its own while it's thinking, so "stop" stops the search
+// right away; the other commands wait for the search to finish.
+//
+// See <https://www.shredderchess.com/chess-features/uci-universal-chess-interface.html>
+// for the protocol.
+
+use std::io;
+use std::io::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::board;
+use super::scan;
+use super::search;
+use super::util;
+
+use super::data::Data;
+use super::defs::Int;
+use super::engine::MAX_HASH_MB;
+use super::search::{SearchLimits, UciOutput};
+
+/// uci() is a substitute for main() that speaks UCI. If greet is true, it
+/// first answers the "uci" command that started it.
+
+pub fn uci(d: &mut Data, greet: bool, out: &mut dyn Write) -> io::Result<()> {
+    let mut out = io::BufWriter::new(out);
+    let result = uci_loop(d, greet, &mut out);
+    out.flush()?;
+    result
+}
+
+/// uci_loop() reads the GUI's commands and answers them, until it says "quit"
+/// (or there are no more)
+
+fn uci_loop(d: &mut Data, greet: bool, out: &mut dyn Write) -> io::Result<()> {
+    // "stop" stops the search, and "quit" stops it too, to be carried out
+    // after it
+    let thinking = Arc::new(AtomicBool::new(false));
+    {
+        let stop = Arc::clone(&d.search.stop);
+        let thinking = Arc::clone(&thinking);
+        scan::start_input_thread(move |line| {
+            if !thinking.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Some("stop") | Some("quit") = line.split_whitespace().next() {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+    board::init_board(&mut d.pos);
+    d.pos.hist_dat.clear();
+    board::gen_legal(d);
+    if greet {
+        command(d, "uci", &[], out)?;
+    }
+    loop {
+        out.flush()?;
+        let (command_name, line_ended) = match scan::scan_token_line() {
+            Ok(token) => token,
+            Err(err) => {
+                writeln!(out, "info string input error: {}", err)?;
+                return Ok(());
+            }
+        };
+        if command_name.is_empty() {
+            // #rust: EOF
+            return Ok(());
+        }
+        let args = scan::scan_args(line_ended)?;
+        thinking.store(command_name == "go", Ordering::Relaxed);
+        let more = command(d, &command_name, &args, out);
+        thinking.store(false, Ordering::Relaxed);
+        if !more? {
+            return Ok(());
+        }
+    }
+}
+
+/// command() carries out a command, and returns false if it's "quit"
+
+fn command(d: &mut Data, command: &str, args: &[String], out: &mut dyn Write) -> io::Result<bool> {
+    match command {
+        "uci" => {
+            writeln!(out, "id name TSCP")?;
+            writeln!(out, "id author Tom Kerrigan")?;
+            writeln!(
+                out,
+                "option name Hash type spin default 0 min 0 max {}",
+                MAX_HASH_MB
+            )?;
+            writeln!(out, "uciok")?;
+        }
+        "isready" => writeln!(out, "readyok")?,
+        "debug" => d.search.debug = args.first().map(String::as_str) == Some("on"),
+        "setoption" => {
+            // "setoption name Hash value n" is the only option
+            match args {
+                [name, hash, value, n] if name == "name" && hash == "Hash" && value == "value" => {
+                    match n.parse::<usize>() {
+                        Ok(mb) if mb <= MAX_HASH_MB => {
+                            util::set_hash_mb(d, mb).expect("mb is at most MAX_HASH_MB");
+                            if d.search.debug {
+                                writeln!(out, "info string {}", util::hash_str(d))?;
+                            }
+                        }
+                        _ => writeln!(out, "info string bad Hash value: {}", n)?,
+                    }
+                }
+                _ => writeln!(out, "info string unknown option: {}", args.join(" "))?,
+            }
+        }
+        "ucinewgame" => board::new_game(d, true),
+        "position" => {
+            if let Err(err) = set_position(d, args) {
+                writeln!(out, "info string {}", err)?;
+            }
+        }
+        "go" => go(d, &go_limits(args), out)?,
+        "stop" | "ponderhit" => {
+            // they only mean something during a search
+        }
+        "quit" => return Ok(false),
+        _ => writeln!(out, "info string unknown command: {}", command)?,
+    }
+    Ok(true)
+}
+
+/// set_position() sets up the position a "position" command describes:
+/// "startpos" or "fen" and a FEN, then optionally "moves" and the moves made
+/// since. It returns a message if the position or a move doesn't make sense,
+/// leaving the position as far as it got.
+
+fn set_position(d: &mut Data, args: &[String]) -> Result<(), String> {
+    let moves_at = args
+        .iter()
+        .position(|arg| arg == "moves")
+        .unwrap_or(args.len());
+    match args.first().map(String::as_str) {
+        Some("startpos") => {
+            board::init_board(&mut d.pos);
+            d.pos.hist_dat.clear();
+            board::gen_legal(d);
+        }
+        Some("fen") => {
+            let fen = args[1..moves_at].join(" ");
+            board::set_fen(d, &fen).map_err(|err| format!("{}: {}", err, fen))?;
+        }
+        _ => return Err(String::from("position needs startpos or fen")),
+    }
+    for s in args.iter().skip(moves_at + 1) {
+        let m = util::parse_move(d, s).map_err(|err| format!("{}: {}", err, s))?;
+        let m = d.search.gen_dat[0][m].m.bytes();
+        board::try_move(d, m).map_err(|err| format!("{}: {}", err, s))?;
+    }
+    Ok(())
+}
+
+/// go_limits() reads a "go" command's arguments into SearchLimits. With no
+/// limits at all, the search goes on until it's stopped, as with "infinite".
+
+fn go_limits(args: &[String]) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "infinite" {
+            limits.infinite = true;
+            continue;
+        }
+        let limit = match arg.as_str() {
+            "depth" => &mut limits.depth,
+            "nodes" => &mut limits.nodes,
+            "movetime" => &mut limits.movetime,
+            "wtime" => &mut limits.wtime,
+            "btime" => &mut limits.btime,
+            "movestogo" => &mut limits.movestogo,
+            "mate" => &mut limits.mate,
+            "winc" | "binc" => {
+                let n = args.next().and_then(|n| n.parse::<Int>().ok()).unwrap_or(0);
+                if arg == "winc" {
+                    limits.winc = n;
+                } else {
+                    limits.binc = n;
+                }
+                continue;
+            }
+            // e.g., "ponder", which isn't supported, and "searchmoves"'s moves
+            _ => continue,
+        };
+        *limit = args.next().and_then(|n| n.parse::<Int>().ok());
+    }
+    if limits == SearchLimits::default() {
+        limits.infinite = true;
+    }
+    limits
+}
+
+/// go() searches the position, and sends the best move (and the reply the
+/// engine expects), or "0000" if there are no legal moves. An infinite search
+/// (see go_limits()) has to be stopped, so the tests don't make one.
+
+fn go(d: &mut Data, limits: &SearchLimits, out: &mut dyn Write) -> io::Result<()> {
+    d.search.stop.store(false, Ordering::Relaxed);
+    let result = search::think(d, limits, &mut UciOutput(out))?;
+    // with "infinite", the best move waits for "stop", even if there was
+    // nothing to search (e.g., for a book move)
+    out.flush()?;
+    while limits.infinite && !d.search.stop.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    match (result.best_move, result.ponder_move) {
+        (Some(m), Some(p)) => writeln!(
+            out,
+            "bestmove {} ponder {}",
+            util::move_str(m),
+            util::move_str(p)
+        ),
+        (Some(m), None) => writeln!(out, "bestmove {}", util::move_str(m)),
+        _ => writeln!(out, "bestmove 0000"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    fn new_data() -> Data {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        d
+    }
+
+    #[test]
+    fn test_uci() {
+        let mut d = new_data();
+        let mut out = Vec::new();
+        assert!(command(&mut d, "uci", &[], &mut out).unwrap());
+        assert!(command(&mut d, "isready", &[], &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("id name TSCP\n"), "{}", out);
+        assert!(out.ends_with("uciok\nreadyok\n"), "{}", out);
+
+        // Scholar's mate, one move short
+        let moves = "position startpos moves e2e4 e7e5 d1h5 b8c6 f1c4 g8f6";
+        let position = args(moves);
+        assert!(command(&mut d, "position", &position[1..], &mut Vec::new()).unwrap());
+        assert_eq!(d.pos.hply, 6);
+        let mut out = Vec::new();
+        command(&mut d, "debug", &args("on"), &mut out).unwrap();
+        command(&mut d, "setoption", &args("name Hash value 1"), &mut out).unwrap();
+        let hash = String::from_utf8(out).unwrap();
+        assert_eq!(hash, "info string Hash: 1 MB (65536 entries)\n");
+        let mut out = Vec::new();
+        command(&mut d, "go", &args("depth 2"), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("info string "), "{}", out);
+        assert!(out.contains("info depth 2 seldepth "), "{}", out);
+        assert!(out.contains(" score mate 1 "), "{}", out);
+        assert!(out.contains(" hashfull "), "{}", out);
+        assert!(out.ends_with("bestmove h5f7\n"), "{}", out);
+
+        let mut out = Vec::new();
+        let position = args("fen 4k3/8/8/8/8/8/8/R3K3 w Q - 0 1 moves e1c1");
+        command(&mut d, "position", &position, &mut out).unwrap();
+        assert_eq!(board::fen(&d.pos), "4k3/8/8/8/8/8/8/2KR4 b - - 1 1");
+        command(&mut d, "position", &args("startpos moves e2e5"), &mut out).unwrap();
+        assert!(!command(&mut d, "quit", &[], &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("info string "), "{}", out);
+    }
+
+    #[test]
+    fn test_go_limits() {
+        let limits = go_limits(&args(
+            "wtime 60000 btime 50000 winc 1000 binc 500 movestogo 20",
+        ));
+        assert_eq!(limits.wtime, Some(60000));
+        assert_eq!(limits.btime, Some(50000));
+        assert_eq!((limits.winc, limits.binc), (1000, 500));
+        assert_eq!(limits.movestogo, Some(20));
+        assert!(!limits.infinite);
+        assert_eq!(go_limits(&args("depth 5 ponder")).depth, Some(5));
+        assert!(go_limits(&args("infinite")).infinite);
+        assert!(go_limits(&[]).infinite);
+    }
+}
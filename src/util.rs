@@ -5,16 +5,39 @@
 //
 // Rust port by Kristopher Johnson
 
+use alloc::string::{String, ToString};
+#[cfg(feature = "cli")]
+use std::io;
+#[cfg(feature = "cli")]
+use std::io::{IsTerminal, Write};
+#[cfg(feature = "cli")]
+use std::sync::Arc;
+#[cfg(feature = "std")]
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use super::board;
+#[cfg(feature = "cli")]
+use super::perft;
+#[cfg(feature = "cli")]
 use super::search;
 
-use super::data::{Data, PIECE_CHAR};
-use super::defs::{Int, MoveBytes, BISHOP, DARK, EMPTY, KNIGHT, LIGHT, ROOK};
+use super::data::{Data, Position};
+use super::defs::{GameResult, Int, MoveBytes, ParseMoveError, BISHOP, EMPTY, KING, KNIGHT};
+
+#[cfg(feature = "cli")]
+use super::data::{PIECE_CHAR, PIECE_GLYPH};
+#[cfg(feature = "cli")]
+use super::defs::{Color, DARK, LIGHT, MAX_PLY};
+#[cfg(feature = "cli")]
+use super::engine::MAX_HASH_MB;
+#[cfg(feature = "cli")]
+use super::search::SearchLimits;
+#[cfg(feature = "cli")]
+use super::tt::TranspositionTable;
 
 /// get_ms() returns the milliseconds elapsed since midnight, January 1, 1970
 
+#[cfg(feature = "std")]
 pub fn get_ms() -> u128 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -22,132 +45,926 @@ pub fn get_ms() -> u128 {
         .as_millis()
 }
 
-/// parse the move s (in coordinate notation) and return the move's index in
-/// d.gen_dat, or -1 if the move is illegal
+/// the index of a move in d.search.gen_dat[0], as returned by parse_move()
+pub type MoveIndex = usize;
 
-pub fn parse_move(d: &Data, s: &str) -> Int {
-    // convert string to vector of characters
-    let s: Vec<char> = String::from(s).chars().collect();
+/// parse the move s (in coordinate notation) and return the move's index in
+/// d.search.gen_dat[0], or an error saying why it isn't one of the moves there
 
+pub fn parse_move(d: &Data, s: &str) -> Result<MoveIndex, ParseMoveError> {
     // make sure the string looks like a move
-    let len = s.len();
-    if len < 4
-        || s[0] < 'a'
-        || s[0] > 'h'
-        || s[1] < '0'
-        || s[1] > '9'
-        || s[2] < 'a'
-        || s[2] > 'h'
-        || s[3] < '0'
-        || s[3] > '9'
-    {
-        return -1;
-    }
-
-    let from = s[0] as u32 - 'a' as u32;
-    let from = from + 8 * (8 - (s[1] as u32 - '0' as u32));
-    let from = from as u8;
-    let to = s[2] as u32 - 'a' as u32;
-    let to = to + 8 * (8 - (s[3] as u32 - '0' as u32));
-    let to = to as u8;
-
-    for i in 0..d.first_move[1] {
-        if d.gen_dat[i].m.bytes().from == from && d.gen_dat[i].m.bytes().to == to {
-            // if the move is a promotion, handle the promotion piece; assume
-            // that the promotion moves occur consecutively in d.gen_dat.
-            if (d.gen_dat[i].m.bytes().bits & 32) != 0 {
-                if s.len() < 5 {
-                    return i as Int + 3; // assume it's a queen
-                }
-                return match s[4] {
-                    'N' | 'n' => i,
-                    'B' | 'b' => i + 1,
-                    'R' | 'r' => i + 2,
-                    _ => i + 3, // assume it's a queen
-                } as Int;
+    let m: MoveBytes = s.parse().map_err(|_| ParseMoveError::Malformed)?;
+
+    for (i, g) in d.search.gen_dat[0].iter().enumerate() {
+        let g = g.m.bytes();
+        if g.from == m.from && g.to == m.to {
+            // if the move is a promotion, find the one with the promotion
+            // piece (the C version assumes a queen if there isn't one)
+            if !g.is_promotion() {
+                return if s.len() == 4 {
+                    Ok(i)
+                } else {
+                    Err(ParseMoveError::Illegal)
+                };
             }
-            return i as Int;
+            if s.len() == 4 {
+                return Err(ParseMoveError::AmbiguousPromotion);
+            }
+            return d.search.gen_dat[0][i..]
+                .iter()
+                .position(|g| g.m.bytes().promote == m.promote)
+                .map(|n| i + n)
+                .ok_or(ParseMoveError::Illegal);
         }
     }
 
     // didn't find the move
-    -1
+    Err(ParseMoveError::Illegal)
 }
 
 /// move_str returns a string with move m in coordinate notation
 
 pub fn move_str(m: MoveBytes) -> String {
-    unsafe {
-        let from_col = std::char::from_u32_unchecked(col!(m.from) as u32 + 'a' as u32);
-        let from_row = 8 - row!(m.from);
-        let to_col = std::char::from_u32_unchecked(col!(m.to) as u32 + 'a' as u32);
-        let to_row = 8 - row!(m.to);
-
-        if (m.bits & 32) != 0 {
-            let c = match m.promote as Int {
-                KNIGHT => 'n',
-                BISHOP => 'b',
-                ROOK => 'r',
-                _ => 'q',
-            };
-            format!("{}{}{}{}{}", from_col, from_row, to_col, to_row, c)
-        } else {
-            format!("{}{}{}{}", from_col, from_row, to_col, to_row)
+    m.to_string()
+}
+
+/// divide() is perft() with a breakdown of the node counts for each move from
+/// the current position. When perft() returns the wrong count, comparing the
+/// output of divide() to that of a program that's known to be correct shows
+/// which move leads to the bug.
+
+#[cfg(feature = "cli")]
+pub fn divide(d: &mut Data, depth: Int, out: &mut dyn Write) -> io::Result<()> {
+    let start_time = get_ms();
+    let mut total = 0;
+    for (m, nodes) in perft_counts(d, depth) {
+        writeln!(out, "{}: {}", move_str(m), nodes)?;
+        total += nodes;
+    }
+    writeln!(out)?;
+    writeln!(out, "Nodes: {}", total)?;
+    writeln!(out, "Time: {} ms", get_ms() - start_time)
+}
+
+/// perft_counts() returns the perft count of each move from the current
+/// position, for the perft and divide commands. It uses as many threads as
+/// the computer has cores, and a 64 MB table.
+
+#[cfg(feature = "cli")]
+pub fn perft_counts(d: &Data, depth: Int) -> Vec<(MoveBytes, u64)> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let table = perft::PerftTable::new(64);
+    perft::perft_divide(&d.pos, depth, threads, Some(&table))
+}
+
+/// set_hash_mb() gives d an empty transposition table of mb megabytes (as
+/// many as fit; see TranspositionTable::new()), in place of the one it has,
+/// or takes its table away if mb is 0, so it searches without one like the C
+/// version. It returns a message if mb is more than MAX_HASH_MB. It's only
+/// meant to be called between searches.
+
+#[cfg(feature = "cli")]
+pub fn set_hash_mb(d: &mut Data, mb: usize) -> Result<(), String> {
+    if mb > MAX_HASH_MB {
+        return Err(format!(
+            "Bad hash size: {} MB (the most is {})",
+            mb, MAX_HASH_MB
+        ));
+    }
+    d.search.tt = match mb {
+        0 => None,
+        _ => Some(Arc::new(TranspositionTable::new(mb))),
+    };
+    Ok(())
+}
+
+/// hash_str() describes d's transposition table: its size, and how many
+/// entries it has room for
+
+#[cfg(feature = "cli")]
+pub fn hash_str(d: &Data) -> String {
+    match &d.search.tt {
+        Some(tt) => format!("Hash: {} MB ({} entries)", tt.bytes() >> 20, tt.len()),
+        None => String::from("Hash: off"),
+    }
+}
+
+/// print_tt_stats() prints the size of d's transposition table, how full it
+/// is, and what's happened to its probes and stores since it was created or
+/// cleared
+
+#[cfg(feature = "cli")]
+pub fn print_tt_stats(d: &Data, out: &mut dyn Write) -> io::Result<()> {
+    let tt = match &d.search.tt {
+        Some(tt) => tt,
+        None => return writeln!(out, "{}", hash_str(d)),
+    };
+    let hashfull = tt.hashfull();
+    let stats = tt.stats();
+    writeln!(
+        out,
+        "{}, {}.{}% full",
+        hash_str(d),
+        hashfull / 10,
+        hashfull % 10
+    )?;
+    writeln!(
+        out,
+        "Probes: {}, hits: {} ({:.1}%), collisions: {}",
+        stats.probes,
+        stats.hits,
+        stats.hit_rate(),
+        stats.collisions
+    )?;
+    writeln!(
+        out,
+        "Stores: {}, overwrites: {}",
+        stats.stores, stats.overwrites
+    )
+}
+
+/// how print_board() draws the board
+
+#[cfg(feature = "cli")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BoardStyle {
+    /// draw the board from black's side, with rank 1 at the top
+    pub flipped: bool,
+    /// draw the pieces with the Unicode chess symbols instead of letters
+    pub unicode: bool,
+    /// color the squares and pieces with ANSI escape codes, and highlight the
+    /// last move's squares. This is only for terminals; see color_supported().
+    pub color: bool,
+}
+
+/// the ANSI escape codes print_board() uses when it draws in color: the
+/// backgrounds of the light and dark squares and the last move's squares, the
+/// foregrounds of the light and dark pieces, and the code that resets them
+#[cfg(feature = "cli")]
+const LIGHT_SQUARE: &str = "\x1b[48;5;180m";
+#[cfg(feature = "cli")]
+const DARK_SQUARE: &str = "\x1b[48;5;137m";
+#[cfg(feature = "cli")]
+const LAST_MOVE_SQUARE: &str = "\x1b[48;5;143m";
+#[cfg(feature = "cli")]
+const LIGHT_PIECE: &str = "\x1b[1;97m";
+#[cfg(feature = "cli")]
+const DARK_PIECE: &str = "\x1b[1;30m";
+#[cfg(feature = "cli")]
+const RESET: &str = "\x1b[0m";
+
+/// color_supported() returns true if standard output is a terminal, which can
+/// show print_board()'s colors. (If it's a file or a pipe, the escape codes
+/// would just get in the way.)
+
+#[cfg(feature = "cli")]
+pub fn color_supported() -> bool {
+    io::stdout().is_terminal()
+}
+
+/// unicode_supported() guesses whether the terminal can show the Unicode chess
+/// symbols, from the locale settings in the environment. (On Windows, where
+/// those aren't usually set, it assumes it can.)
+
+#[cfg(feature = "cli")]
+pub fn unicode_supported() -> bool {
+    if cfg!(windows) {
+        return true;
+    }
+    for var in &["LC_ALL", "LC_CTYPE", "LANG"] {
+        if let Ok(value) = std::env::var(var) {
+            if !value.is_empty() {
+                let value = value.to_ascii_lowercase();
+                return value.contains("utf-8") || value.contains("utf8");
+            }
         }
     }
+    false
 }
 
 /// print_board() prints the board
 
-pub fn print_board(d: &Data) {
-    print!("\n8 ");
-    for i in 0..64 {
-        match d.color[i] {
-            EMPTY => {
-                print!(" .");
-            }
-            LIGHT => {
-                print!(" {}", PIECE_CHAR[d.piece[i as usize] as usize]);
-            }
-            DARK => {
-                let light_char = PIECE_CHAR[d.piece[i as usize] as usize];
-                let dark_u32 = light_char as u32 + 'a' as u32 - 'A' as u32;
-                unsafe {
-                    print!(" {}", std::char::from_u32_unchecked(dark_u32));
+#[cfg(feature = "cli")]
+pub fn print_board(pos: &Position, style: &BoardStyle, out: &mut dyn Write) -> io::Result<()> {
+    if style.color {
+        return print_color_board(pos, style, out);
+    }
+
+    // #rust The C version prints the squares in order, from a8 to h1. To draw
+    // the board flipped, we go through the rows and columns in reverse.
+    let flip = |n: usize| if style.flipped { 7 - n } else { n };
+    writeln!(out)?;
+    for r in 0..8 {
+        let row = flip(r);
+        write!(out, "{} ", 8 - row)?;
+        for c in 0..8 {
+            print_square(pos, row * 8 + flip(c), style, out)?;
+        }
+        if r != 7 {
+            writeln!(out)?;
+        }
+    }
+    if style.flipped {
+        write!(out, "\n\n   h g f e d c b a\n\n")
+    } else {
+        write!(out, "\n\n   a b c d e f g h\n\n")
+    }
+}
+
+/// print_color_board() is print_board() in color. Each square is three
+/// columns wide, so the squares are closer to square.
+
+#[cfg(feature = "cli")]
+fn print_color_board(pos: &Position, style: &BoardStyle, out: &mut dyn Write) -> io::Result<()> {
+    let flip = |n: usize| if style.flipped { 7 - n } else { n };
+    let last_move = pos
+        .history()
+        .last()
+        .map(|h| h.m.bytes())
+        .map(|m| [m.from as usize, m.to as usize]);
+    writeln!(out)?;
+    for r in 0..8 {
+        let row = flip(r);
+        write!(out, "{} ", 8 - row)?;
+        for c in 0..8 {
+            let i = row * 8 + flip(c);
+            let background = if last_move.is_some_and(|squares| squares.contains(&i)) {
+                LAST_MOVE_SQUARE
+            } else if (row + col!(i)) % 2 == 0 {
+                LIGHT_SQUARE
+            } else {
+                DARK_SQUARE
+            };
+            let foreground = if pos.color(i) == LIGHT {
+                LIGHT_PIECE
+            } else {
+                DARK_PIECE
+            };
+            write!(out, "{}{}", background, foreground)?;
+            match pos.color(i) {
+                EMPTY => write!(out, "   ")?,
+                _ => {
+                    // (print_square() puts a space before the piece)
+                    print_square(
+                        pos,
+                        i,
+                        &BoardStyle {
+                            color: false,
+                            ..*style
+                        },
+                        out,
+                    )?;
+                    write!(out, " ")?;
                 }
             }
-            _ => {}
         }
-        if (i + 1) % 8 == 0 && i != 63 {
-            print!("\n{} ", 7 - row!(i));
+        writeln!(out, "{}", RESET)?;
+    }
+    if style.flipped {
+        write!(out, "\n   h  g  f  e  d  c  b  a\n\n")
+    } else {
+        write!(out, "\n   a  b  c  d  e  f  g  h\n\n")
+    }
+}
+
+/// print_square() prints the piece on square i, or a dot if it's empty. (The
+/// Unicode symbols are one column wide in monospaced fonts, like the letters,
+/// so the board lines up either way.)
+
+#[cfg(feature = "cli")]
+fn print_square(
+    pos: &Position,
+    i: usize,
+    style: &BoardStyle,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    match pos.color(i) {
+        EMPTY => write!(out, " ."),
+        LIGHT | DARK if style.unicode => write!(
+            out,
+            " {}",
+            PIECE_GLYPH[pos.color(i) as usize][pos.piece(i) as usize]
+        ),
+        LIGHT => write!(out, " {}", PIECE_CHAR[pos.piece(i) as usize]),
+        DARK => {
+            let light_char = PIECE_CHAR[pos.piece(i) as usize];
+            let dark_u32 = light_char as u32 + 'a' as u32 - 'A' as u32;
+            unsafe { write!(out, " {}", std::char::from_u32_unchecked(dark_u32)) }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// print_moves() prints the moves of the game so far, in Standard Algebraic
+/// Notation, numbered, with white's and black's moves in two columns
+
+#[cfg(feature = "cli")]
+pub fn print_moves(pos: &Position, out: &mut dyn Write) -> io::Result<()> {
+    let moves = board::history_san(pos);
+    if moves.is_empty() {
+        return writeln!(out, "No moves have been made.");
+    }
+    // line is white's move, waiting for black's
+    let mut line = String::new();
+    for (i, san) in moves.iter().enumerate() {
+        let ply = pos.start_ply + i;
+        if ply.is_multiple_of(2) {
+            line = format!("{:3}. {}", ply / 2 + 1, san);
+        } else {
+            if i == 0 {
+                // the game started with black to move
+                line = format!("{:3}. ...", ply / 2 + 1);
+            }
+            writeln!(out, "{:<14}{}", line, san)?;
+            line.clear();
+        }
+    }
+    if !line.is_empty() {
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// game_result() checks to see if the game is over, and if so, how it ended.
+
+pub fn game_result(d: &mut Data) -> GameResult {
+    board::gen_legal(d);
+    if d.search.gen_dat[d.search.ply].is_empty() {
+        if board::side_in_check(&d.pos, d.pos.side) {
+            GameResult::Checkmate(d.pos.side_to_move().opposite())
+        } else {
+            GameResult::Stalemate
         }
+    } else if repetitions(&d.pos) >= 2 {
+        GameResult::DrawRepetition
+    } else if d.pos.fifty >= 100 {
+        GameResult::DrawFifty
+    } else if insufficient_material(&d.pos) {
+        GameResult::DrawMaterial
+    } else {
+        GameResult::Ongoing
+    }
+}
+
+/// repetitions() returns the number of times the current position has
+/// occurred before in the game. Unlike search::reps(), which is fast enough
+/// for the search, it counts a position as the same only if the castling
+/// permissions are the same too, as the rules for claiming a draw require.
+
+fn repetitions(pos: &Position) -> usize {
+    pos.hist_dat[pos.hply.saturating_sub(pos.fifty as usize)..pos.hply]
+        .iter()
+        .filter(|h| h.hash == pos.hash && h.castle as Int == pos.castle)
+        .count()
+}
+
+/// insufficient_material() returns true if neither side can possibly checkmate:
+/// there are no pawns, rooks, or queens, and the only other pieces are a single
+/// knight or any number of bishops on squares of the same color.
+
+fn insufficient_material(pos: &Position) -> bool {
+    let mut knights = 0;
+    let mut bishops = [0; 2]; // the number of bishops on each color of square
+    for i in 0..64 {
+        match pos.piece(i) {
+            KNIGHT => knights += 1,
+            BISHOP => bishops[(row!(i) + col!(i)) & 1] += 1,
+            KING | EMPTY => {}
+            _ => return false,
+        }
+    }
+    match knights {
+        0 => bishops[0] == 0 || bishops[1] == 0,
+        1 => bishops[0] + bishops[1] == 0,
+        _ => false,
     }
-    print!("\n\n   a b c d e f g h\n\n");
 }
 
 /// print_result() checks to see if the game is over, and if so, prints the result.
 
-pub fn print_result(d: &mut Data) {
-    let mut i = 0;
-    while i < d.first_move[1] {
-        if board::makemove(d, d.gen_dat[i].m.bytes()) {
-            board::takeback(d);
-            break;
+#[cfg(feature = "cli")]
+pub fn print_result(d: &mut Data, out: &mut dyn Write) -> io::Result<()> {
+    let result = match game_result(d) {
+        GameResult::Checkmate(Color::Light) => "1-0 {White mates}",
+        GameResult::Checkmate(_) => "0-1 {Black mates}",
+        GameResult::Stalemate => "1/2-1/2 {Stalemate}",
+        GameResult::DrawRepetition => "1/2-1/2 {Draw by repetition}",
+        GameResult::DrawFifty => "1/2-1/2 {Draw by fifty move rule}",
+        GameResult::DrawMaterial => "1/2-1/2 {Insufficient material}",
+        GameResult::Ongoing => return Ok(()),
+    };
+    writeln!(out, "{}", result)
+}
+
+/// the limits on the search for a hint: short, so the user isn't kept waiting
+#[cfg(feature = "cli")]
+const HINT_DEPTH: Int = 6;
+#[cfg(feature = "cli")]
+const HINT_MS: Int = 2000;
+
+/// hint() searches the current position briefly, and describes the move it
+/// finds for the user: the move, its score, and the line the engine expects
+/// to follow it, e.g., "Nf3 (+0.25: 1. Nf3 Nf6 2. d4 d5)". It returns the
+/// move and the description, or None if there are no legal moves.
+
+#[cfg(feature = "cli")]
+pub fn hint(d: &mut Data) -> io::Result<Option<(MoveBytes, String)>> {
+    let limits = SearchLimits {
+        depth: Some(HINT_DEPTH),
+        movetime: Some(HINT_MS),
+        ..SearchLimits::default()
+    };
+    // the hint is the best move the engine finds, even if it plays humanlike
+    // or for variety
+    let humanlike = d.search.humanlike.take();
+    let variety = d.search.variety.take();
+    let result = search::think(d, &limits, &mut search::NoOutput);
+    d.search.humanlike = humanlike;
+    d.search.variety = variety;
+    let result = result?;
+    let m = match result.best_move {
+        Some(m) => m,
+        None => return Ok(None),
+    };
+    let line = board::line_san(d, &result.pv);
+    let san = match line.first() {
+        Some(san) => san.clone(),
+        None => return Ok(Some((m, move_str(m)))),
+    };
+    let description = if result.depth == 0 {
+        format!("{} (book move)", san)
+    } else {
+        format!(
+            "{} ({}: {})",
+            san,
+            score_str(result.score),
+            numbered_line(d.pos.start_ply + d.pos.hply, &line)
+        )
+    };
+    Ok(Some((m, description)))
+}
+
+/// the depth of the searches assess_move() compares a move with the
+/// engine's choice at: shallow, so it's quick enough to do on every move
+#[cfg(feature = "cli")]
+pub const ASSESS_DEPTH: Int = 4;
+
+/// what assess_move() finds out about a move
+
+#[cfg(feature = "cli")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assessment {
+    /// the move, in SAN, and its score
+    pub san: String,
+    pub score: Int,
+    /// the move the engine prefers, in SAN, and its score (the same as the
+    /// move's, if it's the same move)
+    pub best_san: String,
+    pub best_score: Int,
+}
+
+#[cfg(feature = "cli")]
+impl Assessment {
+    /// is_best() returns true if the move is the one the engine prefers
+    pub fn is_best(&self) -> bool {
+        self.san == self.best_san
+    }
+
+    /// loss() returns how many centipawns worse the move is than the
+    /// engine's choice
+    pub fn loss(&self) -> Int {
+        (self.best_score - self.score).max(0)
+    }
+    /// describe() compares the move with the engine's choice for the user,
+    /// e.g., "Nf3 is the engine's choice too (+0.25)", or "the engine prefers
+    /// e4 (+0.30); Nf3 (-0.15) is 0.45 worse"
+    pub fn describe(&self) -> String {
+        if self.is_best() {
+            return format!(
+                "{} is the engine's choice too ({})",
+                self.san,
+                score_str(self.score)
+            );
         }
-        i += 1;
+        let mut s = format!(
+            "the engine prefers {} ({}); {} ({})",
+            self.best_san,
+            score_str(self.best_score),
+            self.san,
+            score_str(self.score)
+        );
+        // (a difference between mate scores isn't in pawns)
+        if self.best_score.abs() < 9000 && self.score.abs() < 9000 {
+            s += &format!(" is {:.2} worse", self.loss() as f64 / 100.0);
+        }
+        s
     }
-    if i == d.first_move[1] {
-        if board::in_check(d, d.side) {
-            match d.side {
-                LIGHT => println!("0-1 {{Black mates}}"),
-                _ => println!("1-0 {{White mates}}"),
-            }
+}
+
+/// assess_move() compares the move m with the move the engine would make in
+/// the current position, by searching the position (ASSESS_DEPTH ply), and,
+/// if m is a different move, the position after m (one ply less). Scores are
+/// from the side to move's point of view. The opening book isn't used, so
+/// the scores always come from a search. It returns None if m isn't legal.
+/// The position is left as it was.
+
+#[cfg(feature = "cli")]
+pub fn assess_move(d: &mut Data, m: MoveBytes) -> io::Result<Option<Assessment>> {
+    let limits = SearchLimits {
+        depth: Some(ASSESS_DEPTH),
+        ..SearchLimits::default()
+    };
+    assess_move_with(d, m, &limits)
+}
+
+/// assess_move_with() is assess_move() with other limits on the search of the
+/// current position. The position after m is searched with the same limits,
+/// but, if there's a depth limit, one ply less deep.
+
+#[cfg(feature = "cli")]
+pub fn assess_move_with(
+    d: &mut Data,
+    m: MoveBytes,
+    limits: &SearchLimits,
+) -> io::Result<Option<Assessment>> {
+    let san = match board::san(d, m) {
+        Some(san) => san,
+        None => return Ok(None),
+    };
+    // the engine's choice is its best move, even if it plays humanlike or
+    // for variety
+    let books = core::mem::take(&mut d.books);
+    let humanlike = d.search.humanlike.take();
+    let variety = d.search.variety.take();
+    let result = assess(d, m, san, limits);
+    d.books = books;
+    d.search.humanlike = humanlike;
+    d.search.variety = variety;
+    board::gen_legal(d);
+    result
+}
+
+#[cfg(feature = "cli")]
+fn assess(
+    d: &mut Data,
+    m: MoveBytes,
+    san: String,
+    limits: &SearchLimits,
+) -> io::Result<Option<Assessment>> {
+    let result = search::think(d, limits, &mut search::NoOutput)?;
+    let best = match result.best_move {
+        Some(best) => best,
+        None => return Ok(None),
+    };
+    let best_san = board::san(d, best).unwrap_or_else(|| move_str(best));
+    if best_san == san {
+        return Ok(Some(Assessment {
+            san,
+            score: result.score,
+            best_san,
+            best_score: result.score,
+        }));
+    }
+
+    board::makemove(&mut d.pos, m);
+    let limits = SearchLimits {
+        depth: limits.depth.map(|depth| depth - 1),
+        ..*limits
+    };
+    let reply = search::think(d, &limits, &mut search::NoOutput);
+    board::takeback(&mut d.pos);
+    // the reply's score is from the other side's point of view, and a mate is
+    // a ply further away from here
+    let mut score = -reply?.score;
+    if score > 9000 {
+        score -= 1;
+    } else if score < -9000 {
+        score += 1;
+    }
+    Ok(Some(Assessment {
+        san,
+        score,
+        best_san,
+        best_score: result.score,
+    }))
+}
+
+/// score_str() returns a score, from the side to move's point of view, in
+/// pawns (e.g., "+0.25"), or as the number of moves to a mate
+
+#[cfg(feature = "cli")]
+pub fn score_str(score: Int) -> String {
+    // a mate is scored as 10000 minus the number of ply to it
+    let mate_ply = 10000 - score.abs();
+    if mate_ply < MAX_PLY as Int {
+        let moves = (mate_ply + 1) / 2;
+        if score > 0 {
+            format!("mate in {}", moves)
         } else {
-            println!("1/2-1/2 {{Stalemate}}");
+            format!("mated in {}", moves)
+        }
+    } else {
+        format!("{:+.2}", score as f64 / 100.0)
+    }
+}
+
+/// numbered_line() numbers a line of moves in SAN, the first of which is
+/// played at the given ply of the game, e.g., "12... Nf6 13. Bb5 a6"
+
+#[cfg(feature = "cli")]
+fn numbered_line(first_ply: usize, moves: &[String]) -> String {
+    let mut s = String::new();
+    for (i, san) in moves.iter().enumerate() {
+        let ply = first_ply + i;
+        if i > 0 {
+            s.push(' ');
+        }
+        if ply.is_multiple_of(2) {
+            s += &format!("{}. ", ply / 2 + 1);
+        } else if i == 0 {
+            s += &format!("{}... ", ply / 2 + 1);
         }
-    } else if search::reps(d) == 2 {
-        println!("1/2-1/2 {{Draw by repetition}}");
-    } else if d.fifty >= 100 {
-        println!("1/2-1/2 {{Draw by fifty move rule}}");
+        s += san;
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::defs::Color;
+    #[cfg(feature = "cli")]
+    use super::super::tt::TtStats;
+
+    fn play(d: &mut Data, moves: &[&str]) {
+        for s in moves {
+            let m = parse_move(d, s).unwrap();
+            let m = d.search.gen_dat[0][m].m.bytes();
+            board::try_move(d, m).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_move() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::set_fen(&mut d, "7k/P7/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        let m = parse_move(&d, "a7a8n").unwrap();
+        assert_eq!(d.search.gen_dat[0][m].m.bytes().to_string(), "a7a8n");
+        let m = parse_move(&d, "g6f6").unwrap();
+        assert_eq!(d.search.gen_dat[0][m].m.bytes().to_string(), "g6f6");
+        assert_eq!(
+            parse_move(&d, "a7a8"),
+            Err(ParseMoveError::AmbiguousPromotion)
+        );
+        assert_eq!(parse_move(&d, "g6g7"), Err(ParseMoveError::Illegal));
+        assert_eq!(parse_move(&d, "g6f6q"), Err(ParseMoveError::Illegal));
+        for s in ["", "g6", "a7a8k", "a9a8", "i1a1", "Nf6", "g6f6qq"].iter() {
+            assert_eq!(parse_move(&d, s), Err(ParseMoveError::Malformed), "{}", s);
+        }
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_print_board() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::set_fen(&mut d, "4k3/8/8/8/8/8/1P6/R3K3 b - - 0 1").unwrap();
+        fn print(pos: &Position, style: BoardStyle) -> String {
+            let mut out = Vec::new();
+            print_board(pos, &style, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        }
+        assert_eq!(
+            print(&d.pos, BoardStyle::default()),
+            "\n8  . . . . k . . .\n7  . . . . . . . .\n6  . . . . . . . .\n\
+             5  . . . . . . . .\n4  . . . . . . . .\n3  . . . . . . . .\n\
+             2  . P . . . . . .\n1  R . . . K . . .\n\n   a b c d e f g h\n\n"
+        );
+        assert_eq!(
+            print(
+                &d.pos,
+                BoardStyle {
+                    flipped: true,
+                    ..BoardStyle::default()
+                }
+            ),
+            "\n1  . . . K . . . R\n2  . . . . . . P .\n3  . . . . . . . .\n\
+             4  . . . . . . . .\n5  . . . . . . . .\n6  . . . . . . . .\n\
+             7  . . . . . . . .\n8  . . . k . . . .\n\n   h g f e d c b a\n\n"
+        );
+        let unicode = print(
+            &d.pos,
+            BoardStyle {
+                unicode: true,
+                ..BoardStyle::default()
+            },
+        );
+        assert!(unicode.starts_with("\n8  . . . . \u{265a} . . .\n"));
+        assert!(unicode.contains("\n1  \u{2656} . . . \u{2654} . . .\n"));
+
+        // in color, the squares of the last move are highlighted
+        board::try_move(&mut d, "e8d7".parse().unwrap()).unwrap();
+        let color = print(
+            &d.pos,
+            BoardStyle {
+                color: true,
+                ..BoardStyle::default()
+            },
+        );
+        let rank_7 = color.lines().nth(2).unwrap();
+        assert_eq!(
+            rank_7,
+            format!(
+                "7 {d}{k}   {l}{k}   {d}{k}   {m}{k} k {d}{k}   {l}{k}   {d}{k}   {l}{k}   {r}",
+                l = LIGHT_SQUARE,
+                d = DARK_SQUARE,
+                m = LAST_MOVE_SQUARE,
+                k = DARK_PIECE,
+                r = RESET
+            )
+        );
+        assert!(color.contains(&format!(
+            "{}{}   {}",
+            DARK_SQUARE, DARK_PIECE, LAST_MOVE_SQUARE
+        )));
+        assert!(color.contains(&format!("{}{} P ", DARK_SQUARE, LIGHT_PIECE)));
+        assert!(color.ends_with("\n   a  b  c  d  e  f  g  h\n\n"));
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_print_moves() {
+        fn print(d: &Data) -> String {
+            let mut out = Vec::new();
+            print_moves(&d.pos, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        }
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        assert_eq!(print(&d), "No moves have been made.\n");
+        for m in &["e2e4", "e7e5", "g1f3"] {
+            board::try_move(&mut d, m.parse().unwrap()).unwrap();
+        }
+        assert_eq!(print(&d), "  1. e4       e5\n  2. Nf3\n");
+
+        board::set_fen(&mut d, "4k3/8/8/8/8/8/8/R3K3 b Q - 0 40").unwrap();
+        for m in &["e8d7", "e1c1"] {
+            board::try_move(&mut d, m.parse().unwrap()).unwrap();
+        }
+        assert_eq!(print(&d), " 40. ...      Kd7\n 41. O-O-O+\n");
+    }
+
+    #[test]
+    fn test_game_result() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        assert_eq!(game_result(&mut d), GameResult::Ongoing);
+
+        play(
+            &mut d,
+            &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"],
+        );
+        assert_eq!(game_result(&mut d), GameResult::Ongoing);
+        play(&mut d, &["f6g8"]);
+        assert_eq!(game_result(&mut d), GameResult::DrawRepetition);
+        // (and the fourth time)
+        play(&mut d, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(game_result(&mut d), GameResult::DrawRepetition);
+
+        // the same pieces on the same squares, but different castling
+        // permissions, isn't the same position
+        board::set_fen(&mut d, "r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1").unwrap();
+        for _ in 0..2 {
+            play(&mut d, &["e1e2", "a8a7", "e2e1", "a7a8"]);
+        }
+        assert_eq!(game_result(&mut d), GameResult::Ongoing);
+        play(&mut d, &["e1e2", "a8a7", "e2e1", "a7a8"]);
+        assert_eq!(game_result(&mut d), GameResult::DrawRepetition);
+
+        board::set_fen(&mut d, "4k3/8/8/8/8/8/8/4K2R w - - 99 80").unwrap();
+        assert_eq!(game_result(&mut d), GameResult::Ongoing);
+        play(&mut d, &["h1h2"]);
+        assert_eq!(game_result(&mut d), GameResult::DrawFifty);
+
+        board::init_board(&mut d.pos);
+        board::gen_legal(&mut d);
+        play(&mut d, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        assert_eq!(game_result(&mut d), GameResult::Checkmate(Color::Dark));
+        #[cfg(feature = "cli")]
+        {
+            let mut out = Vec::new();
+            print_result(&mut d, &mut out).unwrap();
+            assert_eq!(out, b"0-1 {Black mates}\n");
+        }
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_assess_move() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::set_fen(&mut d, "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 31").unwrap();
+        let fen = board::fen(&d.pos);
+
+        let a = assess_move(&mut d, "a1a8".parse().unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(a.is_best());
+        assert_eq!(a.loss(), 0);
+        assert_eq!(a.describe(), "Ra8# is the engine's choice too (mate in 1)");
+
+        let a = assess_move(&mut d, "a1a2".parse().unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(!a.is_best());
+        assert_eq!(a.best_san, "Ra8#");
+        assert!(a.score < 9000);
+        assert!(a
+            .describe()
+            .starts_with("the engine prefers Ra8# (mate in 1); Ra2 ("));
+        assert_eq!(board::fen(&d.pos), fen);
+
+        assert_eq!(assess_move(&mut d, "a1b2".parse().unwrap()).unwrap(), None);
+
+        let a = Assessment {
+            san: String::from("Nf3"),
+            score: -15,
+            best_san: String::from("e4"),
+            best_score: 30,
+        };
+        assert_eq!(a.loss(), 45);
+        assert_eq!(
+            a.describe(),
+            "the engine prefers e4 (+0.30); Nf3 (-0.15) is 0.45 worse"
+        );
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_set_hash_mb() {
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::init_board(&mut d.pos);
+        assert_eq!(hash_str(&d), "Hash: off");
+        set_hash_mb(&mut d, 2).unwrap();
+        assert_eq!(hash_str(&d), "Hash: 2 MB (131072 entries)");
+        assert!(set_hash_mb(&mut d, MAX_HASH_MB + 1).is_err());
+        assert_eq!(d.search.tt.as_ref().map(|tt| tt.len()), Some(131072));
+
+        let mut out = Vec::new();
+        print_tt_stats(&d, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Hash: 2 MB (131072 entries), 0.0% full\n\
+             Probes: 0, hits: 0 (0.0%), collisions: 0\n\
+             Stores: 0, overwrites: 0\n"
+        );
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        search::think(&mut d, &limits, &mut search::NoOutput).unwrap();
+        let stats = d.search.tt.as_ref().unwrap().stats();
+        assert!(stats.probes > 0 && stats.hits > 0 && stats.stores > 0);
+        assert_eq!(d.search.tt_stats, TtStats::default());
+
+        set_hash_mb(&mut d, 0).unwrap();
+        assert!(d.search.tt.is_none());
+        let mut out = Vec::new();
+        print_tt_stats(&d, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Hash: off\n");
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_hint() {
+        assert_eq!(score_str(25), "+0.25");
+        assert_eq!(score_str(-150), "-1.50");
+        assert_eq!(score_str(10000 - 3), "mate in 2");
+        assert_eq!(score_str(-10000 + 4), "mated in 2");
+
+        let mut d = Data::new();
+        board::init_hash(&mut d.pos);
+        board::set_fen(&mut d, "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 31").unwrap();
+        let fen = board::fen(&d.pos);
+        let (m, description) = hint(&mut d).unwrap().unwrap();
+        assert_eq!(m.to_string(), "a1a8");
+        assert_eq!(description, "Ra8# (mate in 1: 31. Ra8#)");
+        assert_eq!(board::fen(&d.pos), fen);
+
+        // black's move, and white is ahead
+        play(&mut d, &["g1f1"]);
+        let (m, description) = hint(&mut d).unwrap().unwrap();
+        let san = board::san(&mut d, m).unwrap();
+        let expected = format!("{} (-", san);
+        assert!(description.starts_with(&expected), "{}", description);
+        let expected = format!(": 31... {} 32. ", san);
+        assert!(description.contains(&expected), "{}", description);
     }
 }
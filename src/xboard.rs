@@ -10,6 +10,8 @@
 /// <http://www.research.digital.com/SRC/personal/mann/xboard/engine-intf.html>
 use std::io;
 use std::io::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use super::board;
 use super::scan;
@@ -17,154 +19,303 @@ use super::search;
 use super::util;
 
 use super::data::Data;
-use super::defs::{DARK, EMPTY, LIGHT};
-use super::search::ThinkOutput::*;
+use super::defs::{GameResult, ParseMoveError, DARK, EMPTY, LIGHT};
+use super::engine::MAX_HASH_MB;
+use super::search::{
+    ObserverError, Resignation, SearchInfo, SearchLimits, SearchObserver, XboardOutput,
+};
 
-pub fn xboard(d: &mut Data) {
-    let mut post = NoOutput;
+/// the commands that make the engine stop thinking without making a move:
+/// they change the game, or what the engine is playing, out from under it
 
-    unsafe {
-        libc::signal(libc::SIGINT, libc::SIG_IGN);
+const CANCEL_COMMANDS: [&str; 8] = [
+    "new", "force", "quit", "result", "undo", "remove", "white", "black",
+];
+
+/// DebugOutput is the observer when "post" is off: it only passes on the
+/// debug messages (if "debug on" was entered before "xboard", or --debug was
+/// given), which XBoard doesn't show
+
+struct DebugOutput<'a>(&'a mut dyn Write);
+
+impl SearchObserver for DebugOutput<'_> {
+    fn info(&mut self, _info: &SearchInfo) -> Result<(), ObserverError> {
+        Ok(())
+    }
+
+    fn debug(&mut self, message: &str) -> Result<(), ObserverError> {
+        XboardOutput(self.0).debug(message)
+    }
+}
+
+pub fn xboard(d: &mut Data, limits: SearchLimits, out: &mut dyn Write) -> io::Result<()> {
+    // #rust The responses are buffered, and flushed when they've all been
+    // written, before the next command is read (and after each line of the
+    // search's output), rather than line by line.
+    let mut out = io::BufWriter::new(out);
+    let result = xboard_loop(d, limits, &mut out);
+    out.flush()?;
+    result
+}
+
+/// xboard_loop() reads XBoard's commands and answers them, until it says
+/// "quit" (or there are no more)
+
+fn xboard_loop(d: &mut Data, limits: SearchLimits, out: &mut dyn Write) -> io::Result<()> {
+    let mut limits = limits;
+    let mut post = false;
+
+    // #rust The C version ignores SIGINT, which XBoard sends to interrupt the
+    // engine. We make it stop the search instead, using the ctrlc crate, which
+    // works on Windows as well as Unix. A handler can only be installed once,
+    // so if that's already been done, the existing one stays in place.
+    let stop = Arc::clone(&d.search.stop);
+    let _ = ctrlc::set_handler(move || stop.store(true, Ordering::Relaxed));
+
+    // #rust The C version only reads XBoard's commands between searches. Here
+    // they're read by a thread of their own, which stops the search as soon
+    // as a command comes in that says to: "?" says to move now, and the ones
+    // in CANCEL_COMMANDS say to give up on the move altogether (they're
+    // carried out after the search, as usual).
+    let thinking = Arc::new(AtomicBool::new(false));
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let stop = Arc::clone(&d.search.stop);
+        let thinking = Arc::clone(&thinking);
+        let cancel = Arc::clone(&cancel);
+        scan::start_input_thread(move |line| {
+            if !thinking.load(Ordering::Relaxed) {
+                return;
+            }
+            match line.split_whitespace().next() {
+                Some("?") => stop.store(true, Ordering::Relaxed),
+                Some(command) if CANCEL_COMMANDS.contains(&command) => {
+                    cancel.store(true, Ordering::Relaxed);
+                    stop.store(true, Ordering::Relaxed);
+                }
+                _ => {}
+            }
+        });
     }
-    println!();
-    board::init_board(d);
-    board::gen(d);
+    writeln!(out)?;
+    board::init_board(&mut d.pos);
+    board::gen_legal(d);
     let mut computer_side = EMPTY;
+    let mut resignation = Resignation::default();
     loop {
-        io::stdout()
-            .flush()
-            .expect("unable to flush standard output");
-        if d.side == computer_side {
-            search::think(d, post);
-            if d.pv[0][0].value() == 0 {
+        out.flush()?;
+        if d.pos.side == computer_side {
+            // if the opponent's move made a draw that can be claimed, claim it
+            // instead of moving
+            if claim_draw(d, out)? {
+                computer_side = EMPTY;
+                continue;
+            }
+            cancel.store(false, Ordering::Relaxed);
+            d.search.stop.store(false, Ordering::Relaxed);
+            thinking.store(true, Ordering::Relaxed);
+            let result = if post {
+                search::think(d, &limits, &mut XboardOutput(out))
+            } else {
+                search::think(d, &limits, &mut DebugOutput(out))
+            };
+            thinking.store(false, Ordering::Relaxed);
+            let result = result?;
+            if cancel.load(Ordering::Relaxed) {
+                // the command that cancelled the move decides what the engine
+                // does next
                 computer_side = EMPTY;
                 continue;
             }
-            let m = d.pv[0][0].bytes();
-            println!("move {}", util::move_str(m));
-            board::makemove(d, m);
-            d.ply = 0;
-            board::gen(d);
-            util::print_result(d);
+            let m = match result.best_move {
+                Some(m) => m,
+                None => {
+                    computer_side = EMPTY;
+                    continue;
+                }
+            };
+            if resignation.update(&result) {
+                writeln!(out, "resign")?;
+                computer_side = EMPTY;
+                continue;
+            }
+            writeln!(out, "move {}", util::move_str(m))?;
+            board::makemove(&mut d.pos, m);
+            board::gen_legal(d);
+            // a draw claim goes right after the move that makes it
+            if !claim_draw(d, out)? {
+                print_game_over(d, out)?;
+            }
             continue;
         }
         let command = match scan::scan_token() {
             Ok(s) => s,
             Err(err) => {
-                println!("input error: {}", err);
-                return;
+                writeln!(out, "input error: {}", err)?;
+                return Ok(());
             }
         };
         if command.is_empty() {
             // #rust: EOF
-            return;
+            return Ok(());
         }
         match command.as_ref() {
             "xboard" => continue,
             "new" => {
-                board::init_board(d);
-                board::gen(d);
+                board::new_game(d, true);
+                resignation.reset();
                 computer_side = DARK;
             }
-            "quit" => return,
+            "quit" => return Ok(()),
             "force" => {
                 computer_side = EMPTY;
             }
             "white" => {
-                d.side = LIGHT;
-                d.xside = DARK;
-                board::gen(d);
+                d.pos.side = LIGHT;
+                d.pos.xside = DARK;
+                board::gen_legal(d);
                 computer_side = DARK;
             }
             "black" => {
-                d.side = DARK;
-                d.xside = LIGHT;
-                board::gen(d);
+                d.pos.side = DARK;
+                d.pos.xside = LIGHT;
+                board::gen_legal(d);
                 computer_side = LIGHT;
             }
             "st" => {
                 let n = match scan::scan_int() {
                     Ok(n) => n,
                     Err(err) => {
-                        println!("unable to read st argument: {}", err);
-                        return;
+                        writeln!(out, "unable to read st argument: {}", err)?;
+                        return Ok(());
                     }
                 };
-                d.max_time = n * 1000;
-                d.max_depth = 32;
+                limits = SearchLimits {
+                    movetime: Some(n * 1000),
+                    ..SearchLimits::default()
+                };
             }
             "sd" => {
                 let n = match scan::scan_int() {
                     Ok(n) => n,
                     Err(err) => {
-                        println!("unable to read sd argument: {}", err);
-                        return;
+                        writeln!(out, "unable to read sd argument: {}", err)?;
+                        return Ok(());
                     }
                 };
-                d.max_depth = n;
-                d.max_time = 1 << 25;
+                limits = SearchLimits {
+                    depth: Some(n),
+                    ..SearchLimits::default()
+                };
             }
             "time" => {
                 let n = match scan::scan_int() {
                     Ok(n) => n,
                     Err(err) => {
-                        println!("unable to read time argument: {}", err);
-                        return;
+                        writeln!(out, "unable to read time argument: {}", err)?;
+                        return Ok(());
                     }
                 };
-                d.max_time = (n * 10) / 30;
-                d.max_depth = 32;
+                // time is the engine's clock, in centiseconds, whichever
+                // side it's playing
+                limits = SearchLimits {
+                    wtime: Some(n * 10),
+                    btime: Some(n * 10),
+                    ..SearchLimits::default()
+                };
             }
             "otim" => continue,
             "go" => {
-                computer_side = d.side;
+                computer_side = d.pos.side;
             }
             "hint" => {
-                search::think(d, NoOutput);
-                if d.pv[0][0].value() == 0 {
-                    continue;
+                // XBoard only wants the move in the hint, so the rest goes in a
+                // message for it to show the user
+                if let Some((m, description)) = util::hint(d)? {
+                    writeln!(out, "Hint: {}", util::move_str(m))?;
+                    writeln!(out, "telluser Hint: {}", description)?;
                 }
-                println!("Hint: {}", util::move_str(d.pv[0][0].bytes()));
             }
             "undo" => {
-                if d.hply == 0 {
-                    continue;
-                }
-                board::takeback(d);
-                d.ply = 0;
-                board::gen(d);
+                board::undo_move(d);
             }
             "remove" => {
-                if d.hply < 2 {
+                if d.pos.hply < 2 {
                     continue;
                 }
-                board::takeback(d);
-                board::takeback(d);
-                d.ply = 0;
-                board::gen(d);
+                board::undo_moves(d, 2);
+            }
+            "protover" => {
+                // the features XBoard only uses if they're asked for
+                let _ = scan::scan_token();
+                writeln!(out, "feature memory=1 done=1")?;
+            }
+            "memory" => {
+                // the most memory the engine's hash tables may use, in MB;
+                // the transposition table gets all of it
+                let n = match scan::scan_int() {
+                    Ok(n) => n,
+                    Err(err) => {
+                        writeln!(out, "unable to read memory argument: {}", err)?;
+                        return Ok(());
+                    }
+                };
+                let mb = (n.max(0) as usize).min(MAX_HASH_MB);
+                util::set_hash_mb(d, mb).expect("mb is at most MAX_HASH_MB");
+                if d.search.debug {
+                    writeln!(out, "# {}", util::hash_str(d))?;
+                }
+            }
+            "?" => {
+                // "move now" only means something during a search
             }
             "post" => {
-                post = XboardOutput;
+                post = true;
             }
             "nopost" => {
-                post = NoOutput;
-            }
-            _ => {
-                let m = util::parse_move(d, &command);
-                match m {
-                    -1 => println!("Error (unknown command): {}", command),
-                    _ => {
-                        let m = d.gen_dat[m as usize].m.bytes();
-                        if !board::makemove(d, m) {
-                            println!("Error (unknown command): {}", command);
-                        } else {
-                            d.ply = 0;
-                            board::gen(d);
-                            util::print_result(d);
-                        }
+                post = false;
+            }
+            _ => match util::parse_move(d, &command) {
+                Ok(m) => {
+                    let m = d.search.gen_dat[0][m].m.bytes();
+                    if board::try_move(d, m).is_err() {
+                        writeln!(out, "Illegal move: {}", command)?;
+                    } else {
+                        print_game_over(d, out)?;
                     }
                 }
-            }
+                Err(ParseMoveError::Malformed) => {
+                    writeln!(out, "Error (unknown command): {}", command)?
+                }
+                Err(ParseMoveError::Illegal) => writeln!(out, "Illegal move: {}", command)?,
+                Err(err) => writeln!(out, "Illegal move ({}): {}", err, command)?,
+            },
         }
     }
 }
+
+/// claim_draw() claims a draw by repetition or the fifty move rule, if the
+/// position qualifies, with the result and comment CECP expects, and returns
+/// true if it did. (The position qualifies on the third occurrence, or after
+/// 100 half-moves without a capture or pawn move; see util::game_result().)
+
+fn claim_draw(d: &mut Data, out: &mut dyn Write) -> io::Result<bool> {
+    let claim = match util::game_result(d) {
+        GameResult::DrawRepetition => "1/2-1/2 {Draw by repetition}",
+        GameResult::DrawFifty => "1/2-1/2 {Draw by 50 move rule}",
+        _ => return Ok(false),
+    };
+    writeln!(out, "{}", claim)?;
+    Ok(true)
+}
+
+/// print_game_over() prints the result if the game is over by itself, i.e.,
+/// by checkmate, stalemate, or insufficient material. A draw that has to be
+/// claimed is left to claim_draw(), which claims it on the engine's move.
+
+fn print_game_over(d: &mut Data, out: &mut dyn Write) -> io::Result<()> {
+    match util::game_result(d) {
+        GameResult::DrawRepetition | GameResult::DrawFifty => Ok(()),
+        _ => util::print_result(d, out),
+    }
+}